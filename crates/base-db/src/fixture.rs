@@ -12,8 +12,8 @@ use vfs::{file_set::FileSet, VfsPath};
 use crate::{
     input::{CrateName, CrateOrigin, LangCrateOrigin},
     Change, CrateDisplayName, CrateGraph, CrateId, Dependency, Edition, Env, FileId, FilePosition,
-    FileRange, ProcMacro, ProcMacroExpander, ProcMacroExpansionError, SourceDatabaseExt,
-    SourceRoot, SourceRootId,
+    FileRange, ProcMacro, ProcMacroExpander, ProcMacroExpansionError, ProcMacroExpansionPolicy,
+    SourceDatabaseExt, SourceRoot, SourceRootId,
 };
 
 pub const WORKSPACE: SourceRootId = SourceRootId(0);
@@ -309,6 +309,7 @@ pub fn identity(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 name: "identity".into(),
                 kind: crate::ProcMacroKind::Attr,
                 expander: Arc::new(IdentityProcMacroExpander),
+                policy: ProcMacroExpansionPolicy::Expand,
             },
         ),
         (
@@ -323,6 +324,7 @@ pub fn derive_identity(item: TokenStream) -> TokenStream {
                 name: "DeriveIdentity".into(),
                 kind: crate::ProcMacroKind::CustomDerive,
                 expander: Arc::new(IdentityProcMacroExpander),
+                policy: ProcMacroExpansionPolicy::Expand,
             },
         ),
         (
@@ -337,6 +339,7 @@ pub fn input_replace(attr: TokenStream, _item: TokenStream) -> TokenStream {
                 name: "input_replace".into(),
                 kind: crate::ProcMacroKind::Attr,
                 expander: Arc::new(AttributeInputReplaceProcMacroExpander),
+                policy: ProcMacroExpansionPolicy::Expand,
             },
         ),
         (
@@ -351,6 +354,7 @@ pub fn mirror(input: TokenStream) -> TokenStream {
                 name: "mirror".into(),
                 kind: crate::ProcMacroKind::FuncLike,
                 expander: Arc::new(MirrorProcMacroExpander),
+                policy: ProcMacroExpansionPolicy::Expand,
             },
         ),
     ]
@@ -438,9 +442,9 @@ impl From<Fixture> for FileMeta {
     }
 }
 
-// Identity mapping
+/// Identity mapping, for tests which need proc-macros beyond the default ones.
 #[derive(Debug)]
-struct IdentityProcMacroExpander;
+pub struct IdentityProcMacroExpander;
 impl ProcMacroExpander for IdentityProcMacroExpander {
     fn expand(
         &self,
@@ -70,9 +70,18 @@ impl SourceRoot {
 ///
 /// `CrateGraph` is `!Serialize` by design, see
 /// <https://github.com/rust-analyzer/rust-analyzer/blob/master/docs/dev/architecture.md#serialization>
-#[derive(Debug, Clone, Default /* Serialize, Deserialize */)]
+#[derive(Clone, Default /* Serialize, Deserialize */)]
 pub struct CrateGraph {
     arena: FxHashMap<CrateId, CrateData>,
+    /// The id of the next crate added, which is above the ids of all the crates. The ids aren't
+    /// necessarily contiguous, see [`CrateGraph::extend_at`].
+    next_id: u32,
+}
+
+impl fmt::Debug for CrateGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CrateGraph").field("arena", &self.arena).finish()
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -216,6 +225,19 @@ pub enum ProcMacroKind {
     Attr,
 }
 
+/// How invocations of a proc-macro are treated, as configured by the user.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum ProcMacroExpansionPolicy {
+    /// Run the macro's expander.
+    Expand,
+    /// Don't run the expander. Attribute macros leave the annotated item as is,
+    /// function-like macros expand to their input and derives expand to nothing.
+    Identity,
+    /// Don't run the expander and expand to nothing. Attribute macros remove the
+    /// annotated item.
+    Strip,
+}
+
 pub trait ProcMacroExpander: fmt::Debug + Send + Sync + RefUnwindSafe {
     fn expand(
         &self,
@@ -238,6 +260,7 @@ pub struct ProcMacro {
     pub name: SmolStr,
     pub kind: ProcMacroKind,
     pub expander: Arc<dyn ProcMacroExpander>,
+    pub policy: ProcMacroExpansionPolicy,
 }
 
 #[derive(Debug, Clone)]
@@ -259,6 +282,9 @@ pub struct CrateData {
     pub proc_macro: ProcMacroLoadResult,
     pub origin: CrateOrigin,
     pub is_proc_macro: bool,
+    /// The target triple this crate is analyzed for, if the same crate is
+    /// analyzed for several targets.
+    pub target: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -325,8 +351,10 @@ impl CrateGraph {
             dependencies: Vec::new(),
             origin,
             is_proc_macro,
+            target: None,
         };
-        let crate_id = CrateId(self.arena.len() as u32);
+        let crate_id = CrateId(self.next_id);
+        self.next_id += 1;
         let prev = self.arena.insert(crate_id, data);
         assert!(prev.is_none());
         crate_id
@@ -353,10 +381,22 @@ impl CrateGraph {
         Ok(())
     }
 
+    pub fn set_cfg_options(&mut self, krate: CrateId, cfg_options: CfgOptions) {
+        self.arena.get_mut(&krate).unwrap().cfg_options = cfg_options;
+    }
+
+    pub fn set_target(&mut self, krate: CrateId, target: String) {
+        self.arena.get_mut(&krate).unwrap().target = Some(target);
+    }
+
     pub fn is_empty(&self) -> bool {
         self.arena.is_empty()
     }
 
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = CrateId> + '_ {
         self.arena.keys().copied()
     }
@@ -446,7 +486,20 @@ impl CrateGraph {
     /// The ids of the crates in the `other` graph are shifted by the return
     /// amount.
     pub fn extend(&mut self, other: CrateGraph) -> u32 {
-        let start = self.arena.len() as u32;
+        let start = self.next_id;
+        self.extend_at(other, start);
+        start
+    }
+
+    /// Extends this crate graph by adding a complete disjoint second crate
+    /// graph, shifting the ids of its crates by `start`.
+    ///
+    /// This leaves a gap in the ids if `start` is above those of this graph,
+    /// which keeps the ids of a graph stable when another one before it is
+    /// dropped.
+    pub fn extend_at(&mut self, other: CrateGraph, start: u32) {
+        assert!(start >= self.next_id, "crate ids {} overlap those of the graph", start);
+        self.next_id = start + other.next_id;
         self.arena.extend(other.arena.into_iter().map(|(id, mut data)| {
             let new_id = id.shift(start);
             for dep in &mut data.dependencies {
@@ -454,7 +507,6 @@ impl CrateGraph {
             }
             (new_id, data)
         }));
-        start
     }
 
     fn find_path(
@@ -617,7 +669,9 @@ impl fmt::Display for CyclicDependenciesError {
 mod tests {
     use crate::CrateOrigin;
 
-    use super::{CfgOptions, CrateGraph, CrateName, Dependency, Edition::Edition2018, Env, FileId};
+    use super::{
+        CfgOptions, CrateGraph, CrateId, CrateName, Dependency, Edition::Edition2018, Env, FileId,
+    };
 
     #[test]
     fn detect_cyclic_dependency_indirect() {
@@ -789,4 +843,54 @@ mod tests {
             vec![Dependency::new(CrateName::new("crate_name_with_dashes").unwrap(), crate2)]
         );
     }
+
+    #[test]
+    fn extend_at_keeps_ids() {
+        let graph_of = |files: &[u32]| {
+            let mut graph = CrateGraph::default();
+            let crates: Vec<_> = files
+                .iter()
+                .map(|&file| {
+                    graph.add_crate_root(
+                        FileId(file),
+                        Edition2018,
+                        None,
+                        None,
+                        CfgOptions::default(),
+                        CfgOptions::default(),
+                        Env::default(),
+                        Ok(Vec::new()),
+                        false,
+                        CrateOrigin::CratesIo { repo: None },
+                    )
+                })
+                .collect();
+            graph
+                .add_dep(crates[0], Dependency::new(CrateName::new("dep").unwrap(), crates[1]))
+                .unwrap();
+            graph
+        };
+
+        let mut graph = CrateGraph::default();
+        graph.extend_at(graph_of(&[1, 2]), 3);
+        assert_eq!(graph.crate_id_for_crate_root(FileId(1)), Some(CrateId(3)));
+        assert_eq!(graph[CrateId(3)].dependencies[0].crate_id, CrateId(4));
+
+        // Crates added afterwards don't reuse the ids of the gap.
+        assert_eq!(graph.extend(graph_of(&[3, 4])), 5);
+        let krate = graph.add_crate_root(
+            FileId(5u32),
+            Edition2018,
+            None,
+            None,
+            CfgOptions::default(),
+            CfgOptions::default(),
+            Env::default(),
+            Ok(Vec::new()),
+            false,
+            CrateOrigin::CratesIo { repo: None },
+        );
+        assert_eq!(krate, CrateId(7));
+        assert_eq!(graph.len(), 5);
+    }
 }
@@ -5,7 +5,7 @@ pub mod fixture;
 
 use std::{panic, sync::Arc};
 
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
 use syntax::{ast, Parse, SourceFile, TextRange, TextSize};
 
 pub use crate::{
@@ -13,7 +13,8 @@ pub use crate::{
     input::{
         CrateData, CrateDisplayName, CrateGraph, CrateId, CrateName, CrateOrigin, Dependency,
         Edition, Env, LangCrateOrigin, ProcMacro, ProcMacroExpander, ProcMacroExpansionError,
-        ProcMacroId, ProcMacroKind, ProcMacroLoadResult, SourceRoot, SourceRootId,
+        ProcMacroExpansionPolicy, ProcMacroId, ProcMacroKind, ProcMacroLoadResult, SourceRoot,
+        SourceRootId,
     },
 };
 pub use salsa::{self, Cancelled};
@@ -69,6 +70,11 @@ pub trait SourceDatabase: FileLoader + std::fmt::Debug {
     /// The crate graph.
     #[salsa::input]
     fn crate_graph(&self) -> Arc<CrateGraph>;
+
+    /// For crates analyzed for several targets, the target to prefer when
+    /// mapping their files to modules, keyed by the crate root file.
+    #[salsa::input]
+    fn active_targets(&self) -> Arc<FxHashMap<FileId, String>>;
 }
 
 fn parse_query(db: &dyn SourceDatabase, file_id: FileId) -> Parse<ast::SourceFile> {
@@ -114,6 +114,16 @@ impl CfgDiff {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Returns the atoms enabled by this diff.
+    pub fn enable(&self) -> &[CfgAtom] {
+        &self.enable
+    }
+
+    /// Returns the atoms disabled by this diff.
+    pub fn disable(&self) -> &[CfgAtom] {
+        &self.disable
+    }
 }
 
 impl fmt::Display for CfgDiff {
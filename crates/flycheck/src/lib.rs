@@ -2,9 +2,13 @@
 //! another compatible command (f.x. clippy) in a background thread and provide
 //! LSP diagnostics based on the output of the command.
 
-use std::{fmt, io, process::Command, time::Duration};
+use std::{
+    fmt, io,
+    process::Command,
+    time::{Duration, Instant},
+};
 
-use crossbeam_channel::{never, select, unbounded, Receiver, Sender};
+use crossbeam_channel::{at, never, select, unbounded, Receiver, Sender};
 use paths::AbsPathBuf;
 use serde::Deserialize;
 use stdx::process::streaming_output;
@@ -49,7 +53,7 @@ impl fmt::Display for FlycheckConfig {
 #[derive(Debug)]
 pub struct FlycheckHandle {
     // XXX: drop order is significant
-    sender: Sender<Restart>,
+    sender: Sender<StateChange>,
     _thread: jod_thread::JoinHandle,
 }
 
@@ -61,7 +65,7 @@ impl FlycheckHandle {
         workspace_root: AbsPathBuf,
     ) -> FlycheckHandle {
         let actor = FlycheckActor::new(id, sender, config, workspace_root);
-        let (sender, receiver) = unbounded::<Restart>();
+        let (sender, receiver) = unbounded::<StateChange>();
         let thread = jod_thread::Builder::new()
             .name("Flycheck".to_owned())
             .spawn(move || actor.run(receiver))
@@ -71,13 +75,30 @@ impl FlycheckHandle {
 
     /// Schedule a re-start of the cargo check worker.
     pub fn update(&self) {
-        self.sender.send(Restart).unwrap();
+        self.sender.send(StateChange::Restart(Restart::Workspace)).unwrap();
+    }
+
+    /// Stop the running check, if any.
+    pub fn cancel(&self) {
+        self.sender.send(StateChange::Cancel).unwrap();
+    }
+
+    /// Schedule a re-start of the cargo check worker checking only `packages`, followed by a
+    /// check of the whole workspace once no other restart was requested for `workspace_delay`.
+    pub fn update_packages(&self, packages: Vec<String>, workspace_delay: Duration) {
+        let restart = Restart::Packages { packages, workspace_delay };
+        self.sender.send(StateChange::Restart(restart)).unwrap();
     }
 }
 
 pub enum Message {
     /// Request adding a diagnostic with fixes included to a file
-    AddDiagnostic { workspace_root: AbsPathBuf, diagnostic: Diagnostic },
+    AddDiagnostic {
+        /// Flycheck instance ID
+        id: usize,
+        workspace_root: AbsPathBuf,
+        diagnostic: Diagnostic,
+    },
 
     /// Request check progress notification to client
     Progress {
@@ -90,8 +111,9 @@ pub enum Message {
 impl fmt::Debug for Message {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Message::AddDiagnostic { workspace_root, diagnostic } => f
+            Message::AddDiagnostic { id, workspace_root, diagnostic } => f
                 .debug_struct("AddDiagnostic")
+                .field("id", id)
                 .field("workspace_root", workspace_root)
                 .field("diagnostic_code", &diagnostic.code.as_ref().map(|it| &it.code))
                 .finish(),
@@ -105,12 +127,43 @@ impl fmt::Debug for Message {
 #[derive(Debug)]
 pub enum Progress {
     DidStart,
+    /// Like `DidStart`, but only the given packages are checked.
+    DidStartPackages(Vec<String>),
     DidCheckCrate(String),
     DidFinish(io::Result<()>),
     DidCancel,
 }
 
-struct Restart;
+enum StateChange {
+    Restart(Restart),
+    Cancel,
+}
+
+#[derive(Debug, PartialEq)]
+enum Restart {
+    Workspace,
+    Packages { packages: Vec<String>, workspace_delay: Duration },
+}
+
+impl Restart {
+    /// Merges two restart requests received in quick succession.
+    fn merge(self, other: Restart) -> Restart {
+        match (self, other) {
+            (
+                Restart::Packages { mut packages, .. },
+                Restart::Packages { packages: other, workspace_delay },
+            ) => {
+                for package in other {
+                    if !packages.contains(&package) {
+                        packages.push(package);
+                    }
+                }
+                Restart::Packages { packages, workspace_delay }
+            }
+            _ => Restart::Workspace,
+        }
+    }
+}
 
 struct FlycheckActor {
     id: usize,
@@ -123,10 +176,17 @@ struct FlycheckActor {
     /// have to wrap sub-processes output handling in a thread and pass messages
     /// back over a channel.
     cargo_handle: Option<CargoHandle>,
+    /// The delay after which to check the whole workspace, if the running check only covers
+    /// some packages.
+    workspace_delay: Option<Duration>,
+    /// When to check the whole workspace after a check of some packages finished.
+    workspace_check_at: Option<Instant>,
+    /// The packages the running check covers, or `None` if it covers the whole workspace.
+    packages: Option<Vec<String>>,
 }
 
 enum Event {
-    Restart(Restart),
+    RequestStateChange(StateChange),
     CheckEvent(Option<CargoMessage>),
 }
 
@@ -137,30 +197,74 @@ impl FlycheckActor {
         config: FlycheckConfig,
         workspace_root: AbsPathBuf,
     ) -> FlycheckActor {
-        FlycheckActor { id, sender, config, workspace_root, cargo_handle: None }
+        FlycheckActor {
+            id,
+            sender,
+            config,
+            workspace_root,
+            cargo_handle: None,
+            workspace_delay: None,
+            workspace_check_at: None,
+            packages: None,
+        }
     }
     fn progress(&self, progress: Progress) {
         self.send(Message::Progress { id: self.id, progress });
     }
-    fn next_event(&self, inbox: &Receiver<Restart>) -> Option<Event> {
+    fn next_event(&self, inbox: &Receiver<StateChange>) -> Option<Event> {
         let check_chan = self.cargo_handle.as_ref().map(|cargo| &cargo.receiver);
+        let workspace_check = self.workspace_check_at.map_or_else(never, at);
         select! {
-            recv(inbox) -> msg => msg.ok().map(Event::Restart),
+            recv(inbox) -> msg => msg.ok().map(Event::RequestStateChange),
             recv(check_chan.unwrap_or(&never())) -> msg => Some(Event::CheckEvent(msg.ok())),
+            recv(workspace_check) -> _ => {
+                Some(Event::RequestStateChange(StateChange::Restart(Restart::Workspace)))
+            }
         }
     }
-    fn run(mut self, inbox: Receiver<Restart>) {
-        while let Some(event) = self.next_event(&inbox) {
+    fn run(mut self, inbox: Receiver<StateChange>) {
+        'events: while let Some(event) = self.next_event(&inbox) {
             match event {
-                Event::Restart(Restart) => {
-                    while let Ok(Restart) = inbox.recv_timeout(Duration::from_millis(50)) {}
+                Event::RequestStateChange(StateChange::Cancel) => {
+                    self.cancel_check_process();
+                    self.workspace_check_at = None;
+                }
+                Event::RequestStateChange(StateChange::Restart(mut restart)) => {
+                    while let Ok(next) = inbox.recv_timeout(Duration::from_millis(50)) {
+                        match next {
+                            StateChange::Restart(next) => restart = restart.merge(next),
+                            StateChange::Cancel => {
+                                self.cancel_check_process();
+                                self.workspace_check_at = None;
+                                continue 'events;
+                            }
+                        }
+                    }
 
                     self.cancel_check_process();
+                    self.workspace_check_at = None;
 
-                    let command = self.check_command();
+                    let packages = match restart {
+                        // Only cargo knows about packages.
+                        Restart::Packages { packages, workspace_delay }
+                            if matches!(self.config, FlycheckConfig::CargoCommand { .. }) =>
+                        {
+                            self.workspace_delay = Some(workspace_delay);
+                            Some(packages)
+                        }
+                        Restart::Packages { .. } | Restart::Workspace => {
+                            self.workspace_delay = None;
+                            None
+                        }
+                    };
+                    let command = self.check_command(packages.as_deref());
                     tracing::info!("restart flycheck {:?}", command);
                     self.cargo_handle = Some(CargoHandle::spawn(command));
-                    self.progress(Progress::DidStart);
+                    self.packages = packages.clone();
+                    self.progress(match packages {
+                        Some(packages) => Progress::DidStartPackages(packages),
+                        None => Progress::DidStart,
+                    });
                 }
                 Event::CheckEvent(None) => {
                     // Watcher finished, replace it with a never channel to
@@ -170,10 +274,12 @@ impl FlycheckActor {
                     if res.is_err() {
                         tracing::error!(
                             "Flycheck failed to run the following command: {:?}",
-                            self.check_command()
+                            self.check_command(self.packages.as_deref())
                         );
                     }
                     self.progress(Progress::DidFinish(res));
+                    self.workspace_check_at =
+                        self.workspace_delay.take().map(|delay| Instant::now() + delay);
                 }
                 Event::CheckEvent(Some(message)) => match message {
                     CargoMessage::CompilerArtifact(msg) => {
@@ -182,6 +288,7 @@ impl FlycheckActor {
 
                     CargoMessage::Diagnostic(msg) => {
                         self.send(Message::AddDiagnostic {
+                            id: self.id,
                             workspace_root: self.workspace_root.clone(),
                             diagnostic: msg,
                         });
@@ -197,7 +304,8 @@ impl FlycheckActor {
             self.progress(Progress::DidCancel);
         }
     }
-    fn check_command(&self) -> Command {
+    /// Returns the command checking `packages`, or the whole workspace.
+    fn check_command(&self, packages: Option<&[String]>) -> Command {
         let mut cmd = match &self.config {
             FlycheckConfig::CargoCommand {
                 command,
@@ -211,7 +319,17 @@ impl FlycheckActor {
                 let mut cmd = Command::new(toolchain::cargo());
                 cmd.arg(command);
                 cmd.current_dir(&self.workspace_root);
-                cmd.args(&["--workspace", "--message-format=json", "--manifest-path"])
+                match packages {
+                    Some(packages) => {
+                        for package in packages {
+                            cmd.args(&["-p", package]);
+                        }
+                    }
+                    None => {
+                        cmd.arg("--workspace");
+                    }
+                }
+                cmd.args(&["--message-format=json", "--manifest-path"])
                     .arg(self.workspace_root.join("Cargo.toml").as_os_str());
 
                 if let Some(target) = target_triple {
@@ -349,3 +467,26 @@ enum JsonMessage {
     Cargo(cargo_metadata::Message),
     Rustc(Diagnostic),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packages(packages: &[&str], delay: u64) -> Restart {
+        Restart::Packages {
+            packages: packages.iter().map(|it| it.to_string()).collect(),
+            workspace_delay: Duration::from_secs(delay),
+        }
+    }
+
+    #[test]
+    fn merge_restarts() {
+        assert_eq!(
+            packages(&["a", "b"], 1).merge(packages(&["b", "c"], 2)),
+            packages(&["a", "b", "c"], 2)
+        );
+        assert_eq!(packages(&["a"], 1).merge(Restart::Workspace), Restart::Workspace);
+        assert_eq!(Restart::Workspace.merge(packages(&["a"], 1)), Restart::Workspace);
+        assert_eq!(Restart::Workspace.merge(Restart::Workspace), Restart::Workspace);
+    }
+}
@@ -38,8 +38,18 @@ use crate::{
 };
 
 #[track_caller]
-fn check(ra_fixture: &str, mut expect: Expect) {
-    let extra_proc_macros = vec![(
+fn check(ra_fixture: &str, expect: Expect) {
+    check_with_proc_macros(ra_fixture, Vec::new(), expect)
+}
+
+/// Like [`check`], but makes the given proc-macros available in addition to the default ones.
+#[track_caller]
+fn check_with_proc_macros(
+    ra_fixture: &str,
+    mut extra_proc_macros: Vec<(String, ProcMacro)>,
+    mut expect: Expect,
+) {
+    extra_proc_macros.push((
         r#"
 #[proc_macro_attribute]
 pub fn identity_when_valid(_attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -51,8 +61,9 @@ pub fn identity_when_valid(_attr: TokenStream, item: TokenStream) -> TokenStream
             name: "identity_when_valid".into(),
             kind: base_db::ProcMacroKind::Attr,
             expander: Arc::new(IdentityWhenValidProcMacroExpander),
+            policy: base_db::ProcMacroExpansionPolicy::Expand,
         },
-    )];
+    ));
     let db = TestDB::with_files_extra_proc_macros(ra_fixture, extra_proc_macros);
     let krate = db.crate_graph().iter().next().unwrap();
     let def_map = db.crate_def_map(krate);
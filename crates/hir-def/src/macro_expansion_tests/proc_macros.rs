@@ -3,9 +3,70 @@
 //! Note `//- proc_macros: identity` fixture metas in tests -- we don't use real
 //! proc-macros here, as that would be slow. Instead, we use several hard-coded
 //! in-memory macros.
-use expect_test::expect;
+use std::sync::Arc;
 
-use crate::macro_expansion_tests::check;
+use base_db::{ProcMacro, ProcMacroExpansionPolicy, ProcMacroKind};
+use expect_test::{expect, Expect};
+use tt::Subtree;
+
+use crate::macro_expansion_tests::{check, check_with_proc_macros};
+
+/// Checks the expansion of an attribute, a function-like and a derive macro,
+/// all configured with the given policy.
+#[track_caller]
+fn check_with_policy(policy: ProcMacroExpansionPolicy, ra_fixture: &str, expect: Expect) {
+    let proc_macro = |source: &str, name: &str, kind| {
+        let expander = Arc::new(UnreachableProcMacroExpander);
+        (source.to_string(), ProcMacro { name: name.into(), kind, expander, policy })
+    };
+    let proc_macros = vec![
+        proc_macro(
+            r#"
+#[proc_macro_attribute]
+pub fn attr_with_policy(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    item
+}
+"#,
+            "attr_with_policy",
+            ProcMacroKind::Attr,
+        ),
+        proc_macro(
+            r#"
+#[proc_macro]
+pub fn fn_with_policy(input: TokenStream) -> TokenStream {
+    input
+}
+"#,
+            "fn_with_policy",
+            ProcMacroKind::FuncLike,
+        ),
+        proc_macro(
+            r#"
+#[proc_macro_derive(DeriveWithPolicy)]
+pub fn derive_with_policy(item: TokenStream) -> TokenStream {
+    item
+}
+"#,
+            "DeriveWithPolicy",
+            ProcMacroKind::CustomDerive,
+        ),
+    ];
+    check_with_proc_macros(ra_fixture, proc_macros, expect);
+}
+
+// Macros which aren't expanded by their policy must never reach the expander.
+#[derive(Debug)]
+struct UnreachableProcMacroExpander;
+impl base_db::ProcMacroExpander for UnreachableProcMacroExpander {
+    fn expand(
+        &self,
+        _: &Subtree,
+        _: Option<&Subtree>,
+        _: &base_db::Env,
+    ) -> Result<Subtree, base_db::ProcMacroExpansionError> {
+        panic!("expanded a proc-macro with a policy")
+    }
+}
 
 #[test]
 fn attribute_macro_attr_censoring() {
@@ -128,3 +189,58 @@ macro_rules! id {
 "##]],
     );
 }
+
+#[test]
+fn identity_policy() {
+    check_with_policy(
+        ProcMacroExpansionPolicy::Identity,
+        r#"
+//- proc_macros: attr_with_policy, fn_with_policy, derive_with_policy
+//- minicore: derive
+#[proc_macros::attr_with_policy]
+fn f() {}
+
+#[derive(proc_macros::DeriveWithPolicy)]
+struct S;
+
+proc_macros::fn_with_policy!(struct T;);
+"#,
+        expect![[r#"
+#[proc_macros::attr_with_policy]
+fn f() {}
+
+#[derive(proc_macros::DeriveWithPolicy)]
+struct S;
+
+struct T;
+
+fn f() {}"#]],
+    );
+}
+
+#[test]
+fn strip_policy() {
+    check_with_policy(
+        ProcMacroExpansionPolicy::Strip,
+        r#"
+//- proc_macros: attr_with_policy, fn_with_policy, derive_with_policy
+//- minicore: derive
+#[proc_macros::attr_with_policy]
+fn f() {}
+
+#[derive(proc_macros::DeriveWithPolicy)]
+struct S;
+
+proc_macros::fn_with_policy!(struct T;);
+"#,
+        expect![[r#"
+#[proc_macros::attr_with_policy]
+fn f() {}
+
+#[derive(proc_macros::DeriveWithPolicy)]
+struct S;
+
+
+"#]],
+    );
+}
@@ -1063,19 +1063,35 @@ impl DefCollector<'_> {
 
             match &directive.kind {
                 MacroDirectiveKind::FnLike { ast_id, expand_to } => {
+                    let mut error = None;
                     let call_id = macro_call_as_call_id(
                         self.db,
                         ast_id,
                         *expand_to,
                         self.def_map.krate,
                         &resolver,
-                        &mut |_err| (),
+                        &mut |err| {
+                            error.get_or_insert(err);
+                        },
                     );
-                    if let Ok(Ok(call_id)) = call_id {
+                    let call_id = match call_id {
+                        Ok(Ok(call_id)) => Some(call_id),
+                        // Built-in macro failed eager expansion.
+                        Ok(Err(_)) => None,
+                        Err(UnresolvedMacro { .. }) => return true,
+                    };
+                    if let Some(err) = error {
+                        self.def_map.diagnostics.push(DefDiagnostic::macro_error(
+                            directive.module_id,
+                            MacroCallKind::FnLike { ast_id: ast_id.ast_id, expand_to: *expand_to },
+                            err.to_string(),
+                        ));
+                    }
+                    if let Some(call_id) = call_id {
                         push_resolved(directive, call_id);
-                        res = ReachedFixedPoint::No;
-                        return false;
                     }
+                    res = ReachedFixedPoint::No;
+                    return false;
                 }
                 MacroDirectiveKind::Derive { ast_id, derive_attr, derive_pos } => {
                     let call_id = derive_macro_as_call_id(
@@ -192,13 +192,17 @@ fn lazy_expand(
     ExpandResult { value, err }
 }
 
+fn in_nested_call(path: &ModPath, err: ExpandError) -> ExpandError {
+    ExpandError::Other(format!("in `{path}!`: {err}").into())
+}
+
 fn eager_macro_recur(
     db: &dyn AstDatabase,
     hygiene: &Hygiene,
     curr: InFile<SyntaxNode>,
     krate: CrateId,
     macro_resolver: &dyn Fn(ModPath) -> Option<MacroDefId>,
-    mut diagnostic_sink: &mut dyn FnMut(ExpandError),
+    diagnostic_sink: &mut dyn FnMut(ExpandError),
 ) -> Result<Result<SyntaxNode, ErrorEmitted>, UnresolvedMacro> {
     let original = curr.value.clone_for_update();
 
@@ -207,13 +211,19 @@ fn eager_macro_recur(
 
     // Collect replacement
     for child in children {
-        let def = match child.path().and_then(|path| ModPath::from_src(db, path, hygiene)) {
-            Some(path) => macro_resolver(path.clone()).ok_or_else(|| UnresolvedMacro { path })?,
+        let path = match child.path().and_then(|path| ModPath::from_src(db, path, hygiene)) {
+            Some(path) => path,
             None => {
                 diagnostic_sink(ExpandError::Other("malformed macro invocation".into()));
                 continue;
             }
         };
+        let def =
+            macro_resolver(path.clone()).ok_or_else(|| UnresolvedMacro { path: path.clone() })?;
+        // Errors are reported on the outermost eager call, so record which nested call they
+        // originate from.
+        let mut nested_sink = |err| diagnostic_sink(in_nested_call(&path, err));
+        let mut diagnostic_sink: &mut dyn FnMut(ExpandError) = &mut nested_sink;
         let insert = match def.kind {
             MacroDefKind::BuiltInEager(..) => {
                 let id = match expand_eager_macro(
@@ -222,7 +232,7 @@ fn eager_macro_recur(
                     curr.with_value(child.clone()),
                     def,
                     macro_resolver,
-                    diagnostic_sink,
+                    &mut *diagnostic_sink,
                 ) {
                     Ok(Ok(it)) => it,
                     Ok(Err(err)) => return Ok(Err(err)),
@@ -245,7 +255,14 @@ fn eager_macro_recur(
 
                 // replace macro inside
                 let hygiene = Hygiene::new(db, val.file_id);
-                match eager_macro_recur(db, &hygiene, val, krate, macro_resolver, diagnostic_sink) {
+                match eager_macro_recur(
+                    db,
+                    &hygiene,
+                    val,
+                    krate,
+                    macro_resolver,
+                    &mut *diagnostic_sink,
+                ) {
                     Ok(Ok(it)) => it,
                     Ok(Err(err)) => return Ok(Err(err)),
                     Err(err) => return Err(err),
@@ -1,6 +1,8 @@
 //! Proc Macro Expander stub
 
-use base_db::{CrateId, ProcMacroExpansionError, ProcMacroId, ProcMacroKind};
+use base_db::{
+    CrateId, ProcMacroExpansionError, ProcMacroExpansionPolicy, ProcMacroId, ProcMacroKind,
+};
 
 use crate::{db::AstDatabase, ExpandError, ExpandResult};
 
@@ -24,6 +26,14 @@ impl ProcMacroExpander {
         self.proc_macro_id.is_none()
     }
 
+    /// Returns the user-configured expansion policy of the loaded proc-macro.
+    pub fn policy(&self, db: &dyn AstDatabase) -> Option<ProcMacroExpansionPolicy> {
+        let id = self.proc_macro_id?;
+        let krate_graph = db.crate_graph();
+        let proc_macros = krate_graph[self.krate].proc_macro.as_ref().ok()?;
+        Some(proc_macros.get(id.0 as usize)?.policy)
+    }
+
     pub fn expand(
         self,
         db: &dyn AstDatabase,
@@ -51,6 +61,19 @@ impl ProcMacroExpander {
                     }
                 };
 
+                match proc_macro.policy {
+                    ProcMacroExpansionPolicy::Expand => (),
+                    ProcMacroExpansionPolicy::Identity => {
+                        return ExpandResult::ok(match proc_macro.kind {
+                            ProcMacroKind::Attr | ProcMacroKind::FuncLike => tt.clone(),
+                            ProcMacroKind::CustomDerive => tt::Subtree::default(),
+                        })
+                    }
+                    ProcMacroExpansionPolicy::Strip => {
+                        return ExpandResult::ok(tt::Subtree::default())
+                    }
+                }
+
                 // Proc macros have access to the environment variables of the invoking crate.
                 let env = &krate_graph[calling_crate].env;
                 match proc_macro.expander.expand(tt, attr_arg, env) {
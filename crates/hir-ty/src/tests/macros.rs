@@ -1244,3 +1244,41 @@ fn infinitely_recursive_macro_type() {
         "#]],
     );
 }
+
+#[test]
+fn infer_builtin_macros_include_nested_in_user_macro() {
+    check_types(
+        r#"
+//- /main.rs crate:main env:OUT_DIR=out
+#[rustc_builtin_macro]
+macro_rules! include {() => {}}
+
+#[rustc_builtin_macro]
+macro_rules! concat {() => {}}
+
+#[rustc_builtin_macro]
+macro_rules! env {() => {}}
+
+macro_rules! out_dir {
+    () => { env!("OUT_DIR") };
+}
+macro_rules! gen_path {
+    ($name:literal) => { concat!(out_dir!(), "/", $name) };
+}
+macro_rules! include_gen {
+    ($name:literal) => { include!(gen_path!($name)); };
+}
+
+mod generated {
+    include_gen!("gen.rs");
+}
+
+fn main() {
+    generated::bar();
+} //^^^^^^^^^^^^^^^^ u32
+
+//- /out/gen.rs
+pub fn bar() -> u32 {0}
+"#,
+    );
+}
@@ -35,7 +35,10 @@ mod display;
 use std::{iter, ops::ControlFlow, sync::Arc};
 
 use arrayvec::ArrayVec;
-use base_db::{CrateDisplayName, CrateId, CrateOrigin, Edition, FileId, ProcMacroKind};
+use base_db::{
+    CrateDisplayName, CrateId, CrateOrigin, Edition, FileId, ProcMacroExpansionPolicy,
+    ProcMacroKind,
+};
 use either::Either;
 use hir_def::{
     adt::{ReprKind, VariantData},
@@ -57,6 +60,7 @@ use hir_ty::{
     all_super_traits, autoderef,
     consteval::{unknown_const_as_generic, ComputedExpr, ConstEvalError, ConstExt},
     diagnostics::BodyValidationDiagnostic,
+    direct_super_traits,
     method_resolution::{self, TyFingerprint},
     primitive::UintTy,
     subst_prefix,
@@ -203,6 +207,11 @@ impl Crate {
         db.crate_graph()[self.id].version.clone()
     }
 
+    /// Returns the target triple this crate is analyzed for, if it is analyzed for several.
+    pub fn target(self, db: &dyn HirDatabase) -> Option<String> {
+        db.crate_graph()[self.id].target.clone()
+    }
+
     pub fn display_name(self, db: &dyn HirDatabase) -> Option<CrateDisplayName> {
         db.crate_graph()[self.id].display_name.clone()
     }
@@ -1694,6 +1703,10 @@ impl Trait {
         db.trait_data(self.id).items.iter().map(|(_name, it)| (*it).into()).collect()
     }
 
+    pub fn direct_supertraits(self, db: &dyn HirDatabase) -> Vec<Trait> {
+        direct_super_traits(db.upcast(), self.id).into_iter().map(Trait::from).collect()
+    }
+
     pub fn items_with_supertraits(self, db: &dyn HirDatabase) -> Vec<AssocItem> {
         let traits = all_super_traits(db.upcast(), self.into());
         traits.iter().flat_map(|tr| Trait::from(*tr).items(db)).collect()
@@ -1880,6 +1893,17 @@ impl Macro {
     pub fn is_derive(&self, db: &dyn HirDatabase) -> bool {
         matches!(self.kind(db), MacroKind::Derive)
     }
+
+    /// Returns how invocations of this proc-macro are expanded, if it is a loaded proc-macro.
+    pub fn proc_macro_expansion_policy(
+        &self,
+        db: &dyn HirDatabase,
+    ) -> Option<ProcMacroExpansionPolicy> {
+        match self.id {
+            MacroId::ProcMacroId(it) => it.lookup(db.upcast()).expander.policy(db.upcast()),
+            MacroId::Macro2Id(_) | MacroId::MacroRulesId(_) => None,
+        }
+    }
 }
 
 impl HasVisibility for Macro {
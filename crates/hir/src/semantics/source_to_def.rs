@@ -127,6 +127,17 @@ impl SourceToDefCtx<'_, '_> {
                     .map(|local_id| crate_def_map.module_id(local_id)),
             )
         }
+        // Put the modules of crates analyzed for the active target first, so that they are the
+        // ones picked when a single module is needed.
+        let active_targets = self.db.active_targets();
+        if !active_targets.is_empty() {
+            let crate_graph = self.db.crate_graph();
+            mods.sort_by_key(|module: &ModuleId| {
+                let krate = &crate_graph[module.krate()];
+                let active = active_targets.get(&krate.root_file_id);
+                active.map_or(true, |active| krate.target.as_ref() != Some(active))
+            });
+        }
         mods
     }
 
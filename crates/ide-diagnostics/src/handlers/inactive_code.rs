@@ -1,5 +1,7 @@
-use cfg::DnfExpr;
+use cfg::{CfgAtom, DnfExpr};
+use itertools::Itertools;
 use stdx::format_to;
+use syntax::SmolStr;
 
 use crate::{Diagnostic, DiagnosticsContext, Severity};
 
@@ -21,6 +23,18 @@ pub(crate) fn inactive_code(
     if let Some(inactive) = inactive {
         format_to!(message, ": {}", inactive);
     }
+    if let Some(features) = enabling_features(ctx, d) {
+        match &*features {
+            [feature] => format_to!(message, "; enable the `{}` feature to activate it", feature),
+            [features @ .., last] => format_to!(
+                message,
+                "; enable the {} and `{}` features to activate it",
+                features.iter().map(|it| format!("`{}`", it)).join(", "),
+                last
+            ),
+            [] => (),
+        }
+    }
 
     let res = Diagnostic::new(
         "inactive-code",
@@ -32,8 +46,44 @@ pub(crate) fn inactive_code(
     Some(res)
 }
 
+/// Returns the smallest set of the crate's features which would activate the inactive code, if
+/// enabling features is all it takes.
+fn enabling_features(ctx: &DiagnosticsContext<'_>, d: &hir::InactiveCode) -> Option<Vec<SmolStr>> {
+    let file_id = d.node.file_id.original_file(ctx.sema.db);
+    let potential_cfg = ctx.sema.to_module_def(file_id)?.krate().potential_cfg(ctx.sema.db);
+    let known_features: Vec<_> = potential_cfg.get_cfg_values("feature").collect();
+
+    DnfExpr::new(d.cfg.clone())
+        .compute_enable_hints(&d.opts)
+        .filter(|diff| diff.disable().is_empty())
+        .filter_map(|diff| {
+            diff.enable()
+                .iter()
+                .map(|atom| match atom {
+                    CfgAtom::KeyValue { key, value }
+                        if key == "feature" && known_features.contains(&value) =>
+                    {
+                        Some(value.clone())
+                    }
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+        })
+        .min_by_key(|features| features.len())
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use cfg::CfgOptions;
+    use expect_test::expect;
+    use ide_db::{
+        assists::AssistResolveStrategy,
+        base_db::{fixture::WithFixture, salsa::Durability, SourceDatabase},
+        RootDatabase,
+    };
+
     use crate::{tests::check_diagnostics_with_config, DiagnosticsConfig};
 
     pub(crate) fn check(ra_fixture: &str) {
@@ -113,4 +163,41 @@ fn f() {
 "#,
         );
     }
+
+    #[test]
+    fn inactive_code_names_enabling_features() {
+        let (mut db, file_id) = RootDatabase::with_single_file(
+            r#"
+//- /lib.rs crate:foo cfg:feature=std,feature=alloc
+#[cfg(feature = "std")] fn f() {}
+#[cfg(all(feature = "std", feature = "alloc"))] fn g() {}
+#[cfg(any(feature = "alloc", feature = "nope"))] fn h() {}
+#[cfg(feature = "nope")] fn i() {}
+"#,
+        );
+        // Disable all features, while keeping them in the crate's potential cfgs.
+        let mut crate_graph = (*db.crate_graph()).clone();
+        for krate in crate_graph.iter().collect::<Vec<_>>() {
+            crate_graph.set_cfg_options(krate, CfgOptions::default());
+        }
+        db.set_crate_graph_with_durability(Arc::new(crate_graph), Durability::HIGH);
+
+        let messages: Vec<_> = crate::diagnostics(
+            &db,
+            &DiagnosticsConfig::default(),
+            &AssistResolveStrategy::All,
+            file_id,
+        )
+        .into_iter()
+        .map(|d| d.message)
+        .collect();
+        expect![[r#"
+            [
+                "code is inactive due to #[cfg] directives: feature = \"std\" is disabled; enable the `std` feature to activate it",
+                "code is inactive due to #[cfg] directives: feature = \"alloc\" and feature = \"std\" are disabled; enable the `alloc` and `std` features to activate it",
+                "code is inactive due to #[cfg] directives: feature = \"alloc\" and feature = \"nope\" are disabled; enable the `alloc` feature to activate it",
+                "code is inactive due to #[cfg] directives: feature = \"nope\" is disabled",
+            ]
+        "#]].assert_debug_eq(&messages);
+    }
 }
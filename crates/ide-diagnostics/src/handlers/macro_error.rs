@@ -111,7 +111,46 @@ macro_rules! env { () => {} }
 macro_rules! concat { () => {} }
 
   include!(concat!(env!("OUT_DIR"), "/out.rs"));
-//^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ error: `OUT_DIR` not set, enable "build scripts" to fix
+//^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ error: in `concat!`: in `env!`: `OUT_DIR` not set, enable "build scripts" to fix
+"#,
+        );
+    }
+
+    #[test]
+    fn nested_eager_error_in_user_macro() {
+        check_diagnostics(
+            r#"
+//- /lib.rs crate:lib deps:core
+macro_rules! include_gen {
+    ($name:literal) => { include!(concat!(env!("OUT_DIR"), "/", $name)); };
+}
+macro_rules! name {
+    () => { missing!() };
+}
+
+mod generated {
+    include_gen!("gen.rs");
+  //^^^^^^^^^^^^^^^^^^^^^^^ error: in `concat!`: in `env!`: `OUT_DIR` not set, enable "build scripts" to fix
+}
+
+fn f() {
+    concat!("a", name!());
+  //^^^^^^ error: unresolved macro `missing!`
+}
+//- /core.rs crate:core
+pub mod prelude {
+    pub mod rust_2018 {
+        pub use crate::macros::{concat, env, include};
+    }
+}
+mod macros {
+    #[rustc_builtin_macro]
+    pub macro include {}
+    #[rustc_builtin_macro]
+    pub macro concat {}
+    #[rustc_builtin_macro]
+    pub macro env {}
+}
 "#,
         );
     }
@@ -2,9 +2,9 @@
 
 use hir::db::DefDatabase;
 use ide_db::{
-    base_db::{FileId, FileLoader, SourceDatabase, SourceDatabaseExt},
+    base_db::{FileId, FileLoader, SourceDatabase, SourceDatabaseExt, SourceRoot, VfsPath},
     source_change::SourceChange,
-    RootDatabase,
+    FxHashSet, RootDatabase,
 };
 use syntax::{
     ast::{self, HasModuleItem, HasName},
@@ -32,12 +32,41 @@ pub(crate) fn unlinked_file(ctx: &DiagnosticsContext, acc: &mut Vec<Diagnostic>,
     );
 }
 
+/// Returns the change adding `mod` declaration for a file about to be created at `path`, if
+/// there's a module next to it which could declare it.
+pub fn add_mod_declaration_for_path(db: &RootDatabase, path: &VfsPath) -> Option<SourceChange> {
+    let crate_graph = db.crate_graph();
+    let source_roots = crate_graph
+        .iter()
+        .map(|krate| db.file_source_root(crate_graph[krate].root_file_id))
+        .collect::<FxHashSet<_>>();
+    source_roots.into_iter().map(|id| db.source_root(id)).filter(|it| !it.is_library).find_map(
+        |source_root| {
+            let (parent_id, module_name) = parent_module_file(db, &source_root, path)?;
+            let mut fixes = make_fixes(db, parent_id, &module_name, TextRange::default())?;
+            fixes.remove(0).source_change
+        },
+    )
+}
+
 fn fixes(ctx: &DiagnosticsContext, file_id: FileId) -> Option<Vec<Assist>> {
     // If there's an existing module that could add `mod` or `pub mod` items to include the unlinked file,
     // suggest that as a fix.
 
     let source_root = ctx.sema.db.source_root(ctx.sema.db.file_source_root(file_id));
     let our_path = source_root.path_for_file(&file_id)?;
+    let (parent_id, module_name) = parent_module_file(ctx.sema.db, &source_root, our_path)?;
+    let trigger_range = ctx.sema.db.parse(file_id).tree().syntax().text_range();
+    make_fixes(ctx.sema.db, parent_id, &module_name, trigger_range)
+}
+
+/// Finds the file of a module which could declare the module of the file at `our_path`, and the
+/// name of the latter.
+fn parent_module_file(
+    db: &RootDatabase,
+    source_root: &SourceRoot,
+    our_path: &VfsPath,
+) -> Option<(FileId, String)> {
     let (mut module_name, _) = our_path.name_and_extension()?;
 
     // Candidates to look for:
@@ -67,8 +96,8 @@ fn fixes(ctx: &DiagnosticsContext, file_id: FileId) -> Option<Vec<Assist>> {
     };
 
     for &parent_id in paths.iter().filter_map(|path| source_root.file_for_path(path)) {
-        for &krate in ctx.sema.db.relevant_crates(parent_id).iter() {
-            let crate_def_map = ctx.sema.db.crate_def_map(krate);
+        for &krate in db.relevant_crates(parent_id).iter() {
+            let crate_def_map = db.crate_def_map(krate);
             for (_, module) in crate_def_map.modules() {
                 if module.origin.is_inline() {
                     // We don't handle inline `mod parent {}`s, they use different paths.
@@ -76,7 +105,7 @@ fn fixes(ctx: &DiagnosticsContext, file_id: FileId) -> Option<Vec<Assist>> {
                 }
 
                 if module.origin.file_id() == Some(parent_id) {
-                    return make_fixes(ctx.sema.db, parent_id, module_name, file_id);
+                    return Some((parent_id, module_name.to_string()));
                 }
             }
         }
@@ -89,7 +118,7 @@ fn make_fixes(
     db: &RootDatabase,
     parent_file_id: FileId,
     new_mod_name: &str,
-    added_file_id: FileId,
+    trigger_range: TextRange,
 ) -> Option<Vec<Assist>> {
     fn is_outline_mod(item: &ast::Item) -> bool {
         matches!(item, ast::Item::Module(m) if m.item_list().is_none())
@@ -144,7 +173,6 @@ fn make_fixes(
         }
     }
 
-    let trigger_range = db.parse(added_file_id).tree().syntax().text_range();
     Some(vec![
         fix(
             "add_mod_declaration",
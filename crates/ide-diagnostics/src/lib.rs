@@ -63,6 +63,8 @@ use ide_db::{
 };
 use syntax::{ast::AstNode, TextRange};
 
+pub use crate::handlers::unlinked_file::add_mod_declaration_for_path;
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct DiagnosticCode(pub &'static str);
 
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use hir::Semantics;
+use ide_db::{
+    base_db::{FileId, SourceDatabase},
+    RootDatabase,
+};
+use itertools::Itertools;
+
+// Feature: Switch Active Target
+//
+// When crates are analyzed for several targets (see `rust-analyzer.cargo.extraTargets`), switches
+// the target the current file is analyzed for by all other features.
+//
+// |===
+// | Editor  | Action Name
+//
+// | VS Code | **Rust Analyzer: Switch Active Target**
+// |===
+
+/// Returns the targets the crates of `file_id` are analyzed for, the active one first.
+pub(crate) fn file_targets(db: &RootDatabase, file_id: FileId) -> Vec<String> {
+    let sema = Semantics::new(db);
+    sema.to_module_defs(file_id).filter_map(|module| module.krate().target(db)).unique().collect()
+}
+
+pub(crate) fn set_active_target(db: &mut RootDatabase, file_id: FileId, target: String) {
+    let sema = Semantics::new(db);
+    let roots: Vec<FileId> = sema
+        .to_module_defs(file_id)
+        .map(|module| module.krate())
+        .filter(|krate| krate.target(db).as_ref() == Some(&target))
+        .map(|krate| krate.root_file(db))
+        .unique()
+        .collect();
+    if roots.is_empty() {
+        return;
+    }
+
+    let mut active_targets = (*db.active_targets()).clone();
+    for root in roots {
+        active_targets.insert(root, target.clone());
+    }
+    db.set_active_targets(Arc::new(active_targets));
+}
+
+pub(crate) fn clear_active_targets(db: &mut RootDatabase) {
+    if !db.active_targets().is_empty() {
+        db.set_active_targets(Default::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ide_db::base_db::fixture::ChangeFixture;
+
+    use crate::AnalysisHost;
+
+    #[test]
+    fn switch_active_target() {
+        let mut fixture = ChangeFixture::parse(
+            r#"
+//- /main.rs crate:main cfg:unix
+fn main() {}
+"#,
+        );
+        let file_id = fixture.files[0];
+        let crate_graph = fixture.change.crate_graph.as_mut().unwrap();
+        let linux = crate_graph.iter().next().unwrap();
+        let windows = crate_graph.add_crate_root(
+            file_id,
+            crate_graph[linux].edition,
+            crate_graph[linux].display_name.clone(),
+            None,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Ok(Vec::new()),
+            false,
+            crate_graph[linux].origin.clone(),
+        );
+        crate_graph.set_target(linux, "x86_64-unknown-linux-gnu".to_string());
+        crate_graph.set_target(windows, "x86_64-pc-windows-msvc".to_string());
+
+        let mut host = AnalysisHost::default();
+        host.apply_change(fixture.change);
+        let targets = host.analysis().file_targets(file_id).unwrap();
+        assert_eq!(targets.len(), 2);
+
+        for target in ["x86_64-pc-windows-msvc", "x86_64-unknown-linux-gnu"] {
+            host.set_active_target(file_id, target.to_string());
+            assert_eq!(host.analysis().file_targets(file_id).unwrap()[0], target);
+        }
+
+        host.clear_active_targets();
+        assert_eq!(host.analysis().file_targets(file_id).unwrap()[0], "x86_64-unknown-linux-gnu");
+    }
+}
@@ -0,0 +1,248 @@
+use hir::{InFile, Semantics};
+use ide_db::{
+    base_db::{AnchoredPathBuf, FileId},
+    RootDatabase,
+};
+use syntax::{
+    ast::{self, IsString},
+    AstNode, AstToken, TextRange, TextSize,
+};
+
+use crate::{
+    doc_links::{doc_attributes, extract_definitions_from_docs, resolve_doc_path_for_def},
+    NavigationTarget, TryToNav,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentLink {
+    pub range: TextRange,
+    pub target: DocumentLinkTarget,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocumentLinkTarget {
+    /// The definition an intra-doc link resolves to.
+    Definition(NavigationTarget),
+    /// The file of a module with a `#[path]` attribute.
+    File(FileId),
+    /// A path in `include!` and the like, which may not be loaded.
+    Path(AnchoredPathBuf),
+    /// A URL in a comment.
+    Url(String),
+}
+
+const INCLUDE_MACROS: &[&str] = &["include", "include_str", "include_bytes"];
+
+// Feature: Document Links
+//
+// Makes intra-doc links, the paths in `include!`, `include_str!`, `include_bytes!` and
+// `#[path = "..."]` attributes, and the URLs in comments clickable.
+pub(crate) fn document_links(db: &RootDatabase, file_id: FileId) -> Vec<DocumentLink> {
+    let sema = Semantics::new(db);
+    let source_file = sema.parse(file_id);
+    let mut res = Vec::new();
+
+    for node in source_file.syntax().descendants() {
+        if let Some((attributes, def)) = doc_attributes(&sema, &node) {
+            if let Some((docs, doc_mapping)) = attributes.docs_with_rangemap(db) {
+                let links = extract_definitions_from_docs(&docs).into_iter().filter_map(
+                    |(range, link, ns)| {
+                        let InFile { file_id: link_file_id, value: range } =
+                            doc_mapping.map(range)?;
+                        if link_file_id != file_id.into() {
+                            return None;
+                        }
+                        let nav = resolve_doc_path_for_def(db, def, &link, ns)?.try_to_nav(db)?;
+                        Some(DocumentLink { range, target: DocumentLinkTarget::Definition(nav) })
+                    },
+                );
+                res.extend(links);
+            }
+        }
+        if let Some(macro_call) = ast::MacroCall::cast(node.clone()) {
+            res.extend(include_link(file_id, &macro_call));
+        }
+        if let Some(module) = ast::Module::cast(node) {
+            res.extend(path_attr_link(&sema, &module));
+        }
+    }
+
+    for comment in source_file.syntax().descendants_with_tokens().filter_map(|it| {
+        let token = it.into_token()?;
+        ast::Comment::cast(token)
+    }) {
+        let start = comment.syntax().text_range().start();
+        res.extend(urls(comment.text()).into_iter().map(|(range, url)| DocumentLink {
+            range: range + start,
+            target: DocumentLinkTarget::Url(url.to_string()),
+        }));
+    }
+
+    res.sort_by_key(|it| it.range.start());
+    res
+}
+
+fn include_link(file_id: FileId, macro_call: &ast::MacroCall) -> Option<DocumentLink> {
+    let name = macro_call.path()?.segment()?.name_ref()?;
+    if !INCLUDE_MACROS.contains(&name.text().as_str()) {
+        return None;
+    }
+    let first_token = macro_call.token_tree()?.syntax().children_with_tokens().nth(1)?;
+    let path = ast::String::cast(first_token.into_token()?)?;
+    Some(DocumentLink {
+        range: path.text_range_between_quotes()?,
+        target: DocumentLinkTarget::Path(AnchoredPathBuf {
+            anchor: file_id,
+            path: path.value()?.into_owned(),
+        }),
+    })
+}
+
+fn path_attr_link(sema: &Semantics<RootDatabase>, module: &ast::Module) -> Option<DocumentLink> {
+    let attr =
+        ast::HasAttrs::attrs(module).find(|attr| attr.simple_name().as_deref() == Some("path"))?;
+    let path = match attr.expr()? {
+        ast::Expr::Literal(lit) => ast::String::cast(lit.token())?,
+        _ => return None,
+    };
+    let file_id = sema.to_def(module)?.definition_source(sema.db).file_id.original_file(sema.db);
+    Some(DocumentLink {
+        range: path.text_range_between_quotes()?,
+        target: DocumentLinkTarget::File(file_id),
+    })
+}
+
+/// Finds the `http` and `https` URLs in `text`, with their ranges.
+fn urls(text: &str) -> Vec<(TextRange, &str)> {
+    let mut res = Vec::new();
+    let mut offset = 0;
+    while let Some(idx) = text[offset..].find("http") {
+        let start = offset + idx;
+        let rest = &text[start..];
+        // Parentheses are fine in URLs, as long as they are balanced.
+        let mut depth = 0;
+        let len = rest
+            .char_indices()
+            .find(|&(_, c)| match c {
+                '(' => {
+                    depth += 1;
+                    false
+                }
+                ')' if depth > 0 => {
+                    depth -= 1;
+                    false
+                }
+                _ => c.is_whitespace() || matches!(c, '<' | '>' | '"' | '`' | ')' | ']'),
+            })
+            .map_or(rest.len(), |(idx, _)| idx);
+        let url = rest[..len].trim_end_matches(|c| matches!(c, '.' | ',' | ';' | ':' | '!' | '?'));
+        let is_url = (url.starts_with("http://") || url.starts_with("https://"))
+            && url.len() > "https://".len();
+        let is_word_start = !text[..start].ends_with(|c: char| c.is_alphanumeric());
+        if is_url && is_word_start {
+            let range = TextRange::at(
+                TextSize::try_from(start).unwrap(),
+                TextSize::try_from(url.len()).unwrap(),
+            );
+            res.push((range, url));
+        }
+        offset = start + len.max(1);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+
+    use crate::fixture;
+
+    use super::DocumentLinkTarget;
+
+    fn check(ra_fixture: &str, expect: Expect) {
+        let (analysis, file_id) = fixture::file(ra_fixture);
+        let text = analysis.file_text(file_id).unwrap();
+        let links = analysis.document_links(file_id).unwrap();
+        let actual = links
+            .into_iter()
+            .map(|link| {
+                let target = match link.target {
+                    DocumentLinkTarget::Definition(nav) => nav.debug_render(),
+                    DocumentLinkTarget::File(file_id) => format!("{:?}", file_id),
+                    DocumentLinkTarget::Path(path) => format!("{:?}", path),
+                    DocumentLinkTarget::Url(url) => url,
+                };
+                format!("{:?} {:?}: {}\n", link.range, &text[link.range], target)
+            })
+            .collect::<String>();
+        expect.assert_eq(&actual);
+    }
+
+    #[test]
+    fn intra_doc_links() {
+        check(
+            r#"
+/// Calls [`bar`] and [Baz], see also [nothing].
+fn foo() {}
+fn bar() {}
+struct Baz;
+"#,
+            expect![[r#"
+                10..17 "[`bar`]": bar Function FileId(0) 61..72 64..67
+                22..27 "[Baz]": Baz Struct FileId(0) 73..84 80..83
+            "#]],
+        );
+    }
+
+    #[test]
+    fn include_paths() {
+        check(
+            r#"
+const DATA: &str = include_str!("data.txt");
+const BYTES: &[u8] = include_bytes!("../data.bin");
+include!(concat!(env!("OUT_DIR"), "/gen.rs"));
+const OTHER: &str = concat!("not_a_path.rs");
+"#,
+            expect![[r#"
+                33..41 "data.txt": AnchoredPathBuf { anchor: FileId(0), path: "data.txt" }
+                82..93 "../data.bin": AnchoredPathBuf { anchor: FileId(0), path: "../data.bin" }
+            "#]],
+        );
+    }
+
+    #[test]
+    fn path_attributes() {
+        check(
+            r#"
+//- /main.rs
+#[path = "other.rs"]
+mod foo;
+mod bar;
+//- /other.rs
+//- /bar.rs
+"#,
+            expect![[r#"
+                10..18 "other.rs": FileId(1)
+            "#]],
+        );
+    }
+
+    #[test]
+    fn comment_urls() {
+        check(
+            r#"
+// See https://example.com/docs, or <http://example.org/a_(b)>.
+/// [Docs](https://docs.rs/foo/latest/foo/).
+fn foo() {
+    let s = "https://not.a/comment";
+}
+// xhttps://no.pe and https:// are not URLs
+"#,
+            expect![[r#"
+                7..31 "https://example.com/docs": https://example.com/docs
+                37..61 "http://example.org/a_(b)": http://example.org/a_(b)
+                75..106 "https://docs.rs/foo/latest/foo/": https://docs.rs/foo/latest/foo/
+            "#]],
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Edits keeping the module tree in sync with files being created or deleted.
+
+use std::iter;
+
+use either::Either;
+use hir::Semantics;
+use ide_db::{
+    base_db::{FileId, SourceDatabaseExt, VfsPath},
+    defs::Definition,
+    search::FileReference,
+    source_change::SourceChange,
+    symbol_index::SymbolsDatabase,
+    FxHashMap, FxHashSet, RootDatabase,
+};
+use syntax::{algo, ast, AstNode, NodeOrToken, SyntaxNode};
+use text_edit::TextEdit;
+
+// Feature: File Operations
+//
+// When a file is created next to a module, declares it with `mod`. When the file of a module, or
+// the directory of its files, is deleted, removes its `mod` declaration, as well as the imports
+// from it if `rust-analyzer.files.removeImportsOnDelete.enable` is set.
+//
+// Requires an editor which supports `workspace/willCreateFiles` and `workspace/willDeleteFiles`.
+pub(crate) fn will_create_file(db: &RootDatabase, path: &VfsPath) -> Option<SourceChange> {
+    ide_diagnostics::add_mod_declaration_for_path(db, path)
+}
+
+pub(crate) fn will_delete_files(
+    db: &RootDatabase,
+    paths: &[VfsPath],
+    remove_imports: bool,
+) -> Option<SourceChange> {
+    let sema = Semantics::new(db);
+    let deleted_files = files_in(db, paths);
+    let modules: Vec<_> = deleted_files.iter().filter_map(|&it| sema.to_module_def(it)).collect();
+    // Code in the deleted modules and their children goes away with them.
+    let is_deleted = |file_id: FileId| {
+        deleted_files.contains(&file_id)
+            || sema
+                .to_module_def(file_id)
+                .map_or(false, |it| it.path_to_root(db).iter().any(|it| modules.contains(it)))
+    };
+
+    let mut removals: FxHashMap<FileId, Vec<Either<ast::Item, ast::UseTree>>> =
+        FxHashMap::default();
+    for &module in &modules {
+        if let Some(decl) = module.declaration_source(db) {
+            let decl_file = decl.file_id.original_file(db);
+            if !decl.file_id.is_macro() && !deleted_files.contains(&decl_file) {
+                removals
+                    .entry(decl_file)
+                    .or_default()
+                    .push(Either::Left(ast::Item::Module(decl.value)));
+            }
+        }
+        if !remove_imports {
+            continue;
+        }
+
+        let usages = Definition::Module(module).usages(&sema).all();
+        for (ref_file, refs) in usages.references {
+            if is_deleted(ref_file) {
+                continue;
+            }
+            for FileReference { name, .. } in refs {
+                if let ast::NameLike::NameRef(name_ref) = name {
+                    if let Some(removal) = dangling_import(&name_ref) {
+                        removals.entry(ref_file).or_default().push(removal);
+                    }
+                }
+            }
+        }
+    }
+    if removals.is_empty() {
+        return None;
+    }
+
+    let mut change = SourceChange::default();
+    for (file_id, removals) in removals {
+        let source = sema.parse(file_id).syntax().clone();
+        let mutable = source.clone_for_update();
+        // Find all the nodes first, as removing them shifts the ranges of the others.
+        let removals = removals
+            .into_iter()
+            .filter_map(|removal| match removal {
+                Either::Left(item) => find_in(&mutable, &item).map(Either::Left),
+                Either::Right(use_tree) => find_in(&mutable, &use_tree).map(Either::Right),
+            })
+            .collect::<Vec<_>>();
+        for removal in removals {
+            // Skip the nodes inside an already removed one.
+            let node = removal.as_ref().either(|it| it.syntax(), |it| it.syntax());
+            if node.ancestors().last().as_ref() != Some(&mutable) {
+                continue;
+            }
+            match removal {
+                Either::Left(ast::Item::Module(it)) => it.remove(),
+                Either::Left(ast::Item::Use(it)) => it.remove(),
+                Either::Left(_) => (),
+                Either::Right(it) => it.remove(),
+            }
+        }
+        let mut edit = TextEdit::builder();
+        algo::diff(&source, &mutable).into_text_edit(&mut edit);
+        change.insert_source_edit(file_id, edit.finish());
+    }
+    Some(change)
+}
+
+/// Returns the local files at `paths`, or in the directories at `paths`.
+fn files_in(db: &RootDatabase, paths: &[VfsPath]) -> FxHashSet<FileId> {
+    let mut files = FxHashSet::default();
+    for &root in db.local_roots().iter() {
+        let source_root = db.source_root(root);
+        files.extend(source_root.iter().filter(|file_id| {
+            let path = match source_root.path_for_file(file_id) {
+                Some(it) => it,
+                None => return false,
+            };
+            iter::successors(Some(path.clone()), VfsPath::parent).any(|it| paths.contains(&it))
+        }));
+    }
+    files
+}
+
+/// Returns the `use` item or the use tree to remove when `name_ref` can't be imported anymore.
+fn dangling_import(name_ref: &ast::NameRef) -> Option<Either<ast::Item, ast::UseTree>> {
+    let mut use_tree = name_ref.syntax().ancestors().find_map(ast::UseTree::cast)?;
+    loop {
+        let parent = use_tree.syntax().parent()?;
+        if let Some(use_) = ast::Use::cast(parent.clone()) {
+            return Some(Either::Left(ast::Item::Use(use_)));
+        }
+        let list = ast::UseTreeList::cast(parent)?;
+        if list.use_trees().nth(1).is_some() {
+            return Some(Either::Right(use_tree));
+        }
+        // Don't leave `use foo::{};` behind.
+        use_tree = list.parent_use_tree();
+    }
+}
+
+/// Finds the node of `root` with the range and kind of `node`.
+fn find_in<N: AstNode>(root: &SyntaxNode, node: &N) -> Option<N> {
+    let range = node.syntax().text_range();
+    let covering = match root.covering_element(range) {
+        NodeOrToken::Node(it) => it,
+        NodeOrToken::Token(it) => it.parent()?,
+    };
+    covering.ancestors().take_while(|it| it.text_range() == range).find_map(N::cast)
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+    use ide_db::{base_db::VfsPath, source_change::SourceChange};
+
+    use crate::{fixture, Analysis};
+
+    fn render(analysis: &Analysis, change: Option<SourceChange>) -> String {
+        let change = match change {
+            Some(it) => it,
+            None => return "None".to_string(),
+        };
+        let mut edits = change.source_file_edits.into_iter().collect::<Vec<_>>();
+        edits.sort_by_key(|(file_id, _)| *file_id);
+        edits
+            .into_iter()
+            .map(|(file_id, edit)| {
+                let mut text = analysis.file_text(file_id).unwrap().to_string();
+                edit.apply(&mut text);
+                format!("{:?}:\n{}", file_id, text)
+            })
+            .collect()
+    }
+
+    fn check_create(ra_fixture: &str, path: &str, expect: Expect) {
+        let (analysis, _) = fixture::file(ra_fixture);
+        let path = VfsPath::new_virtual_path(path.to_string());
+        let change = analysis.will_create_file(&path).unwrap();
+        expect.assert_eq(&render(&analysis, change));
+    }
+
+    fn check_delete(ra_fixture: &str, paths: &[&str], expect: Expect) {
+        let (analysis, _) = fixture::file(ra_fixture);
+        let paths: Vec<_> =
+            paths.iter().map(|it| VfsPath::new_virtual_path(it.to_string())).collect();
+        let change = analysis.will_delete_files(&paths, true).unwrap();
+        expect.assert_eq(&render(&analysis, change));
+    }
+
+    #[test]
+    fn creating_a_file_declares_it() {
+        check_create(
+            r#"
+//- /main.rs
+mod bar;
+fn main() {}
+//- /bar.rs
+"#,
+            "/foo.rs",
+            expect![[r#"
+                FileId(0):
+                mod bar;
+                mod foo;
+                fn main() {}
+            "#]],
+        );
+    }
+
+    #[test]
+    fn creating_a_file_in_an_unknown_directory_does_nothing() {
+        check_create(
+            r#"
+//- /main.rs
+fn main() {}
+"#,
+            "/foo/bar/baz.rs",
+            expect![["None"]],
+        );
+    }
+
+    #[test]
+    fn deleting_a_file_removes_its_declaration_and_imports() {
+        check_delete(
+            r#"
+//- /main.rs
+mod foo;
+mod bar;
+use foo::Foo;
+use crate::{foo::Bar, bar::Baz};
+fn main() {}
+//- /foo.rs
+pub struct Foo;
+pub struct Bar;
+use crate::bar::Baz;
+//- /bar.rs
+pub struct Baz;
+use crate::foo::{self, Foo};
+"#,
+            &["/foo.rs"],
+            expect![[r#"
+                FileId(0):
+                mod bar;
+                use crate::{bar::Baz};
+                fn main() {}
+                FileId(2):
+                pub struct Baz;
+            "#]],
+        );
+    }
+
+    #[test]
+    fn deleting_several_files_merges_the_edits() {
+        check_delete(
+            r#"
+//- /main.rs
+mod foo;
+mod bar;
+mod baz;
+use foo::Foo;
+use bar::Bar;
+fn main() {}
+//- /foo.rs
+pub struct Foo;
+//- /bar.rs
+pub struct Bar;
+//- /baz.rs
+"#,
+            &["/foo.rs", "/bar.rs"],
+            expect![[r#"
+                FileId(0):
+                mod baz;
+                fn main() {}
+            "#]],
+        );
+    }
+
+    #[test]
+    fn deleting_a_directory_removes_the_modules_of_its_files() {
+        check_delete(
+            r#"
+//- /main.rs
+mod foo;
+use foo::bar::Bar;
+fn main() {}
+//- /foo.rs
+pub mod bar;
+pub mod baz;
+//- /foo/bar.rs
+pub struct Bar;
+//- /foo/baz.rs
+use super::bar::Bar;
+"#,
+            &["/foo"],
+            expect![[r#"
+                FileId(0):
+                mod foo;
+                fn main() {}
+                FileId(1):
+            "#]],
+        );
+    }
+
+    #[test]
+    fn deleting_a_mod_rs_directory_removes_its_declaration() {
+        check_delete(
+            r#"
+//- /main.rs
+mod foo;
+fn main() {}
+//- /foo/mod.rs
+mod bar;
+//- /foo/bar.rs
+"#,
+            &["/foo"],
+            expect![[r#"
+                FileId(0):
+                fn main() {}
+            "#]],
+        );
+    }
+}
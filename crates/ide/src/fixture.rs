@@ -1,6 +1,6 @@
 //! Utilities for creating `Analysis` instances for tests.
 use hir::db::DefDatabase;
-use ide_db::base_db::fixture::ChangeFixture;
+use ide_db::base_db::{fixture::ChangeFixture, ProcMacro};
 use test_utils::{extract_annotations, RangeOrOffset};
 
 use crate::{Analysis, AnalysisHost, FileId, FilePosition, FileRange};
@@ -16,8 +16,16 @@ pub(crate) fn file(ra_fixture: &str) -> (Analysis, FileId) {
 
 /// Creates analysis from a multi-file fixture, returns positions marked with $0.
 pub(crate) fn position(ra_fixture: &str) -> (Analysis, FilePosition) {
+    position_with_proc_macros(ra_fixture, Vec::new())
+}
+
+/// Like [`position`], but makes the given proc-macros available in addition to the default ones.
+pub(crate) fn position_with_proc_macros(
+    ra_fixture: &str,
+    proc_macros: Vec<(String, ProcMacro)>,
+) -> (Analysis, FilePosition) {
     let mut host = AnalysisHost::default();
-    let change_fixture = ChangeFixture::parse(ra_fixture);
+    let change_fixture = ChangeFixture::parse_with_proc_macros(ra_fixture, proc_macros);
     host.db.set_enable_proc_attr_macros(true);
     host.db.apply_change(change_fixture.change);
     let (file_id, range_or_offset) = change_fixture.file_position.expect("expected a marker ($0)");
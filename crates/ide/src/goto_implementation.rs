@@ -5,7 +5,7 @@ use ide_db::{
     RootDatabase,
 };
 use itertools::Itertools;
-use syntax::{ast, AstNode, SyntaxKind::*, T};
+use syntax::{ast, AstNode, SyntaxKind::*, TextRange, T};
 
 use crate::{FilePosition, NavigationTarget, RangeInfo, TryToNav};
 
@@ -25,39 +25,9 @@ pub(crate) fn goto_implementation(
     position: FilePosition,
 ) -> Option<RangeInfo<Vec<NavigationTarget>>> {
     let sema = Semantics::new(db);
-    let source_file = sema.parse(position.file_id);
-    let syntax = source_file.syntax().clone();
-
-    let original_token =
-        pick_best_token(syntax.token_at_offset(position.offset), |kind| match kind {
-            IDENT | T![self] => 1,
-            _ => 0,
-        })?;
-    let range = original_token.text_range();
-    let navs = sema
-        .descend_into_macros(original_token)
+    let (range, defs) = definitions_at(&sema, position)?;
+    let navs = defs
         .into_iter()
-        .filter_map(|token| token.parent().and_then(ast::NameLike::cast))
-        .filter_map(|node| match &node {
-            ast::NameLike::Name(name) => {
-                NameClass::classify(&sema, name).map(|class| match class {
-                    NameClass::Definition(it) | NameClass::ConstReference(it) => it,
-                    NameClass::PatFieldShorthand { local_def, field_ref: _ } => {
-                        Definition::Local(local_def)
-                    }
-                })
-            }
-            ast::NameLike::NameRef(name_ref) => {
-                NameRefClass::classify(&sema, name_ref).map(|class| match class {
-                    NameRefClass::Definition(def) => def,
-                    NameRefClass::FieldShorthand { local_ref, field_ref: _ } => {
-                        Definition::Local(local_ref)
-                    }
-                })
-            }
-            ast::NameLike::Lifetime(_) => None,
-        })
-        .unique()
         .filter_map(|def| {
             let navs = match def {
                 Definition::Trait(trait_) => impls_for_trait(&sema, trait_),
@@ -86,6 +56,47 @@ pub(crate) fn goto_implementation(
     Some(RangeInfo { range, info: navs })
 }
 
+/// Returns the definitions the name or reference at `position` refers to, with the range of its
+/// token.
+pub(crate) fn definitions_at(
+    sema: &Semantics<RootDatabase>,
+    position: FilePosition,
+) -> Option<(TextRange, Vec<Definition>)> {
+    let source_file = sema.parse(position.file_id);
+    let syntax = source_file.syntax().clone();
+
+    let original_token =
+        pick_best_token(syntax.token_at_offset(position.offset), |kind| match kind {
+            IDENT | T![self] => 1,
+            _ => 0,
+        })?;
+    let range = original_token.text_range();
+    let defs = sema
+        .descend_into_macros(original_token)
+        .into_iter()
+        .filter_map(|token| token.parent().and_then(ast::NameLike::cast))
+        .filter_map(|node| match &node {
+            ast::NameLike::Name(name) => NameClass::classify(sema, name).map(|class| match class {
+                NameClass::Definition(it) | NameClass::ConstReference(it) => it,
+                NameClass::PatFieldShorthand { local_def, field_ref: _ } => {
+                    Definition::Local(local_def)
+                }
+            }),
+            ast::NameLike::NameRef(name_ref) => {
+                NameRefClass::classify(sema, name_ref).map(|class| match class {
+                    NameRefClass::Definition(def) => def,
+                    NameRefClass::FieldShorthand { local_ref, field_ref: _ } => {
+                        Definition::Local(local_ref)
+                    }
+                })
+            }
+            ast::NameLike::Lifetime(_) => None,
+        })
+        .unique()
+        .collect();
+    Some((range, defs))
+}
+
 fn impls_for_ty(sema: &Semantics<RootDatabase>, ty: hir::Type) -> Vec<NavigationTarget> {
     Impl::all_for_type(sema.db, ty).into_iter().filter_map(|imp| imp.try_to_nav(sema.db)).collect()
 }
@@ -4,7 +4,7 @@ use std::fmt::Display;
 use either::Either;
 use hir::{AsAssocItem, AttributeTemplate, HasAttrs, HirDisplay, Semantics, TypeInfo};
 use ide_db::{
-    base_db::SourceDatabase,
+    base_db::{ProcMacroExpansionPolicy, SourceDatabase},
     defs::Definition,
     famous_defs::FamousDefs,
     generated::lints::{CLIPPY_LINTS, DEFAULT_LINTS, FEATURES},
@@ -384,10 +384,32 @@ pub(super) fn definition(
         }),
         None => None,
     };
-    let docs = docs.filter(|_| config.documentation.is_some()).map(Into::into);
+    let mut docs = docs.filter(|_| config.documentation.is_some()).map(Into::into);
+    if let Definition::Macro(it) = def {
+        if let Some(note) = proc_macro_policy_note(db, it) {
+            docs = Some(match docs {
+                Some(docs) => format!("{}\n\n{}", note, docs),
+                None => note.to_owned(),
+            });
+        }
+    }
     markup(docs, label, mod_path)
 }
 
+/// Describes how invocations of proc-macros are treated which the user configured to not be
+/// expanded.
+fn proc_macro_policy_note(db: &RootDatabase, mac: hir::Macro) -> Option<&'static str> {
+    match mac.proc_macro_expansion_policy(db)? {
+        ProcMacroExpansionPolicy::Expand => None,
+        ProcMacroExpansionPolicy::Identity => Some(
+            "*Not expanded: invocations are treated as identity (`rust-analyzer.procMacro.policy`)*",
+        ),
+        ProcMacroExpansionPolicy::Strip => {
+            Some("*Not expanded: invocations are stripped (`rust-analyzer.procMacro.policy`)*")
+        }
+    }
+}
+
 fn render_builtin_attr(db: &RootDatabase, attr: hir::BuiltinAttr) -> Option<Markup> {
     let name = attr.name(db);
     let desc = format!("#[{}]", name);
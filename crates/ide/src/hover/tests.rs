@@ -1,5 +1,10 @@
+use std::sync::Arc;
+
 use expect_test::{expect, Expect};
-use ide_db::base_db::{FileLoader, FileRange};
+use ide_db::base_db::{
+    fixture::IdentityProcMacroExpander, FileLoader, FileRange, ProcMacro, ProcMacroExpansionPolicy,
+    ProcMacroKind,
+};
 use syntax::TextRange;
 
 use crate::{fixture, hover::HoverDocFormat, HoverConfig};
@@ -1092,6 +1097,51 @@ fn foo$0() {}
     );
 }
 
+#[test]
+fn test_hover_proc_macro_with_policy() {
+    let stripped = ProcMacro {
+        name: "stripped".into(),
+        kind: ProcMacroKind::Attr,
+        expander: Arc::new(IdentityProcMacroExpander),
+        policy: ProcMacroExpansionPolicy::Strip,
+    };
+    let source = r#"
+#[proc_macro_attribute]
+pub fn stripped(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    item
+}
+"#;
+    let (analysis, position) = fixture::position_with_proc_macros(
+        r#"
+//- proc_macros: stripped
+#[proc_macros::stripped$0]
+fn foo() {}
+"#,
+        vec![(source.to_string(), stripped)],
+    );
+    let hover = analysis
+        .hover(
+            &HoverConfig { links_in_hover: true, documentation: Some(HoverDocFormat::Markdown) },
+            FileRange { file_id: position.file_id, range: TextRange::empty(position.offset) },
+        )
+        .unwrap()
+        .unwrap();
+    expect![[r#"
+
+        ```rust
+        proc_macros
+        ```
+
+        ```rust
+        proc_macro stripped
+        ```
+
+        ---
+
+        *Not expanded: invocations are stripped (`rust-analyzer.procMacro.policy`)*"#]]
+    .assert_eq(hover.info.markup.as_str());
+}
+
 #[test]
 fn test_hover_through_expr_in_macro() {
     check(
@@ -5,6 +5,7 @@ use ide_db::{
     RootDatabase,
 };
 use itertools::Itertools;
+use std::fmt;
 use stdx::to_lower_snake_case;
 use syntax::{
     ast::{self, AstNode, HasArgList, HasGenericParams, HasName, UnaryOp},
@@ -12,7 +13,7 @@ use syntax::{
     TextSize, T,
 };
 
-use crate::FileId;
+use crate::{FileId, TryToNav};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct InlayHintsConfig {
@@ -69,7 +70,7 @@ pub enum InlayKind {
 pub struct InlayHint {
     pub range: TextRange,
     pub kind: InlayKind,
-    pub label: String,
+    pub label: InlayHintLabel,
     pub tooltip: Option<InlayTooltip>,
 }
 
@@ -80,6 +81,83 @@ pub enum InlayTooltip {
     HoverOffset(FileId, TextSize),
 }
 
+/// The text of an inlay hint, split into parts which may link to a location.
+pub struct InlayHintLabel {
+    pub parts: Vec<InlayHintLabelPart>,
+}
+
+impl InlayHintLabel {
+    /// Returns the text of the label if it's a single part without a link.
+    pub fn as_simple_str(&self) -> Option<&str> {
+        match &*self.parts {
+            [part] if part.linked_location.is_none() => Some(&part.text),
+            _ => None,
+        }
+    }
+
+    pub fn prepend_str(&mut self, s: &str) {
+        match &mut *self.parts {
+            [InlayHintLabelPart { text, linked_location: None }, ..] => text.insert_str(0, s),
+            _ => self.parts.insert(0, InlayHintLabelPart { text: s.into(), linked_location: None }),
+        }
+    }
+
+    pub fn append_str(&mut self, s: &str) {
+        match &mut *self.parts {
+            [.., InlayHintLabelPart { text, linked_location: None }] => text.push_str(s),
+            _ => self.parts.push(InlayHintLabelPart { text: s.into(), linked_location: None }),
+        }
+    }
+}
+
+impl From<String> for InlayHintLabel {
+    fn from(s: String) -> Self {
+        Self { parts: vec![InlayHintLabelPart { text: s, linked_location: None }] }
+    }
+}
+
+impl From<&str> for InlayHintLabel {
+    fn from(s: &str) -> Self {
+        Self { parts: vec![InlayHintLabelPart { text: s.into(), linked_location: None }] }
+    }
+}
+
+impl fmt::Display for InlayHintLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.parts.iter().map(|part| &part.text).format(""))
+    }
+}
+
+impl fmt::Debug for InlayHintLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.as_simple_str() {
+            Some(s) => s.fmt(f),
+            None => f.debug_list().entries(&self.parts).finish(),
+        }
+    }
+}
+
+pub struct InlayHintLabelPart {
+    pub text: String,
+    /// Source location represented by this label part. The client will use this to fetch the
+    /// part's hover tooltip, and Ctrl+Clicking the label part will navigate to the definition the
+    /// location refers to.
+    pub linked_location: Option<FileRange>,
+}
+
+impl fmt::Debug for InlayHintLabelPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.linked_location {
+            None => self.text.fmt(f),
+            Some(loc) => f
+                .debug_struct("InlayHintLabelPart")
+                .field("text", &self.text)
+                .field("linked_location", loc)
+                .finish(),
+        }
+    }
+}
+
 // Feature: Inlay Hints
 //
 // rust-analyzer shows additional information inline with the source code.
@@ -281,7 +359,7 @@ fn closing_brace_hints(
     acc.push(InlayHint {
         range: closing_token.text_range(),
         kind: InlayKind::ClosingBraceHint,
-        label,
+        label: label.into(),
         tooltip: name_offset.map(|it| InlayTooltip::HoverOffset(file_id, it)),
     });
 
@@ -311,7 +389,7 @@ fn implicit_static_hints(
             acc.push(InlayHint {
                 range: t.text_range(),
                 kind: InlayKind::LifetimeHint,
-                label: "'static".to_owned(),
+                label: "'static".into(),
                 tooltip: Some(InlayTooltip::String("Elided static lifetime".into())),
             });
         }
@@ -329,10 +407,10 @@ fn fn_lifetime_fn_hints(
         return None;
     }
 
-    let mk_lt_hint = |t: SyntaxToken, label| InlayHint {
+    let mk_lt_hint = |t: SyntaxToken, label: String| InlayHint {
         range: t.text_range(),
         kind: InlayKind::LifetimeHint,
-        label,
+        label: label.into(),
         tooltip: Some(InlayTooltip::String("Elided lifetime".into())),
     };
 
@@ -486,7 +564,8 @@ fn fn_lifetime_fn_hints(
                     "{}{}",
                     allocated_lifetimes.iter().format(", "),
                     if is_empty { "" } else { ", " }
-                ),
+                )
+                .into(),
                 tooltip: Some(InlayTooltip::String("Elided lifetimes".into())),
             });
         }
@@ -534,8 +613,15 @@ fn closure_ret_hints(
     acc.push(InlayHint {
         range: param_list.syntax().text_range(),
         kind: InlayKind::ClosureReturnTypeHint,
-        label: hint_iterator(sema, &famous_defs, config, &ty)
-            .unwrap_or_else(|| ty.display_truncated(sema.db, config.max_length).to_string()),
+        label: hint_iterator(sema, &famous_defs, config, &ty).map(Into::into).unwrap_or_else(
+            || {
+                type_label(
+                    sema.db,
+                    &ty,
+                    ty.display_truncated(sema.db, config.max_length).to_string(),
+                )
+            },
+        ),
         tooltip: Some(InlayTooltip::HoverRanged(file_id, param_list.syntax().text_range())),
     });
     Some(())
@@ -562,7 +648,7 @@ fn reborrow_hints(
     acc.push(InlayHint {
         range: expr.syntax().text_range(),
         kind: InlayKind::ImplicitReborrowHint,
-        label: label.to_string(),
+        label: label.into(),
         tooltip: Some(InlayTooltip::String("Compiler inserted reborrow".into())),
     });
     Some(())
@@ -620,9 +706,12 @@ fn chaining_hints(
             acc.push(InlayHint {
                 range: expr.syntax().text_range(),
                 kind: InlayKind::ChainingHint,
-                label: hint_iterator(sema, &famous_defs, config, &ty).unwrap_or_else(|| {
-                    ty.display_truncated(sema.db, config.max_length).to_string()
-                }),
+                label: hint_iterator(sema, &famous_defs, config, &ty)
+                    .map(Into::into)
+                    .unwrap_or_else(|| {
+                        let label = ty.display_truncated(sema.db, config.max_length).to_string();
+                        type_label(sema.db, &ty, label)
+                    }),
                 tooltip: Some(InlayTooltip::HoverRanged(file_id, expr.syntax().text_range())),
             });
         }
@@ -674,7 +763,9 @@ fn param_name_hints(
             InlayHint {
                 range,
                 kind: InlayKind::ParameterHint,
-                label: param_name,
+                label: InlayHintLabel {
+                    parts: vec![InlayHintLabelPart { text: param_name, linked_location: tooltip }],
+                },
                 tooltip: tooltip.map(|it| InlayTooltip::HoverOffset(it.file_id, it.range.start())),
             }
         });
@@ -705,7 +796,7 @@ fn binding_mode_hints(
         acc.push(InlayHint {
             range,
             kind: InlayKind::BindingModeHint,
-            label: r.to_string(),
+            label: r.into(),
             tooltip: Some(InlayTooltip::String("Inferred binding mode".into())),
         });
     });
@@ -720,7 +811,7 @@ fn binding_mode_hints(
             acc.push(InlayHint {
                 range,
                 kind: InlayKind::BindingModeHint,
-                label: bm.to_string(),
+                label: bm.into(),
                 tooltip: Some(InlayTooltip::String("Inferred binding mode".into())),
             });
         }
@@ -754,7 +845,7 @@ fn bind_pat_hints(
     let label = hint_iterator(sema, &famous_defs, config, &ty);
 
     let label = match label {
-        Some(label) => label,
+        Some(label) => label.into(),
         None => {
             let ty_name = ty.display_truncated(sema.db, config.max_length).to_string();
             if config.hide_named_constructor_hints
@@ -762,7 +853,7 @@ fn bind_pat_hints(
             {
                 return None;
             }
-            ty_name
+            type_label(sema.db, &ty, ty_name)
         }
     };
 
@@ -782,6 +873,52 @@ fn bind_pat_hints(
     Some(())
 }
 
+/// Splits `label`, the rendering of `ty`, into parts linking the names of the ADTs in `ty`, like
+/// its generic arguments or referenced types, to their definitions.
+fn type_label(db: &RootDatabase, ty: &hir::Type, label: String) -> InlayHintLabel {
+    let mut locations: FxHashMap<String, Option<FileRange>> = FxHashMap::default();
+    ty.walk(db, |ty| {
+        if let Some(adt) = ty.as_adt() {
+            let location = adt
+                .try_to_nav(db)
+                .map(|nav| FileRange { file_id: nav.file_id, range: nav.focus_or_full_range() });
+            // Names shared by several types can't be told apart in the label, don't link them.
+            locations
+                .entry(adt.name(db).to_string())
+                .and_modify(|it| {
+                    if *it != location {
+                        *it = None;
+                    }
+                })
+                .or_insert(location);
+        }
+    });
+    if locations.is_empty() || label.is_empty() {
+        return label.into();
+    }
+
+    let mut parts: Vec<InlayHintLabelPart> = Vec::new();
+    let mut rest = &*label;
+    while let Some(c) = rest.chars().next() {
+        let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+        let len = if is_ident_char(c) {
+            rest.find(|c| !is_ident_char(c)).unwrap_or(rest.len())
+        } else {
+            c.len_utf8()
+        };
+        let text = &rest[..len];
+        rest = &rest[len..];
+        let linked_location = locations.get(text).copied().flatten();
+        match parts.last_mut() {
+            Some(last) if last.linked_location.is_none() && linked_location.is_none() => {
+                last.text.push_str(text)
+            }
+            _ => parts.push(InlayHintLabelPart { text: text.to_string(), linked_location }),
+        }
+    }
+    InlayHintLabel { parts }
+}
+
 fn is_named_constructor(
     sema: &Semantics<RootDatabase>,
     pat: &ast::IdentPat,
@@ -2223,7 +2360,17 @@ fn main() {
                     InlayHint {
                         range: 147..172,
                         kind: ChainingHint,
-                        label: "B",
+                        label: [
+                            InlayHintLabelPart {
+                                text: "B",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 63..64,
+                                },
+                            },
+                        ],
                         tooltip: Some(
                             HoverRanged(
                                 FileId(
@@ -2236,7 +2383,17 @@ fn main() {
                     InlayHint {
                         range: 147..154,
                         kind: ChainingHint,
-                        label: "A",
+                        label: [
+                            InlayHintLabelPart {
+                                text: "A",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 7..8,
+                                },
+                            },
+                        ],
                         tooltip: Some(
                             HoverRanged(
                                 FileId(
@@ -2294,7 +2451,17 @@ fn main() {
                     InlayHint {
                         range: 143..190,
                         kind: ChainingHint,
-                        label: "C",
+                        label: [
+                            InlayHintLabelPart {
+                                text: "C",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 51..52,
+                                },
+                            },
+                        ],
                         tooltip: Some(
                             HoverRanged(
                                 FileId(
@@ -2307,7 +2474,17 @@ fn main() {
                     InlayHint {
                         range: 143..179,
                         kind: ChainingHint,
-                        label: "B",
+                        label: [
+                            InlayHintLabelPart {
+                                text: "B",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 29..30,
+                                },
+                            },
+                        ],
                         tooltip: Some(
                             HoverRanged(
                                 FileId(
@@ -2350,7 +2527,28 @@ fn main() {
                     InlayHint {
                         range: 246..283,
                         kind: ChainingHint,
-                        label: "B<X<i32, bool>>",
+                        label: [
+                            InlayHintLabelPart {
+                                text: "B",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 23..24,
+                                },
+                            },
+                            "<",
+                            InlayHintLabelPart {
+                                text: "X",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 55..56,
+                                },
+                            },
+                            "<i32, bool>>",
+                        ],
                         tooltip: Some(
                             HoverRanged(
                                 FileId(
@@ -2363,7 +2561,28 @@ fn main() {
                     InlayHint {
                         range: 246..265,
                         kind: ChainingHint,
-                        label: "A<X<i32, bool>>",
+                        label: [
+                            InlayHintLabelPart {
+                                text: "A",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 7..8,
+                                },
+                            },
+                            "<",
+                            InlayHintLabelPart {
+                                text: "X",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 55..56,
+                                },
+                            },
+                            "<i32, bool>>",
+                        ],
                         tooltip: Some(
                             HoverRanged(
                                 FileId(
@@ -2447,7 +2666,18 @@ fn main() {
                     InlayHint {
                         range: 174..189,
                         kind: ChainingHint,
-                        label: "&mut MyIter",
+                        label: [
+                            "&mut ",
+                            InlayHintLabelPart {
+                                text: "MyIter",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 24..30,
+                                },
+                            },
+                        ],
                         tooltip: Some(
                             HoverRanged(
                                 FileId(
@@ -2489,7 +2719,17 @@ fn main() {
                     InlayHint {
                         range: 124..130,
                         kind: TypeHint,
-                        label: "Struct",
+                        label: [
+                            InlayHintLabelPart {
+                                text: "Struct",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 7..13,
+                                },
+                            },
+                        ],
                         tooltip: Some(
                             HoverRanged(
                                 FileId(
@@ -2502,7 +2742,17 @@ fn main() {
                     InlayHint {
                         range: 145..185,
                         kind: ChainingHint,
-                        label: "Struct",
+                        label: [
+                            InlayHintLabelPart {
+                                text: "Struct",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 7..13,
+                                },
+                            },
+                        ],
                         tooltip: Some(
                             HoverRanged(
                                 FileId(
@@ -2515,7 +2765,17 @@ fn main() {
                     InlayHint {
                         range: 145..168,
                         kind: ChainingHint,
-                        label: "Struct",
+                        label: [
+                            InlayHintLabelPart {
+                                text: "Struct",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 7..13,
+                                },
+                            },
+                        ],
                         tooltip: Some(
                             HoverRanged(
                                 FileId(
@@ -2528,7 +2788,17 @@ fn main() {
                     InlayHint {
                         range: 222..228,
                         kind: ParameterHint,
-                        label: "self",
+                        label: [
+                            InlayHintLabelPart {
+                                text: "self",
+                                linked_location: FileRange {
+                                    file_id: FileId(
+                                        0,
+                                    ),
+                                    range: 42..46,
+                                },
+                            },
+                        ],
                         tooltip: Some(
                             HoverOffset(
                                 FileId(
@@ -26,9 +26,12 @@ mod annotations;
 mod call_hierarchy;
 mod signature_help;
 mod doc_links;
+mod document_links;
 mod highlight_related;
+mod linked_editing;
 mod expand_macro;
 mod extend_selection;
+mod file_operations;
 mod file_structure;
 mod fn_references;
 mod folding_ranges;
@@ -52,11 +55,14 @@ mod static_index;
 mod status;
 mod syntax_highlighting;
 mod syntax_tree;
+mod type_hierarchy;
 mod typing;
+mod used_dependencies;
 mod view_crate_graph;
 mod view_hir;
 mod view_item_tree;
 mod shuffle_crate_graph;
+mod active_target;
 
 use std::sync::Arc;
 
@@ -75,14 +81,15 @@ use crate::navigation_target::{ToNav, TryToNav};
 pub use crate::{
     annotations::{Annotation, AnnotationConfig, AnnotationKind},
     call_hierarchy::CallItem,
+    document_links::{DocumentLink, DocumentLinkTarget},
     expand_macro::ExpandedMacro,
     file_structure::{StructureNode, StructureNodeKind},
     folding_ranges::{Fold, FoldKind},
     highlight_related::{HighlightRelatedConfig, HighlightedRange},
     hover::{HoverAction, HoverConfig, HoverDocFormat, HoverGotoTypeData, HoverResult},
     inlay_hints::{
-        ClosureReturnTypeHints, InlayHint, InlayHintsConfig, InlayKind, InlayTooltip,
-        LifetimeElisionHints, ReborrowHints,
+        ClosureReturnTypeHints, InlayHint, InlayHintLabel, InlayHintLabelPart, InlayHintsConfig,
+        InlayKind, InlayTooltip, LifetimeElisionHints, ReborrowHints,
     },
     join_lines::JoinLinesConfig,
     markup::Markup,
@@ -184,6 +191,16 @@ impl AnalysisHost {
     pub fn shuffle_crate_graph(&mut self) {
         shuffle_crate_graph::shuffle_crate_graph(&mut self.db);
     }
+
+    /// Makes the crates of `file_id` analyzed for `target` the ones used by all queries.
+    pub fn set_active_target(&mut self, file_id: FileId, target: String) {
+        active_target::set_active_target(&mut self.db, file_id, target);
+    }
+
+    /// Goes back to the default target for all crates analyzed for several targets.
+    pub fn clear_active_targets(&mut self) {
+        active_target::clear_active_targets(&mut self.db);
+    }
 }
 
 impl Default for AnalysisHost {
@@ -372,6 +389,11 @@ impl Analysis {
         self.with_db(|db| inlay_hints::inlay_hints(db, file_id, range, config))
     }
 
+    /// Returns the links to other files and to URLs in the file.
+    pub fn document_links(&self, file_id: FileId) -> Cancellable<Vec<DocumentLink>> {
+        self.with_db(|db| document_links::document_links(db, file_id))
+    }
+
     /// Returns the set of folding ranges.
     pub fn folding_ranges(&self, file_id: FileId) -> Cancellable<Vec<Fold>> {
         self.with_db(|db| folding_ranges::folding_ranges(&db.parse(file_id).tree()))
@@ -481,6 +503,24 @@ impl Analysis {
         self.with_db(|db| call_hierarchy::outgoing_calls(db, position))
     }
 
+    /// Computes type hierarchy candidates for the given file position.
+    pub fn type_hierarchy(
+        &self,
+        position: FilePosition,
+    ) -> Cancellable<Option<RangeInfo<Vec<NavigationTarget>>>> {
+        self.with_db(|db| type_hierarchy::type_hierarchy(db, position))
+    }
+
+    /// Computes the supertypes of the type or trait at the given file position.
+    pub fn supertypes(&self, position: FilePosition) -> Cancellable<Option<Vec<NavigationTarget>>> {
+        self.with_db(|db| type_hierarchy::supertypes(db, position))
+    }
+
+    /// Computes the subtypes of the trait at the given file position.
+    pub fn subtypes(&self, position: FilePosition) -> Cancellable<Option<Vec<NavigationTarget>>> {
+        self.with_db(|db| type_hierarchy::subtypes(db, position))
+    }
+
     /// Returns a `mod name;` declaration which created the current module.
     pub fn parent_module(&self, position: FilePosition) -> Cancellable<Vec<NavigationTarget>> {
         self.with_db(|db| parent_module::parent_module(db, position))
@@ -491,11 +531,28 @@ impl Analysis {
         self.with_db(|db| parent_module::crate_for(db, file_id))
     }
 
+    /// Returns the crates of the source root of the given file, without resolving its modules.
+    pub fn relevant_crates_for(&self, file_id: FileId) -> Cancellable<Vec<CrateId>> {
+        self.with_db(|db| db.relevant_crates(file_id).iter().copied().collect())
+    }
+
+    /// Returns the targets the crates of this file are analyzed for, the active one first.
+    ///
+    /// This is empty unless crates are analyzed for several targets.
+    pub fn file_targets(&self, file_id: FileId) -> Cancellable<Vec<String>> {
+        self.with_db(|db| active_target::file_targets(db, file_id))
+    }
+
     /// Returns the edition of the given crate.
     pub fn crate_edition(&self, crate_id: CrateId) -> Cancellable<Edition> {
         self.with_db(|db| db.crate_graph()[crate_id].edition)
     }
 
+    /// Returns the names of the dependencies of the given crate which its sources refer to.
+    pub fn used_dependencies(&self, crate_id: CrateId) -> Cancellable<Vec<String>> {
+        self.with_db(|db| used_dependencies::used_dependencies(db, crate_id))
+    }
+
     /// Returns the root file of the given crate.
     pub fn crate_root(&self, crate_id: CrateId) -> Cancellable<FileId> {
         self.with_db(|db| db.crate_graph()[crate_id].root_file_id)
@@ -531,6 +588,14 @@ impl Analysis {
         })
     }
 
+    /// Returns the ranges to edit along with the name at `position`, if there are any.
+    pub fn linked_editing_ranges(
+        &self,
+        position: FilePosition,
+    ) -> Cancellable<Option<Vec<TextRange>>> {
+        self.with_db(|db| linked_editing::linked_editing_ranges(db, position))
+    }
+
     /// Computes syntax highlighting for the given file range.
     pub fn highlight_range(&self, frange: FileRange) -> Cancellable<Vec<HlRange>> {
         self.with_db(|db| {
@@ -636,6 +701,21 @@ impl Analysis {
         self.with_db(|db| rename::will_rename_file(db, file_id, new_name_stem))
     }
 
+    /// Returns the edit declaring the module of a file about to be created at `path`.
+    pub fn will_create_file(&self, path: &VfsPath) -> Cancellable<Option<SourceChange>> {
+        self.with_db(|db| file_operations::will_create_file(db, path))
+    }
+
+    /// Returns the edit removing the declarations of the modules of the files or directories at
+    /// `paths`, which are about to be deleted, and optionally the imports from them.
+    pub fn will_delete_files(
+        &self,
+        paths: &[VfsPath],
+        remove_imports: bool,
+    ) -> Cancellable<Option<SourceChange>> {
+        self.with_db(|db| file_operations::will_delete_files(db, paths, remove_imports))
+    }
+
     pub fn structural_search_replace(
         &self,
         query: &str,
@@ -0,0 +1,212 @@
+use hir::Semantics;
+use ide_db::{base_db::FilePosition, defs::Definition, helpers::pick_best_token, RootDatabase};
+use itertools::Itertools;
+use syntax::{
+    ast, AstNode,
+    SyntaxKind::{IDENT, LIFETIME_IDENT},
+    SyntaxNode, SyntaxToken, TextRange, T,
+};
+
+use crate::highlight_related::{self, find_defs, HighlightRelatedConfig};
+
+// Feature: Linked Editing
+//
+// Edits all occurrences of a name together, as long as they can't be affected by anything
+// outside of the current item:
+//
+// . a `$metavar` within a single `macro_rules!` arm
+// . a generic lifetime, type or const parameter within its item
+// . a local binding within its scope
+//
+// Bindings used in field shorthands like `Foo { x }` are left alone, as editing them would
+// also change the field name.
+pub(crate) fn linked_editing_ranges(
+    db: &RootDatabase,
+    position: FilePosition,
+) -> Option<Vec<TextRange>> {
+    let sema = Semantics::new(db);
+    let syntax = sema.parse(position.file_id).syntax().clone();
+    let token = pick_best_token(syntax.token_at_offset(position.offset), |kind| match kind {
+        IDENT | LIFETIME_IDENT => 1,
+        _ => 0,
+    })?;
+    if !matches!(token.kind(), IDENT | LIFETIME_IDENT) {
+        return None;
+    }
+
+    if let Some(ranges) = metavar_ranges(&token) {
+        return Some(ranges);
+    }
+
+    let defs = find_defs(&sema, token.clone());
+    if defs.is_empty()
+        || !defs.iter().all(|def| matches!(def, Definition::GenericParam(_) | Definition::Local(_)))
+    {
+        return None;
+    }
+    let config = HighlightRelatedConfig { references: true, ..HighlightRelatedConfig::default() };
+    // Point at the start of the token, so that `highlight_related` picks the same one.
+    let position = FilePosition { offset: token.text_range().start(), ..position };
+    let mut ranges = highlight_related::highlight_related(&sema, config, position)?
+        .into_iter()
+        .map(|it| it.range)
+        .collect::<Vec<_>>();
+    if ranges.iter().any(|&range| {
+        syntax.text_range().contains_range(range)
+            && (&syntax.text().slice(range).to_string() != token.text()
+                || is_field_shorthand(&syntax, range))
+    }) {
+        return None;
+    }
+    ranges.sort_by_key(|it| it.start());
+    ranges.dedup();
+    Some(ranges)
+}
+
+/// Returns the ranges of the metavariable at `token` in the `macro_rules!` arm containing it.
+fn metavar_ranges(token: &SyntaxToken) -> Option<Vec<TextRange>> {
+    if token.kind() != IDENT || token.prev_token()?.kind() != T![$] {
+        return None;
+    }
+    let macro_rules = token.parent_ancestors().find_map(ast::MacroRules::cast)?;
+    let offset = token.text_range().start();
+    let (matcher, transcriber) = macro_rules
+        .token_tree()?
+        .syntax()
+        .children()
+        .filter_map(ast::TokenTree::cast)
+        .tuples()
+        .find(|(matcher, transcriber): &(ast::TokenTree, ast::TokenTree)| {
+            matcher.syntax().text_range().contains(offset)
+                || transcriber.syntax().text_range().contains(offset)
+        })?;
+    let ranges = [matcher, transcriber]
+        .iter()
+        .flat_map(|tt| tt.syntax().descendants_with_tokens())
+        .filter_map(|it| it.into_token())
+        .filter(|it| {
+            it.kind() == IDENT
+                && it.text() == token.text()
+                && it.prev_token().map_or(false, |prev| prev.kind() == T![$])
+        })
+        .map(|it| it.text_range())
+        .collect();
+    Some(ranges)
+}
+
+fn is_field_shorthand(syntax: &SyntaxNode, range: TextRange) -> bool {
+    let token = match syntax.covering_element(range).into_token() {
+        Some(it) => it,
+        None => return false,
+    };
+    let parent = match token.parent() {
+        Some(it) => it,
+        None => return false,
+    };
+    if let Some(name_ref) = ast::NameRef::cast(parent.clone()) {
+        return ast::RecordExprField::for_field_name(&name_ref)
+            .map_or(false, |field| field.name_ref().is_none());
+    }
+    if let Some(name) = ast::Name::cast(parent) {
+        return ast::RecordPatField::for_field_name(&name).is_some();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+
+    use crate::fixture;
+
+    fn check(ra_fixture: &str, expect: Expect) {
+        let (analysis, position) = fixture::position(ra_fixture);
+        let text = analysis.file_text(position.file_id).unwrap();
+        let actual = match analysis.linked_editing_ranges(position).unwrap() {
+            Some(ranges) => ranges
+                .into_iter()
+                .map(|range| format!("{:?} {:?}\n", range, &text[range]))
+                .collect::<String>(),
+            None => "None".to_string(),
+        };
+        expect.assert_eq(&actual);
+    }
+
+    #[test]
+    fn macro_metavariables() {
+        check(
+            r#"
+macro_rules! m {
+    ($x:expr, $($y:ident),*) => { $x$0 + $($y +)* $x };
+    ($x:ty) => { $x };
+}
+"#,
+            expect![[r#"
+                23..24 "x"
+                52..53 "x"
+                66..67 "x"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn generic_params() {
+        check(
+            r#"
+struct S<'a, T$0: 'a>(&'a T);
+impl<T> S<'_, T> {}
+"#,
+            expect![[r#"
+                13..14 "T"
+                24..25 "T"
+            "#]],
+        );
+        check(
+            r#"
+fn foo<'a$0>(x: &'a u32) -> &'a u32 { x }
+"#,
+            expect![[r#"
+                7..9 "'a"
+                15..17 "'a"
+                27..29 "'a"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn locals() {
+        check(
+            r#"
+fn foo() {
+    let x$0 = 1;
+    let y = x + x;
+}
+"#,
+            expect![[r#"
+                19..20 "x"
+                38..39 "x"
+                42..43 "x"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn no_ranges_for_field_shorthands_and_items() {
+        check(
+            r#"
+struct S { x: u32 }
+fn foo() {
+    let x$0 = 1;
+    let s = S { x };
+}
+"#,
+            expect![["None"]],
+        );
+        check(
+            r#"
+fn foo$0() {}
+"#,
+            expect![["None"]],
+        );
+    }
+}
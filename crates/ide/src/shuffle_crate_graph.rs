@@ -37,6 +37,9 @@ pub(crate) fn shuffle_crate_graph(db: &mut RootDatabase) {
             data.is_proc_macro,
             data.origin.clone(),
         );
+        if let Some(target) = &data.target {
+            new_graph.set_target(new_id, target.clone());
+        }
         map.insert(old_id, new_id);
     }
 
@@ -0,0 +1,184 @@
+//! Entry point for type-hierarchy
+
+use hir::{Impl, ModuleDef, Semantics, Trait};
+use ide_db::{defs::Definition, RootDatabase};
+use itertools::Itertools;
+
+use crate::{goto_implementation, FilePosition, NavigationTarget, RangeInfo, TryToNav};
+
+// Feature: Type Hierarchy
+//
+// Shows the supertraits of a trait and the traits and types extending or implementing it, or
+// the traits a type implements.
+//
+// |===
+// | Editor  | Action Name
+//
+// | VS Code | **Show Type Hierarchy**
+// |===
+pub(crate) fn type_hierarchy(
+    db: &RootDatabase,
+    position: FilePosition,
+) -> Option<RangeInfo<Vec<NavigationTarget>>> {
+    let sema = Semantics::new(db);
+    let (range, defs) = goto_implementation::definitions_at(&sema, position)?;
+    let navs = defs
+        .into_iter()
+        .filter_map(|def| match def {
+            Definition::Trait(it) => it.try_to_nav(db),
+            Definition::Adt(it) => it.try_to_nav(db),
+            _ => None,
+        })
+        .collect();
+    Some(RangeInfo { range, info: navs })
+}
+
+/// Returns the direct supertraits of a trait, or the traits a type implements.
+pub(crate) fn supertypes(
+    db: &RootDatabase,
+    position: FilePosition,
+) -> Option<Vec<NavigationTarget>> {
+    let sema = Semantics::new(db);
+    let (_, defs) = goto_implementation::definitions_at(&sema, position)?;
+    let navs = defs
+        .into_iter()
+        .flat_map(|def| match def {
+            Definition::Trait(trait_) => trait_.direct_supertraits(db),
+            Definition::Adt(adt) => Impl::all_for_type(db, adt.ty(db))
+                .into_iter()
+                .filter_map(|imp| imp.trait_(db))
+                .collect(),
+            _ => Vec::new(),
+        })
+        .unique()
+        .filter_map(|trait_| trait_.try_to_nav(db))
+        .collect();
+    Some(navs)
+}
+
+/// Returns the traits having a trait as a direct supertrait and the types implementing it.
+/// Implementations for types which aren't ADTs, like references or primitives, are returned
+/// themselves.
+pub(crate) fn subtypes(db: &RootDatabase, position: FilePosition) -> Option<Vec<NavigationTarget>> {
+    let sema = Semantics::new(db);
+    let (_, defs) = goto_implementation::definitions_at(&sema, position)?;
+    let navs =
+        defs.into_iter()
+            .flat_map(|def| match def {
+                Definition::Trait(trait_) => {
+                    let subtraits =
+                        direct_subtraits(db, trait_).into_iter().filter_map(|it| it.try_to_nav(db));
+                    let impls = Impl::all_for_trait(db, trait_).into_iter().filter_map(|imp| {
+                        match imp.self_ty(db).as_adt() {
+                            Some(adt) => adt.try_to_nav(db),
+                            None => imp.try_to_nav(db),
+                        }
+                    });
+                    subtraits.chain(impls).collect()
+                }
+                _ => Vec::new(),
+            })
+            .unique()
+            .collect();
+    Some(navs)
+}
+
+/// Returns the traits declared in modules which have `trait_` as a direct supertrait.
+fn direct_subtraits(db: &RootDatabase, trait_: Trait) -> Vec<Trait> {
+    trait_
+        .module(db)
+        .krate()
+        .transitive_reverse_dependencies(db)
+        .flat_map(|krate| krate.modules(db))
+        .flat_map(|module| module.declarations(db))
+        .filter_map(|def| match def {
+            ModuleDef::Trait(it) => Some(it),
+            _ => None,
+        })
+        .filter(|it| it.direct_supertraits(db).contains(&trait_))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+    use ide_db::base_db::FilePosition;
+    use itertools::Itertools;
+
+    use crate::fixture;
+
+    fn check_hierarchy(
+        ra_fixture: &str,
+        expected: Expect,
+        expected_supertypes: Expect,
+        expected_subtypes: Expect,
+    ) {
+        let (analysis, pos) = fixture::position(ra_fixture);
+
+        let mut navs = analysis.type_hierarchy(pos).unwrap().unwrap().info;
+        assert_eq!(navs.len(), 1);
+        let nav = navs.pop().unwrap();
+        expected.assert_eq(&nav.debug_render());
+
+        let item_pos =
+            FilePosition { file_id: nav.file_id, offset: nav.focus_or_full_range().start() };
+        let supertypes = analysis.supertypes(item_pos).unwrap().unwrap();
+        expected_supertypes.assert_eq(&supertypes.iter().map(|it| it.debug_render()).join("\n"));
+
+        let subtypes = analysis.subtypes(item_pos).unwrap().unwrap();
+        expected_subtypes.assert_eq(&subtypes.iter().map(|it| it.debug_render()).join("\n"));
+    }
+
+    #[test]
+    fn test_type_hierarchy_of_trait() {
+        check_hierarchy(
+            r#"
+trait Base {}
+trait Other {}
+trait Tr$0ait: Base where Self: Other {}
+struct Foo;
+impl Trait for Foo {}
+impl Trait for u32 {}
+trait Sub: Trait {}
+trait Indirect: Sub {}
+"#,
+            expect![["Trait Trait FileId(0) 29..67 35..40"]],
+            expect![[r#"
+                Base Trait FileId(0) 0..13 6..10
+                Other Trait FileId(0) 14..28 20..25"#]],
+            expect![[r#"
+                Sub Trait FileId(0) 124..143 130..133
+                Foo Struct FileId(0) 68..79 75..78
+                impl Impl FileId(0) 102..123 117..120"#]],
+        );
+    }
+
+    #[test]
+    fn test_type_hierarchy_of_type() {
+        check_hierarchy(
+            r#"
+trait A {}
+trait B {}
+struct Foo;
+impl Foo {}
+impl A for Foo {}
+impl B for F$0oo {}
+"#,
+            expect![["Foo Struct FileId(0) 22..33 29..32"]],
+            expect![[r#"
+                A Trait FileId(0) 0..10 6..7
+                B Trait FileId(0) 11..21 17..18"#]],
+            expect![[]],
+        );
+    }
+
+    #[test]
+    fn test_type_hierarchy_of_function() {
+        let (analysis, pos) = fixture::position(
+            r#"
+fn f$0oo() {}
+"#,
+        );
+        assert!(analysis.type_hierarchy(pos).unwrap().unwrap().info.is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+//! Finds the dependencies a crate refers to.
+
+use hir::Crate;
+use ide_db::{
+    base_db::{CrateId, FileId, SourceDatabase},
+    FxHashSet, RootDatabase,
+};
+use syntax::{AstNode, SyntaxKind};
+
+/// Returns the names of the dependencies of `crate_id` its modules refer to, in paths, `extern
+/// crate` items or the arguments of macro calls. Tokens passed to macros aren't resolved, so an
+/// identifier there counts whatever it ends up meaning.
+pub(crate) fn used_dependencies(db: &RootDatabase, crate_id: CrateId) -> Vec<String> {
+    let files: FxHashSet<FileId> = Crate::from(crate_id)
+        .modules(db)
+        .into_iter()
+        .map(|module| module.definition_source(db).file_id.original_file(db))
+        .collect();
+    let mut names = FxHashSet::default();
+    for file_id in files {
+        let file = db.parse(file_id).tree();
+        let tokens = file.syntax().descendants_with_tokens().filter_map(|it| it.into_token());
+        names.extend(
+            tokens
+                .filter(|token| {
+                    token.kind() == SyntaxKind::IDENT
+                        && token.parent().map_or(false, |it| {
+                            matches!(it.kind(), SyntaxKind::NAME_REF | SyntaxKind::TOKEN_TREE)
+                        })
+                })
+                .map(|token| token.text().to_string()),
+        );
+    }
+    db.crate_graph()[crate_id]
+        .dependencies
+        .iter()
+        .map(|dep| dep.name.to_string())
+        .filter(|name| names.contains(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixture;
+
+    fn check(ra_fixture: &str, expected: &[&str]) {
+        let (analysis, file_id) = fixture::file(ra_fixture);
+        let crate_id = analysis.crate_for(file_id).unwrap()[0];
+        let mut used = analysis.used_dependencies(crate_id).unwrap();
+        used.sort();
+        assert_eq!(used, expected);
+    }
+
+    #[test]
+    fn test_used_dependencies() {
+        check(
+            r#"
+//- /main.rs crate:main deps:used,in_macro,in_module,extern_crate,unused,shadowed
+extern crate extern_crate;
+mod module;
+use used::Foo;
+fn main() {
+    let shadowed = 1;
+    println!("{}", in_macro::VALUE);
+}
+//- /module.rs
+fn f() -> in_module::Bar { in_module::Bar }
+//- /used.rs crate:used
+pub struct Foo;
+//- /in_macro.rs crate:in_macro
+pub const VALUE: u32 = 0;
+//- /in_module.rs crate:in_module
+pub struct Bar;
+//- /extern_crate.rs crate:extern_crate
+//- /unused.rs crate:unused
+//- /shadowed.rs crate:shadowed
+"#,
+            &["extern_crate", "in_macro", "in_module", "used"],
+        );
+    }
+}
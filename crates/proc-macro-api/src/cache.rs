@@ -0,0 +1,228 @@
+//! On-disk cache of proc-macro expansions.
+//!
+//! Expanding proc-macros is expensive, and after a restart of the server we
+//! would otherwise have to re-expand every single invocation in the workspace.
+//! Expansions are pure functions of the dylib, the macro name, the input token
+//! trees and the environment, so we name cache entries by a hash of those and
+//! store the resulting [`FlatTree`] as a json file in the cache directory. The
+//! entry also records the inputs themselves, which are compared on lookup, so
+//! that a hash collision can't return the expansion of another invocation.
+//!
+//! Only successful expansions are cached: panics and server errors are always
+//! recomputed.
+
+use std::{
+    fs, io,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use paths::{AbsPath, AbsPathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::msg::{ExpandMacro, FlatTree};
+
+/// Bump this when the format of the cache entries changes, so that stale
+/// entries written by a different rust-analyzer version are never read.
+const CACHE_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub struct ExpansionCache {
+    dir: AbsPathBuf,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExpansionCacheStats {
+    pub entries: usize,
+    pub total_bytes: u64,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl ExpansionCache {
+    pub fn new(dir: AbsPathBuf) -> ExpansionCache {
+        let dir = dir.join(format!("v{}", CACHE_VERSION));
+        ExpansionCache { dir, hits: AtomicUsize::new(0), misses: AtomicUsize::new(0) }
+    }
+
+    pub fn dir(&self) -> &AbsPath {
+        &self.dir
+    }
+
+    pub fn stats(&self) -> ExpansionCacheStats {
+        let mut res = ExpansionCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ..ExpansionCacheStats::default()
+        };
+        if let Ok(read_dir) = fs::read_dir(&self.dir) {
+            for entry in read_dir.filter_map(Result::ok) {
+                if let Ok(metadata) = entry.metadata() {
+                    if metadata.is_file() {
+                        res.entries += 1;
+                        res.total_bytes += metadata.len();
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    /// Removes all cached expansions from disk.
+    pub fn clear(&self) -> io::Result<()> {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        match fs::remove_dir_all(&self.dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn key(dylib_hash: DylibHash, task: &ExpandMacro) -> CacheKey {
+        let inputs = KeyInputs {
+            dylib_hash,
+            macro_name: &task.macro_name,
+            macro_body: &task.macro_body,
+            attributes: task.attributes.as_ref(),
+            env: &task.env,
+        };
+        // Serializing plain data can't fail.
+        let inputs = serde_json::to_string(&inputs).unwrap();
+        CacheKey { hash: stable_hash(inputs.as_bytes()), inputs }
+    }
+
+    pub(crate) fn get(&self, key: &CacheKey) -> Option<FlatTree> {
+        let res = fs::read_to_string(self.entry_path(key))
+            .ok()
+            .and_then(|text| serde_json::from_str::<CacheEntry<String, FlatTree>>(&text).ok())
+            .filter(|entry| entry.key == key.inputs)
+            .map(|entry| entry.expansion);
+        let counter = if res.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        res
+    }
+
+    pub(crate) fn insert(&self, key: &CacheKey, tree: &FlatTree) {
+        if let Err(err) = self.try_insert(key, tree) {
+            tracing::warn!("failed to write proc-macro expansion cache entry: {}", err);
+        }
+    }
+
+    fn try_insert(&self, key: &CacheKey, tree: &FlatTree) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let text = serde_json::to_string(&CacheEntry { key: &key.inputs, expansion: tree })?;
+        // Write to a temporary file first, so that concurrent readers (or a
+        // crash mid-write) never observe a partially written entry.
+        let path = self.entry_path(key);
+        let tmp = self.dir.join(format!("{:016x}.{}.tmp", key.hash, std::process::id()));
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, &path)
+    }
+
+    fn entry_path(&self, key: &CacheKey) -> AbsPathBuf {
+        self.dir.join(format!("{:016x}.json", key.hash))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CacheKey {
+    /// The serialized inputs of the expansion.
+    inputs: String,
+    hash: u64,
+}
+
+#[derive(Serialize)]
+struct KeyInputs<'a> {
+    dylib_hash: DylibHash,
+    macro_name: &'a str,
+    macro_body: &'a FlatTree,
+    attributes: Option<&'a FlatTree>,
+    env: &'a [(String, String)],
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<K, T> {
+    key: K,
+    expansion: T,
+}
+
+/// Identifies the contents of a proc-macro dylib.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct DylibHash {
+    len: u64,
+    hash: u64,
+}
+
+/// Hashes the contents of a proc-macro dylib, so that rebuilding the macro
+/// crate invalidates all of its cached expansions.
+pub(crate) fn hash_dylib(path: &AbsPath) -> io::Result<DylibHash> {
+    let bytes = fs::read(path)?;
+    Ok(DylibHash { len: bytes.len() as u64, hash: stable_hash(&bytes) })
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher`, its output doesn't change between
+/// Rust releases, which matters for hashes persisted to disk.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tt::*;
+
+    #[test]
+    fn stable_hash_is_fnv1a() {
+        assert_eq!(stable_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    fn task(name: &str) -> ExpandMacro {
+        let mut subtree = Subtree::default();
+        subtree
+            .token_trees
+            .push(TokenTree::Leaf(Ident { text: "struct".into(), id: TokenId(0) }.into()));
+        ExpandMacro {
+            macro_body: FlatTree::new(&subtree),
+            macro_name: name.to_string(),
+            attributes: None,
+            lib: std::env::current_dir().unwrap(),
+            env: vec![("CARGO_PKG_NAME".to_string(), "foo".to_string())],
+            current_dir: None,
+        }
+    }
+
+    #[test]
+    fn cache_roundtrip() {
+        let dir =
+            std::env::temp_dir().join(format!("ra-proc-macro-cache-test-{}", std::process::id()));
+        let cache = ExpansionCache::new(AbsPathBuf::assert(dir));
+
+        let dylib = DylibHash { len: 1, hash: 92 };
+        let key = ExpansionCache::key(dylib, &task("derive_foo"));
+        assert_ne!(key, ExpansionCache::key(DylibHash { hash: 93, ..dylib }, &task("derive_foo")));
+        assert_ne!(key, ExpansionCache::key(dylib, &task("derive_bar")));
+
+        assert!(cache.get(&key).is_none());
+        let expanded = task("derive_foo").macro_body;
+        cache.insert(&key, &expanded);
+        assert_eq!(cache.get(&key), Some(expanded));
+
+        // An entry for other inputs with a colliding hash is never returned.
+        let colliding =
+            CacheKey { hash: key.hash, ..ExpansionCache::key(dylib, &task("derive_bar")) };
+        assert!(cache.get(&colliding).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 2));
+
+        cache.clear().unwrap();
+        assert_eq!(cache.stats(), ExpansionCacheStats::default());
+    }
+}
@@ -5,6 +5,7 @@
 //! is used to provide basic infrastructure for communication between two
 //! processes: Client (RA itself), Server (the external program)
 
+mod cache;
 pub mod msg;
 mod process;
 mod version;
@@ -12,8 +13,9 @@ mod version;
 use paths::AbsPathBuf;
 use std::{
     ffi::OsStr,
-    fmt, io,
+    fmt, io, iter,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use serde::{Deserialize, Serialize};
@@ -24,7 +26,11 @@ use crate::{
     process::ProcMacroProcessSrv,
 };
 
-pub use version::{read_dylib_info, RustCInfo};
+pub use cache::{ExpansionCache, ExpansionCacheStats};
+pub use version::{read_dylib_info, BridgeAbi, RustCInfo};
+
+/// How long the toolchain's proc-macro server may take to answer the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum ProcMacroKind {
@@ -44,10 +50,20 @@ pub struct ProcMacroServer {
     ///
     /// Therefore, we just wrap the `ProcMacroProcessSrv` in a mutex here.
     process: Arc<Mutex<ProcMacroProcessSrv>>,
+    /// Optional on-disk cache, consulted before sending expansion requests to
+    /// the server process.
+    expansion_cache: Option<Arc<ExpansionCache>>,
+    /// How long to wait for a single expansion before giving up on the macro
+    /// and restarting the server.
+    expansion_timeout: Option<Duration>,
+    /// Whether this is the server distributed with the toolchain rather than
+    /// our own one with the vendored ABIs.
+    toolchain: bool,
 }
 
 pub struct MacroDylib {
     path: AbsPathBuf,
+    info: RustCInfo,
 }
 
 impl MacroDylib {
@@ -62,7 +78,11 @@ impl MacroDylib {
             return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
         }
 
-        Ok(MacroDylib { path })
+        Ok(MacroDylib { path, info })
+    }
+
+    pub fn info(&self) -> &RustCInfo {
+        &self.info
     }
 }
 
@@ -76,6 +96,9 @@ pub struct ProcMacro {
     dylib_path: AbsPathBuf,
     name: String,
     kind: ProcMacroKind,
+    /// The expansion cache together with the hash of the dylib contents.
+    expansion_cache: Option<(Arc<ExpansionCache>, cache::DylibHash)>,
+    expansion_timeout: Option<Duration>,
 }
 
 impl Eq for ProcMacro {}
@@ -114,8 +137,62 @@ impl ProcMacroServer {
         process_path: AbsPathBuf,
         args: impl IntoIterator<Item = impl AsRef<OsStr>>,
     ) -> io::Result<ProcMacroServer> {
-        let process = ProcMacroProcessSrv::run(process_path, args)?;
-        Ok(ProcMacroServer { process: Arc::new(Mutex::new(process)) })
+        let process = ProcMacroProcessSrv::run(process_path, args, &[])?;
+        Ok(ProcMacroServer {
+            process: Arc::new(Mutex::new(process)),
+            expansion_cache: None,
+            expansion_timeout: None,
+            toolchain: false,
+        })
+    }
+
+    /// Spawns the proc-macro server distributed with a toolchain (see
+    /// `Sysroot::proc_macro_srv`), which needs no vendored ABIs.
+    ///
+    /// The server is unstable and understands none of our flags. As it isn't
+    /// under our control, it has to pass a handshake before it is used.
+    pub fn spawn_toolchain(process_path: AbsPathBuf) -> io::Result<ProcMacroServer> {
+        let mut process = ProcMacroProcessSrv::run(
+            process_path,
+            iter::empty::<&OsStr>(),
+            &[("RUST_ANALYZER_INTERNAL_DO_NOT_USE", "this is unstable")],
+        )?;
+        process
+            .handshake(HANDSHAKE_TIMEOUT)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+        Ok(ProcMacroServer {
+            process: Arc::new(Mutex::new(process)),
+            expansion_cache: None,
+            expansion_timeout: None,
+            toolchain: true,
+        })
+    }
+
+    /// Returns the bridge that will be used to expand the macros of `dylib`,
+    /// or `None` if no vendored ABI supports it.
+    pub fn bridge_abi(&self, dylib: &MacroDylib) -> Option<BridgeAbi> {
+        if self.toolchain {
+            // The server passed the handshake and is used for the workspaces of
+            // its own sysroot, whose proc-macros it was built to load.
+            return Some(BridgeAbi::Toolchain);
+        }
+        dylib.info.vendored_abi().map(BridgeAbi::Vendored)
+    }
+
+    /// Serves expansions of macros loaded afterwards from the given on-disk cache.
+    pub fn set_expansion_cache(&mut self, cache: Option<Arc<ExpansionCache>>) {
+        self.expansion_cache = cache;
+    }
+
+    pub fn expansion_cache(&self) -> Option<&Arc<ExpansionCache>> {
+        self.expansion_cache.as_ref()
+    }
+
+    /// Limits how long expansions of macros loaded afterwards may run. When a
+    /// macro exceeds the limit, the server is restarted and the macro is
+    /// disabled after repeated failures.
+    pub fn set_expansion_timeout(&mut self, timeout: Option<Duration>) {
+        self.expansion_timeout = timeout;
     }
 
     pub fn load_dylib(&self, dylib: MacroDylib) -> Result<Vec<ProcMacro>, ServerError> {
@@ -123,6 +200,21 @@ impl ProcMacroServer {
         let macros =
             self.process.lock().unwrap_or_else(|e| e.into_inner()).find_proc_macros(&dylib.path)?;
 
+        let expansion_cache = match &self.expansion_cache {
+            Some(cache) => match cache::hash_dylib(&dylib.path) {
+                Ok(hash) => Some((cache.clone(), hash)),
+                Err(err) => {
+                    tracing::warn!(
+                        "failed to hash {}, expansions will not be cached: {}",
+                        dylib.path.display(),
+                        err
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+
         match macros {
             Ok(macros) => Ok(macros
                 .into_iter()
@@ -131,6 +223,8 @@ impl ProcMacroServer {
                     name,
                     kind,
                     dylib_path: dylib.path.clone(),
+                    expansion_cache: expansion_cache.clone(),
+                    expansion_timeout: self.expansion_timeout,
                 })
                 .collect()),
             Err(message) => Err(ServerError { message, io: None }),
@@ -167,10 +261,29 @@ impl ProcMacro {
             current_dir,
         };
 
+        let cache_key = self
+            .expansion_cache
+            .as_ref()
+            .map(|(cache, dylib_hash)| (cache, ExpansionCache::key(*dylib_hash, &task)));
+        if let Some((cache, key)) = &cache_key {
+            if let Some(tree) = cache.get(key) {
+                return Ok(Ok(tree.to_subtree()));
+            }
+        }
+
         let request = msg::Request::ExpandMacro(task);
-        let response = self.process.lock().unwrap_or_else(|e| e.into_inner()).send_task(request)?;
+        let response = self
+            .process
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .send_task(request, self.expansion_timeout)?;
         match response {
-            msg::Response::ExpandMacro(it) => Ok(it.map(FlatTree::to_subtree)),
+            msg::Response::ExpandMacro(it) => {
+                if let (Some((cache, key)), Ok(tree)) = (&cache_key, &it) {
+                    cache.insert(key, tree);
+                }
+                Ok(it.map(FlatTree::to_subtree))
+            }
             msg::Response::ListMacros { .. } => {
                 Err(ServerError { message: "unexpected response".to_string(), io: None })
             }
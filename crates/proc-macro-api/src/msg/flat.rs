@@ -43,7 +43,7 @@ use std::{
 use serde::{Deserialize, Serialize};
 use tt::TokenId;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
 pub struct FlatTree {
     subtree: Vec<u32>,
     literal: Vec<u32>,
@@ -1,9 +1,14 @@
 //! Handle process life-time and message passing for proc-macro client
 
 use std::{
+    collections::HashMap,
     ffi::{OsStr, OsString},
-    io::{self, BufRead, BufReader, Write},
-    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    io::{self, BufReader},
+    path::PathBuf,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
 };
 
 use paths::{AbsPath, AbsPathBuf};
@@ -14,22 +19,48 @@ use crate::{
     ProcMacroKind, ServerError,
 };
 
+/// The proc-macro server process, supervised by the client.
+///
+/// If an expansion does not finish within the configured timeout, or the
+/// server dies while expanding (for example because it hit its memory limit),
+/// the server is restarted and the failure is counted against the offending
+/// macro. Once a macro has failed [`MAX_FAILURES`] times, further invocations
+/// of it error out immediately instead of wedging the server again.
 #[derive(Debug)]
 pub(crate) struct ProcMacroProcessSrv {
-    _process: Process,
+    process_path: AbsPathBuf,
+    args: Vec<OsString>,
+    env: Vec<(OsString, OsString)>,
+    process: Process,
     stdin: ChildStdin,
-    stdout: BufReader<ChildStdout>,
+    responses: Receiver<io::Result<Response>>,
+    /// How often macros (dylib path and name) timed out or crashed the server.
+    failed_macros: HashMap<(PathBuf, String), u32>,
 }
 
+/// How often a macro may time out or crash the server before it is disabled.
+const MAX_FAILURES: u32 = 3;
+
 impl ProcMacroProcessSrv {
     pub(crate) fn run(
         process_path: AbsPathBuf,
         args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+        env: &[(&str, &str)],
     ) -> io::Result<ProcMacroProcessSrv> {
-        let mut process = Process::run(process_path, args)?;
-        let (stdin, stdout) = process.stdio().expect("couldn't access child stdio");
+        let args: Vec<OsString> = args.into_iter().map(|s| s.as_ref().into()).collect();
+        let env: Vec<(OsString, OsString)> =
+            env.iter().map(|&(k, v)| (k.into(), v.into())).collect();
+        let (process, stdin, responses) = Process::run(&process_path, &args, &env)?;
 
-        let srv = ProcMacroProcessSrv { _process: process, stdin, stdout };
+        let srv = ProcMacroProcessSrv {
+            process_path,
+            args,
+            env,
+            process,
+            stdin,
+            responses,
+            failed_macros: HashMap::new(),
+        };
 
         Ok(srv)
     }
@@ -40,7 +71,7 @@ impl ProcMacroProcessSrv {
     ) -> Result<Result<Vec<(String, ProcMacroKind)>, String>, ServerError> {
         let request = Request::ListMacros { dylib_path: dylib_path.to_path_buf().into() };
 
-        let response = self.send_task(request)?;
+        let response = self.send_task(request, None)?;
 
         match response {
             Response::ListMacros(it) => Ok(it),
@@ -50,57 +81,207 @@ impl ProcMacroProcessSrv {
         }
     }
 
-    pub(crate) fn send_task(&mut self, req: Request) -> Result<Response, ServerError> {
-        let mut buf = String::new();
-        send_request(&mut self.stdin, &mut self.stdout, req, &mut buf)
+    /// Checks that the server speaks our protocol, by asking it for the macros
+    /// of a dylib which doesn't exist: a compatible server answers with an
+    /// error message.
+    pub(crate) fn handshake(&mut self, timeout: Duration) -> Result<(), ServerError> {
+        let request = Request::ListMacros { dylib_path: PathBuf::new() };
+        match self.send_task(request, Some(timeout))? {
+            Response::ListMacros(_) => Ok(()),
+            Response::ExpandMacro { .. } => {
+                Err(ServerError { message: "unexpected response".to_string(), io: None })
+            }
+        }
+    }
+
+    /// Sends the request to the server, waiting at most `timeout` for the
+    /// response to arrive.
+    pub(crate) fn send_task(
+        &mut self,
+        req: Request,
+        timeout: Option<Duration>,
+    ) -> Result<Response, ServerError> {
+        let expanded_macro = match &req {
+            Request::ExpandMacro(task) => Some((task.lib.clone(), task.macro_name.clone())),
+            Request::ListMacros { .. } => None,
+        };
+        if let Some(key) = &expanded_macro {
+            if self.failed_macros.get(key).map_or(false, |&it| it >= MAX_FAILURES) {
+                return Err(ServerError {
+                    message: format!(
+                        "proc-macro `{}` is disabled because it timed out or crashed the \
+                         proc-macro server {} times",
+                        key.1, MAX_FAILURES
+                    ),
+                    io: None,
+                });
+            }
+        }
+
+        let (message, io) = match req.write(&mut self.stdin) {
+            Ok(()) => {
+                let response = match timeout {
+                    Some(timeout) => self.responses.recv_timeout(timeout),
+                    None => self.responses.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match response {
+                    Ok(Ok(response)) => return Ok(response),
+                    Ok(Err(err)) => ("failed to read the response".to_string(), Some(err)),
+                    Err(RecvTimeoutError::Timeout) => (
+                        format!("timed out after {}s", timeout.unwrap_or_default().as_secs_f32()),
+                        None,
+                    ),
+                    Err(RecvTimeoutError::Disconnected) => ("server exited".to_string(), None),
+                }
+            }
+            // Most likely the server died and we got EPIPE.
+            Err(err) => ("failed to write the request".to_string(), Some(err)),
+        };
+
+        // Whatever went wrong, the connection is unusable now, so start over
+        // with a fresh server.
+        let message = match expanded_macro {
+            Some((lib, name)) => {
+                tracing::error!("proc-macro `{}` from {}: {}", name, lib.display(), message);
+                *self.failed_macros.entry((lib, name.clone())).or_default() += 1;
+                format!("proc-macro `{}` {}, restarting the proc-macro server", name, message)
+            }
+            None => {
+                tracing::error!("listing proc-macros: {}", message);
+                format!("{}, restarting the proc-macro server", message)
+            }
+        };
+        let io = match self.restart() {
+            Ok(()) => io,
+            Err(err) => Some(err),
+        };
+        Err(ServerError { message, io })
+    }
+
+    fn restart(&mut self) -> io::Result<()> {
+        let (process, stdin, responses) = Process::run(&self.process_path, &self.args, &self.env)?;
+        // Replacing the old process kills it.
+        self.process = process;
+        self.stdin = stdin;
+        self.responses = responses;
+        Ok(())
     }
 }
 
 #[derive(Debug)]
 struct Process {
-    child: JodChild,
+    _child: JodChild,
 }
 
 impl Process {
+    /// Spawns the server, together with a thread that reads its responses.
     fn run(
-        path: AbsPathBuf,
-        args: impl IntoIterator<Item = impl AsRef<OsStr>>,
-    ) -> io::Result<Process> {
-        let args: Vec<OsString> = args.into_iter().map(|s| s.as_ref().into()).collect();
-        let child = JodChild(mk_child(&path, &args)?);
-        Ok(Process { child })
-    }
+        path: &AbsPath,
+        args: &[OsString],
+        env: &[(OsString, OsString)],
+    ) -> io::Result<(Process, ChildStdin, Receiver<io::Result<Response>>)> {
+        let mut child = JodChild(mk_child(path, args, env)?);
+        let stdin = child.stdin.take().expect("couldn't access child stdio");
+        let stdout = child.stdout.take().expect("couldn't access child stdio");
 
-    fn stdio(&mut self) -> Option<(ChildStdin, BufReader<ChildStdout>)> {
-        let stdin = self.child.stdin.take()?;
-        let stdout = self.child.stdout.take()?;
-        let read = BufReader::new(stdout);
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new().name("ProcMacroServerReader".to_owned()).spawn(move || {
+            let mut stdout = BufReader::new(stdout);
+            let mut buf = String::new();
+            loop {
+                let res = match Response::read(&mut stdout, &mut buf) {
+                    Ok(Some(response)) => Ok(response),
+                    Ok(None) => break,
+                    Err(err) => Err(err),
+                };
+                let is_err = res.is_err();
+                if sender.send(res).is_err() || is_err {
+                    break;
+                }
+            }
+        })?;
 
-        Some((stdin, read))
+        Ok((Process { _child: child }, stdin, receiver))
     }
 }
 
 fn mk_child(
     path: &AbsPath,
     args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    env: &[(OsString, OsString)],
 ) -> io::Result<Child> {
     Command::new(path.as_os_str())
         .args(args)
+        .envs(env.iter().map(|(k, v)| (k, v)))
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::inherit())
         .spawn()
 }
 
-fn send_request(
-    mut writer: &mut impl Write,
-    mut reader: &mut impl BufRead,
-    req: Request,
-    buf: &mut String,
-) -> Result<Response, ServerError> {
-    req.write(&mut writer)
-        .map_err(|err| ServerError { message: "failed to write request".into(), io: Some(err) })?;
-    let res = Response::read(&mut reader, buf)
-        .map_err(|err| ServerError { message: "failed to read response".into(), io: Some(err) })?;
-    res.ok_or_else(|| ServerError { message: "server exited".into(), io: None })
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Instant;
+
+    use crate::msg::{ExpandMacro, FlatTree};
+
+    use super::*;
+
+    #[test]
+    fn hanging_expansion_times_out() {
+        // A "server" which never responds.
+        let mut srv =
+            ProcMacroProcessSrv::run(AbsPathBuf::assert("/bin/sh".into()), ["-c", "sleep 60"], &[])
+                .unwrap();
+        let task = || {
+            Request::ExpandMacro(ExpandMacro {
+                macro_body: FlatTree::new(&tt::Subtree::default()),
+                macro_name: "hang".to_string(),
+                attributes: None,
+                lib: std::env::current_dir().unwrap(),
+                env: Vec::new(),
+                current_dir: None,
+            })
+        };
+
+        let start = Instant::now();
+        let err = srv.send_task(task(), Some(Duration::from_millis(100))).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(30));
+        assert!(err.message.contains("timed out"), "{}", err.message);
+
+        for _ in 1..MAX_FAILURES {
+            let err = srv.send_task(task(), Some(Duration::from_millis(100))).unwrap_err();
+            assert!(err.message.contains("timed out"), "{}", err.message);
+        }
+
+        let err = srv.send_task(task(), Some(Duration::from_millis(100))).unwrap_err();
+        assert!(err.message.contains("is disabled"), "{}", err.message);
+    }
+
+    #[test]
+    fn dead_server_is_restarted() {
+        // A "server" which exits right away, so that reading the response fails.
+        let mut srv =
+            ProcMacroProcessSrv::run(AbsPathBuf::assert("/bin/sh".into()), ["-c", "exit 0"], &[])
+                .unwrap();
+        let task = || Request::ListMacros { dylib_path: std::env::current_dir().unwrap() };
+
+        for _ in 0..2 {
+            let err = srv.send_task(task(), None).unwrap_err();
+            assert!(err.message.contains("restarting"), "{}", err.message);
+        }
+    }
+
+    #[test]
+    fn handshake_rejects_foreign_servers() {
+        // A "server" which answers with something that isn't our protocol.
+        let mut srv = ProcMacroProcessSrv::run(
+            AbsPathBuf::assert("/bin/sh".into()),
+            ["-c", r#"printf '{"%s": 1}\n' "$GREETING"; sleep 60"#],
+            &[("GREETING", "hello")],
+        )
+        .unwrap();
+        let err = srv.handshake(Duration::from_secs(10)).unwrap_err();
+        assert!(err.message.contains("failed to read"), "{}", err.message);
+    }
 }
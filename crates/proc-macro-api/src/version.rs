@@ -1,6 +1,7 @@
 //! Reading proc-macro rustc version information from binary data
 
 use std::{
+    fmt,
     fs::File,
     io::{self, Read},
 };
@@ -18,6 +19,40 @@ pub struct RustCInfo {
     pub date: Option<String>,
 }
 
+/// The copies of the `proc_macro` bridge vendored in `proc_macro_srv::abis`,
+/// identified by the oldest rustc version each of them supports.
+const VENDORED_ABIS: &[(usize, usize)] = &[(1, 48), (1, 54), (1, 56), (1, 57), (1, 58), (1, 63)];
+
+impl RustCInfo {
+    /// Returns the vendored bridge used to talk to proc-macros built by this
+    /// compiler. Compilers newer than the last vendored ABI get the latest one,
+    /// which may or may not be compatible.
+    pub fn vendored_abi(&self) -> Option<(usize, usize)> {
+        let (major, minor, _) = self.version;
+        VENDORED_ABIS.iter().rev().copied().find(|&abi| abi <= (major, minor) && major == abi.0)
+    }
+}
+
+/// Which implementation of the `proc_macro` bridge expands the macros of a dylib.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BridgeAbi {
+    /// The proc-macro server shipped with the toolchain, built against that
+    /// toolchain's own `proc_macro` crate.
+    Toolchain,
+    /// rust-analyzer's own server, using the vendored bridge copied from the
+    /// given rustc version.
+    Vendored((usize, usize)),
+}
+
+impl fmt::Display for BridgeAbi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BridgeAbi::Toolchain => f.write_str("toolchain proc-macro server"),
+            BridgeAbi::Vendored((major, minor)) => write!(f, "vendored ABI {}.{}", major, minor),
+        }
+    }
+}
+
 /// Read rustc dylib information
 pub fn read_dylib_info(dylib_path: &AbsPath) -> io::Result<RustCInfo> {
     macro_rules! err {
@@ -147,3 +182,21 @@ fn read_version(dylib_path: &AbsPath) -> io::Result<String> {
     let version_string = String::from_utf8(version_string_utf8);
     version_string.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vendored_abi() {
+        let abi = |minor| {
+            RustCInfo { version: (1, minor, 0), channel: String::new(), commit: None, date: None }
+                .vendored_abi()
+        };
+        assert_eq!(abi(47), None);
+        assert_eq!(abi(48), Some((1, 48)));
+        assert_eq!(abi(55), Some((1, 54)));
+        assert_eq!(abi(62), Some((1, 58)));
+        assert_eq!(abi(70), Some((1, 63)));
+    }
+}
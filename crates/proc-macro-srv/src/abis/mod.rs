@@ -19,8 +19,16 @@
 //! Then you'll need to add a branch to the `Abi` enum and an implementation of
 //! `Abi::expand`, `Abi::list_macros` and `Abi::from_lib` for the new ABI. See
 //! `proc_macro_srv/src/abis/abi_1_47/mod.rs` for an example. Finally you'll
-//! need to update the conditionals in `Abi::from_lib` to return your new ABI
-//! for the relevant versions of the rust compiler
+//! need to add the new ABI to `VENDORED_ABIS` in `proc_macro_api::version`,
+//! and a branch returning it to `Abi::from_lib`.
+//!
+//! # Toolchain proc-macro server
+//!
+//! Toolchains may ship their own proc-macro server in
+//! `$sysroot/libexec/rust-analyzer-proc-macro-srv`, built against their own
+//! `proc_macro` crate. When it is available, rust-analyzer uses it instead of
+//! this crate, so the vendored ABIs are only a fallback for toolchains which
+//! don't distribute it.
 //!
 
 // pub(crate) so tests can use the TokenStream, more notes in test/utils.rs
@@ -75,30 +83,30 @@ impl Abi {
         symbol_name: String,
         info: RustCInfo,
     ) -> Result<Abi, LoadProcMacroDylibError> {
-        // FIXME: this should use exclusive ranges when they're stable
-        // https://github.com/rust-lang/rust/issues/37854
-        match (info.version.0, info.version.1) {
-            (1, 48..=53) => {
+        // The mapping from compiler versions to vendored ABIs lives in
+        // `proc_macro_api::version`, so that the client can report it as well.
+        match info.vendored_abi() {
+            Some((1, 48)) => {
                 let inner = unsafe { Abi_1_48::from_lib(lib, symbol_name) }?;
                 Ok(Abi::Abi1_48(inner))
             }
-            (1, 54..=55) => {
+            Some((1, 54)) => {
                 let inner = unsafe { Abi_1_54::from_lib(lib, symbol_name) }?;
                 Ok(Abi::Abi1_54(inner))
             }
-            (1, 56) => {
+            Some((1, 56)) => {
                 let inner = unsafe { Abi_1_56::from_lib(lib, symbol_name) }?;
                 Ok(Abi::Abi1_56(inner))
             }
-            (1, 57) => {
+            Some((1, 57)) => {
                 let inner = unsafe { Abi_1_57::from_lib(lib, symbol_name) }?;
                 Ok(Abi::Abi1_57(inner))
             }
-            (1, 58..=62) => {
+            Some((1, 58)) => {
                 let inner = unsafe { Abi_1_58::from_lib(lib, symbol_name) }?;
                 Ok(Abi::Abi1_58(inner))
             }
-            (1, 63..) => {
+            Some((1, 63)) => {
                 let inner = unsafe { Abi_1_63::from_lib(lib, symbol_name) }?;
                 Ok(Abi::Abi1_63(inner))
             }
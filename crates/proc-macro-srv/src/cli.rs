@@ -1,11 +1,26 @@
 //! Driver for proc macro server
-use std::io;
+use std::{io, process, thread, time::Duration};
 
 use proc_macro_api::msg::{self, Message};
 
 use crate::ProcMacroSrv;
 
-pub fn run() -> io::Result<()> {
+/// Runs the server, exiting if its memory usage exceeds `memory_limit`
+/// megabytes. The client notices the exit and restarts the server.
+///
+/// Memory usage can't be measured everywhere, see [`profile::MemoryUsage::is_supported`].
+/// The limit is refused on those platforms rather than silently ignored.
+pub fn run(memory_limit: Option<usize>) -> io::Result<()> {
+    if let Some(limit) = memory_limit {
+        if !profile::MemoryUsage::is_supported() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "--memory-limit is not supported on this platform",
+            ));
+        }
+        spawn_memory_watchdog(limit)?;
+    }
+
     let mut srv = ProcMacroSrv::default();
     let mut buf = String::new();
 
@@ -22,6 +37,16 @@ pub fn run() -> io::Result<()> {
     Ok(())
 }
 
+fn spawn_memory_watchdog(limit_mb: usize) -> io::Result<()> {
+    thread::Builder::new().name("MemoryWatchdog".to_owned()).spawn(move || loop {
+        thread::sleep(Duration::from_secs(2));
+        if profile::memory_usage().allocated.megabytes() > limit_mb as isize {
+            process::exit(101);
+        }
+    })?;
+    Ok(())
+}
+
 fn read_request(buf: &mut String) -> io::Result<Option<msg::Request>> {
     msg::Request::read(&mut io::stdin().lock(), buf)
 }
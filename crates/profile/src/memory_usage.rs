@@ -24,6 +24,16 @@ impl std::ops::Sub for MemoryUsage {
 }
 
 impl MemoryUsage {
+    /// Whether the allocated memory can be measured on this platform. Elsewhere,
+    /// [`MemoryUsage::now`] always reports zero.
+    pub fn is_supported() -> bool {
+        cfg!(any(
+            all(feature = "jemalloc", not(target_env = "msvc")),
+            all(target_os = "linux", target_env = "gnu"),
+            windows
+        ))
+    }
+
     pub fn now() -> MemoryUsage {
         cfg_if! {
             if #[cfg(all(feature = "jemalloc", not(target_env = "msvc")))] {
@@ -6,19 +6,25 @@
 //! This module implements this second part. We use "build script" terminology
 //! here, but it covers procedural macros as well.
 
-use std::{cell::RefCell, io, path::PathBuf, process::Command};
+use std::{
+    cell::RefCell,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
 
 use cargo_metadata::{camino::Utf8Path, Message};
 use la_arena::ArenaMap;
-use paths::AbsPathBuf;
+use paths::{AbsPath, AbsPathBuf};
 use rustc_hash::FxHashMap;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{cfg_flag::CfgFlag, CargoConfig, CargoWorkspace, Package};
+use crate::{cfg_flag::CfgFlag, utf8_stdout, CargoConfig, CargoWorkspace, Package, TargetKind};
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct WorkspaceBuildScripts {
-    outputs: ArenaMap<Package, Option<BuildScriptOutput>>,
+    pub(crate) outputs: ArenaMap<Package, Option<BuildScriptOutput>>,
     error: Option<String>,
 }
 
@@ -58,6 +64,10 @@ impl WorkspaceBuildScripts {
             cmd.args(&["--target", target]);
         }
 
+        if config.offline {
+            cmd.arg("--offline");
+        }
+
         if config.all_features {
             cmd.arg("--all-features");
         } else {
@@ -77,6 +87,27 @@ impl WorkspaceBuildScripts {
         config: &CargoConfig,
         workspace: &CargoWorkspace,
         progress: &dyn Fn(String),
+    ) -> io::Result<WorkspaceBuildScripts> {
+        if !config.reuse_build_scripts {
+            return Self::run_cargo(config, workspace, progress);
+        }
+        if let Some(res) = Self::reuse(config, workspace) {
+            progress("reused build script outputs".to_string());
+            return Ok(res);
+        }
+        let res = Self::run_cargo(config, workspace, progress)?;
+        if res.error.is_none() {
+            if let Err(err) = res.save_snapshot(config, workspace) {
+                tracing::warn!("failed to save build script outputs: {}", err);
+            }
+        }
+        Ok(res)
+    }
+
+    fn run_cargo(
+        config: &CargoConfig,
+        workspace: &CargoWorkspace,
+        progress: &dyn Fn(String),
     ) -> io::Result<WorkspaceBuildScripts> {
         let mut cmd = Self::build_command(config);
 
@@ -224,11 +255,413 @@ impl WorkspaceBuildScripts {
         self.error.as_deref()
     }
 
+    /// Loads the outputs of a previous build instead of running cargo: from the snapshot saved
+    /// after the last run, or else from the `target` directory. Returns `None` if some outputs are
+    /// missing or cargo would run a build script again: when `Cargo.lock`, a manifest or a build
+    /// script of the workspace changed, as well as the files and environment variables the build
+    /// script asked to be rerun for, or the toolchain.
+    pub(crate) fn reuse(
+        config: &CargoConfig,
+        workspace: &CargoWorkspace,
+    ) -> Option<WorkspaceBuildScripts> {
+        let inputs_mtime = inputs_mtime(workspace)?;
+        let toolchain = toolchain_version(workspace)?;
+        Self::load_snapshot(config, workspace, inputs_mtime, &toolchain)
+            .or_else(|| Self::from_target_dir(config, workspace, inputs_mtime, &toolchain))
+    }
+
+    fn load_snapshot(
+        config: &CargoConfig,
+        workspace: &CargoWorkspace,
+        inputs_mtime: SystemTime,
+        toolchain: &str,
+    ) -> Option<WorkspaceBuildScripts> {
+        let path = snapshot_path(workspace);
+        let saved = mtime(&path)?;
+        if saved < inputs_mtime {
+            return None;
+        }
+        let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+        if snapshot.command != format!("{:?}", Self::build_command(config))
+            || snapshot.toolchain != toolchain
+        {
+            return None;
+        }
+
+        let mut res = WorkspaceBuildScripts::default();
+        for package in workspace.packages() {
+            let output = match snapshot.packages.get(&workspace[package].id) {
+                Some(it) => it,
+                None if needs_build(workspace, package) => return None,
+                None => {
+                    res.outputs.insert(package, None);
+                    continue;
+                }
+            };
+            if !output.is_fresh(workspace.target_directory(), saved) {
+                return None;
+            }
+            let existing = |path: &Option<PathBuf>| match path {
+                Some(path) if path.exists() => Some(Some(AbsPathBuf::try_from(path.clone()).ok()?)),
+                Some(_) => None,
+                None => Some(None),
+            };
+            let output = BuildScriptOutput {
+                cfgs: output.cfgs.clone(),
+                envs: output.envs.clone(),
+                out_dir: existing(&output.out_dir)?,
+                proc_macro_dylib_path: existing(&output.proc_macro_dylib_path)?,
+            };
+            res.outputs.insert(package, Some(output));
+        }
+        Some(res)
+    }
+
+    pub(crate) fn save_snapshot(
+        &self,
+        config: &CargoConfig,
+        workspace: &CargoWorkspace,
+    ) -> io::Result<()> {
+        let toolchain = toolchain_version(workspace)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "unknown toolchain version"))?;
+        let packages = workspace
+            .packages()
+            .filter_map(|package| {
+                let output = self.get_output(package)?;
+                let (rerun_if_changed, rerun_if_env_changed) = rerun_if(workspace, package, output);
+                let output = SnapshotOutput {
+                    cfgs: output.cfgs.clone(),
+                    envs: output.envs.clone(),
+                    out_dir: output.out_dir.clone().map(Into::into),
+                    proc_macro_dylib_path: output.proc_macro_dylib_path.clone().map(Into::into),
+                    rerun_if_changed,
+                    rerun_if_env_changed,
+                };
+                Some((workspace[package].id.clone(), output))
+            })
+            .collect();
+        let snapshot =
+            Snapshot { command: format!("{:?}", Self::build_command(config)), toolchain, packages };
+        let path = snapshot_path(workspace);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(&snapshot)?)
+    }
+
+    /// Reads the outputs of the build scripts and the proc-macro libraries a previous
+    /// `cargo build` or `cargo check` left in the `target` directory, with the same freshness
+    /// checks as for the snapshot.
+    fn from_target_dir(
+        config: &CargoConfig,
+        workspace: &CargoWorkspace,
+        inputs_mtime: SystemTime,
+        toolchain: &str,
+    ) -> Option<WorkspaceBuildScripts> {
+        let target_dir = workspace.target_directory();
+        if !built_by_toolchain(target_dir, toolchain) {
+            return None;
+        }
+        let mut profile_dirs = vec![target_dir.join("debug")];
+        profile_dirs.extend(config.target.as_ref().map(|it| target_dir.join(it).join("debug")));
+
+        let mut res = WorkspaceBuildScripts::default();
+        for package in workspace.packages() {
+            let package_data = &workspace[package];
+            let mut output = BuildScriptOutput::default();
+            let mut found = false;
+            for &target in &package_data.targets {
+                let target = &workspace[target];
+                if target.kind == TargetKind::BuildScript {
+                    let (dir, dir_mtime) = newest_artifact(&profile_dirs, "build", |name| {
+                        hashed_name(name, &package_data.name) == Some("")
+                    })?;
+                    let text = fs::read_to_string(dir.join("output")).ok()?;
+                    let (files, env) = parse_rerun_if(&text);
+                    let files = rerun_if_changed(workspace, package, files);
+                    // The values the variables had when the build script ran aren't recorded, so
+                    // only the ones which are still unset count as unchanged.
+                    let env: Vec<_> = env.into_iter().map(|var| (var, None)).collect();
+                    if dir_mtime < inputs_mtime || !is_fresh(&files, &env, target_dir, dir_mtime) {
+                        return None;
+                    }
+                    let (cfgs, envs) = parse_build_script_output(&text);
+                    let out_dir = dir.join("out");
+                    output.cfgs = cfgs;
+                    output.envs = envs;
+                    if let Some(out_dir) = out_dir.as_os_str().to_str() {
+                        output.envs.push(("OUT_DIR".to_string(), out_dir.to_string()));
+                    }
+                    output.out_dir = Some(out_dir);
+                    found = true;
+                }
+                if target.is_proc_macro {
+                    let lib_name = target.name.replace('-', "_");
+                    let (path, path_mtime) = newest_artifact(&profile_dirs, "deps", |name| {
+                        let name = name.strip_prefix("lib").unwrap_or(name);
+                        matches!(hashed_name(name, &lib_name), Some(".so" | ".dylib" | ".dll"))
+                    })?;
+                    // Cargo rebuilds the library when any file of the package changes.
+                    let package_dir = [package_data.manifest.parent().to_path_buf().into()];
+                    let sources = if package_data.is_local { &package_dir[..] } else { &[] };
+                    if path_mtime < inputs_mtime || !is_fresh(sources, &[], target_dir, path_mtime)
+                    {
+                        return None;
+                    }
+                    output.proc_macro_dylib_path = Some(path);
+                    found = true;
+                }
+            }
+            res.outputs.insert(package, found.then(|| output));
+        }
+        Some(res)
+    }
+
     pub(crate) fn get_output(&self, idx: Package) -> Option<&BuildScriptOutput> {
         self.outputs.get(idx)?.as_ref()
     }
 }
 
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    /// The command the outputs were produced with, as they depend on the features and target.
+    command: String,
+    /// The version of the toolchain the build scripts were compiled with.
+    toolchain: String,
+    packages: FxHashMap<String, SnapshotOutput>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotOutput {
+    cfgs: Vec<CfgFlag>,
+    envs: Vec<(String, String)>,
+    out_dir: Option<PathBuf>,
+    proc_macro_dylib_path: Option<PathBuf>,
+    /// The files and directories the build script is rerun for when they change.
+    rerun_if_changed: Vec<PathBuf>,
+    /// The environment variables the build script is rerun for, with their values at the time.
+    rerun_if_env_changed: Vec<(String, Option<String>)>,
+}
+
+impl SnapshotOutput {
+    /// Whether cargo wouldn't rerun the build script since the outputs were saved at `saved`.
+    fn is_fresh(&self, target_dir: &AbsPath, saved: SystemTime) -> bool {
+        is_fresh(&self.rerun_if_changed, &self.rerun_if_env_changed, target_dir, saved)
+    }
+}
+
+/// Whether none of `files` changed since `since`, and the environment variables still have the
+/// given values.
+fn is_fresh(
+    files: &[PathBuf],
+    env: &[(String, Option<String>)],
+    target_dir: &AbsPath,
+    since: SystemTime,
+) -> bool {
+    let files_unchanged =
+        files.iter().all(|path| newest_mtime(path, target_dir).map_or(false, |it| it <= since));
+    let env_unchanged = env.iter().all(|(var, value)| std::env::var(var).ok() == *value);
+    files_unchanged && env_unchanged
+}
+
+fn snapshot_path(workspace: &CargoWorkspace) -> AbsPathBuf {
+    workspace.target_directory().join("rust-analyzer").join("build-scripts.json")
+}
+
+fn mtime(path: impl AsRef<Path>) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|it| it.modified()).ok()
+}
+
+/// Returns the mtime of the most recently modified of `Cargo.lock`, the manifests and the build
+/// scripts of the workspace, which all build outputs must be newer than.
+fn inputs_mtime(workspace: &CargoWorkspace) -> Option<SystemTime> {
+    let mut res = mtime(workspace.workspace_root().join("Cargo.lock"))?;
+    for package in workspace.packages().filter(|&it| workspace[it].is_local) {
+        let package_data = &workspace[package];
+        let build_scripts = package_data
+            .targets
+            .iter()
+            .filter(|&&it| workspace[it].kind == TargetKind::BuildScript)
+            .map(|&it| &*workspace[it].root);
+        for path in build_scripts.chain([&*package_data.manifest]) {
+            res = res.max(mtime(path)?);
+        }
+    }
+    Some(res)
+}
+
+/// Whether the package has a build script or proc-macros, whose outputs are needed.
+fn needs_build(workspace: &CargoWorkspace, package: Package) -> bool {
+    workspace[package].targets.iter().any(|&it| {
+        let target = &workspace[it];
+        target.kind == TargetKind::BuildScript || target.is_proc_macro
+    })
+}
+
+/// Returns the mtime of the most recently modified file in `path`, or `None` if it doesn't exist.
+/// Below `path`, `target_dir` and hidden directories, like those of version control systems, are
+/// skipped, as are entries which can't be read. Like for cargo, the mtimes of directories don't
+/// count.
+fn newest_mtime(path: &Path, target_dir: &AbsPath) -> Option<SystemTime> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_dir() {
+        return metadata.modified().ok();
+    }
+    let mut res = SystemTime::UNIX_EPOCH;
+    let entries = match fs::read_dir(path) {
+        Ok(it) => it,
+        Err(_) => return Some(res),
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let is_hidden_dir = entry.file_type().map_or(false, |it| it.is_dir())
+            && entry.file_name().to_str().map_or(false, |it| it.starts_with('.'));
+        let path = entry.path();
+        if is_hidden_dir || path == target_dir.as_ref() {
+            continue;
+        }
+        if let Some(mtime) = newest_mtime(&path, target_dir) {
+            res = res.max(mtime);
+        }
+    }
+    Some(res)
+}
+
+/// Strips the `{name}-{hash}` prefix off an artifact's file name, returning the rest.
+fn hashed_name<'a>(file_name: &'a str, name: &str) -> Option<&'a str> {
+    let rest = file_name.strip_prefix(name)?.strip_prefix('-')?;
+    let (hash, rest) = rest.split_at(rest.find('.').unwrap_or(rest.len()));
+    (hash.len() == 16 && hash.bytes().all(|it| it.is_ascii_hexdigit())).then(|| rest)
+}
+
+/// Finds the most recently modified entry in the `kind` directory of the profiles whose name
+/// `matches`. In `build`, only the directories of build script runs are considered.
+fn newest_artifact(
+    profile_dirs: &[AbsPathBuf],
+    kind: &str,
+    matches: impl Fn(&str) -> bool,
+) -> Option<(AbsPathBuf, SystemTime)> {
+    profile_dirs
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir.join(kind)).ok())
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            if !matches(entry.file_name().to_str()?) {
+                return None;
+            }
+            let path = AbsPathBuf::try_from(entry.path()).ok()?;
+            let mtime = match kind {
+                // The `output` file is what a build script run leaves behind.
+                "build" => mtime(path.join("output"))?,
+                _ => mtime(&path)?,
+            };
+            Some((path, mtime))
+        })
+        .max_by_key(|(_, mtime)| *mtime)
+}
+
+/// Whether cargo last used the toolchain with the given `rustc -vV` output for `target_dir`,
+/// according to the information it caches about rustc there.
+fn built_by_toolchain(target_dir: &AbsPath, toolchain: &str) -> bool {
+    let info = match fs::read_to_string(target_dir.join(".rustc_info.json")) {
+        Ok(it) => it,
+        Err(_) => return false,
+    };
+    let info: serde_json::Value = match serde_json::from_str(&info) {
+        Ok(it) => it,
+        Err(_) => return false,
+    };
+    info["outputs"].as_object().map_or(false, |outputs| {
+        outputs.values().any(|it| it["stdout"].as_str().map(str::trim) == Some(toolchain))
+    })
+}
+
+pub(crate) fn toolchain_version(workspace: &CargoWorkspace) -> Option<String> {
+    let mut cmd = Command::new(toolchain::rustc());
+    cmd.current_dir(workspace.workspace_root()).arg("-vV");
+    utf8_stdout(cmd).ok()
+}
+
+/// Returns what the build script of `package` asked to be rerun for in the `output` file cargo
+/// keeps next to its `OUT_DIR`, with the current values of the environment variables.
+fn rerun_if(
+    workspace: &CargoWorkspace,
+    package: Package,
+    output: &BuildScriptOutput,
+) -> (Vec<PathBuf>, Vec<(String, Option<String>)>) {
+    let text = match &output.out_dir {
+        Some(out_dir) => out_dir
+            .parent()
+            .and_then(|dir| fs::read_to_string(dir.join("output")).ok())
+            .unwrap_or_default(),
+        None => return (Vec::new(), Vec::new()),
+    };
+    let (files, env) = parse_rerun_if(&text);
+    let files = rerun_if_changed(workspace, package, files);
+    let env = env.into_iter().map(|var| (var.clone(), std::env::var(var).ok())).collect();
+    (files, env)
+}
+
+/// Resolves the `rerun-if-changed` paths of the build script of `package`.
+fn rerun_if_changed(
+    workspace: &CargoWorkspace,
+    package: Package,
+    files: Vec<String>,
+) -> Vec<PathBuf> {
+    let package_dir = workspace[package].manifest.parent();
+    let mut files: Vec<PathBuf> = files.into_iter().map(|it| package_dir.join(it).into()).collect();
+    // Without `rerun-if-changed`, cargo reruns build scripts when any file of the package changes.
+    // Packages from registries don't change, though.
+    if files.is_empty() && workspace[package].is_local {
+        files.push(package_dir.to_path_buf().into());
+    }
+    files
+}
+
+/// Parses the `cargo:` instructions a build script printed, returning the cfgs and environment
+/// variables it set.
+pub(crate) fn parse_build_script_output(text: &str) -> (Vec<CfgFlag>, Vec<(String, String)>) {
+    let mut cfgs = Vec::new();
+    let mut envs = Vec::new();
+    for line in text.lines() {
+        let instruction = match line.strip_prefix("cargo::").or_else(|| line.strip_prefix("cargo:"))
+        {
+            Some(it) => it,
+            None => continue,
+        };
+        if let Some(cfg) = instruction.strip_prefix("rustc-cfg=") {
+            match cfg.parse() {
+                Ok(it) => cfgs.push(it),
+                Err(err) => tracing::error!("invalid cfg from build script output: {}", err),
+            }
+        } else if let Some(env) = instruction.strip_prefix("rustc-env=") {
+            if let Some((key, value)) = env.split_once('=') {
+                envs.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+    (cfgs, envs)
+}
+
+/// Parses the `rerun-if-changed` and `rerun-if-env-changed` instructions a build script printed.
+pub(crate) fn parse_rerun_if(text: &str) -> (Vec<String>, Vec<String>) {
+    let mut files = Vec::new();
+    let mut env = Vec::new();
+    for line in text.lines() {
+        let instruction = match line.strip_prefix("cargo::").or_else(|| line.strip_prefix("cargo:"))
+        {
+            Some(it) => it,
+            None => continue,
+        };
+        if let Some(path) = instruction.strip_prefix("rerun-if-changed=") {
+            files.push(path.to_string());
+        } else if let Some(var) = instruction.strip_prefix("rerun-if-env-changed=") {
+            env.push(var.to_string());
+        }
+    }
+    (files, env)
+}
+
 // FIXME: File a better way to know if it is a dylib.
 fn is_dylib(path: &Utf8Path) -> bool {
     match path.extension().map(|e| e.to_string().to_lowercase()) {
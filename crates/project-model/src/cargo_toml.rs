@@ -0,0 +1,290 @@
+//! A small analysis of the `Cargo.toml` of workspace members, on top of the
+//! resolved [`CargoWorkspace`].
+//!
+//! The dependency tables are parsed with `toml`, whose [`Spanned`] values give
+//! us the ranges of dependency names and features.
+
+use std::{collections::BTreeMap, fmt, iter, ops::Range};
+
+use paths::AbsPath;
+use rustc_hash::FxHashSet;
+use serde::{
+    de::{self, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use toml::Spanned;
+
+use crate::{
+    cargo_workspace::{DepKind, TargetKind},
+    CargoWorkspace, Package,
+};
+
+/// A dependency as written in a `Cargo.toml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestDependency {
+    /// The key of the dependency, which is the (possibly renamed) name it is imported by.
+    pub name: String,
+    pub name_range: Range<usize>,
+    pub kind: DepKind,
+    /// The features enabled explicitly, with their ranges.
+    pub features: Vec<(String, Range<usize>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestDiagnostic {
+    pub range: Range<usize>,
+    pub kind: ManifestDiagnosticKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestDiagnosticKind {
+    UnknownFeature,
+    UnusedDependency,
+}
+
+/// The dependencies the crates of a package refer to, by the names they are imported by.
+#[derive(Debug, Default, Clone)]
+pub struct UsedDependencies {
+    /// Those the build script refers to.
+    pub build_script: FxHashSet<String>,
+    /// Those the other targets refer to.
+    pub targets: FxHashSet<String>,
+}
+
+/// The `Cargo.toml` of a workspace member.
+pub struct CargoToml<'a> {
+    cargo: &'a CargoWorkspace,
+    pkg: Package,
+    dependencies: Vec<ManifestDependency>,
+}
+
+impl<'a> CargoToml<'a> {
+    /// Returns the manifest at `path`, if it belongs to a member of `cargo`.
+    pub fn new(cargo: &'a CargoWorkspace, path: &AbsPath, text: &str) -> Option<CargoToml<'a>> {
+        let pkg =
+            cargo.packages().find(|&pkg| cargo[pkg].is_member && *cargo[pkg].manifest == *path)?;
+        Some(CargoToml { cargo, pkg, dependencies: parse_dependencies(text) })
+    }
+
+    /// Returns the crate roots of the targets of the package, with their kinds.
+    pub fn targets(&self) -> impl Iterator<Item = (TargetKind, &'a AbsPath)> + '_ {
+        let cargo = self.cargo;
+        cargo[self.pkg].targets.iter().map(move |&it| (cargo[it].kind, &*cargo[it].root))
+    }
+
+    /// Returns the package `dep` resolved to, if it is enabled.
+    pub fn resolve(&self, dep: &ManifestDependency) -> Option<Package> {
+        let name = dep.name.replace('-', "_");
+        let deps = &self.cargo[self.pkg].dependencies;
+        deps.iter()
+            .find(|it| it.name == name && it.kind == dep.kind)
+            .or_else(|| deps.iter().find(|it| it.name == name))
+            .map(|it| it.pkg)
+    }
+
+    /// Describes the dependency or the dependency feature at `offset` as markdown.
+    pub fn hover(&self, offset: usize) -> Option<(Range<usize>, String)> {
+        for dep in &self.dependencies {
+            let pkg = match self.resolve(dep) {
+                Some(it) => &self.cargo[it],
+                None => continue,
+            };
+            if contains(&dep.name_range, offset) {
+                let mut markup = format!("**{}** `{}`", pkg.name, pkg.version);
+                if let Some(repository) = &pkg.repository {
+                    markup.push_str(&format!("\n\n{}", repository));
+                }
+                let mut features: Vec<_> = pkg.active_features.iter().collect();
+                features.sort_unstable();
+                if features.is_empty() {
+                    markup.push_str("\n\nNo features enabled");
+                } else {
+                    let features: Vec<_> = features.iter().map(|it| format!("`{}`", it)).collect();
+                    markup.push_str(&format!("\n\nEnabled features: {}", features.join(", ")));
+                }
+                return Some((dep.name_range.clone(), markup));
+            }
+            if let Some((feature, range)) =
+                dep.features.iter().find(|(_, range)| contains(range, offset))
+            {
+                let enables = pkg.features.get(feature)?;
+                let enables: Vec<_> = enables.iter().map(|it| format!("`{}`", it)).collect();
+                let markup = if enables.is_empty() {
+                    format!("Feature `{}` of **{}**", feature, pkg.name)
+                } else {
+                    format!(
+                        "Feature `{}` of **{}**, enables {}",
+                        feature,
+                        pkg.name,
+                        enables.join(", ")
+                    )
+                };
+                return Some((range.clone(), markup));
+            }
+        }
+        None
+    }
+
+    /// Returns the crate root of the dependency whose name is at `offset`.
+    pub fn definition(&self, offset: usize) -> Option<(Range<usize>, &'a AbsPath)> {
+        let dep = self.dependencies.iter().find(|dep| contains(&dep.name_range, offset))?;
+        let pkg = self.resolve(dep)?;
+        let cargo = self.cargo;
+        let lib = cargo[pkg].targets.iter().find(|&&it| cargo[it].kind == TargetKind::Lib)?;
+        Some((dep.name_range.clone(), &cargo[*lib].root))
+    }
+
+    /// Reports features the dependencies don't have, and, if the `used` dependencies are known,
+    /// dependencies the targets which can use them don't refer to.
+    pub fn diagnostics(&self, used: Option<&UsedDependencies>) -> Vec<ManifestDiagnostic> {
+        let mut res = Vec::new();
+        for dep in &self.dependencies {
+            let pkg = match self.resolve(dep) {
+                Some(it) => &self.cargo[it],
+                None => continue,
+            };
+            for (feature, range) in &dep.features {
+                if !pkg.features.contains_key(feature) {
+                    res.push(ManifestDiagnostic {
+                        range: range.clone(),
+                        kind: ManifestDiagnosticKind::UnknownFeature,
+                        message: format!("`{}` has no feature `{}`", pkg.name, feature),
+                    });
+                }
+            }
+            let used = match used {
+                Some(it) if dep.kind == DepKind::Build => &it.build_script,
+                Some(it) => &it.targets,
+                None => continue,
+            };
+            if !used.contains(&dep.name.replace('-', "_")) {
+                res.push(ManifestDiagnostic {
+                    range: dep.name_range.clone(),
+                    kind: ManifestDiagnosticKind::UnusedDependency,
+                    message: format!("unused dependency `{}`", dep.name),
+                });
+            }
+        }
+        res
+    }
+}
+
+/// Finds the dependencies declared in the `Cargo.toml` with the given text.
+pub fn parse_dependencies(text: &str) -> Vec<ManifestDependency> {
+    let manifest = match toml::from_str::<Manifest>(text) {
+        Ok(it) => it,
+        Err(err) => {
+            tracing::debug!("failed to parse Cargo.toml: {}", err);
+            return Vec::new();
+        }
+    };
+    let mut res = Vec::new();
+    let tables = DependencyTables {
+        dependencies: manifest.dependencies,
+        dev_dependencies: manifest.dev_dependencies,
+        build_dependencies: manifest.build_dependencies,
+    };
+    for tables in iter::once(tables).chain(manifest.target.into_values()) {
+        for (kind, deps) in [
+            (DepKind::Normal, tables.dependencies),
+            (DepKind::Dev, tables.dev_dependencies),
+            (DepKind::Build, tables.build_dependencies),
+        ] {
+            res.extend(deps.into_iter().map(|(name, dep)| {
+                ManifestDependency {
+                    name_range: unquoted_range(text, name.span()),
+                    name: name.into_inner(),
+                    kind,
+                    features: dep
+                        .features
+                        .into_iter()
+                        .map(|it| {
+                            let range = unquoted_range(text, it.span());
+                            (it.into_inner(), range)
+                        })
+                        .collect(),
+                }
+            }));
+        }
+    }
+    res.sort_by_key(|dep| dep.name_range.start);
+    res
+}
+
+// `#[serde(flatten)]` would lose the spans, so the tables are repeated here.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Manifest {
+    #[serde(default)]
+    dependencies: BTreeMap<Spanned<String>, Dependency>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<Spanned<String>, Dependency>,
+    #[serde(default)]
+    build_dependencies: BTreeMap<Spanned<String>, Dependency>,
+    #[serde(default)]
+    target: BTreeMap<String, DependencyTables>,
+}
+
+/// The dependency tables of a `[target.'cfg(..)']` table.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct DependencyTables {
+    #[serde(default)]
+    dependencies: BTreeMap<Spanned<String>, Dependency>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<Spanned<String>, Dependency>,
+    #[serde(default)]
+    build_dependencies: BTreeMap<Spanned<String>, Dependency>,
+}
+
+/// A dependency, either given by its version only or as a table.
+#[derive(Default)]
+struct Dependency {
+    features: Vec<Spanned<String>>,
+}
+
+impl<'de> Deserialize<'de> for Dependency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Dependency, D::Error> {
+        struct DependencyVisitor;
+
+        impl<'de> Visitor<'de> for DependencyVisitor {
+            type Value = Dependency;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a version string or a dependency table")
+            }
+
+            fn visit_str<E: de::Error>(self, _version: &str) -> Result<Dependency, E> {
+                Ok(Dependency::default())
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Dependency, A::Error> {
+                #[derive(Deserialize)]
+                struct Detailed {
+                    #[serde(default)]
+                    features: Vec<Spanned<String>>,
+                }
+                let Detailed { features } =
+                    Detailed::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(Dependency { features })
+            }
+        }
+
+        deserializer.deserialize_any(DependencyVisitor)
+    }
+}
+
+/// Returns the range of the key or string at `span`, without its quotes.
+fn unquoted_range(text: &str, (start, end): (usize, usize)) -> Range<usize> {
+    match text.get(start..end) {
+        Some(it) if it.len() >= 2 && (it.starts_with(['"', '\'']) && it.ends_with(['"', '\''])) => {
+            start + 1..end - 1
+        }
+        _ => start..end,
+    }
+}
+
+fn contains(range: &Range<usize>, offset: usize) -> bool {
+    range.start <= offset && offset <= range.end
+}
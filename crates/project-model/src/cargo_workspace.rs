@@ -1,7 +1,7 @@
 //! See [`CargoWorkspace`].
 
-use std::iter;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::{fs, iter};
 use std::{ops, process::Command};
 
 use anyhow::{Context, Result};
@@ -31,6 +31,9 @@ pub struct CargoWorkspace {
     packages: Arena<PackageData>,
     targets: Arena<TargetData>,
     workspace_root: AbsPathBuf,
+    target_directory: AbsPathBuf,
+    /// Why the dependencies couldn't be resolved, if the workspace was loaded without them.
+    missing_deps: Option<String>,
 }
 
 impl ops::Index<Package> for CargoWorkspace {
@@ -85,6 +88,9 @@ pub struct CargoConfig {
     /// rustc target
     pub target: Option<String>,
 
+    /// Additional targets to analyze the workspace members for
+    pub extra_targets: Vec<String>,
+
     /// Don't load sysroot crates (`std`, `core` & friends). Might be useful
     /// when debugging isolated issues.
     pub no_sysroot: bool,
@@ -92,12 +98,22 @@ pub struct CargoConfig {
     /// rustc private crate source
     pub rustc_source: Option<RustcSource>,
 
+    /// Source of the standard library to use instead of the one of the discovered sysroot.
+    pub sysroot_src: Option<AbsPathBuf>,
+
     /// crates to disable `#[cfg(test)]` on
     pub unset_test_crates: UnsetTestCrates,
 
     pub wrap_rustc_in_build_scripts: bool,
 
     pub run_build_script_command: Option<Vec<String>>,
+
+    /// Reuse the build script outputs of the last run, if they are still fresh.
+    pub reuse_build_scripts: bool,
+
+    /// Never access the network when loading the workspace: resolve dependencies from
+    /// `Cargo.lock`, falling back to a `vendor/` directory and then to no dependencies at all.
+    pub offline: bool,
 }
 
 impl CargoConfig {
@@ -154,6 +170,10 @@ pub struct PackageData {
     pub id: String,
     /// The contents of [package.metadata.rust-analyzer]
     pub metadata: RustAnalyzerPackageMetaData,
+    /// `package.forced-target` from the `Cargo.toml`
+    pub forced_target: Option<String>,
+    /// `package.default-target` from the `Cargo.toml`
+    pub default_target: Option<String>,
 }
 
 #[derive(Deserialize, Default, Debug, Clone, Eq, PartialEq)]
@@ -168,7 +188,7 @@ pub struct PackageDependency {
     pub kind: DepKind,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
 pub enum DepKind {
     /// Available to the library, binary, and dev targets in the package (but not the build script).
     Normal,
@@ -254,12 +274,17 @@ struct PackageMetadata {
 }
 
 impl CargoWorkspace {
+    /// Runs `cargo metadata` for `cargo_toml`.
+    ///
+    /// In offline mode, `Cargo.lock` is only updated if it is out of date, and vendored
+    /// dependencies are used if they can't be resolved otherwise. If that fails too, the workspace
+    /// members are loaded without them, and the error is returned alongside the metadata.
     pub fn fetch_metadata(
         cargo_toml: &ManifestPath,
         current_dir: &AbsPath,
         config: &CargoConfig,
         progress: &dyn Fn(String),
-    ) -> Result<cargo_metadata::Metadata> {
+    ) -> Result<(cargo_metadata::Metadata, Option<String>)> {
         let target = config
             .target
             .clone()
@@ -283,19 +308,81 @@ impl CargoWorkspace {
         }
         meta.current_dir(current_dir.as_os_str());
 
+        let mut other_options = Vec::new();
         if let Some(target) = target {
-            meta.other_options(vec![String::from("--filter-platform"), target]);
+            other_options.extend([String::from("--filter-platform"), target]);
+        }
+        if config.offline {
+            other_options.push(String::from("--offline"));
         }
+        meta.other_options(other_options.clone());
 
         // FIXME: Fetching metadata is a slow process, as it might require
         // calling crates.io. We should be reporting progress here, but it's
         // unclear whether cargo itself supports it.
         progress("metadata".to_string());
 
-        let meta =
-            meta.exec().with_context(|| format!("Failed to run `{:?}`", meta.cargo_command()))?;
+        let exec = |meta: &MetadataCommand| {
+            meta.exec().with_context(|| format!("Failed to run `{:?}`", meta.cargo_command()))
+        };
+        if !config.offline {
+            return exec(&meta).map(|it| (it, None));
+        }
 
-        Ok(meta)
+        let mut locked = meta.clone();
+        locked.other_options(
+            other_options.iter().cloned().chain([String::from("--locked")]).collect::<Vec<_>>(),
+        );
+        let err = match exec(&locked) {
+            Ok(it) => return Ok((it, None)),
+            Err(err) => err,
+        };
+        // `--locked` fails if `Cargo.lock` is out of date, which doesn't need the network to fix.
+        tracing::info!(
+            "failed to load the workspace with `--locked`, updating Cargo.lock: {:?}",
+            err
+        );
+        let err = match exec(&meta) {
+            Ok(it) => return Ok((it, None)),
+            Err(err) => err,
+        };
+
+        progress("metadata (no dependencies)".to_string());
+        let mut no_deps = meta.clone();
+        no_deps.no_deps();
+        let members = exec(&no_deps)?;
+
+        let workspace_root = members.workspace_root.as_std_path();
+        let vendor = iter::successors(Some(cargo_toml.parent().as_ref()), |it| it.parent())
+            .take_while(|it| it.starts_with(workspace_root))
+            .map(|it| it.join("vendor"))
+            .find(|it| fs::metadata(it).map_or(false, |it| it.is_dir()));
+        if let Some(vendor) = vendor {
+            progress("metadata (vendored)".to_string());
+            let mut vendored = meta.clone();
+            vendored.other_options(
+                other_options
+                    .iter()
+                    .cloned()
+                    .chain([
+                        "--config".to_string(),
+                        "source.crates-io.replace-with=\"vendored-sources\"".to_string(),
+                        "--config".to_string(),
+                        format!(
+                            "source.vendored-sources.directory={:?}",
+                            vendor.display().to_string()
+                        ),
+                    ])
+                    .collect::<Vec<_>>(),
+            );
+            match exec(&vendored) {
+                Ok(it) => return Ok((it, None)),
+                Err(err) => tracing::warn!("failed to resolve vendored dependencies: {:?}", err),
+            }
+        }
+
+        tracing::warn!("loading the workspace without dependencies: {:?}", err);
+        Ok((members, Some(format!("{:#}", err))))
     }
 
     pub fn new(mut meta: cargo_metadata::Metadata) -> CargoWorkspace {
@@ -326,6 +413,15 @@ impl CargoWorkspace {
             // the current workspace, as well as any path dependency outside the workspace.
             let is_local = meta_pkg.source.is_none();
             let is_member = ws_members.contains(id);
+            // `cargo metadata` doesn't report per-package targets, so read them from the manifest.
+            // Only local packages are checked, to avoid reading the manifest of every dependency.
+            let (forced_target, default_target) = if is_local {
+                fs::read_to_string(manifest_path)
+                    .map(|text| package_targets(&text))
+                    .unwrap_or_default()
+            } else {
+                (None, None)
+            };
 
             let pkg = packages.alloc(PackageData {
                 id: id.repr.clone(),
@@ -341,6 +437,8 @@ impl CargoWorkspace {
                 features: meta_pkg.features.clone().into_iter().collect(),
                 active_features: Vec::new(),
                 metadata: meta.rust_analyzer.unwrap_or_default(),
+                forced_target,
+                default_target,
             });
             let pkg_data = &mut packages[pkg];
             pkg_by_id.insert(id, pkg);
@@ -357,8 +455,38 @@ impl CargoWorkspace {
                 pkg_data.targets.push(tgt);
             }
         }
-        let resolve = meta.resolve.expect("metadata executed with deps");
-        for mut node in resolve.nodes {
+        let nodes = match meta.resolve {
+            Some(resolve) => resolve.nodes,
+            None => {
+                // Without a resolve (`--no-deps`), link the path dependencies between the loaded
+                // packages, which is the best we can do.
+                for meta_pkg in &meta.packages {
+                    let from = pkg_by_id[&meta_pkg.id];
+                    for dep in &meta_pkg.dependencies {
+                        let path = match &dep.path {
+                            Some(it) => it,
+                            None => continue,
+                        };
+                        let to = packages
+                            .iter()
+                            .find(|(_, it)| it.manifest.parent().as_ref() == path.as_std_path());
+                        let to = match to {
+                            Some((to, _)) => to,
+                            None => continue,
+                        };
+                        let kind = match dep.kind {
+                            cargo_metadata::DependencyKind::Development => DepKind::Dev,
+                            cargo_metadata::DependencyKind::Build => DepKind::Build,
+                            _ => DepKind::Normal,
+                        };
+                        let name = dep.rename.as_ref().unwrap_or(&dep.name).replace('-', "_");
+                        packages[from].dependencies.push(PackageDependency { name, pkg: to, kind });
+                    }
+                }
+                Vec::new()
+            }
+        };
+        for mut node in nodes {
             let source = match pkg_by_id.get(&node.id) {
                 Some(&src) => src,
                 // FIXME: replace this and a similar branch below with `.unwrap`, once
@@ -393,8 +521,10 @@ impl CargoWorkspace {
 
         let workspace_root =
             AbsPathBuf::assert(PathBuf::from(meta.workspace_root.into_os_string()));
+        let target_directory =
+            AbsPathBuf::assert(PathBuf::from(meta.target_directory.into_os_string()));
 
-        CargoWorkspace { packages, targets, workspace_root }
+        CargoWorkspace { packages, targets, workspace_root, target_directory, missing_deps: None }
     }
 
     pub fn packages<'a>(&'a self) -> impl Iterator<Item = Package> + ExactSizeIterator + 'a {
@@ -412,6 +542,19 @@ impl CargoWorkspace {
         &self.workspace_root
     }
 
+    pub fn target_directory(&self) -> &AbsPath {
+        &self.target_directory
+    }
+
+    /// Returns why the dependencies are missing, if the workspace was loaded without them.
+    pub fn missing_deps(&self) -> Option<&str> {
+        self.missing_deps.as_deref()
+    }
+
+    pub(crate) fn set_missing_deps(&mut self, error: Option<String>) {
+        self.missing_deps = error;
+    }
+
     pub fn package_flag(&self, package: &PackageData) -> String {
         if self.is_unique(&*package.name) {
             package.name.clone()
@@ -458,7 +601,7 @@ impl CargoWorkspace {
     }
 }
 
-fn rustc_discover_host_triple(cargo_toml: &ManifestPath) -> Option<String> {
+pub(crate) fn rustc_discover_host_triple(cargo_toml: &ManifestPath) -> Option<String> {
     let mut rustc = Command::new(toolchain::rustc());
     rustc.current_dir(cargo_toml.parent()).arg("-vV");
     tracing::debug!("Discovering host platform by {:?}", rustc);
@@ -481,7 +624,7 @@ fn rustc_discover_host_triple(cargo_toml: &ManifestPath) -> Option<String> {
     }
 }
 
-fn cargo_config_build_target(cargo_toml: &ManifestPath) -> Option<String> {
+pub(crate) fn cargo_config_build_target(cargo_toml: &ManifestPath) -> Option<String> {
     let mut cargo_config = Command::new(toolchain::cargo());
     cargo_config
         .current_dir(cargo_toml.parent())
@@ -497,3 +640,60 @@ fn cargo_config_build_target(cargo_toml: &ManifestPath) -> Option<String> {
         Err(_) => None,
     }
 }
+
+/// Returns the crates `-Zbuild-std` builds, as configured by `unstable.build-std` in the cargo
+/// config files of the workspace. The most specific config file that sets it wins.
+pub(crate) fn cargo_config_build_std(cargo_toml: &ManifestPath) -> Option<Vec<String>> {
+    let workspace_dir: &Path = cargo_toml.parent().as_ref();
+    workspace_dir
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(home::cargo_home().ok())
+        .flat_map(|dir| [dir.join("config"), dir.join("config.toml")])
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .find_map(|config| parse_build_std(&config))
+}
+
+pub(crate) fn parse_build_std(config: &str) -> Option<Vec<String>> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct Unstable {
+        build_std: Option<Vec<String>>,
+    }
+    #[derive(Deserialize)]
+    struct Config {
+        unstable: Option<Unstable>,
+    }
+
+    let crates = match toml::from_str::<Config>(config) {
+        Ok(config) => config.unstable?.build_std?,
+        Err(err) => {
+            tracing::warn!("failed to parse cargo config: {}", err);
+            return None;
+        }
+    };
+    (!crates.is_empty()).then(|| crates)
+}
+
+/// Extracts `forced-target` and `default-target` from the `[package]` table of a `Cargo.toml`.
+pub(crate) fn package_targets(manifest: &str) -> (Option<String>, Option<String>) {
+    #[derive(Deserialize, Default)]
+    #[serde(rename_all = "kebab-case")]
+    struct Package {
+        forced_target: Option<String>,
+        default_target: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct Manifest {
+        #[serde(default)]
+        package: Package,
+    }
+
+    match toml::from_str::<Manifest>(manifest) {
+        Ok(Manifest { package }) => (package.forced_target, package.default_target),
+        Err(err) => {
+            tracing::warn!("failed to parse Cargo.toml: {}", err);
+            (None, None)
+        }
+    }
+}
@@ -3,7 +3,7 @@
 //! rustc main.rs --cfg foo --cfg 'feature="bar"'
 use std::{fmt, str::FromStr};
 
-use cfg::CfgOptions;
+use cfg::{CfgAtom, CfgOptions};
 
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub enum CfgFlag {
@@ -38,6 +38,20 @@ impl<'de> serde::Deserialize<'de> for CfgFlag {
     }
 }
 
+impl serde::Serialize for CfgFlag {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            CfgFlag::Atom(atom) => serializer.serialize_str(atom),
+            CfgFlag::KeyValue { key, value } => {
+                serializer.serialize_str(&format!("{}=\"{}\"", key, value))
+            }
+        }
+    }
+}
+
 impl Extend<CfgFlag> for CfgOptions {
     fn extend<T: IntoIterator<Item = CfgFlag>>(&mut self, iter: T) {
         for cfg_flag in iter {
@@ -49,6 +63,17 @@ impl Extend<CfgFlag> for CfgOptions {
     }
 }
 
+impl From<CfgFlag> for CfgAtom {
+    fn from(cfg_flag: CfgFlag) -> CfgAtom {
+        match cfg_flag {
+            CfgFlag::Atom(it) => CfgAtom::Flag(it.into()),
+            CfgFlag::KeyValue { key, value } => {
+                CfgAtom::KeyValue { key: key.into(), value: value.into() }
+            }
+        }
+    }
+}
+
 impl fmt::Display for CfgFlag {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -17,6 +17,7 @@
 
 mod manifest_path;
 mod cargo_workspace;
+mod cargo_toml;
 mod cfg_flag;
 mod project_json;
 mod sysroot;
@@ -39,12 +40,17 @@ use rustc_hash::FxHashSet;
 
 pub use crate::{
     build_scripts::WorkspaceBuildScripts,
+    cargo_toml::{
+        parse_dependencies, CargoToml, ManifestDependency, ManifestDiagnostic,
+        ManifestDiagnosticKind, UsedDependencies,
+    },
     cargo_workspace::{
-        CargoConfig, CargoWorkspace, Package, PackageData, PackageDependency, RustcSource, Target,
-        TargetData, TargetKind, UnsetTestCrates,
+        CargoConfig, CargoWorkspace, DepKind, Package, PackageData, PackageDependency, RustcSource,
+        Target, TargetData, TargetKind, UnsetTestCrates,
     },
     manifest_path::ManifestPath,
-    project_json::{ProjectJson, ProjectJsonData},
+    project_json::{ProjectJson, ProjectJsonData, Runnable, RunnableKind},
+    rustc_cfg::TargetCfgs,
     sysroot::Sysroot,
     workspace::{CfgOverrides, PackageRoot, ProjectWorkspace},
 };
@@ -5,14 +5,15 @@
 //! system to generate `rust-project.json` which can be ingested by
 //! rust-analyzer.
 
-use std::path::PathBuf;
+use std::{path::PathBuf, process::Command};
 
+use anyhow::{format_err, Context, Result};
 use base_db::{CrateDisplayName, CrateId, CrateName, Dependency, Edition};
 use paths::{AbsPath, AbsPathBuf};
 use rustc_hash::FxHashMap;
 use serde::{de, Deserialize};
 
-use crate::cfg_flag::CfgFlag;
+use crate::{cfg_flag::CfgFlag, utf8_stdout};
 
 /// Roots and crates that compose this Rust project.
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -40,6 +41,53 @@ pub struct Crate {
     pub(crate) exclude: Vec<AbsPathBuf>,
     pub(crate) is_proc_macro: bool,
     pub(crate) repository: Option<String>,
+    /// Build script output directory, exposed to the crate as `OUT_DIR`.
+    pub(crate) out_dir: Option<AbsPathBuf>,
+    /// Flags the crate is compiled with; only `--cfg` flags are taken into account.
+    pub(crate) rustc_flags: Vec<String>,
+    pub(crate) runnables: Vec<Runnable>,
+    /// Sources of the sysroot this crate is built against, instead of the project's one.
+    pub(crate) sysroot_src: Option<AbsPathBuf>,
+}
+
+/// A command template to check, run or test a crate with.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Runnable {
+    pub kind: RunnableKind,
+    pub program: String,
+    /// Arguments, in which `{test_id}` is replaced by the path of the test to run.
+    pub args: Vec<String>,
+    pub cwd: AbsPathBuf,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RunnableKind {
+    Check,
+    Run,
+    Test,
+}
+
+impl Crate {
+    /// Cfgs set with `--cfg` in the crate's rustc flags.
+    pub(crate) fn flag_cfgs(&self) -> impl Iterator<Item = CfgFlag> + '_ {
+        let mut flags = self.rustc_flags.iter();
+        std::iter::from_fn(move || loop {
+            let flag = flags.next()?;
+            let cfg = match flag.strip_prefix("--cfg") {
+                Some("") => flags.next()?,
+                Some(it) => match it.strip_prefix('=') {
+                    Some(it) => it,
+                    None => continue,
+                },
+                None => continue,
+            };
+            match cfg.parse() {
+                Ok(it) => return Some(it),
+                Err(err) => tracing::error!("invalid `--cfg` flag {:?}: {}", cfg, err),
+            }
+        })
+    }
 }
 
 impl ProjectJson {
@@ -101,11 +149,41 @@ impl ProjectJson {
                         exclude,
                         is_proc_macro: crate_data.is_proc_macro,
                         repository: crate_data.repository,
+                        out_dir: crate_data.out_dir.map(|it| base.join(it).normalize()),
+                        rustc_flags: crate_data.rustc_flags,
+                        runnables: crate_data
+                            .runnables
+                            .into_iter()
+                            .map(|it| Runnable {
+                                kind: it.kind,
+                                program: it.program,
+                                args: it.args,
+                                cwd: base.join(it.cwd.unwrap_or_default()).normalize(),
+                            })
+                            .collect(),
+                        sysroot_src: crate_data.sysroot_src.map(|it| base.join(it).normalize()),
                     }
                 })
                 .collect::<Vec<_>>(),
         }
     }
+
+    /// Runs an external project discovery command for `file`, which must print the
+    /// `rust-project.json` data of the project `file` belongs to to stdout.
+    ///
+    /// The path of `file` is passed as the last argument, and the command is run in `root`, which
+    /// relative paths in the output are resolved against.
+    pub fn discover(command: &[String], file: &AbsPath, root: &AbsPath) -> Result<ProjectJson> {
+        let (program, args) =
+            command.split_first().ok_or_else(|| format_err!("empty project discovery command"))?;
+        let mut cmd = Command::new(program);
+        cmd.args(args).arg(file.as_os_str()).current_dir(root);
+        let stdout = utf8_stdout(cmd)?;
+        let data: ProjectJsonData = serde_json::from_str(&stdout).with_context(|| {
+            format!("failed to deserialize project discovery output for {}", file.display())
+        })?;
+        Ok(ProjectJson::new(root, data))
+    }
     /// Returns the number of crates in the project.
     pub fn n_crates(&self) -> usize {
         self.crates.len()
@@ -114,6 +192,19 @@ impl ProjectJson {
     pub fn crates(&self) -> impl Iterator<Item = (CrateId, &Crate)> + '_ {
         self.crates.iter().enumerate().map(|(idx, krate)| (CrateId(idx as u32), krate))
     }
+    /// Returns the `kind` command template of the crate whose root module is `root_module`.
+    pub fn runnable(&self, root_module: &AbsPath, kind: RunnableKind) -> Option<&Runnable> {
+        let krate = self.crates.iter().find(|krate| *krate.root_module == *root_module)?;
+        krate.runnables.iter().find(|it| it.kind == kind)
+    }
+    /// Returns the `kind` command templates of the workspace member crates.
+    pub fn member_runnables(&self, kind: RunnableKind) -> impl Iterator<Item = &Runnable> + '_ {
+        self.crates
+            .iter()
+            .filter(|krate| krate.is_workspace_member)
+            .flat_map(|krate| &krate.runnables)
+            .filter(move |it| it.kind == kind)
+    }
     /// Returns the path to the project's root folder.
     pub fn path(&self) -> &AbsPath {
         &self.project_root
@@ -146,6 +237,21 @@ struct CrateData {
     is_proc_macro: bool,
     #[serde(default)]
     repository: Option<String>,
+    out_dir: Option<PathBuf>,
+    #[serde(default)]
+    rustc_flags: Vec<String>,
+    #[serde(default)]
+    runnables: Vec<RunnableData>,
+    sysroot_src: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RunnableData {
+    kind: RunnableKind,
+    program: String,
+    #[serde(default)]
+    args: Vec<String>,
+    cwd: Option<PathBuf>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
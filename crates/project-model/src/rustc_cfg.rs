@@ -3,8 +3,176 @@
 use std::process::Command;
 
 use anyhow::Result;
+use rustc_hash::FxHashMap;
 
-use crate::{cfg_flag::CfgFlag, utf8_stdout, ManifestPath};
+use crate::{
+    cargo_workspace::{rustc_discover_host_triple, DepKind},
+    cfg_flag::CfgFlag,
+    utf8_stdout, CargoWorkspace, ManifestPath, Package, PackageData,
+};
+
+/// The cfg flags of every target triple a Cargo workspace is compiled for.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TargetCfgs {
+    /// The target passed via `--target` or set as `build.target`, if any.
+    pub(crate) target: Option<String>,
+    /// Cfgs of the target crates are compiled for, unless they say otherwise.
+    pub(crate) default: Vec<CfgFlag>,
+    /// Cfgs of the host, which build scripts and proc-macros are compiled for.
+    pub(crate) host: Vec<CfgFlag>,
+    /// Cfgs of the targets requested by packages via `forced-target` or `default-target`.
+    pub(crate) per_package: FxHashMap<String, Vec<CfgFlag>>,
+    /// The triple of the default target, only known if there are extra targets.
+    pub(crate) default_triple: Option<String>,
+    /// Cfgs of the additional targets workspace members are analyzed for.
+    pub(crate) extra: Vec<(String, Vec<CfgFlag>)>,
+}
+
+impl TargetCfgs {
+    pub(crate) fn load<'a>(
+        cargo_toml: &ManifestPath,
+        target: Option<String>,
+        extra_targets: &[String],
+        packages: impl Iterator<Item = &'a PackageData>,
+    ) -> TargetCfgs {
+        let default = get(Some(cargo_toml), target.as_deref());
+        let host = match &target {
+            // Without an explicit target everything is compiled for the host.
+            None => default.clone(),
+            Some(_) => get(None, None),
+        };
+        let extra: Vec<_> = extra_targets
+            .iter()
+            .filter(|&it| Some(it) != target.as_ref())
+            .map(|it| (it.clone(), get(Some(cargo_toml), Some(it))))
+            .collect();
+        let default_triple = match &target {
+            _ if extra.is_empty() => None,
+            Some(target) => Some(target.clone()),
+            None => rustc_discover_host_triple(cargo_toml),
+        };
+        let mut res = TargetCfgs {
+            target,
+            default,
+            host,
+            per_package: FxHashMap::default(),
+            default_triple,
+            extra,
+        };
+        for pkg in packages {
+            if let Some(pkg_target) = res.package_target(pkg) {
+                if !res.per_package.contains_key(pkg_target) {
+                    let cfgs = get(Some(cargo_toml), Some(pkg_target));
+                    res.per_package.insert(pkg_target.to_owned(), cfgs);
+                }
+            }
+        }
+        res
+    }
+
+    /// Returns the target `pkg` asks to be compiled for, if it differs from the default one.
+    ///
+    /// Like Cargo, `forced-target` always wins, while `default-target` only applies if no target
+    /// was requested explicitly.
+    pub(crate) fn package_target<'a>(&'a self, pkg: &'a PackageData) -> Option<&'a str> {
+        match (&pkg.forced_target, &self.target) {
+            (Some(target), _) => Some(target),
+            (None, Some(_)) => None,
+            (None, None) => pkg.default_target.as_deref(),
+        }
+    }
+
+    /// Returns what each package of `cargo` is compiled for.
+    ///
+    /// Dependencies are compiled for the platform of the packages depending on them: the host
+    /// for build dependencies and dependencies of proc-macros, the target of a package with a
+    /// `forced-target` for its dependencies. Packages shared by several platforms fall back to
+    /// the default target, as each package is analyzed for a single one.
+    pub(crate) fn package_platforms<'a>(
+        &'a self,
+        cargo: &'a CargoWorkspace,
+    ) -> FxHashMap<Package, Platform<'a>> {
+        let mut dependents: FxHashMap<Package, Vec<(Package, DepKind)>> = FxHashMap::default();
+        for pkg in cargo.packages() {
+            for dep in &cargo[pkg].dependencies {
+                // Dev-dependencies are only used by the tests of the package itself.
+                if dep.kind != DepKind::Dev {
+                    dependents.entry(dep.pkg).or_default().push((pkg, dep.kind));
+                }
+            }
+        }
+        let mut res = FxHashMap::default();
+        for pkg in cargo.packages() {
+            self.package_platform(cargo, &dependents, pkg, &mut res);
+        }
+        res
+    }
+
+    fn package_platform<'a>(
+        &'a self,
+        cargo: &'a CargoWorkspace,
+        dependents: &FxHashMap<Package, Vec<(Package, DepKind)>>,
+        pkg: Package,
+        res: &mut FxHashMap<Package, Platform<'a>>,
+    ) -> Platform<'a> {
+        if let Some(&it) = res.get(&pkg) {
+            return it;
+        }
+        // Guards against dependency cycles.
+        res.insert(pkg, Platform::Default);
+
+        let platform = if let Some(target) = self.package_target(&cargo[pkg]) {
+            Platform::Target(target)
+        } else if is_proc_macro(cargo, pkg) {
+            Platform::Host
+        } else {
+            let mut platforms =
+                dependents.get(&pkg).into_iter().flatten().map(|&(dependent, kind)| {
+                    if kind == DepKind::Build || is_proc_macro(cargo, dependent) {
+                        Platform::Host
+                    } else {
+                        self.package_platform(cargo, dependents, dependent, res)
+                    }
+                });
+            match platforms.next() {
+                Some(first) if platforms.all(|it| it == first) => first,
+                _ => Platform::Default,
+            }
+        };
+        res.insert(pkg, platform);
+        platform
+    }
+
+    /// Returns the cfgs the sysroot crates are compiled with: those of the target all workspace
+    /// members are built for, as `-Zbuild-std` rebuilds the sysroot for it.
+    pub(crate) fn sysroot(&self, cargo: &CargoWorkspace) -> &[CfgFlag] {
+        let mut targets = cargo
+            .packages()
+            .filter(|&pkg| cargo[pkg].is_member)
+            .map(|pkg| self.package_target(&cargo[pkg]));
+        match targets.next() {
+            Some(Some(target)) if targets.all(|it| it == Some(target)) => {
+                self.per_package.get(target).map_or(&self.default, |it| it.as_slice())
+            }
+            _ => &self.default,
+        }
+    }
+}
+
+/// What a package is compiled for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Platform<'a> {
+    /// The default target of the workspace.
+    Default,
+    /// The host, like build scripts and proc-macros.
+    Host,
+    /// A target requested by a package.
+    Target(&'a str),
+}
+
+fn is_proc_macro(cargo: &CargoWorkspace, pkg: Package) -> bool {
+    cargo[pkg].targets.iter().any(|&tgt| cargo[tgt].is_proc_macro)
+}
 
 pub(crate) fn get(cargo_toml: Option<&ManifestPath>, target: Option<&str>) -> Vec<CfgFlag> {
     let _p = profile::span("rustc_cfg::get");
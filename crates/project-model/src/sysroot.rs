@@ -9,6 +9,7 @@ use std::{env, fs, iter, ops, path::PathBuf, process::Command};
 use anyhow::{format_err, Result};
 use la_arena::{Arena, Idx};
 use paths::{AbsPath, AbsPathBuf};
+use rustc_hash::FxHashSet;
 
 use crate::{utf8_stdout, ManifestPath};
 
@@ -16,6 +17,10 @@ use crate::{utf8_stdout, ManifestPath};
 pub struct Sysroot {
     root: AbsPathBuf,
     crates: Arena<SysrootCrateData>,
+    /// The proc-macro server distributed with the toolchain, if any.
+    proc_macro_srv: Option<AbsPathBuf>,
+    /// The crates `-Zbuild-std` builds for the target, if restricted.
+    build_std: Option<FxHashSet<SysrootCrate>>,
 }
 
 pub(crate) type SysrootCrate = Idx<SysrootCrateData>;
@@ -39,6 +44,13 @@ impl Sysroot {
         &self.root
     }
 
+    /// Returns the proc-macro server shipped with the toolchain. Unlike ours, it
+    /// is built against the toolchain's own `proc_macro` crate, so it speaks the
+    /// exact ABI of the proc-macros compiled by that toolchain.
+    pub fn proc_macro_srv(&self) -> Option<&AbsPath> {
+        self.proc_macro_srv.as_deref()
+    }
+
     pub fn public_deps(&self) -> impl Iterator<Item = (&'static str, SysrootCrate, bool)> + '_ {
         // core is added as a dependency before std in order to
         // mimic rustcs dependency order
@@ -61,7 +73,22 @@ impl Sysroot {
         tracing::debug!("Discovering sysroot for {}", dir.display());
         let sysroot_dir = discover_sysroot_dir(dir)?;
         let sysroot_src_dir = discover_sysroot_src_dir(&sysroot_dir, dir)?;
-        let res = Sysroot::load(sysroot_src_dir)?;
+        let mut res = Sysroot::load(sysroot_src_dir)?;
+        res.proc_macro_srv = get_proc_macro_srv(&sysroot_dir);
+        Ok(res)
+    }
+
+    /// Like [`Sysroot::discover`], but takes the sources from `sysroot_src_dir`. The toolchain
+    /// of `dir` still provides the proc-macro server.
+    pub fn discover_with_src_override(
+        dir: &AbsPath,
+        sysroot_src_dir: AbsPathBuf,
+    ) -> Result<Sysroot> {
+        let mut res = Sysroot::load(sysroot_src_dir)?;
+        match discover_sysroot_dir(dir) {
+            Ok(sysroot_dir) => res.proc_macro_srv = get_proc_macro_srv(&sysroot_dir),
+            Err(e) => tracing::debug!("failed to discover sysroot for {}: {}", dir.display(), e),
+        }
         Ok(res)
     }
 
@@ -72,7 +99,12 @@ impl Sysroot {
     }
 
     pub fn load(sysroot_src_dir: AbsPathBuf) -> Result<Sysroot> {
-        let mut sysroot = Sysroot { root: sysroot_src_dir, crates: Arena::default() };
+        let mut sysroot = Sysroot {
+            root: sysroot_src_dir,
+            crates: Arena::default(),
+            proc_macro_srv: None,
+            build_std: None,
+        };
 
         for path in SYSROOT_CRATES.trim().lines() {
             let name = path.split('/').last().unwrap();
@@ -127,6 +159,33 @@ impl Sysroot {
         Ok(sysroot)
     }
 
+    /// Restricts the crates target crates can use to those `-Zbuild-std` builds, so that e.g. a
+    /// `no_std` target which only builds `core` and `alloc` doesn't see `std` either. Building
+    /// `std` brings in everything. Build scripts and proc-macros run on the host and keep seeing
+    /// the whole sysroot.
+    pub fn set_build_std(&mut self, build_std: &[String]) {
+        if build_std.iter().any(|it| it == "std") {
+            self.build_std = None;
+            return;
+        }
+        let mut keep = FxHashSet::default();
+        let mut stack: Vec<_> = iter::once("core")
+            .chain(build_std.iter().map(String::as_str))
+            .filter_map(|name| self.by_name(name))
+            .collect();
+        while let Some(krate) = stack.pop() {
+            if keep.insert(krate) {
+                stack.extend(self.crates[krate].deps.iter().copied());
+            }
+        }
+        self.build_std = Some(keep);
+    }
+
+    /// Whether target crates may depend on `krate`, see [`Sysroot::set_build_std`].
+    pub fn builds_for_target(&self, krate: SysrootCrate) -> bool {
+        self.build_std.as_ref().map_or(true, |it| it.contains(&krate))
+    }
+
     fn by_name(&self, name: &str) -> Option<SysrootCrate> {
         let (id, _data) = self.crates.iter().find(|(_id, data)| data.name == name)?;
         Some(id)
@@ -186,6 +245,17 @@ fn get_rustc_src(sysroot_path: &AbsPath) -> Option<ManifestPath> {
     }
 }
 
+fn get_proc_macro_srv(sysroot_path: &AbsPath) -> Option<AbsPathBuf> {
+    let name = format!("rust-analyzer-proc-macro-srv{}", env::consts::EXE_SUFFIX);
+    let proc_macro_srv = sysroot_path.join("libexec").join(name);
+    tracing::debug!("Checking for toolchain proc-macro server: {}", proc_macro_srv.display());
+    if fs::metadata(&proc_macro_srv).is_ok() {
+        Some(proc_macro_srv)
+    } else {
+        None
+    }
+}
+
 fn get_rust_src(sysroot_path: &AbsPath) -> Option<AbsPathBuf> {
     let rust_src = sysroot_path.join("lib/rustlib/src/rust/library");
     tracing::debug!("Checking sysroot: {}", rust_src.display());
@@ -8,10 +8,17 @@ use cfg::{CfgAtom, CfgDiff};
 use expect_test::{expect, Expect};
 use paths::{AbsPath, AbsPathBuf};
 use serde::de::DeserializeOwned;
+use test_utils::skip_slow_tests;
 
 use crate::{
-    CargoWorkspace, CfgOverrides, ProjectJson, ProjectJsonData, ProjectWorkspace, Sysroot,
-    WorkspaceBuildScripts,
+    build_scripts::{
+        parse_build_script_output, parse_rerun_if, toolchain_version, BuildScriptOutput,
+    },
+    cargo_workspace::{cargo_config_build_std, package_targets, parse_build_std},
+    cfg_flag::CfgFlag,
+    parse_dependencies, CargoConfig, CargoToml, CargoWorkspace, CfgOverrides, ManifestPath,
+    ProjectJson, ProjectJsonData, ProjectWorkspace, RunnableKind, Sysroot, TargetCfgs, TargetKind,
+    UsedDependencies, WorkspaceBuildScripts,
 };
 
 fn load_cargo(file: &str) -> CrateGraph {
@@ -26,7 +33,7 @@ fn load_cargo_with_overrides(file: &str, cfg_overrides: CfgOverrides) -> CrateGr
         build_scripts: WorkspaceBuildScripts::default(),
         sysroot: None,
         rustc: None,
-        rustc_cfg: Vec::new(),
+        rustc_cfg: TargetCfgs::default(),
         cfg_overrides,
     };
     to_crate_graph(project_workspace)
@@ -36,7 +43,12 @@ fn load_rust_project(file: &str) -> CrateGraph {
     let data = get_test_json_file(file);
     let project = rooted_project_json(data);
     let sysroot = Some(get_fake_sysroot());
-    let project_workspace = ProjectWorkspace::Json { project, sysroot, rustc_cfg: Vec::new() };
+    let project_workspace = ProjectWorkspace::Json {
+        project,
+        sysroot,
+        crate_sysroots: Vec::new(),
+        rustc_cfg: Vec::new(),
+    };
     to_crate_graph(project_workspace)
 }
 
@@ -49,7 +61,10 @@ fn get_test_json_file<T: DeserializeOwned>(file: &str) -> T {
 
     fn fixup_paths(val: &mut serde_json::Value) {
         match val {
-            serde_json::Value::String(s) => replace_root(s, true),
+            serde_json::Value::String(s) => {
+                replace_root(s, true);
+                *s = s.replace("$TEST_DATA$", &format!("{}/", get_test_path("").display()));
+            }
             serde_json::Value::Array(vals) => vals.iter_mut().for_each(fixup_paths),
             serde_json::Value::Object(kvals) => kvals.values_mut().for_each(fixup_paths),
             serde_json::Value::Null | serde_json::Value::Bool(_) | serde_json::Value::Number(_) => {
@@ -179,6 +194,7 @@ fn cargo_hello_world_project_model_with_wildcard_overrides() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         2,
@@ -254,6 +270,7 @@ fn cargo_hello_world_project_model_with_wildcard_overrides() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         4,
@@ -321,6 +338,7 @@ fn cargo_hello_world_project_model_with_wildcard_overrides() {
                             ),
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         1,
@@ -396,6 +414,7 @@ fn cargo_hello_world_project_model_with_wildcard_overrides() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         3,
@@ -471,6 +490,7 @@ fn cargo_hello_world_project_model_with_wildcard_overrides() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                 },
             }"#]],
@@ -561,6 +581,7 @@ fn cargo_hello_world_project_model_with_selective_overrides() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         2,
@@ -638,6 +659,7 @@ fn cargo_hello_world_project_model_with_selective_overrides() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         4,
@@ -705,6 +727,7 @@ fn cargo_hello_world_project_model_with_selective_overrides() {
                             ),
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         1,
@@ -782,6 +805,7 @@ fn cargo_hello_world_project_model_with_selective_overrides() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         3,
@@ -859,6 +883,7 @@ fn cargo_hello_world_project_model_with_selective_overrides() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                 },
             }"#]],
@@ -940,6 +965,7 @@ fn cargo_hello_world_project_model() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         2,
@@ -1017,6 +1043,7 @@ fn cargo_hello_world_project_model() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         4,
@@ -1086,6 +1113,7 @@ fn cargo_hello_world_project_model() {
                             ),
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         1,
@@ -1163,6 +1191,7 @@ fn cargo_hello_world_project_model() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         3,
@@ -1240,6 +1269,7 @@ fn cargo_hello_world_project_model() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                 },
             }"#]],
@@ -1297,6 +1327,7 @@ fn rust_project_hello_world_project_model() {
                             Alloc,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         10,
@@ -1331,6 +1362,7 @@ fn rust_project_hello_world_project_model() {
                             Other,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         7,
@@ -1365,6 +1397,7 @@ fn rust_project_hello_world_project_model() {
                             Other,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         4,
@@ -1409,6 +1442,7 @@ fn rust_project_hello_world_project_model() {
                             Other,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         1,
@@ -1443,6 +1477,7 @@ fn rust_project_hello_world_project_model() {
                             Core,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         11,
@@ -1514,6 +1549,7 @@ fn rust_project_hello_world_project_model() {
                             repo: None,
                         },
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         8,
@@ -1548,6 +1584,7 @@ fn rust_project_hello_world_project_model() {
                             Other,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         5,
@@ -1582,6 +1619,7 @@ fn rust_project_hello_world_project_model() {
                             Other,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         2,
@@ -1616,6 +1654,7 @@ fn rust_project_hello_world_project_model() {
                             Other,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         9,
@@ -1650,6 +1689,7 @@ fn rust_project_hello_world_project_model() {
                             Test,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         6,
@@ -1766,6 +1806,7 @@ fn rust_project_hello_world_project_model() {
                             Std,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                     CrateId(
                         3,
@@ -1800,6 +1841,7 @@ fn rust_project_hello_world_project_model() {
                             Other,
                         ),
                         is_proc_macro: false,
+                        target: None,
                     },
                 },
             }"#]],
@@ -1817,3 +1859,764 @@ fn rust_project_is_proc_macro_has_proc_macro_dep() {
     // on the proc_macro sysroot crate.
     crate_data.dependencies.iter().find(|&dep| dep.name.deref() == "proc_macro").unwrap();
 }
+
+#[test]
+fn rust_project_crate_build_outputs() {
+    let sysroot = get_fake_sysroot();
+    let data = serde_json::from_value(serde_json::json!({
+        "crates": [{
+            "display_name": "hello",
+            "root_module": "hello/src/lib.rs",
+            "edition": "2018",
+            "deps": [],
+            "out_dir": "out/hello",
+            "rustc_flags": ["--cfg", "feature=\"foo\"", "--cfg=bar", "-Copt-level=3"],
+            "sysroot_src": sysroot.root().as_ref(),
+            "runnables": [{ "kind": "test", "program": "buck", "args": ["test", "{test_id}"] }],
+        }],
+    }))
+    .unwrap();
+    let project = rooted_project_json(data);
+    let root = project.path().to_path_buf();
+
+    let runnable = project.runnable(&root.join("hello/src/lib.rs"), RunnableKind::Test).unwrap();
+    assert_eq!((runnable.program.as_str(), &*runnable.cwd), ("buck", &*root));
+    assert!(project.runnable(&root.join("hello/src/lib.rs"), RunnableKind::Run).is_none());
+
+    let project_workspace = ProjectWorkspace::Json {
+        project,
+        sysroot: None,
+        crate_sysroots: vec![sysroot],
+        rustc_cfg: Vec::new(),
+    };
+    let out_dir = root.join("out/hello");
+    assert!(project_workspace.to_roots().iter().any(|it| it.include.contains(&out_dir)));
+
+    let crate_graph = to_crate_graph(project_workspace);
+    let hello = crate_graph
+        .iter()
+        .find(|&krate| crate_graph[krate].display_name.as_deref() == Some("hello"))
+        .unwrap();
+    let hello = &crate_graph[hello];
+    assert_eq!(hello.env.get("OUT_DIR"), Some(out_dir.display().to_string()));
+    let features: Vec<_> = hello.cfg_options.get_cfg_values("feature").collect();
+    assert_eq!(features, ["foo"]);
+    assert!(hello.cfg_options.check(&CfgAtom::Flag("bar".into()).into()) == Some(true));
+    assert!(hello.dependencies.iter().any(|dep| dep.name.deref() == "std"));
+}
+
+#[test]
+fn cargo_crates_use_target_cfgs() {
+    // `firmware` sets a `forced-target` in its manifest and has a build script, `macros` is a
+    // proc-macro, `shared` is used by both `app` and `macros`.
+    let meta = get_test_json_file("target-cfgs-metadata.json");
+    let target_os = |os: &str| CfgFlag::KeyValue { key: "target_os".into(), value: os.into() };
+    let project_workspace = ProjectWorkspace::Cargo {
+        cargo: CargoWorkspace::new(meta),
+        build_scripts: WorkspaceBuildScripts::default(),
+        sysroot: None,
+        rustc: None,
+        rustc_cfg: TargetCfgs {
+            target: Some("x86_64-pc-windows-msvc".into()),
+            default: vec![target_os("windows")],
+            host: vec![target_os("linux")],
+            per_package: [("thumbv7em-none-eabihf".to_string(), vec![target_os("none")])]
+                .into_iter()
+                .collect(),
+            default_triple: None,
+            extra: Vec::new(),
+        },
+        cfg_overrides: CfgOverrides::default(),
+    };
+    let crate_graph = to_crate_graph(project_workspace);
+    let mut crates: Vec<_> = crate_graph
+        .iter()
+        .map(|krate| {
+            let data = &crate_graph[krate];
+            let name = data.display_name.as_ref().unwrap().to_string();
+            let os: Vec<_> =
+                data.cfg_options.get_cfg_values("target_os").map(|it| it.as_str()).collect();
+            (name, os.join(","))
+        })
+        .collect();
+    crates.sort();
+    let crates: Vec<_> = crates.iter().map(|(name, os)| (name.as_str(), os.as_str())).collect();
+    assert_eq!(
+        crates,
+        [
+            ("app", "windows"),
+            ("build_script_build", "linux"),
+            ("cc", "linux"),
+            ("firmware", "none"),
+            ("macros", "linux"),
+            ("shared", "windows"),
+            ("util", "none"),
+        ]
+    );
+}
+
+#[test]
+fn cargo_members_are_copied_for_extra_targets() {
+    let meta = get_test_json_file("hello-world-metadata.json");
+    let target_os = |os: &str| CfgFlag::KeyValue { key: "target_os".into(), value: os.into() };
+    let project_workspace = ProjectWorkspace::Cargo {
+        cargo: CargoWorkspace::new(meta),
+        build_scripts: WorkspaceBuildScripts::default(),
+        sysroot: None,
+        rustc: None,
+        rustc_cfg: TargetCfgs {
+            target: None,
+            default: vec![target_os("linux")],
+            host: vec![target_os("linux")],
+            per_package: Default::default(),
+            default_triple: Some("x86_64-unknown-linux-gnu".into()),
+            extra: vec![("x86_64-pc-windows-msvc".into(), vec![target_os("windows")])],
+        },
+        cfg_overrides: CfgOverrides::default(),
+    };
+    let crate_graph = to_crate_graph(project_workspace);
+
+    let mut crates: Vec<_> = crate_graph
+        .iter()
+        .map(|krate| {
+            let data = &crate_graph[krate];
+            let name = data.display_name.as_ref().unwrap().to_string();
+            let os: Vec<_> = data.cfg_options.get_cfg_values("target_os").cloned().collect();
+            let deps: Vec<_> = data
+                .dependencies
+                .iter()
+                .map(|dep| crate_graph[dep.crate_id].target.clone().unwrap_or_default())
+                .collect();
+            (name, data.target.clone().unwrap_or_default(), os, deps)
+        })
+        .filter(|(name, ..)| name == "hello_world" || name == "an_example")
+        .collect();
+    crates.sort();
+    expect![[r#"
+        [
+            (
+                "an_example",
+                "x86_64-pc-windows-msvc",
+                [
+                    "windows",
+                ],
+                [
+                    "x86_64-pc-windows-msvc",
+                    "",
+                ],
+            ),
+            (
+                "an_example",
+                "x86_64-unknown-linux-gnu",
+                [
+                    "linux",
+                ],
+                [
+                    "x86_64-unknown-linux-gnu",
+                    "",
+                ],
+            ),
+            (
+                "hello_world",
+                "x86_64-pc-windows-msvc",
+                [
+                    "windows",
+                ],
+                [
+                    "",
+                ],
+            ),
+            (
+                "hello_world",
+                "x86_64-pc-windows-msvc",
+                [
+                    "windows",
+                ],
+                [
+                    "x86_64-pc-windows-msvc",
+                    "",
+                ],
+            ),
+            (
+                "hello_world",
+                "x86_64-unknown-linux-gnu",
+                [
+                    "linux",
+                ],
+                [
+                    "",
+                ],
+            ),
+            (
+                "hello_world",
+                "x86_64-unknown-linux-gnu",
+                [
+                    "linux",
+                ],
+                [
+                    "x86_64-unknown-linux-gnu",
+                    "",
+                ],
+            ),
+        ]
+    "#]]
+    .assert_debug_eq(&crates);
+}
+
+#[test]
+fn package_targets_from_manifest() {
+    let manifest = r#"
+[package]
+name = "firmware"
+forced-target = "thumbv7em-none-eabihf"
+default-target = "x86_64-unknown-linux-gnu" # comment
+
+[dependencies]
+default-target = "not-a-target"
+"#;
+    assert_eq!(
+        package_targets(manifest),
+        (Some("thumbv7em-none-eabihf".into()), Some("x86_64-unknown-linux-gnu".into()))
+    );
+    assert_eq!(package_targets("[package]\nname = \"foo\"\n"), (None, None));
+    assert_eq!(
+        package_targets("package.forced-target = 'wasm32-unknown-unknown'"),
+        (Some("wasm32-unknown-unknown".into()), None)
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn project_json_from_discovery_command() {
+    let json_path = get_test_path("hello-world-project.json");
+    let root = AbsPathBuf::assert(get_test_path(""));
+    let command = ["sh", "-c", r#"cat "$0""#, json_path.to_str().unwrap()].map(String::from);
+    let file = root.join("src/lib.rs");
+
+    let project = ProjectJson::discover(&command, &file, &root).unwrap();
+    let data = serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(project, ProjectJson::new(&root, data));
+
+    let err = ProjectJson::discover(&["false".to_string()], &file, &root).unwrap_err();
+    assert!(err.to_string().contains("failed"), "{}", err);
+}
+
+/// A directory in the system's temporary directory, removed again when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("ra-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    /// Writes the files of `fixture`, given as `(path, text)` pairs.
+    fn with_files(self, fixture: &[(&str, &str)]) -> TempDir {
+        for (path, text) in fixture {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        self
+    }
+
+    fn manifest(&self) -> ManifestPath {
+        ManifestPath::try_from(AbsPathBuf::assert(self.0.join("Cargo.toml"))).unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn offline_metadata_falls_back_to_members() {
+    if skip_slow_tests() {
+        return;
+    }
+    let dir = TempDir::new("offline-metadata").with_files(&[
+        (
+            "Cargo.toml",
+            "[package]\nname = \"offline\"\nversion = \"0.1.0\"\n\n[dependencies]\nnot-a-real-crate-ra = \"1\"\n",
+        ),
+        ("src/lib.rs", ""),
+    ]);
+
+    let cargo_toml = dir.manifest();
+    let config = CargoConfig { offline: true, ..CargoConfig::default() };
+    let (meta, missing_deps) =
+        CargoWorkspace::fetch_metadata(&cargo_toml, cargo_toml.parent(), &config, &|_| ()).unwrap();
+
+    assert!(missing_deps.unwrap().contains("not-a-real-crate-ra"));
+    let cargo = CargoWorkspace::new(meta);
+    let names: Vec<_> = cargo.packages().map(|pkg| cargo[pkg].name.as_str()).collect();
+    assert_eq!(names, ["offline"]);
+}
+
+#[test]
+fn offline_metadata_uses_vendored_dependencies() {
+    if skip_slow_tests() {
+        return;
+    }
+    let dir = TempDir::new("vendored-metadata").with_files(&[
+        (
+            "Cargo.toml",
+            "[package]\nname = \"vendoring\"\nversion = \"0.1.0\"\n\n[dependencies]\nvendored-crate-ra = \"1\"\n",
+        ),
+        ("src/lib.rs", ""),
+        (
+            "vendor/vendored-crate-ra/Cargo.toml",
+            "[package]\nname = \"vendored-crate-ra\"\nversion = \"1.0.0\"\n",
+        ),
+        ("vendor/vendored-crate-ra/src/lib.rs", ""),
+        ("vendor/vendored-crate-ra/.cargo-checksum.json", "{\"files\":{},\"package\":null}"),
+    ]);
+
+    let cargo_toml = dir.manifest();
+    let config = CargoConfig { offline: true, ..CargoConfig::default() };
+    let (meta, missing_deps) =
+        CargoWorkspace::fetch_metadata(&cargo_toml, cargo_toml.parent(), &config, &|_| ()).unwrap();
+
+    assert_eq!(missing_deps, None);
+    let cargo = CargoWorkspace::new(meta);
+    let mut names: Vec<_> = cargo.packages().map(|pkg| cargo[pkg].name.as_str()).collect();
+    names.sort_unstable();
+    assert_eq!(names, ["vendored-crate-ra", "vendoring"]);
+}
+
+#[test]
+fn offline_metadata_updates_stale_lockfile() {
+    if skip_slow_tests() {
+        return;
+    }
+    // `Cargo.lock` doesn't know about the path dependency yet.
+    let dir = TempDir::new("stale-lockfile").with_files(&[
+        (
+            "Cargo.toml",
+            "[package]\nname = \"stale\"\nversion = \"0.1.0\"\n\n[dependencies]\ndep = { path = \"dep\" }\n",
+        ),
+        ("Cargo.lock", "version = 3\n\n[[package]]\nname = \"stale\"\nversion = \"0.1.0\"\n"),
+        ("src/lib.rs", ""),
+        ("dep/Cargo.toml", "[package]\nname = \"dep\"\nversion = \"0.1.0\"\n"),
+        ("dep/src/lib.rs", ""),
+    ]);
+
+    let cargo_toml = dir.manifest();
+    let config = CargoConfig { offline: true, ..CargoConfig::default() };
+    let (meta, missing_deps) =
+        CargoWorkspace::fetch_metadata(&cargo_toml, cargo_toml.parent(), &config, &|_| ()).unwrap();
+
+    assert_eq!(missing_deps, None);
+    let cargo = CargoWorkspace::new(meta);
+    let mut names: Vec<_> = cargo.packages().map(|pkg| cargo[pkg].name.as_str()).collect();
+    names.sort_unstable();
+    assert_eq!(names, ["dep", "stale"]);
+}
+
+#[test]
+fn build_scripts_are_reused_while_fresh() {
+    if skip_slow_tests() {
+        return;
+    }
+    let dir = TempDir::new("reuse-build-scripts").with_files(&[
+        ("Cargo.toml", "[package]\nname = \"reuse\"\nversion = \"0.1.0\"\n"),
+        ("Cargo.lock", "version = 3\n"),
+        ("build.rs", ""),
+        ("src/lib.rs", ""),
+        ("data.txt", ""),
+        ("target/debug/build/reuse-0123456789abcdef/out/.keep", ""),
+        (
+            "target/debug/build/reuse-0123456789abcdef/output",
+            "cargo:rerun-if-changed=data.txt\ncargo:rerun-if-env-changed=RA_TEST_REUSE_BUILD_SCRIPTS\n",
+        ),
+    ]);
+    let cargo = reuse_workspace(&dir);
+    let package = cargo.packages().next().unwrap();
+    let out_dir = dir.0.join("target/debug/build/reuse-0123456789abcdef/out");
+    let output = BuildScriptOutput {
+        cfgs: vec![CfgFlag::Atom("from_build".into())],
+        envs: vec![("OUT_DIR".to_string(), out_dir.display().to_string())],
+        out_dir: Some(AbsPathBuf::assert(out_dir)),
+        proc_macro_dylib_path: None,
+    };
+    let mut build_scripts = WorkspaceBuildScripts::default();
+    build_scripts.outputs.insert(package, Some(output));
+    let config = CargoConfig { reuse_build_scripts: true, ..CargoConfig::default() };
+    build_scripts.save_snapshot(&config, &cargo).unwrap();
+
+    assert_eq!(WorkspaceBuildScripts::reuse(&config, &cargo), Some(build_scripts));
+
+    // Different features make cargo rerun the build scripts.
+    let features = CargoConfig { all_features: true, ..config.clone() };
+    assert_eq!(WorkspaceBuildScripts::reuse(&features, &cargo), None);
+
+    std::env::set_var("RA_TEST_REUSE_BUILD_SCRIPTS", "1");
+    assert_eq!(WorkspaceBuildScripts::reuse(&config, &cargo), None);
+    std::env::remove_var("RA_TEST_REUSE_BUILD_SCRIPTS");
+
+    touch_after(&dir.0.join("data.txt"), &dir.0.join("target/rust-analyzer/build-scripts.json"));
+    assert_eq!(WorkspaceBuildScripts::reuse(&config, &cargo), None);
+}
+
+#[test]
+fn build_scripts_are_reused_from_target_dir() {
+    if skip_slow_tests() {
+        return;
+    }
+    let dir = TempDir::new("reuse-target-dir").with_files(&[
+        ("Cargo.toml", "[package]\nname = \"reuse\"\nversion = \"0.1.0\"\n"),
+        ("Cargo.lock", "version = 3\n"),
+        ("build.rs", ""),
+        ("src/lib.rs", ""),
+        ("target/debug/build/reuse-0123456789abcdef/out/.keep", ""),
+        (
+            "target/debug/build/reuse-0123456789abcdef/output",
+            "cargo:rustc-cfg=from_build\ncargo:rustc-env=KEY=value\n",
+        ),
+    ]);
+    let cargo = reuse_workspace(&dir);
+    let package = cargo.packages().next().unwrap();
+    let config = CargoConfig { reuse_build_scripts: true, ..CargoConfig::default() };
+    // Without a record of the toolchain which built them, the outputs aren't trusted.
+    assert_eq!(WorkspaceBuildScripts::reuse(&config, &cargo), None);
+
+    let rustc_info = serde_json::json!({
+        "rustc_fingerprint": 0,
+        "outputs": {
+            "0": {
+                "success": true,
+                "status": "",
+                "code": 0,
+                "stdout": toolchain_version(&cargo).unwrap(),
+                "stderr": "",
+            },
+        },
+        "successes": {},
+    });
+    std::fs::write(dir.0.join("target/.rustc_info.json"), rustc_info.to_string()).unwrap();
+    let out_dir = dir.0.join("target/debug/build/reuse-0123456789abcdef/out");
+    let output = BuildScriptOutput {
+        cfgs: vec![CfgFlag::Atom("from_build".into())],
+        envs: vec![
+            ("KEY".to_string(), "value".to_string()),
+            ("OUT_DIR".to_string(), out_dir.display().to_string()),
+        ],
+        out_dir: Some(AbsPathBuf::assert(out_dir)),
+        proc_macro_dylib_path: None,
+    };
+    let mut build_scripts = WorkspaceBuildScripts::default();
+    build_scripts.outputs.insert(package, Some(output));
+    assert_eq!(WorkspaceBuildScripts::reuse(&config, &cargo), Some(build_scripts));
+
+    // Without `rerun-if-changed`, any file of the package but those of version control counts.
+    let output = dir.0.join("target/debug/build/reuse-0123456789abcdef/output");
+    touch_after(&dir.0.join(".git/HEAD"), &output);
+    assert!(WorkspaceBuildScripts::reuse(&config, &cargo).is_some());
+    touch_after(&dir.0.join("src/lib.rs"), &output);
+    assert_eq!(WorkspaceBuildScripts::reuse(&config, &cargo), None);
+}
+
+/// A package with a build script in `dir`.
+fn reuse_workspace(dir: &TempDir) -> CargoWorkspace {
+    let root = dir.0.display().to_string();
+    let meta = serde_json::json!({
+        "packages": [{
+            "name": "reuse",
+            "version": "0.1.0",
+            "id": "reuse 0.1.0 (path+file:///reuse)",
+            "source": null,
+            "dependencies": [],
+            "targets": [
+                {
+                    "kind": ["lib"],
+                    "crate_types": ["lib"],
+                    "name": "reuse",
+                    "src_path": format!("{}/src/lib.rs", root),
+                    "edition": "2021",
+                },
+                {
+                    "kind": ["custom-build"],
+                    "crate_types": ["bin"],
+                    "name": "build-script-build",
+                    "src_path": format!("{}/build.rs", root),
+                    "edition": "2021",
+                },
+            ],
+            "features": {},
+            "manifest_path": format!("{}/Cargo.toml", root),
+            "metadata": null,
+            "edition": "2021",
+        }],
+        "workspace_members": ["reuse 0.1.0 (path+file:///reuse)"],
+        "resolve": null,
+        "target_directory": format!("{}/target", root),
+        "version": 1,
+        "workspace_root": root,
+        "metadata": null,
+    });
+    CargoWorkspace::new(serde_json::from_value(meta).unwrap())
+}
+
+/// Writes to `path` until it is newer than `other`, whatever the mtime resolution.
+fn touch_after(path: &Path, other: &Path) {
+    let mtime = |path: &Path| std::fs::metadata(path).ok().and_then(|it| it.modified().ok());
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    while mtime(path) <= mtime(other) {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        std::fs::write(path, "changed").unwrap();
+    }
+}
+
+#[test]
+fn build_script_rerun_if_parsing() {
+    let (files, env) = parse_rerun_if(
+        "cargo:rustc-cfg=foo\ncargo:rerun-if-changed=build.rs\ncargo::rerun-if-changed=src/a b\n\
+         cargo:rerun-if-env-changed=CC\nnot an instruction\n",
+    );
+    assert_eq!(files, ["build.rs", "src/a b"]);
+    assert_eq!(env, ["CC"]);
+}
+
+#[test]
+fn build_script_output_parsing() {
+    let (cfgs, envs) = parse_build_script_output(
+        "cargo:rustc-cfg=foo\ncargo::rustc-cfg=feature=\"bar\"\ncargo:rustc-env=KEY=a=b\n\
+         cargo:rerun-if-changed=build.rs\nnot an instruction\n",
+    );
+    assert_eq!(
+        cfgs,
+        [
+            CfgFlag::Atom("foo".into()),
+            CfgFlag::KeyValue { key: "feature".into(), value: "bar".into() }
+        ]
+    );
+    assert_eq!(envs, [("KEY".to_string(), "a=b".to_string())]);
+}
+
+#[test]
+fn build_std_crates_from_cargo_config() {
+    assert_eq!(
+        parse_build_std("unstable.build-std = [\"core\", \"alloc\"]\n"),
+        Some(vec!["core".to_owned(), "alloc".to_owned()])
+    );
+    assert_eq!(parse_build_std("unstable.build-std = []\n"), None);
+    assert_eq!(parse_build_std("build.target = \"thumbv7em-none-eabihf\"\n"), None);
+    assert_eq!(
+        parse_build_std("[unstable]\nbuild-std = [\"core\"]\n"),
+        Some(vec!["core".to_owned()])
+    );
+}
+
+#[test]
+fn build_std_crates_are_read_from_config_files() {
+    let dir = TempDir::new("build-std-config").with_files(&[
+        ("Cargo.toml", "[package]\nname = \"firmware\"\nversion = \"0.1.0\"\n"),
+        ("src/lib.rs", ""),
+        (".cargo/config.toml", "[unstable]\nbuild-std = [\"core\", \"alloc\"]\n"),
+    ]);
+    assert_eq!(
+        cargo_config_build_std(&dir.manifest()),
+        Some(vec!["core".to_owned(), "alloc".to_owned()])
+    );
+}
+
+#[test]
+fn sysroot_is_restricted_to_build_std_crates() {
+    let target_crates = |sysroot: &Sysroot| {
+        let mut names: Vec<_> = sysroot
+            .crates()
+            .filter(|&it| sysroot.builds_for_target(it))
+            .map(|it| sysroot[it].name.clone())
+            .collect();
+        names.sort();
+        names
+    };
+
+    let mut sysroot = get_fake_sysroot();
+    let all = target_crates(&sysroot);
+    sysroot.set_build_std(&["std".to_owned()]);
+    assert_eq!(target_crates(&sysroot), all);
+
+    sysroot.set_build_std(&["alloc".to_owned()]);
+    assert_eq!(target_crates(&sysroot), ["alloc", "core"]);
+    // Host crates still see the whole sysroot.
+    assert_eq!(sysroot.crates().len(), all.len());
+}
+
+#[test]
+fn build_std_only_restricts_target_crates() {
+    let meta = get_test_json_file("target-cfgs-metadata.json");
+    let mut sysroot = get_fake_sysroot();
+    sysroot.set_build_std(&["alloc".to_owned()]);
+    let project_workspace = ProjectWorkspace::Cargo {
+        cargo: CargoWorkspace::new(meta),
+        build_scripts: WorkspaceBuildScripts::default(),
+        sysroot: Some(sysroot),
+        rustc: None,
+        rustc_cfg: TargetCfgs::default(),
+        cfg_overrides: CfgOverrides::default(),
+    };
+    let crate_graph = to_crate_graph(project_workspace);
+    let sysroot_deps = |name: &str| {
+        let krate = crate_graph
+            .iter()
+            .find(|&it| crate_graph[it].display_name.as_ref().unwrap().to_string() == name)
+            .unwrap();
+        let mut deps: Vec<_> = crate_graph[krate]
+            .dependencies
+            .iter()
+            .map(|dep| dep.name.to_string())
+            .filter(|dep| ["core", "alloc", "std", "test", "proc_macro"].contains(&dep.as_str()))
+            .collect();
+        deps.sort();
+        deps
+    };
+    assert_eq!(sysroot_deps("firmware"), ["alloc", "core"]);
+    assert_eq!(sysroot_deps("build_script_build"), ["alloc", "core", "proc_macro", "std", "test"]);
+    assert_eq!(sysroot_deps("macros"), ["alloc", "core", "proc_macro", "std", "test"]);
+}
+
+#[test]
+fn cargo_toml_dependencies() {
+    let text = r#"
+[package]
+name = "foo"
+features = ["not-a-dependency"]
+
+[dependencies]
+libc = "0.2"
+serde = { version = "1", default-features = false, features = ["derive", "std"] }
+"my-rename" = { package = "bar", path = "../bar" }
+
+[target.'cfg(unix)'.dev-dependencies]
+nix = "0.24"
+
+[build-dependencies.cc]
+version = "1"
+features = ["parallel"]
+"#;
+    let deps: Vec<_> = parse_dependencies(text)
+        .into_iter()
+        .map(|dep| {
+            let features: Vec<_> =
+                dep.features.iter().map(|(it, range)| &text[range.clone()] == it).collect();
+            (
+                dep.name.clone(),
+                &text[dep.name_range.clone()] == dep.name,
+                dep.kind,
+                dep.features.len(),
+                features,
+            )
+        })
+        .collect();
+    expect![[r#"
+        [
+            (
+                "libc",
+                true,
+                Normal,
+                0,
+                [],
+            ),
+            (
+                "serde",
+                true,
+                Normal,
+                2,
+                [
+                    true,
+                    true,
+                ],
+            ),
+            (
+                "my-rename",
+                true,
+                Normal,
+                0,
+                [],
+            ),
+            (
+                "nix",
+                true,
+                Dev,
+                0,
+                [],
+            ),
+            (
+                "cc",
+                true,
+                Build,
+                1,
+                [
+                    true,
+                ],
+            ),
+        ]
+    "#]]
+    .assert_debug_eq(&deps);
+}
+
+#[test]
+fn cargo_toml_analysis() {
+    let cargo = CargoWorkspace::new(get_test_json_file("hello-world-metadata.json"));
+    let mut path = "$ROOT$hello-world/Cargo.toml".to_string();
+    replace_root(&mut path, true);
+    let path = AbsPathBuf::assert(PathBuf::from(path));
+    let text = r#"[package]
+name = "hello-world"
+
+[dependencies]
+libc = { version = "0.2", features = ["std", "no-such-feature"] }
+"#;
+    let cargo_toml = CargoToml::new(&cargo, &path, text).unwrap();
+    let offset = text.find("libc").unwrap() + 1;
+
+    let (range, markup) = cargo_toml.hover(offset).unwrap();
+    assert_eq!(&text[range], "libc");
+    expect![[r#"
+        **libc** `0.2.98`
+
+        https://github.com/rust-lang/libc
+
+        Enabled features: `default`, `std`"#]]
+    .assert_eq(&markup);
+    let (_, markup) = cargo_toml.hover(text.find("\"std\"").unwrap() + 1).unwrap();
+    expect!["Feature `std` of **libc**"].assert_eq(&markup);
+
+    let (_, crate_root) = cargo_toml.definition(offset).unwrap();
+    assert!(crate_root.as_ref().ends_with("libc-0.2.98/src/lib.rs"));
+
+    let messages = |used: Option<&UsedDependencies>| -> Vec<_> {
+        cargo_toml.diagnostics(used).into_iter().map(|it| it.message).collect()
+    };
+    expect![[r#"
+        [
+            "`libc` has no feature `no-such-feature`",
+        ]
+    "#]]
+    .assert_debug_eq(&messages(None));
+    // Only the other targets can use normal dependencies.
+    let mut used = UsedDependencies::default();
+    used.build_script.insert("libc".to_string());
+    expect![[r#"
+        [
+            "`libc` has no feature `no-such-feature`",
+            "unused dependency `libc`",
+        ]
+    "#]]
+    .assert_debug_eq(&messages(Some(&used)));
+    used.targets.insert("libc".to_string());
+    expect![[r#"
+        [
+            "`libc` has no feature `no-such-feature`",
+        ]
+    "#]]
+    .assert_debug_eq(&messages(Some(&used)));
+
+    let targets: Vec<_> = cargo_toml.targets().map(|(kind, _)| kind).collect();
+    assert_eq!(targets, [TargetKind::Lib, TargetKind::Bin, TargetKind::Example, TargetKind::Test]);
+}
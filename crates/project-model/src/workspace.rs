@@ -16,9 +16,11 @@ use stdx::always;
 
 use crate::{
     build_scripts::BuildScriptOutput,
-    cargo_workspace::{DepKind, PackageData, RustcSource},
+    cargo_workspace::{
+        cargo_config_build_std, cargo_config_build_target, DepKind, PackageData, RustcSource,
+    },
     cfg_flag::CfgFlag,
-    rustc_cfg,
+    rustc_cfg::{self, Platform, TargetCfgs},
     sysroot::SysrootCrate,
     utf8_stdout, CargoConfig, CargoWorkspace, ManifestPath, ProjectJson, ProjectManifest, Sysroot,
     TargetKind, WorkspaceBuildScripts,
@@ -70,25 +72,27 @@ pub enum ProjectWorkspace {
         build_scripts: WorkspaceBuildScripts,
         sysroot: Option<Sysroot>,
         rustc: Option<CargoWorkspace>,
-        /// Holds cfg flags for the current target. We get those by running
-        /// `rustc --print cfg`.
-        ///
-        /// FIXME: make this a per-crate map, as, eg, build.rs might have a
-        /// different target.
-        rustc_cfg: Vec<CfgFlag>,
+        /// Holds cfg flags for the targets crates are compiled for. We get
+        /// those by running `rustc --print cfg`.
+        rustc_cfg: TargetCfgs,
         cfg_overrides: CfgOverrides,
     },
     /// Project workspace was manually specified using a `rust-project.json` file.
-    Json { project: ProjectJson, sysroot: Option<Sysroot>, rustc_cfg: Vec<CfgFlag> },
-
-    // FIXME: The primary limitation of this approach is that the set of detached files needs to be fixed at the beginning.
-    // That's not the end user experience we should strive for.
-    // Ideally, you should be able to just open a random detached file in existing cargo projects, and get the basic features working.
-    // That needs some changes on the salsa-level though.
-    // In particular, we should split the unified CrateGraph (which currently has maximal durability) into proper crate graph, and a set of ad hoc roots (with minimal durability).
-    // Then, we need to hide the graph behind the queries such that most queries look only at the proper crate graph, and fall back to ad hoc roots only if there's no results.
-    // After this, we should be able to tweak the logic in reload.rs to add newly opened files, which don't belong to any existing crates, to the set of the detached files.
-    // //
+    Json {
+        project: ProjectJson,
+        sysroot: Option<Sysroot>,
+        /// Sysroots of crates which specify their own one.
+        crate_sysroots: Vec<Sysroot>,
+        rustc_cfg: Vec<CfgFlag>,
+    },
+
+    // Files opened later on which don't belong to any crate are not added here, rust-analyzer
+    // appends them to the crate graph as standalone crates instead (see `reload.rs`). This
+    // variant is only used for files known up front, or when there's no workspace at all.
+    //
+    // FIXME: Those standalone crates still live in the unified `CrateGraph`. We should split it
+    // into the proper crate graph and a set of ad hoc roots on the salsa level, and hide the
+    // graph behind queries which only fall back to the ad hoc roots if there are no results.
     /// Project with a set of disjoint files, not belonging to any particular workspace.
     /// Backed by basic sysroot crates for basic completion and highlighting.
     DetachedFiles { files: Vec<AbsPathBuf>, sysroot: Sysroot, rustc_cfg: Vec<CfgFlag> },
@@ -114,15 +118,19 @@ impl fmt::Debug for ProjectWorkspace {
                     "n_rustc_compiler_crates",
                     &rustc.as_ref().map_or(0, |rc| rc.packages().len()),
                 )
-                .field("n_rustc_cfg", &rustc_cfg.len())
+                .field("n_rustc_cfg", &rustc_cfg.default.len())
+                .field("n_rustc_cfg_targets", &rustc_cfg.per_package.len())
                 .field("n_cfg_overrides", &cfg_overrides.len())
                 .finish(),
-            ProjectWorkspace::Json { project, sysroot, rustc_cfg } => {
+            ProjectWorkspace::Json { project, sysroot, crate_sysroots, rustc_cfg } => {
                 let mut debug_struct = f.debug_struct("Json");
                 debug_struct.field("n_crates", &project.n_crates());
                 if let Some(sysroot) = sysroot {
                     debug_struct.field("n_sysroot_crates", &sysroot.crates().len());
                 }
+                if !crate_sysroots.is_empty() {
+                    debug_struct.field("n_crate_sysroots", &crate_sysroots.len());
+                }
                 debug_struct.field("n_rustc_cfg", &rustc_cfg.len());
                 debug_struct.finish()
             }
@@ -161,7 +169,7 @@ impl ProjectWorkspace {
                     cmd
                 })?;
 
-                let meta = CargoWorkspace::fetch_metadata(
+                let (meta, missing_deps) = CargoWorkspace::fetch_metadata(
                     &cargo_toml,
                     cargo_toml.parent(),
                     config,
@@ -174,18 +182,33 @@ impl ProjectWorkspace {
                         cargo_version
                     )
                 })?;
-                let cargo = CargoWorkspace::new(meta);
+                let mut cargo = CargoWorkspace::new(meta);
+                cargo.set_missing_deps(missing_deps);
 
-                let sysroot = if config.no_sysroot {
-                    None
-                } else {
-                    Some(Sysroot::discover(cargo_toml.parent()).with_context(|| {
+                let sysroot = match &config.sysroot_src {
+                    _ if config.no_sysroot => None,
+                    Some(sysroot_src) => Some(
+                        Sysroot::discover_with_src_override(
+                            cargo_toml.parent(),
+                            sysroot_src.clone(),
+                        )
+                        .with_context(|| {
+                            format!("Failed to load sysroot from {}", sysroot_src.display())
+                        })?,
+                    ),
+                    None => Some(Sysroot::discover(cargo_toml.parent()).with_context(|| {
                         format!(
                             "Failed to find sysroot for Cargo.toml file {}. Is rust-src installed?",
                             cargo_toml.display()
                         )
-                    })?)
+                    })?),
                 };
+                let sysroot = sysroot.map(|mut sysroot| {
+                    if let Some(build_std) = cargo_config_build_std(&cargo_toml) {
+                        sysroot.set_build_std(&build_std);
+                    }
+                    sysroot
+                });
 
                 let rustc_dir = match &config.rustc_source {
                     Some(RustcSource::Path(path)) => ManifestPath::try_from(path.clone()).ok(),
@@ -195,7 +218,7 @@ impl ProjectWorkspace {
 
                 let rustc = match rustc_dir {
                     Some(rustc_dir) => Some({
-                        let meta = CargoWorkspace::fetch_metadata(
+                        let (meta, _) = CargoWorkspace::fetch_metadata(
                             &rustc_dir,
                             cargo_toml.parent(),
                             config,
@@ -209,7 +232,14 @@ impl ProjectWorkspace {
                     None => None,
                 };
 
-                let rustc_cfg = rustc_cfg::get(Some(&cargo_toml), config.target.as_deref());
+                let target =
+                    config.target.clone().or_else(|| cargo_config_build_target(&cargo_toml));
+                let rustc_cfg = TargetCfgs::load(
+                    &cargo_toml,
+                    target,
+                    &config.extra_targets,
+                    cargo.packages().map(|pkg| &cargo[pkg]),
+                );
 
                 let cfg_overrides = config.cfg_overrides();
                 ProjectWorkspace::Cargo {
@@ -234,8 +264,16 @@ impl ProjectWorkspace {
             Some(path) => Some(Sysroot::load(path.clone())?),
             None => None,
         };
+        let mut crate_sysroots: Vec<Sysroot> = Vec::new();
+        for (_, krate) in project_json.crates() {
+            if let Some(path) = &krate.sysroot_src {
+                if crate_sysroots.iter().all(|it| it.root() != &**path) {
+                    crate_sysroots.push(Sysroot::load(path.clone())?);
+                }
+            }
+        }
         let rustc_cfg = rustc_cfg::get(None, target);
-        Ok(ProjectWorkspace::Json { project: project_json, sysroot, rustc_cfg })
+        Ok(ProjectWorkspace::Json { project: project_json, sysroot, crate_sysroots, rustc_cfg })
     }
 
     pub fn load_detached_files(detached_files: Vec<AbsPathBuf>) -> Result<ProjectWorkspace> {
@@ -280,16 +318,16 @@ impl ProjectWorkspace {
     /// the root is a member of the current workspace
     pub fn to_roots(&self) -> Vec<PackageRoot> {
         match self {
-            ProjectWorkspace::Json { project, sysroot, rustc_cfg: _ } => project
+            ProjectWorkspace::Json { project, sysroot, crate_sysroots, rustc_cfg: _ } => project
                 .crates()
                 .map(|(_, krate)| PackageRoot {
                     is_local: krate.is_workspace_member,
-                    include: krate.include.clone(),
+                    include: krate.include.iter().chain(&krate.out_dir).cloned().collect(),
                     exclude: krate.exclude.clone(),
                 })
                 .collect::<FxHashSet<_>>()
                 .into_iter()
-                .chain(sysroot.as_ref().into_iter().flat_map(|sysroot| {
+                .chain(sysroot.iter().chain(crate_sysroots).flat_map(|sysroot| {
                     sysroot.crates().map(move |krate| PackageRoot {
                         is_local: false,
                         include: vec![sysroot[krate].root.parent().to_path_buf()],
@@ -373,9 +411,51 @@ impl ProjectWorkspace {
         }
     }
 
+    pub fn sysroot(&self) -> Option<&Sysroot> {
+        match self {
+            ProjectWorkspace::Cargo { sysroot, .. } | ProjectWorkspace::Json { sysroot, .. } => {
+                sysroot.as_ref()
+            }
+            ProjectWorkspace::DetachedFiles { sysroot, .. } => Some(sysroot),
+        }
+    }
+
+    /// Returns the root module and edition of the workspace member crate whose package
+    /// contains `path`, preferring library targets. Standalone files opened inside a package
+    /// get access to that crate and its dependencies.
+    pub fn enclosing_crate_root(&self, path: &AbsPath) -> Option<(&AbsPath, Edition)> {
+        match self {
+            ProjectWorkspace::Cargo { cargo, .. } => {
+                let pkg = cargo
+                    .packages()
+                    .filter(|&pkg| cargo[pkg].is_member)
+                    .filter(|&pkg| path.starts_with(cargo[pkg].manifest.parent()))
+                    .max_by_key(|&pkg| cargo[pkg].manifest.parent().as_ref().as_os_str().len())?;
+                let tgt = cargo[pkg]
+                    .targets
+                    .iter()
+                    .copied()
+                    .filter(|&tgt| cargo[tgt].kind != TargetKind::BuildScript)
+                    .min_by_key(|&tgt| cargo[tgt].kind != TargetKind::Lib)?;
+                Some((&cargo[tgt].root, cargo[pkg].edition))
+            }
+            ProjectWorkspace::Json { project, .. } => project
+                .crates()
+                .map(|(_, krate)| krate)
+                .filter(|krate| krate.is_workspace_member)
+                .find(|krate| krate.include.iter().any(|dir| path.starts_with(dir)))
+                .map(|krate| (&*krate.root_module, krate.edition)),
+            ProjectWorkspace::DetachedFiles { .. } => None,
+        }
+    }
+
     pub fn n_packages(&self) -> usize {
         match self {
-            ProjectWorkspace::Json { project, .. } => project.n_crates(),
+            ProjectWorkspace::Json { project, sysroot, crate_sysroots, .. } => {
+                let sysroot_package_len =
+                    sysroot.iter().chain(crate_sysroots).map(|it| it.crates().len()).sum::<usize>();
+                project.n_crates() + sysroot_package_len
+            }
             ProjectWorkspace::Cargo { cargo, sysroot, rustc, .. } => {
                 let rustc_package_len = rustc.as_ref().map_or(0, |it| it.packages().len());
                 let sysroot_package_len = sysroot.as_ref().map_or(0, |it| it.crates().len());
@@ -395,13 +475,16 @@ impl ProjectWorkspace {
         let _p = profile::span("ProjectWorkspace::to_crate_graph");
 
         let mut crate_graph = match self {
-            ProjectWorkspace::Json { project, sysroot, rustc_cfg } => project_json_to_crate_graph(
-                rustc_cfg.clone(),
-                load_proc_macro,
-                load,
-                project,
-                sysroot,
-            ),
+            ProjectWorkspace::Json { project, sysroot, crate_sysroots, rustc_cfg } => {
+                project_json_to_crate_graph(
+                    rustc_cfg.clone(),
+                    load_proc_macro,
+                    load,
+                    project,
+                    sysroot,
+                    crate_sysroots,
+                )
+            }
             ProjectWorkspace::Cargo {
                 cargo,
                 sysroot,
@@ -410,7 +493,7 @@ impl ProjectWorkspace {
                 cfg_overrides,
                 build_scripts,
             } => cargo_to_crate_graph(
-                rustc_cfg.clone(),
+                rustc_cfg,
                 cfg_overrides,
                 load_proc_macro,
                 load,
@@ -438,11 +521,28 @@ fn project_json_to_crate_graph(
     load: &mut dyn FnMut(&AbsPath) -> Option<FileId>,
     project: &ProjectJson,
     sysroot: &Option<Sysroot>,
+    crate_sysroots: &[Sysroot],
 ) -> CrateGraph {
     let mut crate_graph = CrateGraph::default();
-    let sysroot_deps = sysroot
-        .as_ref()
-        .map(|sysroot| sysroot_to_crate_graph(&mut crate_graph, sysroot, rustc_cfg.clone(), load));
+    // The sysroot is built for the target of the workspace members, if they agree on one.
+    let mut member_targets =
+        project.crates().filter(|(_, krate)| krate.is_workspace_member).map(|(_, it)| &it.target);
+    let sysroot_cfg = match member_targets.next() {
+        Some(Some(target)) if member_targets.all(|it| it.as_ref() == Some(target)) => {
+            rustc_cfg::get(None, Some(target))
+        }
+        _ => rustc_cfg.clone(),
+    };
+    let sysroot_deps = sysroot.as_ref().map(|sysroot| {
+        sysroot_to_crate_graph(&mut crate_graph, sysroot, sysroot_cfg.clone(), load)
+    });
+    let crate_sysroot_deps = crate_sysroots
+        .iter()
+        .map(|sysroot| {
+            let deps = sysroot_to_crate_graph(&mut crate_graph, sysroot, sysroot_cfg.clone(), load);
+            (sysroot.root(), deps)
+        })
+        .collect::<Vec<_>>();
 
     let mut cfg_cache: FxHashMap<&str, Vec<CfgFlag>> = FxHashMap::default();
     let crates: FxHashMap<CrateId, CrateId> = project
@@ -453,7 +553,13 @@ fn project_json_to_crate_graph(
             Some((crate_id, krate, file_id))
         })
         .map(|(crate_id, krate, file_id)| {
-            let env = krate.env.clone().into_iter().collect();
+            let mut env: Env = krate.env.clone().into_iter().collect();
+            if let Some(out_dir) = &krate.out_dir {
+                // `OUT_DIR` set explicitly in the crate's environment wins.
+                if env.get("OUT_DIR").is_none() {
+                    env.set("OUT_DIR", out_dir.display().to_string());
+                }
+            }
             let proc_macro = match krate.proc_macro_dylib_path.clone() {
                 Some(it) => load_proc_macro(
                     krate.display_name.as_ref().map(|it| it.canonical_name()).unwrap_or(""),
@@ -471,6 +577,7 @@ fn project_json_to_crate_graph(
 
             let mut cfg_options = CfgOptions::default();
             cfg_options.extend(target_cfgs.iter().chain(krate.cfg.iter()).cloned());
+            cfg_options.extend(krate.flag_cfgs());
             (
                 crate_id,
                 crate_graph.add_crate_root(
@@ -495,7 +602,14 @@ fn project_json_to_crate_graph(
 
     for (from, krate) in project.crates() {
         if let Some(&from) = crates.get(&from) {
-            if let Some((public_deps, libproc_macro)) = &sysroot_deps {
+            let sysroot_deps = match &krate.sysroot_src {
+                Some(path) => crate_sysroot_deps
+                    .iter()
+                    .find(|(root, _)| *root == &**path)
+                    .map(|(_, deps)| deps),
+                None => sysroot_deps.as_ref(),
+            };
+            if let Some((public_deps, libproc_macro)) = sysroot_deps {
                 public_deps.add(from, &mut crate_graph);
                 if krate.is_proc_macro {
                     if let Some(proc_macro) = libproc_macro {
@@ -520,7 +634,7 @@ fn project_json_to_crate_graph(
 }
 
 fn cargo_to_crate_graph(
-    rustc_cfg: Vec<CfgFlag>,
+    rustc_cfg: &TargetCfgs,
     override_cfg: &CfgOverrides,
     load_proc_macro: &mut dyn FnMut(&str, &AbsPath) -> ProcMacroLoadResult,
     load: &mut dyn FnMut(&AbsPath) -> Option<FileId>,
@@ -532,26 +646,51 @@ fn cargo_to_crate_graph(
     let _p = profile::span("cargo_to_crate_graph");
     let mut crate_graph = CrateGraph::default();
     let (public_deps, libproc_macro) = match sysroot {
-        Some(sysroot) => sysroot_to_crate_graph(&mut crate_graph, sysroot, rustc_cfg.clone(), load),
+        Some(sysroot) => sysroot_to_crate_graph(
+            &mut crate_graph,
+            sysroot,
+            rustc_cfg.sysroot(cargo).to_vec(),
+            load,
+        ),
         None => (SysrootPublicDeps::default(), None),
     };
 
-    let mut cfg_options = CfgOptions::default();
-    cfg_options.extend(rustc_cfg);
+    let to_cfg_options = |cfgs: &[CfgFlag]| {
+        let mut cfg_options = CfgOptions::default();
+        cfg_options.extend(cfgs.iter().cloned());
+        // Add test cfg for non-sysroot crates
+        cfg_options.insert_atom("test".into());
+        cfg_options.insert_atom("debug_assertions".into());
+        cfg_options
+    };
+    let cfg_options = to_cfg_options(&rustc_cfg.default);
+    // Build scripts and proc-macros run on the host, so they see its cfgs rather than the target's.
+    let host_cfg_options = to_cfg_options(&rustc_cfg.host);
+    let per_package_cfg_options: FxHashMap<&str, CfgOptions> = rustc_cfg
+        .per_package
+        .iter()
+        .map(|(target, cfgs)| (target.as_str(), to_cfg_options(cfgs)))
+        .collect();
 
-    let mut pkg_to_lib_crate = FxHashMap::default();
+    let platforms = rustc_cfg.package_platforms(cargo);
 
-    // Add test cfg for non-sysroot crates
-    cfg_options.insert_atom("test".into());
-    cfg_options.insert_atom("debug_assertions".into());
+    let mut pkg_to_lib_crate = FxHashMap::default();
 
     let mut pkg_crates = FxHashMap::default();
+    // Crates running on the host, which can use the whole sysroot regardless of `-Zbuild-std`.
+    let mut host_crates = FxHashSet::default();
     // Does any crate signal to rust-analyzer that they need the rustc_private crates?
     let mut has_private = false;
     // Next, create crates for each package, target pair
     for pkg in cargo.packages() {
-        let mut cfg_options = &cfg_options;
+        let mut cfg_options = match platforms[&pkg] {
+            Platform::Default => &cfg_options,
+            Platform::Host => &host_cfg_options,
+            Platform::Target(target) => per_package_cfg_options.get(target).unwrap_or(&cfg_options),
+        };
+        let mut host_cfg_options = &host_cfg_options;
         let mut replaced_cfg_options;
+        let mut replaced_host_cfg_options;
 
         let overrides = match override_cfg {
             CfgOverrides::Wildcard(cfg_diff) => Some(cfg_diff),
@@ -569,6 +708,9 @@ fn cargo_to_crate_graph(
             replaced_cfg_options = cfg_options.clone();
             replaced_cfg_options.apply_diff(overrides.clone());
             cfg_options = &replaced_cfg_options;
+            replaced_host_cfg_options = host_cfg_options.clone();
+            replaced_host_cfg_options.apply_diff(overrides.clone());
+            host_cfg_options = &replaced_host_cfg_options;
         };
 
         has_private |= cargo[pkg].metadata.rustc_private;
@@ -584,11 +726,13 @@ fn cargo_to_crate_graph(
             }
 
             if let Some(file_id) = load(&cargo[tgt].root) {
+                let runs_on_host =
+                    cargo[tgt].kind == TargetKind::BuildScript || cargo[tgt].is_proc_macro;
                 let crate_id = add_target_crate_root(
                     &mut crate_graph,
                     &cargo[pkg],
                     build_scripts.get_output(pkg),
-                    cfg_options,
+                    if runs_on_host { host_cfg_options } else { cfg_options },
                     &mut |path| load_proc_macro(&cargo[tgt].name, path),
                     file_id,
                     &cargo[tgt].name,
@@ -598,14 +742,20 @@ fn cargo_to_crate_graph(
                     lib_tgt = Some((crate_id, cargo[tgt].name.clone()));
                     pkg_to_lib_crate.insert(pkg, crate_id);
                 }
+                let on_host = runs_on_host || matches!(platforms[&pkg], Platform::Host);
+                if on_host {
+                    host_crates.insert(crate_id);
+                }
                 if let Some(proc_macro) = libproc_macro {
-                    add_dep_with_prelude(
-                        &mut crate_graph,
-                        crate_id,
-                        CrateName::new("proc_macro").unwrap(),
-                        proc_macro,
-                        cargo[tgt].is_proc_macro,
-                    );
+                    if on_host || public_deps.builds_for_target(proc_macro) {
+                        add_dep_with_prelude(
+                            &mut crate_graph,
+                            crate_id,
+                            CrateName::new("proc_macro").unwrap(),
+                            proc_macro,
+                            cargo[tgt].is_proc_macro,
+                        );
+                    }
                 }
 
                 pkg_crates.entry(pkg).or_insert_with(Vec::new).push((crate_id, cargo[tgt].kind));
@@ -615,7 +765,11 @@ fn cargo_to_crate_graph(
         // Set deps to the core, std and to the lib target of the current package
         for (from, kind) in pkg_crates.get(&pkg).into_iter().flatten() {
             // Add sysroot deps first so that a lib target named `core` etc. can overwrite them.
-            public_deps.add(*from, &mut crate_graph);
+            if host_crates.contains(from) {
+                public_deps.add(*from, &mut crate_graph);
+            } else {
+                public_deps.add_for_target(*from, &mut crate_graph);
+            }
 
             if let Some((to, name)) = lib_tgt.clone() {
                 if to != *from && *kind != TargetKind::BuildScript {
@@ -672,9 +826,90 @@ fn cargo_to_crate_graph(
             );
         }
     }
+
+    if !rustc_cfg.extra.is_empty() {
+        add_extra_target_crates(&mut crate_graph, cargo, &pkg_crates, rustc_cfg);
+    }
     crate_graph
 }
 
+/// Adds a copy of the workspace members' crates for each extra target, so that code behind
+/// target-specific cfgs can be analyzed for all of those targets at once.
+///
+/// Only the members are copied: their dependencies and the sysroot are still analyzed for the
+/// default target only, as are build scripts, proc-macros and packages with their own target.
+fn add_extra_target_crates(
+    crate_graph: &mut CrateGraph,
+    cargo: &CargoWorkspace,
+    pkg_crates: &FxHashMap<la_arena::Idx<crate::PackageData>, Vec<(CrateId, TargetKind)>>,
+    rustc_cfg: &TargetCfgs,
+) {
+    let member_crates: Vec<CrateId> = cargo
+        .packages()
+        .filter(|&pkg| cargo[pkg].is_member && rustc_cfg.package_target(&cargo[pkg]).is_none())
+        .flat_map(|pkg| pkg_crates.get(&pkg).into_iter().flatten())
+        .filter(|&&(krate, kind)| {
+            kind != TargetKind::BuildScript && !crate_graph[krate].is_proc_macro
+        })
+        .map(|&(krate, _)| krate)
+        .collect();
+
+    if let Some(default_triple) = &rustc_cfg.default_triple {
+        for &krate in &member_crates {
+            crate_graph.set_target(krate, default_triple.clone());
+        }
+    }
+
+    for (target, cfgs) in &rustc_cfg.extra {
+        // Swap the cfgs of the default target for the ones of this target, keeping everything
+        // else (features, build script cfgs, overrides) as is.
+        let enable = cfgs.iter().filter(|it| !rustc_cfg.default.contains(it));
+        let disable = rustc_cfg.default.iter().filter(|it| !cfgs.contains(it));
+        let diff = match CfgDiff::new(
+            enable.cloned().map(Into::into).collect::<FxHashSet<_>>().into_iter().collect(),
+            disable.cloned().map(Into::into).collect::<FxHashSet<_>>().into_iter().collect(),
+        ) {
+            Some(it) => it,
+            None => {
+                tracing::error!("conflicting cfgs for target {}", target);
+                continue;
+            }
+        };
+
+        let mut copies = FxHashMap::default();
+        for &krate in &member_crates {
+            let data = &crate_graph[krate];
+            let mut cfg_options = data.cfg_options.clone();
+            cfg_options.apply_diff(diff.clone());
+            let mut potential_cfg_options = data.potential_cfg_options.clone();
+            potential_cfg_options.apply_diff(diff.clone());
+            let copy = crate_graph.add_crate_root(
+                data.root_file_id,
+                data.edition,
+                data.display_name.clone(),
+                data.version.clone(),
+                cfg_options,
+                potential_cfg_options,
+                data.env.clone(),
+                data.proc_macro.clone(),
+                data.is_proc_macro,
+                data.origin.clone(),
+            );
+            crate_graph.set_target(copy, target.clone());
+            copies.insert(krate, copy);
+        }
+
+        // Copies depend on the copies of other members, and on the original crates otherwise.
+        for (&krate, &copy) in &copies {
+            for dep in crate_graph[krate].dependencies.clone() {
+                let mut dep = dep;
+                dep.crate_id = copies.get(&dep.crate_id).copied().unwrap_or(dep.crate_id);
+                add_dep_inner(crate_graph, copy, dep);
+            }
+        }
+    }
+}
+
 fn detached_files_to_crate_graph(
     rustc_cfg: Vec<CfgFlag>,
     load: &mut dyn FnMut(&AbsPath) -> Option<FileId>,
@@ -896,6 +1131,8 @@ fn add_target_crate_root(
 #[derive(Default)]
 struct SysrootPublicDeps {
     deps: Vec<(CrateName, CrateId, bool)>,
+    /// The sysroot crates `-Zbuild-std` builds for the target, if restricted.
+    build_std: Option<FxHashSet<CrateId>>,
 }
 
 impl SysrootPublicDeps {
@@ -905,6 +1142,19 @@ impl SysrootPublicDeps {
             add_dep_with_prelude(crate_graph, from, name.clone(), *krate, *prelude);
         }
     }
+
+    /// Like [`SysrootPublicDeps::add`], but only adds the crates built for the target.
+    fn add_for_target(&self, from: CrateId, crate_graph: &mut CrateGraph) {
+        for (name, krate, prelude) in &self.deps {
+            if self.builds_for_target(*krate) {
+                add_dep_with_prelude(crate_graph, from, name.clone(), *krate, *prelude);
+            }
+        }
+    }
+
+    fn builds_for_target(&self, krate: CrateId) -> bool {
+        self.build_std.as_ref().map_or(true, |it| it.contains(&krate))
+    }
 }
 
 fn sysroot_to_crate_graph(
@@ -955,6 +1205,13 @@ fn sysroot_to_crate_graph(
                 (CrateName::new(name).unwrap(), sysroot_crates[&idx], prelude)
             })
             .collect::<Vec<_>>(),
+        build_std: sysroot.crates().any(|it| !sysroot.builds_for_target(it)).then(|| {
+            sysroot_crates
+                .iter()
+                .filter(|(&krate, _)| sysroot.builds_for_target(krate))
+                .map(|(_, &crate_id)| crate_id)
+                .collect()
+        }),
     };
 
     let libproc_macro = sysroot.proc_macro().and_then(|it| sysroot_crates.get(&it).copied());
@@ -2,19 +2,21 @@
 use lsp_types::{
     CallHierarchyServerCapability, ClientCapabilities, CodeActionKind, CodeActionOptions,
     CodeActionProviderCapability, CodeLensOptions, CompletionOptions, DeclarationCapability,
-    DocumentOnTypeFormattingOptions, FileOperationFilter, FileOperationPattern,
-    FileOperationPatternKind, FileOperationRegistrationOptions, FoldingRangeProviderCapability,
-    HoverProviderCapability, ImplementationProviderCapability, InlayHintOptions,
-    InlayHintServerCapabilities, OneOf, RenameOptions, SaveOptions,
-    SelectionRangeProviderCapability, SemanticTokensFullOptions, SemanticTokensLegend,
-    SemanticTokensOptions, ServerCapabilities, SignatureHelpOptions, TextDocumentSyncCapability,
-    TextDocumentSyncKind, TextDocumentSyncOptions, TypeDefinitionProviderCapability,
-    WorkDoneProgressOptions, WorkspaceFileOperationsServerCapabilities,
+    DocumentLinkOptions, DocumentOnTypeFormattingOptions, FileOperationFilter,
+    FileOperationPattern, FileOperationPatternKind, FileOperationRegistrationOptions,
+    FoldingRangeProviderCapability, HoverProviderCapability, ImplementationProviderCapability,
+    InlayHintOptions, InlayHintServerCapabilities, LinkedEditingRangeServerCapabilities, OneOf,
+    RenameOptions, SaveOptions, SelectionRangeProviderCapability, SemanticTokensFullOptions,
+    SemanticTokensLegend, SemanticTokensOptions, ServerCapabilities, SignatureHelpOptions,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TypeDefinitionProviderCapability, WorkDoneProgressOptions,
+    WorkspaceFileOperationsServerCapabilities, WorkspaceFoldersServerCapabilities,
     WorkspaceServerCapabilities,
 };
 use serde_json::json;
 
 use crate::config::{Config, RustfmtConfig};
+use crate::lsp_ext::DiagnosticOptions;
 use crate::semantic_tokens;
 
 pub fn server_capabilities(config: &Config) -> ServerCapabilities {
@@ -69,38 +71,25 @@ pub fn server_capabilities(config: &Config) -> ServerCapabilities {
             prepare_provider: Some(true),
             work_done_progress_options: WorkDoneProgressOptions { work_done_progress: None },
         })),
-        linked_editing_range_provider: None,
-        document_link_provider: None,
+        linked_editing_range_provider: Some(LinkedEditingRangeServerCapabilities::Simple(true)),
+        document_link_provider: Some(DocumentLinkOptions {
+            resolve_provider: Some(false),
+            work_done_progress_options: WorkDoneProgressOptions { work_done_progress: None },
+        }),
         color_provider: None,
         execute_command_provider: None,
         workspace: Some(WorkspaceServerCapabilities {
-            workspace_folders: None,
+            workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                supported: Some(true),
+                change_notifications: Some(OneOf::Left(true)),
+            }),
             file_operations: Some(WorkspaceFileOperationsServerCapabilities {
-                did_create: None,
-                will_create: None,
+                did_create: Some(file_operation_options(false)),
+                will_create: Some(file_operation_options(false)),
                 did_rename: None,
-                will_rename: Some(FileOperationRegistrationOptions {
-                    filters: vec![
-                        FileOperationFilter {
-                            scheme: Some(String::from("file")),
-                            pattern: FileOperationPattern {
-                                glob: String::from("**/*.rs"),
-                                matches: Some(FileOperationPatternKind::File),
-                                options: None,
-                            },
-                        },
-                        FileOperationFilter {
-                            scheme: Some(String::from("file")),
-                            pattern: FileOperationPattern {
-                                glob: String::from("**"),
-                                matches: Some(FileOperationPatternKind::Folder),
-                                options: None,
-                            },
-                        },
-                    ],
-                }),
-                did_delete: None,
-                will_delete: None,
+                will_rename: Some(file_operation_options(true)),
+                did_delete: Some(file_operation_options(true)),
+                will_delete: Some(file_operation_options(true)),
             }),
         }),
         call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
@@ -134,7 +123,7 @@ pub fn server_capabilities(config: &Config) -> ServerCapabilities {
             "openCargoToml": true,
             "parentModule": true,
             "runnables": {
-                "kinds": [ "cargo" ],
+                "kinds": [ "cargo", "shell" ],
             },
             "ssr": true,
             "workspaceSymbolScopeKindFiltering": true,
@@ -142,6 +131,22 @@ pub fn server_capabilities(config: &Config) -> ServerCapabilities {
     }
 }
 
+/// The capabilities of LSP 3.17 `ServerCapabilities` of lsp-types doesn't have yet, to be added
+/// to those.
+pub fn server_capabilities_3_17(config: &Config) -> serde_json::Map<String, serde_json::Value> {
+    let mut caps = serde_json::Map::new();
+    if config.pull_diagnostics() {
+        let options = DiagnosticOptions {
+            identifier: Some("rust-analyzer".to_string()),
+            inter_file_dependencies: true,
+            workspace_diagnostics: true,
+        };
+        caps.insert("diagnosticProvider".to_string(), serde_json::to_value(options).unwrap());
+    }
+    caps.insert("typeHierarchyProvider".to_string(), true.into());
+    caps
+}
+
 fn completions_resolve_provider(client_caps: &ClientCapabilities) -> Option<bool> {
     if completion_item_edit_resolve(client_caps) {
         Some(true)
@@ -170,6 +175,29 @@ pub(crate) fn completion_item_edit_resolve(caps: &ClientCapabilities) -> bool {
     })() == Some(true)
 }
 
+/// Registers for file operations on Rust files and, if `folders` is set, on folders.
+fn file_operation_options(folders: bool) -> FileOperationRegistrationOptions {
+    let mut filters = vec![FileOperationFilter {
+        scheme: Some(String::from("file")),
+        pattern: FileOperationPattern {
+            glob: String::from("**/*.rs"),
+            matches: Some(FileOperationPatternKind::File),
+            options: None,
+        },
+    }];
+    if folders {
+        filters.push(FileOperationFilter {
+            scheme: Some(String::from("file")),
+            pattern: FileOperationPattern {
+                glob: String::from("**"),
+                matches: Some(FileOperationPatternKind::Folder),
+                options: None,
+            },
+        });
+    }
+    FileOperationRegistrationOptions { filters }
+}
+
 fn code_action_capabilities(client_caps: &ClientCapabilities) -> CodeActionProviderCapability {
     client_caps
         .text_document
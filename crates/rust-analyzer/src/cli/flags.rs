@@ -103,7 +103,10 @@ xflags::xflags! {
             optional --debug snippet: String
         }
 
-        cmd proc-macro {}
+        cmd proc-macro {
+            /// Exit if the memory used by the server exceeds this many megabytes.
+            optional --memory-limit megabytes: usize
+        }
 
         cmd lsif
             required path: PathBuf
@@ -196,7 +199,9 @@ pub struct Search {
 }
 
 #[derive(Debug)]
-pub struct ProcMacro;
+pub struct ProcMacro {
+    pub memory_limit: Option<usize>,
+}
 
 #[derive(Debug)]
 pub struct Lsif {
@@ -7,7 +7,7 @@
 //! configure the server itself, feature flags are passed into analysis, and
 //! tweak things like automatic insertion of `()` in completions.
 
-use std::{ffi::OsString, fmt, iter, path::PathBuf};
+use std::{ffi::OsString, fmt, iter, path::PathBuf, time::Duration};
 
 use flycheck::FlycheckConfig;
 use ide::{
@@ -16,6 +16,7 @@ use ide::{
     Snippet, SnippetScope,
 };
 use ide_db::{
+    base_db::ProcMacroExpansionPolicy,
     imports::insert_use::{ImportGranularity, InsertUseConfig, PrefixKind},
     SnippetCap,
 };
@@ -80,9 +81,22 @@ config_data! {
         /// ```
         /// .
         cargo_buildScripts_overrideCommand: Option<Vec<String>> = "null",
+        /// Reuse the build script outputs saved after the last run, or else those a previous
+        /// `cargo check` or `cargo build` left in the target directory, instead of running cargo
+        /// on startup. Cargo is still run when they are older than `Cargo.lock`, a manifest or a
+        /// build script, when the toolchain changed, or when a build script would be rerun for
+        /// its `rerun-if-changed` files or `rerun-if-env-changed` variables. In the target
+        /// directory, only unset `rerun-if-env-changed` variables count as unchanged.
+        cargo_buildScripts_reuse: bool = "false",
         /// Use `RUSTC_WRAPPER=rust-analyzer` when running build scripts to
         /// avoid compiling unnecessary things.
         cargo_buildScripts_useRustcWrapper: bool = "true",
+        /// Additional target triples to analyze the workspace members for, next to
+        /// the one from `#rust-analyzer.cargo.target#`. Use the **Switch Active Target**
+        /// command to pick the target a file is analyzed for.
+        ///
+        /// Dependencies and the sysroot are only analyzed for the main target.
+        cargo_extraTargets: Vec<String> = "[]",
         /// List of features to activate.
         ///
         /// Set this to `"all"` to pass `--all-features` to cargo.
@@ -91,6 +105,14 @@ config_data! {
         cargo_noDefaultFeatures: bool    = "false",
         /// Internal config for debugging, disables loading of sysroot crates.
         cargo_noSysroot: bool            = "false",
+        /// Never access the network when loading the workspace, passing `--offline` and
+        /// `--locked` to cargo. Dependencies which can't be resolved from `Cargo.lock` or a
+        /// `vendor/` directory are left out, with a warning in the server status.
+        cargo_offline: bool              = "false",
+        /// Path to the source of the standard library (the `library` directory of a Rust checkout),
+        /// relative to the workspace root, to use instead of the one discovered with
+        /// `rustc --print sysroot`. Useful for custom toolchains which ship their sources elsewhere.
+        cargo_sysrootSrc: Option<String> = "null",
         /// Compilation target override (target triple).
         cargo_target: Option<String>     = "null",
         /// Unsets `#[cfg(test)]` for the specified crates.
@@ -124,9 +146,34 @@ config_data! {
         /// ```
         /// .
         checkOnSave_overrideCommand: Option<Vec<String>> = "null",
+        /// Only check the packages owning the saved file (`cargo check -p`), and check the
+        /// whole workspace once no file was saved for
+        /// `#rust-analyzer.checkOnSave.workspaceDelay#` milliseconds. Ignored when
+        /// `#rust-analyzer.checkOnSave.overrideCommand#` is set.
+        checkOnSave_perPackage: bool                     = "false",
+        /// Named checks to run instead of the single one configured by the other
+        /// `checkOnSave` settings. Each is an object with a `name`, used as the source of
+        /// its diagnostics, and either a cargo `command` with `extraArgs` and `allTargets`,
+        /// which default to the settings above, or an `overrideCommand`.
+        ///
+        /// Checks with `"trigger": "onSave"`, the default, run in parallel whenever a file is
+        /// saved, and those with `"trigger": "onIdle"` once all of the former finished, or on
+        /// save if there are none:
+        ///
+        /// ```json
+        /// [
+        ///     { "name": "check" },
+        ///     { "name": "clippy", "command": "clippy", "trigger": "onIdle" }
+        /// ]
+        /// ```
+        /// .
+        checkOnSave_profiles: Vec<FlycheckProfileDef>    = "[]",
         /// Check for a specific target. Defaults to
         /// `#rust-analyzer.cargo.target#`.
         checkOnSave_target: Option<String>               = "null",
+        /// Delay in milliseconds after which the whole workspace is checked when
+        /// `#rust-analyzer.checkOnSave.perPackage#` is set.
+        checkOnSave_workspaceDelay: usize                = "3000",
 
         /// Toggles the additional completions that automatically add imports when completed.
         /// Note that your client must specify the `additionalTextEdits` LSP client capability to truly have this feature enabled.
@@ -205,10 +252,30 @@ config_data! {
         /// and a blue icon in the `Problems Panel`.
         diagnostics_warningsAsInfo: Vec<String> = "[]",
 
+        /// Command to run to discover the project an opened file belongs to, for build systems
+        /// other than Cargo.
+        ///
+        /// The path of the file is appended as the last argument, and the command is expected to
+        /// print the project in the `rust-project.json` format to stdout. It is run for opened
+        /// files which don't belong to any crate, and again when one of
+        /// `#rust-analyzer.discoverProject.watchedFiles#` changes.
+        discoverProject_command: Option<Vec<String>> = "null",
+        /// Names of build files which trigger a new project discovery when they change.
+        discoverProject_watchedFiles: Vec<String> = r#"["BUILD", "BUILD.bazel", "BUCK", "TARGETS"]"#,
+
+        /// Analyze opened Rust files that don't belong to any crate as standalone crates, backed
+        /// by the sysroot.
+        files_detached_enable: bool = "true",
+        /// Let standalone files opened inside a workspace package use that package and its
+        /// dependencies.
+        files_detached_workspaceDependencies: bool = "true",
         /// These directories will be ignored by rust-analyzer. They are
         /// relative to the workspace root, and globs are not supported. You may
         /// also need to add the folders to Code's `files.watcherExclude`.
         files_excludeDirs: Vec<PathBuf> = "[]",
+        /// When the files of modules are deleted, also remove the imports from those modules
+        /// throughout the workspace.
+        files_removeImportsOnDelete_enable: bool = "false",
         /// Controls file watching implementation.
         files_watcher: String = "\"client\"",
 
@@ -338,15 +405,37 @@ config_data! {
 
         /// Expand attribute macros. Requires `#rust-analyzer.procMacro.enable#` to be set.
         procMacro_attributes_enable: bool = "true",
+        /// Cache proc-macro expansions on disk, so that unchanged invocations do not need to be
+        /// re-expanded after the proc-macro server is restarted.
+        procMacro_cache_enable: bool = "false",
+        /// Directory to store the proc-macro expansion cache in. Defaults to
+        /// `target/rust-analyzer/proc-macro-cache` in the workspace root.
+        procMacro_cache_path: Option<PathBuf> = "null",
         /// Enable support for procedural macros, implies `#rust-analyzer.cargo.buildScripts.enable#`.
         procMacro_enable: bool                     = "true",
         /// These proc-macros will be ignored when trying to expand them.
         ///
         /// This config takes a map of crate names with the exported proc-macro names to ignore as values.
         procMacro_ignored: FxHashMap<Box<str>, Box<[Box<str>]>>          = "{}",
+        /// Memory limit of the proc-macro server in megabytes. When it is exceeded, the server is
+        /// restarted and the macro being expanded is marked as failed. Memory usage can only be
+        /// measured on Linux with glibc, on Windows and in builds using jemalloc. Elsewhere the
+        /// server is started without a limit and a warning is logged. The proc-macro server
+        /// distributed with the toolchain doesn't support a limit.
+        procMacro_memoryLimit: Option<usize> = "null",
+        /// How to handle invocations of specific proc-macros: `"expand"` them normally, treat
+        /// them as the `"identity"` function, or `"strip"` them from the item.
+        ///
+        /// This config takes a map of `crate_name::macro_name` patterns to policies. Either part
+        /// of a pattern may be `*`, and a bare macro name matches that macro from any crate. The
+        /// most specific matching pattern wins.
+        procMacro_policy: FxHashMap<String, ProcMacroPolicyDef> = "{}",
         /// Internal config, path to proc-macro server executable (typically,
         /// this is rust-analyzer itself, but we override this in tests).
         procMacro_server: Option<PathBuf>          = "null",
+        /// Timeout in seconds for expanding a single proc-macro invocation. Macros that exceed it
+        /// are marked as failed and the proc-macro server is restarted.
+        procMacro_timeout: Option<usize>           = "null",
 
         /// Command to be executed instead of 'cargo' for runnables.
         runnables_command: Option<String> = "null",
@@ -410,7 +499,11 @@ impl Default for ConfigData {
 pub struct Config {
     pub discovered_projects: Option<Vec<ProjectManifest>>,
     caps: lsp_types::ClientCapabilities,
+    /// The client capabilities as sent by the client, for those lsp-types doesn't know about yet.
+    raw_caps: serde_json::Value,
     root_path: AbsPathBuf,
+    /// The workspace folders of the client, in which projects are discovered.
+    workspace_roots: Vec<AbsPathBuf>,
     data: ConfigData,
     detached_files: Vec<AbsPathBuf>,
     snippets: Vec<Snippet>,
@@ -512,6 +605,12 @@ impl HoverActionsConfig {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct DiscoverProjectConfig {
+    pub command: Vec<String>,
+    pub watched_files: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct FilesConfig {
     pub watcher: FilesWatcher,
@@ -529,6 +628,30 @@ pub struct NotificationsConfig {
     pub cargo_toml_not_found: bool,
 }
 
+/// A `cargo check`-like command run on save.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlycheckProfile {
+    /// The source of the diagnostics of the profile, if it is user-defined.
+    pub name: Option<String>,
+    pub config: FlycheckConfig,
+    pub trigger: FlycheckTrigger,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FlycheckTrigger {
+    /// Run whenever a file is saved.
+    OnSave,
+    /// Run once the checks run on save finished, or on save if there are none.
+    OnIdle,
+}
+
+impl Default for FlycheckTrigger {
+    fn default() -> Self {
+        FlycheckTrigger::OnSave
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum RustfmtConfig {
     Rustfmt { extra_args: Vec<String>, enable_range_formatting: bool },
@@ -555,6 +678,30 @@ pub struct WorkspaceSymbolConfig {
     pub search_limit: usize,
 }
 
+/// Per-macro expansion policies, see `#rust-analyzer.procMacro.policy#`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcMacroPolicies {
+    rules: FxHashMap<(String, String), ProcMacroExpansionPolicy>,
+}
+
+impl ProcMacroPolicies {
+    pub fn policy(&self, crate_name: &str, macro_name: &str) -> ProcMacroExpansionPolicy {
+        let crate_name = crate_name.replace('-', "_");
+        let candidates = [
+            (crate_name.clone(), macro_name),
+            (crate_name, "*"),
+            ("*".to_owned(), macro_name),
+            ("*".to_owned(), "*"),
+        ];
+        for (krate, name) in candidates {
+            if let Some(&policy) = self.rules.get(&(krate, name.to_owned())) {
+                return policy;
+            }
+        }
+        ProcMacroExpansionPolicy::Expand
+    }
+}
+
 pub struct ClientCommandsConfig {
     pub run_single: bool,
     pub debug_single: bool,
@@ -589,14 +736,47 @@ impl Config {
     pub fn new(root_path: AbsPathBuf, caps: ClientCapabilities) -> Self {
         Config {
             caps,
+            raw_caps: serde_json::Value::Null,
             data: ConfigData::default(),
             detached_files: Vec::new(),
             discovered_projects: None,
+            workspace_roots: vec![root_path.clone()],
             root_path,
             snippets: Default::default(),
         }
     }
 
+    pub fn set_raw_caps(&mut self, raw_caps: serde_json::Value) {
+        self.raw_caps = raw_caps;
+    }
+
+    pub fn set_workspace_roots(&mut self, workspace_roots: Vec<AbsPathBuf>) {
+        self.workspace_roots = workspace_roots;
+    }
+
+    /// Adds and removes workspace folders, as told by `workspace/didChangeWorkspaceFolders`.
+    pub fn change_workspace_roots(&mut self, added: Vec<AbsPathBuf>, removed: &[AbsPathBuf]) {
+        self.workspace_roots.retain(|root| !removed.contains(root));
+        for root in added {
+            if !self.workspace_roots.contains(&root) {
+                self.workspace_roots.push(root);
+            }
+        }
+    }
+
+    /// Looks for projects in the workspace roots, unless the projects are set explicitly.
+    pub fn rediscover_workspaces(&mut self) {
+        if !self.data.linkedProjects.is_empty() || !self.detached_files.is_empty() {
+            return;
+        }
+        let discovered = ProjectManifest::discover_all(&self.workspace_roots);
+        tracing::info!("discovered projects: {:?}", discovered);
+        if discovered.is_empty() {
+            tracing::error!("failed to find any projects in {:?}", self.workspace_roots);
+        }
+        self.discovered_projects = Some(discovered);
+    }
+
     pub fn update(&mut self, mut json: serde_json::Value) -> Result<(), ConfigUpdateError> {
         tracing::info!("updating config from JSON: {:#}", json);
         if json.is_null() || json.as_object().map_or(false, |it| it.is_empty()) {
@@ -666,6 +846,10 @@ impl Config {
         &self.root_path
     }
 
+    pub fn workspace_roots(&self) -> &[AbsPathBuf] {
+        &self.workspace_roots
+    }
+
     pub fn caps(&self) -> &lsp_types::ClientCapabilities {
         &self.caps
     }
@@ -673,6 +857,14 @@ impl Config {
     pub fn detached_files(&self) -> &[AbsPathBuf] {
         &self.detached_files
     }
+
+    pub fn analyze_detached_files(&self) -> bool {
+        self.data.files_detached_enable
+    }
+
+    pub fn detached_files_workspace_dependencies(&self) -> bool {
+        self.data.files_detached_workspaceDependencies
+    }
 }
 
 macro_rules! try_ {
@@ -815,6 +1007,21 @@ impl Config {
         .any(|it| it == "edit")
     }
 
+    pub fn inlay_hint_resolve_support(&self, property: &str) -> bool {
+        try_or_def!(self
+            .caps
+            .text_document
+            .as_ref()?
+            .inlay_hint
+            .as_ref()?
+            .resolve_support
+            .as_ref()?
+            .properties
+            .as_slice())
+        .iter()
+        .any(|it| it == property)
+    }
+
     pub fn signature_help_label_offsets(&self) -> bool {
         try_or_def!(
             self.caps
@@ -887,17 +1094,84 @@ impl Config {
             Some(it) => self.root_path.join(it),
             None => AbsPathBuf::assert(std::env::current_exe().ok()?),
         };
-        Some((path, vec!["proc-macro".into()]))
+        let mut args: Vec<OsString> = vec!["proc-macro".into()];
+        if let Some(limit) = self.data.procMacro_memoryLimit {
+            // The server refuses limits it can't enforce.
+            if profile::MemoryUsage::is_supported() {
+                args.push("--memory-limit".into());
+                args.push(limit.to_string().into());
+            } else {
+                tracing::warn!("`procMacro.memoryLimit` is not supported on this platform");
+            }
+        }
+        Some((path, args))
+    }
+
+    /// The proc-macro server distributed with the toolchain is preferred over
+    /// ours, unless the server was configured explicitly.
+    pub fn prefer_toolchain_proc_macro_srv(&self) -> bool {
+        self.data.procMacro_server.is_none()
+    }
+
+    pub fn proc_macro_timeout(&self) -> Option<Duration> {
+        self.data.procMacro_timeout.map(|secs| Duration::from_secs(secs as u64))
     }
 
-    pub fn dummy_replacements(&self) -> &FxHashMap<Box<str>, Box<[Box<str>]>> {
-        &self.data.procMacro_ignored
+    pub fn proc_macro_cache(&self) -> Option<AbsPathBuf> {
+        if !self.data.procMacro_cache_enable {
+            return None;
+        }
+        Some(match &self.data.procMacro_cache_path {
+            Some(it) => self.root_path.join(it),
+            None => self.root_path.join("target/rust-analyzer/proc-macro-cache"),
+        })
+    }
+
+    pub fn proc_macro_policies(&self) -> ProcMacroPolicies {
+        let mut rules = FxHashMap::default();
+        for (pattern, policy) in &self.data.procMacro_policy {
+            let (krate, name) = match pattern.split_once("::") {
+                Some((krate, name)) => (krate, name),
+                None => ("*", pattern.as_str()),
+            };
+            let policy = match policy {
+                ProcMacroPolicyDef::Expand => ProcMacroExpansionPolicy::Expand,
+                ProcMacroPolicyDef::Identity => ProcMacroExpansionPolicy::Identity,
+                ProcMacroPolicyDef::Strip => ProcMacroExpansionPolicy::Strip,
+            };
+            rules.insert((krate.replace('-', "_"), name.to_owned()), policy);
+        }
+        // `procMacro.ignored` predates the policy config; its entries behave like `identity`
+        // rules, but never override an explicit policy for the same macro.
+        for (krate, names) in &self.data.procMacro_ignored {
+            for name in names.iter() {
+                rules
+                    .entry((krate.replace('-', "_"), name.to_string()))
+                    .or_insert(ProcMacroExpansionPolicy::Identity);
+            }
+        }
+        ProcMacroPolicies { rules }
     }
 
     pub fn expand_proc_attr_macros(&self) -> bool {
         self.data.procMacro_enable && self.data.procMacro_attributes_enable
     }
 
+    pub fn discover_project(&self) -> Option<DiscoverProjectConfig> {
+        let command = self.data.discoverProject_command.clone()?;
+        if command.is_empty() {
+            return None;
+        }
+        Some(DiscoverProjectConfig {
+            command,
+            watched_files: self.data.discoverProject_watchedFiles.clone(),
+        })
+    }
+
+    pub fn remove_imports_on_delete(&self) -> bool {
+        self.data.files_removeImportsOnDelete_enable
+    }
+
     pub fn files(&self) -> FilesConfig {
         FilesConfig {
             watcher: match self.data.files_watcher.as_str() {
@@ -940,11 +1214,15 @@ impl Config {
                 CargoFeatures::Listed(it) => it.clone(),
             },
             target: self.data.cargo_target.clone(),
+            extra_targets: self.data.cargo_extraTargets.clone(),
             no_sysroot: self.data.cargo_noSysroot,
             rustc_source,
+            sysroot_src: self.data.cargo_sysrootSrc.as_ref().map(|it| self.root_path.join(it)),
             unset_test_crates: UnsetTestCrates::Only(self.data.cargo_unsetTest.clone()),
             wrap_rustc_in_build_scripts: self.data.cargo_buildScripts_useRustcWrapper,
             run_build_script_command: self.data.cargo_buildScripts_overrideCommand.clone(),
+            offline: self.data.cargo_offline,
+            reuse_build_scripts: self.data.cargo_buildScripts_reuse,
         }
     }
 
@@ -962,24 +1240,65 @@ impl Config {
         }
     }
 
-    pub fn flycheck(&self) -> Option<FlycheckConfig> {
-        if !self.data.checkOnSave_enable {
+    /// Returns the delay of the check of the whole workspace, if checks on save are limited to
+    /// the packages of the saved file.
+    pub fn flycheck_workspace_delay(&self) -> Option<Duration> {
+        if !self.data.checkOnSave_perPackage {
             return None;
         }
-        let flycheck_config = match &self.data.checkOnSave_overrideCommand {
+        Some(Duration::from_millis(self.data.checkOnSave_workspaceDelay as u64))
+    }
+
+    pub fn flycheck(&self) -> Vec<FlycheckProfile> {
+        if !self.data.checkOnSave_enable {
+            return Vec::new();
+        }
+        if self.data.checkOnSave_profiles.is_empty() {
+            let config = self.flycheck_config(
+                &self.data.checkOnSave_command,
+                &self.data.checkOnSave_extraArgs,
+                self.data.checkOnSave_allTargets,
+                self.data.checkOnSave_overrideCommand.as_deref(),
+            );
+            return vec![FlycheckProfile { name: None, config, trigger: FlycheckTrigger::OnSave }];
+        }
+        self.data
+            .checkOnSave_profiles
+            .iter()
+            .map(|profile| FlycheckProfile {
+                name: Some(profile.name.clone()),
+                config: self.flycheck_config(
+                    profile.command.as_ref().unwrap_or(&self.data.checkOnSave_command),
+                    profile.extra_args.as_ref().unwrap_or(&self.data.checkOnSave_extraArgs),
+                    profile.all_targets.unwrap_or(self.data.checkOnSave_allTargets),
+                    profile.override_command.as_deref(),
+                ),
+                trigger: profile.trigger,
+            })
+            .collect()
+    }
+
+    fn flycheck_config(
+        &self,
+        command: &str,
+        extra_args: &[String],
+        all_targets: bool,
+        override_command: Option<&[String]>,
+    ) -> FlycheckConfig {
+        match override_command {
             Some(args) if !args.is_empty() => {
-                let mut args = args.clone();
+                let mut args = args.to_vec();
                 let command = args.remove(0);
                 FlycheckConfig::CustomCommand { command, args }
             }
             Some(_) | None => FlycheckConfig::CargoCommand {
-                command: self.data.checkOnSave_command.clone(),
+                command: command.to_string(),
                 target_triple: self
                     .data
                     .checkOnSave_target
                     .clone()
                     .or_else(|| self.data.cargo_target.clone()),
-                all_targets: self.data.checkOnSave_allTargets,
+                all_targets,
                 no_default_features: self
                     .data
                     .checkOnSave_noDefaultFeatures
@@ -997,10 +1316,9 @@ impl Config {
                     CargoFeatures::All => vec![],
                     CargoFeatures::Listed(it) => it,
                 },
-                extra_args: self.data.checkOnSave_extraArgs.clone(),
+                extra_args: extra_args.to_vec(),
             },
-        };
-        Some(flycheck_config)
+        }
     }
 
     pub fn runnables(&self) -> RunnablesConfig {
@@ -1197,6 +1515,15 @@ impl Config {
         try_or_def!(self.caps.workspace.as_ref()?.code_lens.as_ref()?.refresh_support?)
     }
 
+    /// Whether the client pulls diagnostics, rather than having them published.
+    pub fn pull_diagnostics(&self) -> bool {
+        self.raw_caps.pointer("/textDocument/diagnostic").is_some()
+    }
+
+    pub fn diagnostics_refresh(&self) -> bool {
+        try_or_def!(self.raw_caps.pointer("/workspace/diagnostics/refreshSupport")?.as_bool()?)
+    }
+
     pub fn insert_replace_support(&self) -> bool {
         try_or_def!(
             self.caps
@@ -1425,6 +1752,14 @@ pub enum ExprFillDefaultDef {
     Default,
 }
 
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ProcMacroPolicyDef {
+    Expand,
+    Identity,
+    Strip,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
 enum ImportGranularityDef {
@@ -1442,6 +1777,18 @@ enum CallableCompletionDef {
     None,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct FlycheckProfileDef {
+    name: String,
+    command: Option<String>,
+    extra_args: Option<Vec<String>>,
+    all_targets: Option<bool>,
+    override_command: Option<Vec<String>>,
+    #[serde(default)]
+    trigger: FlycheckTrigger,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(untagged)]
 enum CargoFeatures {
@@ -1645,6 +1992,28 @@ fn field_props(field: &str, ty: &str, doc: &[&str], default: &str) -> serde_json
             "type": "array",
             "items": { "type": "string" },
         },
+        "Vec<FlycheckProfileDef>" => set! {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string" },
+                    "command": { "type": "string" },
+                    "extraArgs": { "type": "array", "items": { "type": "string" } },
+                    "allTargets": { "type": "boolean" },
+                    "overrideCommand": { "type": "array", "items": { "type": "string" } },
+                    "trigger": {
+                        "type": "string",
+                        "enum": ["onSave", "onIdle"],
+                        "enumDescriptions": [
+                            "Run whenever a file is saved.",
+                            "Run once the checks run on save finished."
+                        ]
+                    }
+                },
+                "required": ["name"]
+            },
+        },
         "Vec<PathBuf>" => set! {
             "type": "array",
             "items": { "type": "string" },
@@ -1657,6 +2026,18 @@ fn field_props(field: &str, ty: &str, doc: &[&str], default: &str) -> serde_json
         "FxHashMap<Box<str>, Box<[Box<str>]>>" => set! {
             "type": "object",
         },
+        "FxHashMap<String, ProcMacroPolicyDef>" => set! {
+            "type": "object",
+            "additionalProperties": {
+                "type": "string",
+                "enum": ["expand", "identity", "strip"],
+                "enumDescriptions": [
+                    "Expand the macro normally.",
+                    "Do not expand the macro, treat its invocations as the identity function.",
+                    "Do not expand the macro, remove its invocations entirely."
+                ],
+            },
+        },
         "FxHashMap<String, SnippetDef>" => set! {
             "type": "object",
         },
@@ -1947,6 +2328,78 @@ mod tests {
         ensure_file_contents(&docs_path, &expected);
     }
 
+    #[test]
+    fn proc_macro_policy_precedence() {
+        let mut config = Config::new(
+            AbsPathBuf::try_from(project_root()).unwrap(),
+            ClientCapabilities::default(),
+        );
+        config
+            .update(serde_json::json!({
+                "procMacro": {
+                    "policy": {
+                        "tracing_attributes::*": "strip",
+                        "tracing-attributes::instrument": "expand",
+                        "async_trait": "strip",
+                        "*::*": "identity",
+                    },
+                    "ignored": { "async-trait": ["async_trait"] },
+                },
+            }))
+            .unwrap();
+        let policies = config.proc_macro_policies();
+        let check = |krate, name, expected| assert_eq!(policies.policy(krate, name), expected);
+
+        check("tracing-attributes", "instrument", ProcMacroExpansionPolicy::Expand);
+        check("tracing_attributes", "other", ProcMacroExpansionPolicy::Strip);
+        check("async_trait", "async_trait", ProcMacroExpansionPolicy::Identity);
+        check("my_async_trait", "async_trait", ProcMacroExpansionPolicy::Strip);
+        check("serde_derive", "Serialize", ProcMacroExpansionPolicy::Identity);
+    }
+
+    #[test]
+    fn flycheck_profiles() {
+        let mut config = Config::new(
+            AbsPathBuf::try_from(project_root()).unwrap(),
+            ClientCapabilities::default(),
+        );
+        let profiles = config.flycheck();
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].name, None);
+
+        config
+            .update(serde_json::json!({
+                "checkOnSave": {
+                    "extraArgs": ["--locked"],
+                    "profiles": [
+                        { "name": "check" },
+                        { "name": "clippy", "command": "clippy", "trigger": "onIdle" },
+                        { "name": "custom", "overrideCommand": ["make", "check"] },
+                    ],
+                },
+            }))
+            .unwrap();
+        let profiles = config.flycheck();
+        let names: Vec<_> = profiles.iter().map(|it| it.name.as_deref().unwrap()).collect();
+        assert_eq!(names, ["check", "clippy", "custom"]);
+        let triggers: Vec<_> = profiles.iter().map(|it| it.trigger).collect();
+        assert_eq!(
+            triggers,
+            [FlycheckTrigger::OnSave, FlycheckTrigger::OnIdle, FlycheckTrigger::OnSave]
+        );
+        match &profiles[1].config {
+            FlycheckConfig::CargoCommand { command, extra_args, .. } => {
+                assert_eq!(command, "clippy");
+                assert_eq!(extra_args, &["--locked"]);
+            }
+            FlycheckConfig::CustomCommand { .. } => panic!("expected a cargo command"),
+        }
+        assert_eq!(
+            profiles[2].config,
+            FlycheckConfig::CustomCommand { command: "make".into(), args: vec!["check".into()] }
+        );
+    }
+
     fn remove_ws(text: &str) -> String {
         text.replace(char::is_whitespace, "")
     }
@@ -3,12 +3,13 @@ pub(crate) mod to_proto;
 
 use std::{mem, sync::Arc};
 
-use ide::FileId;
+use ide::{CrateGraph, CrateId, FileId};
+use parking_lot::Mutex;
 use rustc_hash::{FxHashMap, FxHashSet};
 
-use crate::lsp_ext;
+use crate::{lsp_ext, mem_docs::DocumentEdit};
 
-pub(crate) type CheckFixes = Arc<FxHashMap<FileId, Vec<Fix>>>;
+pub(crate) type CheckFixes = Arc<FxHashMap<usize, FxHashMap<FileId, Vec<Fix>>>>;
 
 #[derive(Debug, Default, Clone)]
 pub struct DiagnosticsMapConfig {
@@ -22,11 +23,30 @@ pub(crate) struct DiagnosticCollection {
     // FIXME: should be FxHashMap<FileId, Vec<ra_id::Diagnostic>>
     pub(crate) native: FxHashMap<FileId, Vec<lsp_types::Diagnostic>>,
     // FIXME: should be Vec<flycheck::Diagnostic>
-    pub(crate) check: FxHashMap<FileId, Vec<lsp_types::Diagnostic>>,
+    /// The diagnostics of each flycheck instance.
+    pub(crate) check: FxHashMap<usize, FxHashMap<FileId, Vec<lsp_types::Diagnostic>>>,
     pub(crate) check_fixes: CheckFixes,
     changes: FxHashSet<FileId>,
 }
 
+/// The native diagnostics last pulled for each file, with their result id.
+pub(crate) type PulledDiagnostics =
+    Arc<Mutex<FxHashMap<FileId, (String, Vec<lsp_types::Diagnostic>)>>>;
+
+/// Tracks what the native diagnostics of each file depend on, giving them result ids which
+/// only change when the diagnostics might, so that clients pulling them again can be told
+/// they are unchanged.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct DiagnosticRevisions {
+    /// Bumped when the diagnostics of all files might change, e.g. with the crate graph.
+    generation: u64,
+    last_change: u64,
+    /// The last change of each changed file.
+    files: FxHashMap<FileId, u64>,
+    /// The last change of each crate, including those of its dependencies.
+    crates: FxHashMap<CrateId, u64>,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Fix {
     // Fixes may be triggerable from multiple ranges.
@@ -35,9 +55,69 @@ pub(crate) struct Fix {
 }
 
 impl DiagnosticCollection {
-    pub(crate) fn clear_check(&mut self) {
+    pub(crate) fn clear_check(&mut self, flycheck_id: usize) {
+        if let Some(it) = Arc::make_mut(&mut self.check_fixes).get_mut(&flycheck_id) {
+            it.clear();
+        }
+        if let Some(it) = self.check.get_mut(&flycheck_id) {
+            self.changes.extend(it.drain().map(|(key, _value)| key));
+        }
+    }
+
+    pub(crate) fn clear_check_all(&mut self) {
         Arc::make_mut(&mut self.check_fixes).clear();
-        self.changes.extend(self.check.drain().map(|(key, _value)| key))
+        self.changes
+            .extend(self.check.values_mut().flat_map(|it| it.drain().map(|(key, _value)| key)));
+    }
+
+    pub(crate) fn clear_check_for(&mut self, flycheck_id: usize, file_ids: &[FileId]) {
+        if let Some(check_fixes) = Arc::make_mut(&mut self.check_fixes).get_mut(&flycheck_id) {
+            for file_id in file_ids {
+                check_fixes.remove(file_id);
+            }
+        }
+        if let Some(check) = self.check.get_mut(&flycheck_id) {
+            for file_id in file_ids {
+                if check.remove(file_id).is_some() {
+                    self.changes.insert(*file_id);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn clear_check_for_file(&mut self, file_id: FileId) {
+        let ids: Vec<_> = self.check.keys().copied().collect();
+        for id in ids {
+            self.clear_check_for(id, &[file_id]);
+        }
+    }
+
+    /// Moves the flycheck diagnostics of the document `url` along with an edit,
+    /// dropping those it touched.
+    pub(crate) fn apply_check_edit(
+        &mut self,
+        file_id: FileId,
+        url: &lsp_types::Url,
+        edit: &DocumentEdit,
+    ) {
+        let map = |range| edit.map_range(range);
+        for check in self.check.values_mut() {
+            if let Some(diagnostics) = check.get_mut(&file_id) {
+                *diagnostics = mem::take(diagnostics)
+                    .into_iter()
+                    .filter_map(|mut it| map_diagnostic(&mut it, url, map).then(|| it))
+                    .collect();
+                self.changes.insert(file_id);
+            }
+        }
+        for check_fixes in Arc::make_mut(&mut self.check_fixes).values_mut() {
+            if let Some(fixes) = check_fixes.get_mut(&file_id) {
+                *fixes = mem::take(fixes)
+                    .into_iter()
+                    .filter_map(|mut it| map_fix(&mut it, url, map).then(|| it))
+                    .collect();
+            }
+        }
     }
 
     pub(crate) fn clear_native_for(&mut self, file_id: FileId) {
@@ -47,11 +127,12 @@ impl DiagnosticCollection {
 
     pub(crate) fn add_check_diagnostic(
         &mut self,
+        flycheck_id: usize,
         file_id: FileId,
         diagnostic: lsp_types::Diagnostic,
         fix: Option<Fix>,
     ) {
-        let diagnostics = self.check.entry(file_id).or_default();
+        let diagnostics = self.check.entry(flycheck_id).or_default().entry(file_id).or_default();
         for existing_diagnostic in diagnostics.iter() {
             if are_diagnostics_equal(existing_diagnostic, &diagnostic) {
                 return;
@@ -59,7 +140,7 @@ impl DiagnosticCollection {
         }
 
         let check_fixes = Arc::make_mut(&mut self.check_fixes);
-        check_fixes.entry(file_id).or_default().extend(fix);
+        check_fixes.entry(flycheck_id).or_default().entry(file_id).or_default().extend(fix);
         diagnostics.push(diagnostic);
         self.changes.insert(file_id);
     }
@@ -89,7 +170,7 @@ impl DiagnosticCollection {
         file_id: FileId,
     ) -> impl Iterator<Item = &lsp_types::Diagnostic> {
         let native = self.native.get(&file_id).into_iter().flatten();
-        let check = self.check.get(&file_id).into_iter().flatten();
+        let check = self.check.values().filter_map(move |it| it.get(&file_id)).flatten();
         native.chain(check)
     }
 
@@ -101,9 +182,107 @@ impl DiagnosticCollection {
     }
 }
 
+/// Maps the ranges of a flycheck diagnostic in the document `url`, returning
+/// `false` if its own range doesn't map.
+pub(crate) fn map_diagnostic(
+    diagnostic: &mut lsp_types::Diagnostic,
+    url: &lsp_types::Url,
+    map: impl Fn(lsp_types::Range) -> Option<lsp_types::Range>,
+) -> bool {
+    diagnostic.range = match map(diagnostic.range) {
+        Some(it) => it,
+        None => return false,
+    };
+    if let Some(related) = diagnostic.related_information.take() {
+        let related = related.into_iter().filter_map(|mut it| {
+            if it.location.uri == *url {
+                it.location.range = map(it.location.range)?;
+            }
+            Some(it)
+        });
+        diagnostic.related_information = Some(related.collect());
+    }
+    true
+}
+
+/// Maps the ranges of a fix for the document `url`, returning `false` if any of
+/// its edits there doesn't map.
+pub(crate) fn map_fix(
+    fix: &mut Fix,
+    url: &lsp_types::Url,
+    map: impl Fn(lsp_types::Range) -> Option<lsp_types::Range>,
+) -> bool {
+    fix.ranges = fix.ranges.iter().filter_map(|&range| map(range)).collect();
+    if fix.ranges.is_empty() {
+        return false;
+    }
+    let edits = fix
+        .action
+        .edit
+        .as_mut()
+        .and_then(|it| it.changes.as_mut())
+        .and_then(|changes| changes.get_mut(url));
+    for edit in edits.into_iter().flatten() {
+        edit.range = match map(edit.range) {
+            Some(it) => it,
+            None => return false,
+        };
+    }
+    true
+}
+
 fn are_diagnostics_equal(left: &lsp_types::Diagnostic, right: &lsp_types::Diagnostic) -> bool {
     left.source == right.source
         && left.severity == right.severity
         && left.range == right.range
         && left.message == right.message
 }
+
+impl DiagnosticRevisions {
+    /// Invalidates the diagnostics of all files.
+    pub(crate) fn invalidate_all(&mut self) {
+        self.generation += 1;
+        self.files.clear();
+        self.crates.clear();
+    }
+
+    /// Invalidates the diagnostics of `files` and of the files of `crates`, as well as of the
+    /// crates depending on those.
+    pub(crate) fn invalidate(
+        &mut self,
+        files: impl Iterator<Item = FileId>,
+        mut crates: FxHashSet<CrateId>,
+        crate_graph: &CrateGraph,
+    ) {
+        loop {
+            let rev_deps: Vec<_> = crate_graph
+                .iter()
+                .filter(|krate| !crates.contains(krate))
+                .filter(|&krate| {
+                    crate_graph[krate].dependencies.iter().any(|dep| crates.contains(&dep.crate_id))
+                })
+                .collect();
+            if rev_deps.is_empty() {
+                break;
+            }
+            crates.extend(rev_deps);
+        }
+
+        self.last_change += 1;
+        let change = self.last_change;
+        self.files.extend(files.map(|file_id| (file_id, change)));
+        self.crates.extend(crates.into_iter().map(|krate| (krate, change)));
+    }
+
+    /// Returns the result id of the diagnostics of `file_id`, which belongs to `crates`.
+    pub(crate) fn result_id(&self, file_id: FileId, crates: &[CrateId]) -> String {
+        let last_change = crates
+            .iter()
+            .filter_map(|krate| self.crates.get(krate))
+            .chain(self.files.get(&file_id))
+            .max()
+            .copied()
+            .unwrap_or_default();
+        format!("{}.{}", self.generation, last_change)
+    }
+}
@@ -1,5 +1,5 @@
 //! See [RequestDispatcher].
-use std::{fmt, panic, thread};
+use std::{cell::Cell, fmt, panic, thread};
 
 use ide::Cancelled;
 use lsp_server::ExtractError;
@@ -119,6 +119,56 @@ impl<'a> RequestDispatcher<'a> {
         self
     }
 
+    /// Dispatches the request onto thread pool, letting the handler report
+    /// partial results with `N` notifications before it responds.
+    pub(crate) fn on_with_partial_results<R, N>(
+        &mut self,
+        f: fn(GlobalStateSnapshot, R::Params, &dyn Fn(N::Params)) -> Result<R::Result>,
+    ) -> &mut Self
+    where
+        R: lsp_types::request::Request + 'static,
+        R::Params: DeserializeOwned + panic::UnwindSafe + Send + fmt::Debug,
+        R::Result: Serialize,
+        N: lsp_types::notification::Notification + 'static,
+    {
+        let (req, params, panic_context) = match self.parse::<R>() {
+            Some(it) => it,
+            None => return self,
+        };
+
+        self.global_state.task_pool.handle.spawn_with_sender({
+            let world = self.global_state.snapshot();
+            move |sender| {
+                let sent_partial_result = Cell::new(false);
+                let send_partial_result = |params: N::Params| {
+                    let not = lsp_server::Notification::new(N::METHOD.to_string(), params);
+                    sender.send(Task::Notification(not)).unwrap();
+                    sent_partial_result.set(true);
+                };
+                let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                    let _pctx = stdx::panic_context::enter(panic_context);
+                    f(world, params, &send_partial_result)
+                }));
+                let task = match thread_result_to_response::<R>(req.id.clone(), result) {
+                    Ok(response) => Task::Response(response),
+                    // Retrying would report the partial results already sent a second time, so
+                    // the client has to ask again instead.
+                    Err(_) if sent_partial_result.get() => {
+                        Task::Response(lsp_server::Response::new_err(
+                            req.id,
+                            lsp_server::ErrorCode::ContentModified as i32,
+                            "content modified".to_string(),
+                        ))
+                    }
+                    Err(_) => Task::Retry(req),
+                };
+                sender.send(task).unwrap();
+            }
+        });
+
+        self
+    }
+
     pub(crate) fn finish(&mut self) {
         if let Some(req) = self.req.take() {
             tracing::error!("unknown request: {:?}", req);
@@ -230,7 +280,7 @@ impl<'a> NotificationDispatcher<'a> {
         f: fn(&mut GlobalState, N::Params) -> Result<()>,
     ) -> Result<&mut Self>
     where
-        N: lsp_types::notification::Notification,
+        N: lsp_types::notification::Notification + 'static,
         N::Params: DeserializeOwned + Send,
     {
         let not = match self.not.take() {
@@ -3,29 +3,32 @@
 //!
 //! Each tick provides an immutable snapshot of the state as `WorldSnapshot`.
 
-use std::{sync::Arc, time::Instant};
+use std::{ptr, sync::Arc, time::Instant};
 
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use flycheck::FlycheckHandle;
 use ide::{Analysis, AnalysisHost, Cancellable, Change, FileId};
-use ide_db::base_db::{CrateId, FileLoader, SourceDatabase};
-use lsp_types::{SemanticTokens, Url};
+use ide_db::base_db::{CrateGraph, CrateId, FileLoader, SourceDatabase};
+use lsp_types::{Diagnostic, SemanticTokens, Url};
 use parking_lot::{Mutex, RwLock};
-use proc_macro_api::ProcMacroServer;
-use project_model::{CargoWorkspace, ProjectWorkspace, Target, WorkspaceBuildScripts};
+use proc_macro_api::{ExpansionCache, ProcMacroServer};
+use project_model::{
+    CargoToml, CargoWorkspace, ProjectJson, ProjectWorkspace, Target, TargetKind, UsedDependencies,
+    WorkspaceBuildScripts,
+};
 use rustc_hash::FxHashMap;
-use vfs::AnchoredPathBuf;
+use vfs::{AbsPathBuf, AnchoredPathBuf, VfsPath};
 
 use crate::{
-    config::Config,
-    diagnostics::{CheckFixes, DiagnosticCollection},
+    config::{Config, FlycheckProfile, LinkedProject},
+    diagnostics::{CheckFixes, DiagnosticCollection, DiagnosticRevisions, PulledDiagnostics},
     from_proto,
     line_index::{LineEndings, LineIndex},
     lsp_ext,
     main_loop::Task,
     mem_docs::MemDocs,
     op_queue::OpQueue,
-    reload::{self, SourceRootConfig},
+    reload::{self, FetchWorkspaceKind, SourceRootConfig},
     task_pool::TaskPool,
     to_proto::url_from_abs_path,
     Result,
@@ -39,6 +42,17 @@ pub(crate) struct Handle<H, C> {
 
 pub(crate) type ReqHandler = fn(&mut GlobalState, lsp_server::Response);
 pub(crate) type ReqQueue = lsp_server::ReqQueue<(String, Instant), ReqHandler>;
+pub(crate) type ManifestDiagnosticsCache =
+    Arc<Mutex<FxHashMap<FileId, (Arc<String>, Vec<Diagnostic>)>>>;
+
+/// A flycheck instance, running one profile in one workspace.
+pub(crate) struct Flycheck {
+    pub(crate) handle: FlycheckHandle,
+    /// The index of the checked workspace.
+    pub(crate) workspace: usize,
+    pub(crate) profile: FlycheckProfile,
+    pub(crate) is_running: bool,
+}
 
 /// `GlobalState` is the primary mutable state of the language server
 ///
@@ -54,16 +68,21 @@ pub(crate) struct GlobalState {
     pub(crate) loader: Handle<Box<dyn vfs::loader::Handle>, Receiver<vfs::loader::Message>>,
     pub(crate) config: Arc<Config>,
     pub(crate) analysis_host: AnalysisHost,
+    pub(crate) diagnostic_revisions: Arc<DiagnosticRevisions>,
+    pub(crate) pulled_diagnostics: PulledDiagnostics,
     pub(crate) diagnostics: DiagnosticCollection,
     pub(crate) mem_docs: MemDocs,
     pub(crate) semantic_tokens_cache: Arc<Mutex<FxHashMap<Url, SemanticTokens>>>,
+    /// The diagnostics of member manifests, with the text they were computed for.
+    pub(crate) manifest_diagnostics_cache: ManifestDiagnosticsCache,
     pub(crate) shutdown_requested: bool,
     pub(crate) proc_macro_changed: bool,
     pub(crate) last_reported_status: Option<lsp_ext::ServerStatusParams>,
     pub(crate) source_root_config: SourceRootConfig,
-    pub(crate) proc_macro_client: Option<ProcMacroServer>,
+    /// The running proc-macro servers by executable path, `None` if the server failed to start.
+    pub(crate) proc_macro_servers: FxHashMap<AbsPathBuf, Option<ProcMacroServer>>,
 
-    pub(crate) flycheck: Vec<FlycheckHandle>,
+    pub(crate) flycheck: Vec<Flycheck>,
     pub(crate) flycheck_sender: Sender<flycheck::Message>,
     pub(crate) flycheck_receiver: Receiver<flycheck::Message>,
 
@@ -99,22 +118,47 @@ pub(crate) struct GlobalState {
     /// the user just adds comments or whitespace to Cargo.toml, we do not want
     /// to invalidate any salsa caches.
     pub(crate) workspaces: Arc<Vec<ProjectWorkspace>>,
-    pub(crate) fetch_workspaces_queue: OpQueue<Vec<anyhow::Result<ProjectWorkspace>>>,
+    pub(crate) fetch_workspaces_queue:
+        OpQueue<Vec<anyhow::Result<ProjectWorkspace>>, FetchWorkspaceKind>,
+    /// The projects of the last workspace fetch, with the workspaces loaded from them.
+    pub(crate) fetched_projects: Vec<(LinkedProject, ProjectWorkspace)>,
     pub(crate) fetch_build_data_queue:
         OpQueue<(Arc<Vec<ProjectWorkspace>>, Vec<anyhow::Result<WorkspaceBuildScripts>>)>,
 
     pub(crate) prime_caches_queue: OpQueue<()>,
+
+    /// Projects found by the `discoverProject` command, loaded in addition to the linked ones,
+    /// together with the opened file each of them was discovered for.
+    pub(crate) discovered_projects: Vec<(AbsPathBuf, ProjectJson)>,
+    /// Files the discovery command is asked about on its next run: opened files outside of any
+    /// crate, at most one per directory, and files of projects whose build inputs changed.
+    pub(crate) discover_project_queries: Vec<AbsPathBuf>,
+    pub(crate) discover_project_queue: OpQueue<()>,
+
+    /// The crate graph of `workspaces`, before adding `detached_files` to it.
+    pub(crate) workspace_crate_graph: Arc<CrateGraph>,
+    /// The workspaces `workspace_crate_graph` was built from, with the first crate id of each.
+    pub(crate) workspace_crate_ids: (Arc<Vec<ProjectWorkspace>>, Vec<u32>),
+    /// Opened files which don't belong to any crate, analyzed as standalone crates.
+    pub(crate) detached_files: Vec<AbsPathBuf>,
+    /// Features flipped with `rust-analyzer/toggleFeature`, as `(package, feature, enabled)` in
+    /// the order they were toggled in.
+    pub(crate) feature_toggles: Vec<(String, String, bool)>,
 }
 
 /// An immutable snapshot of the world's state at a point in time.
 pub(crate) struct GlobalStateSnapshot {
     pub(crate) config: Arc<Config>,
     pub(crate) analysis: Analysis,
+    pub(crate) diagnostic_revisions: Arc<DiagnosticRevisions>,
+    pub(crate) pulled_diagnostics: PulledDiagnostics,
     pub(crate) check_fixes: CheckFixes,
     mem_docs: MemDocs,
     pub(crate) semantic_tokens_cache: Arc<Mutex<FxHashMap<Url, SemanticTokens>>>,
+    pub(crate) manifest_diagnostics_cache: ManifestDiagnosticsCache,
     vfs: Arc<RwLock<(vfs::Vfs, FxHashMap<FileId, LineEndings>)>>,
     pub(crate) workspaces: Arc<Vec<ProjectWorkspace>>,
+    pub(crate) proc_macro_cache: Option<Arc<ExpansionCache>>,
 }
 
 impl std::panic::UnwindSafe for GlobalStateSnapshot {}
@@ -144,14 +188,17 @@ impl GlobalState {
             loader,
             config: Arc::new(config.clone()),
             analysis_host,
+            diagnostic_revisions: Arc::new(DiagnosticRevisions::default()),
+            pulled_diagnostics: Arc::new(Default::default()),
             diagnostics: Default::default(),
             mem_docs: MemDocs::default(),
             semantic_tokens_cache: Arc::new(Default::default()),
+            manifest_diagnostics_cache: Arc::new(Default::default()),
             shutdown_requested: false,
             proc_macro_changed: false,
             last_reported_status: None,
             source_root_config: SourceRootConfig::default(),
-            proc_macro_client: None,
+            proc_macro_servers: FxHashMap::default(),
 
             flycheck: Vec::new(),
             flycheck_sender,
@@ -165,9 +212,19 @@ impl GlobalState {
 
             workspaces: Arc::new(Vec::new()),
             fetch_workspaces_queue: OpQueue::default(),
+            fetched_projects: Vec::new(),
             prime_caches_queue: OpQueue::default(),
 
+            discovered_projects: Vec::new(),
+            discover_project_queries: Vec::new(),
+            discover_project_queue: OpQueue::default(),
+
             fetch_build_data_queue: OpQueue::default(),
+
+            workspace_crate_graph: Arc::new(CrateGraph::default()),
+            workspace_crate_ids: (Arc::new(Vec::new()), Vec::new()),
+            detached_files: Vec::new(),
+            feature_toggles: Vec::new(),
         };
         // Apply any required database inputs from the config.
         this.update_configuration(config);
@@ -192,8 +249,10 @@ impl GlobalState {
                 if let Some(path) = vfs.file_path(file.file_id).as_path() {
                     let path = path.to_path_buf();
                     if reload::should_refresh_for_change(&path, file.change_kind) {
-                        self.fetch_workspaces_queue
-                            .request_op(format!("vfs file change: {}", path.display()));
+                        self.fetch_workspaces_queue.request_op(
+                            format!("vfs file change: {}", path.display()),
+                            FetchWorkspaceKind::Full,
+                        );
                     }
                     fs_changes.push((path, file.change_kind));
                     if file.is_created_or_deleted() {
@@ -225,8 +284,22 @@ impl GlobalState {
         };
 
         self.analysis_host.apply_change(change);
+        // The dependencies the sources refer to might have changed.
+        self.manifest_diagnostics_cache.lock().clear();
 
         let raw_database = &self.analysis_host.raw_database();
+        // The diagnostics of the changed files and of the crates depending on theirs might change.
+        let changed_crates = changed_files
+            .iter()
+            .flat_map(|file| {
+                raw_database.relevant_crates(file.file_id).iter().copied().collect::<Vec<_>>()
+            })
+            .collect();
+        Arc::make_mut(&mut self.diagnostic_revisions).invalidate(
+            changed_files.iter().map(|file| file.file_id),
+            changed_crates,
+            &raw_database.crate_graph(),
+        );
         self.proc_macro_changed =
             changed_files.iter().filter(|file| !file.is_created_or_deleted()).any(|file| {
                 let crates = raw_database.relevant_crates(file.file_id);
@@ -237,18 +310,39 @@ impl GlobalState {
         true
     }
 
+    /// Applies `change`, which might change the crate graph, to the analysis database.
+    pub(crate) fn apply_change(&mut self, change: Change) {
+        self.analysis_host.apply_change(change);
+        self.invalidate_pulled_diagnostics();
+    }
+
+    /// Invalidates the diagnostics clients pulled for all files.
+    pub(crate) fn invalidate_pulled_diagnostics(&mut self) {
+        Arc::make_mut(&mut self.diagnostic_revisions).invalidate_all();
+        self.pulled_diagnostics.lock().clear();
+    }
+
     pub(crate) fn snapshot(&self) -> GlobalStateSnapshot {
         GlobalStateSnapshot {
             config: Arc::clone(&self.config),
             workspaces: Arc::clone(&self.workspaces),
             analysis: self.analysis_host.analysis(),
+            diagnostic_revisions: Arc::clone(&self.diagnostic_revisions),
+            pulled_diagnostics: Arc::clone(&self.pulled_diagnostics),
             vfs: Arc::clone(&self.vfs),
             check_fixes: Arc::clone(&self.diagnostics.check_fixes),
             mem_docs: self.mem_docs.clone(),
             semantic_tokens_cache: Arc::clone(&self.semantic_tokens_cache),
+            manifest_diagnostics_cache: Arc::clone(&self.manifest_diagnostics_cache),
+            proc_macro_cache: self.proc_macro_cache().cloned(),
         }
     }
 
+    /// The expansion cache shared by the proc-macro servers.
+    pub(crate) fn proc_macro_cache(&self) -> Option<&Arc<ExpansionCache>> {
+        self.proc_macro_servers.values().flatten().find_map(|it| it.expansion_cache())
+    }
+
     pub(crate) fn send_request<R: lsp_types::request::Request>(
         &mut self,
         params: R::Params,
@@ -305,7 +399,7 @@ impl GlobalState {
         }
     }
 
-    fn send(&mut self, message: lsp_server::Message) {
+    pub(crate) fn send(&mut self, message: lsp_server::Message) {
         self.sender.send(message).unwrap()
     }
 }
@@ -345,6 +439,96 @@ impl GlobalStateSnapshot {
         url_from_abs_path(path)
     }
 
+    /// Returns the `kind` command template of the `rust-project.json` crate `file_id` belongs to.
+    pub(crate) fn project_json_runnable(
+        &self,
+        file_id: FileId,
+        kind: project_model::RunnableKind,
+    ) -> Cancellable<Option<project_model::Runnable>> {
+        let crate_id = match &*self.analysis.crate_for(file_id)? {
+            &[crate_id, ..] => crate_id,
+            _ => return Ok(None),
+        };
+        let root = self.analysis.crate_root(crate_id)?;
+        let path = self.vfs.read().0.file_path(root);
+        let path = match path.as_path() {
+            Some(it) => it,
+            None => return Ok(None),
+        };
+        Ok(self.workspaces.iter().find_map(|ws| match ws {
+            ProjectWorkspace::Json { project, .. } => project.runnable(path, kind).cloned(),
+            ProjectWorkspace::Cargo { .. } | ProjectWorkspace::DetachedFiles { .. } => None,
+        }))
+    }
+
+    /// Returns the analysis of `file_id` if it is the `Cargo.toml` of a workspace member.
+    pub(crate) fn cargo_toml(&self, file_id: FileId) -> Cancellable<Option<CargoToml<'_>>> {
+        let path = self.vfs.read().0.file_path(file_id);
+        let path = match path.as_path() {
+            Some(it) if it.file_name().unwrap_or_default() == "Cargo.toml" => it.to_path_buf(),
+            _ => return Ok(None),
+        };
+        let text = self.analysis.file_text(file_id)?;
+        Ok(self.workspaces.iter().find_map(|ws| match ws {
+            ProjectWorkspace::Cargo { cargo, .. } => CargoToml::new(cargo, &path, &text),
+            ProjectWorkspace::Json { .. } | ProjectWorkspace::DetachedFiles { .. } => None,
+        }))
+    }
+
+    /// Returns the dependencies the targets of the package of `cargo_toml` refer to, or `None` if
+    /// some of them aren't loaded.
+    pub(crate) fn used_dependencies(
+        &self,
+        cargo_toml: &CargoToml<'_>,
+    ) -> Cancellable<Option<UsedDependencies>> {
+        let mut res = UsedDependencies::default();
+        for (kind, root) in cargo_toml.targets() {
+            let file_id = match self.vfs.read().0.file_id(&VfsPath::from(root.to_path_buf())) {
+                Some(it) => it,
+                None => return Ok(None),
+            };
+            let used = if kind == TargetKind::BuildScript {
+                &mut res.build_script
+            } else {
+                &mut res.targets
+            };
+            let mut found = false;
+            for crate_id in self.analysis.crate_for(file_id)? {
+                if self.analysis.crate_root(crate_id)? == file_id {
+                    used.extend(self.analysis.used_dependencies(crate_id)?);
+                    found = true;
+                }
+            }
+            if !found {
+                return Ok(None);
+            }
+        }
+        Ok(Some(res))
+    }
+
+    /// Returns the Rust files of the workspace which aren't opened, with their urls.
+    pub(crate) fn unopened_workspace_files(&self) -> Cancellable<Vec<(FileId, Url)>> {
+        let files: Vec<_> = {
+            let vfs = &self.vfs.read().0;
+            vfs.iter()
+                .filter(|&(_, path)| {
+                    !self.mem_docs.contains(path)
+                        && path
+                            .as_path()
+                            .map_or(false, |path| path.extension().unwrap_or_default() == "rs")
+                })
+                .map(|(file_id, _)| (file_id, file_id_to_url(vfs, file_id)))
+                .collect()
+        };
+        let mut res = Vec::new();
+        for (file_id, url) in files {
+            if !self.analysis.is_library_file(file_id)? {
+                res.push((file_id, url));
+            }
+        }
+        Ok(res)
+    }
+
     pub(crate) fn cargo_target_for_crate_root(
         &self,
         crate_id: CrateId,
@@ -360,6 +544,41 @@ impl GlobalStateSnapshot {
             ProjectWorkspace::DetachedFiles { .. } => None,
         })
     }
+
+    /// Returns the `-p` flags of the Cargo packages owning `file_id`, by the index of their
+    /// workspace.
+    pub(crate) fn packages_for_file(
+        &self,
+        file_id: FileId,
+    ) -> Cancellable<FxHashMap<usize, Vec<String>>> {
+        Ok(self.packages_for_crates(self.analysis.crate_for(file_id)?))
+    }
+
+    /// Returns the `-p` flags of the Cargo packages of `crates`, by the index of their workspace.
+    pub(crate) fn packages_for_crates(
+        &self,
+        crates: impl IntoIterator<Item = CrateId>,
+    ) -> FxHashMap<usize, Vec<String>> {
+        let mut res: FxHashMap<usize, Vec<String>> = FxHashMap::default();
+        for crate_id in crates {
+            let (cargo, target) = match self.cargo_target_for_crate_root(crate_id) {
+                Some(it) => it,
+                None => continue,
+            };
+            let ws = self.workspaces.iter().position(|ws| match ws {
+                ProjectWorkspace::Cargo { cargo: it, .. } => ptr::eq(it, cargo),
+                ProjectWorkspace::Json { .. } | ProjectWorkspace::DetachedFiles { .. } => false,
+            });
+            if let Some(ws) = ws {
+                let package = cargo.package_flag(&cargo[cargo[target].package]);
+                let packages = res.entry(ws).or_default();
+                if !packages.contains(&package) {
+                    packages.push(package);
+                }
+            }
+        }
+        res
+    }
 }
 
 pub(crate) fn file_id_to_url(vfs: &vfs::Vfs, id: FileId) -> Url {
@@ -373,3 +592,120 @@ pub(crate) fn url_to_file_id(vfs: &vfs::Vfs, url: &Url) -> Result<FileId> {
     let res = vfs.file_id(&path).ok_or_else(|| format!("file not found: {}", path))?;
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use ide::Change;
+    use ide_db::base_db::{CrateDisplayName, CrateOrigin, Edition, Env, VfsPath};
+    use lsp_types::ClientCapabilities;
+    use serde_json::json;
+    use vfs::file_set::FileSetConfig;
+
+    use super::*;
+
+    /// Returns the metadata of the package in `root/dir` with a single target.
+    fn package(root: &str, dir: &str, name: &str, kind: &str, src: &str) -> serde_json::Value {
+        json!({
+            "name": name,
+            "version": "0.1.0",
+            "id": format!("{} 0.1.0 (path+file:///{})", name, name),
+            "source": null,
+            "dependencies": [],
+            "targets": [{
+                "kind": [kind],
+                "crate_types": [kind],
+                "name": name,
+                "src_path": format!("{}/{}{}", root, dir, src),
+                "edition": "2021",
+            }],
+            "features": {},
+            "manifest_path": format!("{}/{}Cargo.toml", root, dir),
+            "metadata": null,
+            "edition": "2021",
+        })
+    }
+
+    #[test]
+    fn packages_for_file() {
+        // `app` is the root package of the workspace, `util` a member nested in its directory.
+        let root = AbsPathBuf::assert(std::env::temp_dir().join("ra-packages-for-file"));
+        let root_str = root.display().to_string();
+        let meta = json!({
+            "packages": [
+                package(&root_str, "", "app", "bin", "src/main.rs"),
+                package(&root_str, "util/", "util", "lib", "src/lib.rs"),
+            ],
+            "workspace_members": ["app 0.1.0 (path+file:///app)", "util 0.1.0 (path+file:///util)"],
+            "resolve": null,
+            "target_directory": format!("{}/target", root_str),
+            "version": 1,
+            "workspace_root": root_str,
+            "metadata": null,
+        });
+
+        let config = Config::new(root.clone(), ClientCapabilities::default());
+        let mut state = GlobalState::new(unbounded().0, config);
+        state.workspaces = Arc::new(vec![ProjectWorkspace::Cargo {
+            cargo: CargoWorkspace::new(serde_json::from_value(meta).unwrap()),
+            build_scripts: WorkspaceBuildScripts::default(),
+            sysroot: None,
+            rustc: None,
+            rustc_cfg: Default::default(),
+            cfg_overrides: Default::default(),
+        }]);
+        let mut fsc = FileSetConfig::builder();
+        fsc.add_file_set(vec![VfsPath::from(root.clone())]);
+        fsc.add_file_set(vec![VfsPath::from(root.join("util"))]);
+        state.source_root_config =
+            SourceRootConfig { fsc: fsc.build(), local_filesets: vec![0, 1] };
+
+        let file_id = |state: &GlobalState, path: &str| {
+            state.vfs.read().0.file_id(&VfsPath::from(root.join(path))).unwrap()
+        };
+        for (path, text) in [
+            ("src/main.rs", "fn main() {}"),
+            ("util/src/lib.rs", "mod nested;"),
+            ("util/src/nested.rs", ""),
+        ] {
+            let path = VfsPath::from(root.join(path));
+            state.vfs.write().0.set_file_contents(path, Some(text.as_bytes().to_vec()));
+        }
+        state.process_changes();
+
+        let mut crate_graph = CrateGraph::default();
+        for (path, name) in [("src/main.rs", "app"), ("util/src/lib.rs", "util")] {
+            crate_graph.add_crate_root(
+                file_id(&state, path),
+                Edition::CURRENT,
+                Some(CrateDisplayName::from_canonical_name(name.to_string())),
+                None,
+                Default::default(),
+                Default::default(),
+                Env::default(),
+                Ok(Vec::new()),
+                false,
+                CrateOrigin::CratesIo { repo: None },
+            );
+        }
+        let mut change = Change::new();
+        change.set_crate_graph(crate_graph);
+        state.apply_change(change);
+
+        let snap = state.snapshot();
+        let packages = |path: &str| {
+            let mut packages: Vec<_> =
+                snap.packages_for_file(file_id(&state, path)).unwrap().into_iter().collect();
+            packages.sort();
+            packages
+        };
+        assert_eq!(packages("src/main.rs"), [(0, vec!["app".to_string()])]);
+        assert_eq!(packages("util/src/lib.rs"), [(0, vec!["util".to_string()])]);
+        assert_eq!(packages("util/src/nested.rs"), [(0, vec!["util".to_string()])]);
+
+        // Without name resolution, files go by the crates of their source root.
+        let nested = file_id(&state, "util/src/nested.rs");
+        let crates = state.analysis_host.raw_database().relevant_crates(nested);
+        let packages = snap.packages_for_crates(crates.iter().copied());
+        assert_eq!(packages.get(&0), Some(&vec!["util".to_string()]));
+    }
+}
@@ -4,6 +4,7 @@
 
 use std::{
     io::Write as _,
+    mem, ops,
     process::{self, Stdio},
 };
 
@@ -25,7 +26,8 @@ use lsp_types::{
     SemanticTokensRangeParams, SemanticTokensRangeResult, SemanticTokensResult, SymbolInformation,
     SymbolTag, TextDocumentIdentifier, Url, WorkspaceEdit,
 };
-use project_model::{ManifestPath, ProjectWorkspace, TargetKind};
+use project_model::{ManifestDiagnosticKind, ManifestPath, ProjectWorkspace, TargetKind};
+use rustc_hash::FxHashMap;
 use serde_json::json;
 use stdx::{format_to, never};
 use syntax::{algo, ast, AstNode, TextRange, TextSize, T};
@@ -40,14 +42,17 @@ use crate::{
     line_index::LineEndings,
     lsp_ext::{self, PositionOrRange, ViewCrateGraphParams, WorkspaceSymbolParams},
     lsp_utils::{all_edits_are_disjoint, invalid_params_error},
+    reload::FetchWorkspaceKind,
     to_proto, LspError, Result,
 };
 
 pub(crate) fn handle_workspace_reload(state: &mut GlobalState, _: ()) -> Result<()> {
-    state.proc_macro_client = None;
+    state.proc_macro_servers.clear();
     state.proc_macro_changed = false;
-    state.fetch_workspaces_queue.request_op("reload workspace request".to_string());
-    state.fetch_build_data_queue.request_op("reload workspace request".to_string());
+    state
+        .fetch_workspaces_queue
+        .request_op("reload workspace request".to_string(), FetchWorkspaceKind::Full);
+    state.fetch_build_data_queue.request_op("reload workspace request".to_string(), ());
     Ok(())
 }
 
@@ -79,6 +84,20 @@ pub(crate) fn handle_analyzer_status(
             if snap.workspaces.len() == 1 { "" } else { "s" }
         );
     }
+    if let Some(cache) = &snap.proc_macro_cache {
+        let stats = cache.stats();
+        buf.push_str("\nProc-macro expansion cache:\n");
+        format_to!(
+            buf,
+            "{} entries ({} bytes) in {}, {} hits and {} misses this session.\n",
+            stats.entries,
+            stats.total_bytes,
+            cache.dir().display(),
+            stats.hits,
+            stats.misses,
+        );
+        buf.push_str("Use the `rust-analyzer/clearProcMacroCache` request to clear it.\n");
+    }
     buf.push_str("\nAnalysis:\n");
     buf.push_str(
         &snap
@@ -106,6 +125,49 @@ pub(crate) fn handle_shuffle_crate_graph(state: &mut GlobalState, _: ()) -> Resu
     Ok(())
 }
 
+pub(crate) fn handle_clear_proc_macro_cache(state: &mut GlobalState, _: ()) -> Result<()> {
+    if let Some(cache) = state.proc_macro_cache() {
+        cache.clear()?;
+    }
+    Ok(())
+}
+
+pub(crate) fn handle_toggle_feature(
+    state: &mut GlobalState,
+    params: lsp_ext::ToggleFeatureParams,
+) -> Result<bool> {
+    let enabled = state
+        .toggle_feature(&params.package, &params.feature)
+        .map_err(|message| LspError::new(ErrorCode::InvalidParams as i32, message))?;
+    if state.config.publish_diagnostics() {
+        state.update_diagnostics();
+    }
+    Ok(enabled)
+}
+
+pub(crate) fn handle_set_active_target(
+    state: &mut GlobalState,
+    params: lsp_ext::SetActiveTargetParams,
+) -> Result<()> {
+    let file_id = from_proto::file_id(&state.snapshot(), &params.text_document.uri)?;
+    state.analysis_host.set_active_target(file_id, params.target);
+    state.invalidate_pulled_diagnostics();
+    if state.config.publish_diagnostics() {
+        state.update_diagnostics();
+    }
+    Ok(())
+}
+
+pub(crate) fn handle_file_targets(
+    snap: GlobalStateSnapshot,
+    params: lsp_types::TextDocumentIdentifier,
+) -> Result<Vec<String>> {
+    let _p = profile::span("handle_file_targets");
+    let file_id = from_proto::file_id(&snap, &params.uri)?;
+    let res = snap.analysis.file_targets(file_id)?;
+    Ok(res)
+}
+
 pub(crate) fn handle_syntax_tree(
     snap: GlobalStateSnapshot,
     params: lsp_ext::SyntaxTreeParams,
@@ -554,12 +616,66 @@ pub(crate) fn handle_will_rename_files(
     }
 }
 
+pub(crate) fn handle_will_create_files(
+    snap: GlobalStateSnapshot,
+    params: lsp_types::CreateFilesParams,
+) -> Result<Option<lsp_types::WorkspaceEdit>> {
+    let _p = profile::span("handle_will_create_files");
+
+    let mut source_changes = Vec::new();
+    for file_create in params.files {
+        let path =
+            match Url::parse(&file_create.uri).ok().and_then(|it| from_proto::vfs_path(&it).ok()) {
+                Some(it) => it,
+                None => continue,
+            };
+        source_changes.extend(snap.analysis.will_create_file(&path)?);
+    }
+    file_operations_edit(&snap, source_changes)
+}
+
+pub(crate) fn handle_will_delete_files(
+    snap: GlobalStateSnapshot,
+    params: lsp_types::DeleteFilesParams,
+) -> Result<Option<lsp_types::WorkspaceEdit>> {
+    let _p = profile::span("handle_will_delete_files");
+
+    let paths: Vec<_> = params
+        .files
+        .iter()
+        .filter_map(|it| from_proto::vfs_path(&Url::parse(&it.uri).ok()?).ok())
+        .collect();
+    let source_change =
+        snap.analysis.will_delete_files(&paths, snap.config.remove_imports_on_delete())?;
+    file_operations_edit(&snap, source_change.into_iter().collect())
+}
+
+/// Combines the edits for several file operations, merging the edits of each file.
+fn file_operations_edit(
+    snap: &GlobalStateSnapshot,
+    source_changes: Vec<SourceChange>,
+) -> Result<Option<lsp_types::WorkspaceEdit>> {
+    let source_change =
+        source_changes.into_iter().fold(SourceChange::default(), SourceChange::merge);
+    if source_change.source_file_edits.is_empty() {
+        Ok(None)
+    } else {
+        to_proto::workspace_edit(snap, source_change).map(Some)
+    }
+}
+
 pub(crate) fn handle_goto_definition(
     snap: GlobalStateSnapshot,
     params: lsp_types::GotoDefinitionParams,
 ) -> Result<Option<lsp_types::GotoDefinitionResponse>> {
     let _p = profile::span("handle_goto_definition");
     let position = from_proto::file_position(&snap, params.text_document_position_params)?;
+    if let Some(cargo_toml) = snap.cargo_toml(position.file_id)? {
+        let res = cargo_toml.definition(position.offset.into()).map(|(_, crate_root)| {
+            Location::new(to_proto::url_from_abs_path(crate_root), Range::default()).into()
+        });
+        return Ok(res);
+    }
     let nav_info = match snap.analysis.goto_definition(position)? {
         None => return Ok(None),
         Some(it) => it,
@@ -713,8 +829,10 @@ pub(crate) fn handle_runnables(
         }
         let mut runnable = to_proto::runnable(&snap, runnable)?;
         if expect_test {
-            runnable.label = format!("{} + expect", runnable.label);
-            runnable.args.expect_test = Some(true);
+            if let lsp_ext::RunnableArgs::Cargo(args) = &mut runnable.args {
+                runnable.label = format!("{} + expect", runnable.label);
+                args.expect_test = Some(true);
+            }
         }
         res.push(runnable);
     }
@@ -728,7 +846,7 @@ pub(crate) fn handle_runnables(
                     label: format!("cargo {} -p {} --all-targets", cmd, spec.package),
                     location: None,
                     kind: lsp_ext::RunnableKind::Cargo,
-                    args: lsp_ext::CargoRunnable {
+                    args: lsp_ext::RunnableArgs::Cargo(lsp_ext::CargoRunnable {
                         workspace_root: Some(spec.workspace_root.clone().into()),
                         override_cargo: config.override_cargo.clone(),
                         cargo_args: vec![
@@ -740,12 +858,16 @@ pub(crate) fn handle_runnables(
                         cargo_extra_args: config.cargo_extra_args.clone(),
                         executable_args: Vec::new(),
                         expect_test: None,
-                    },
+                    }),
                 })
             }
         }
         None => {
-            if !snap.config.linked_projects().is_empty()
+            let check = snap.project_json_runnable(file_id, project_model::RunnableKind::Check)?;
+            if let Some(template) = check {
+                let label = format!("{} {}", template.program, template.args.join(" "));
+                res.push(to_proto::project_json_runnable(label, None, template, ""));
+            } else if !snap.config.linked_projects().is_empty()
                 || !snap
                     .config
                     .discovered_projects
@@ -757,14 +879,14 @@ pub(crate) fn handle_runnables(
                     label: "cargo check --workspace".to_string(),
                     location: None,
                     kind: lsp_ext::RunnableKind::Cargo,
-                    args: lsp_ext::CargoRunnable {
+                    args: lsp_ext::RunnableArgs::Cargo(lsp_ext::CargoRunnable {
                         workspace_root: None,
                         override_cargo: config.override_cargo,
                         cargo_args: vec!["check".to_string(), "--workspace".to_string()],
                         cargo_extra_args: config.cargo_extra_args,
                         executable_args: Vec::new(),
                         expect_test: None,
-                    },
+                    }),
                 });
             }
         }
@@ -886,6 +1008,20 @@ pub(crate) fn handle_completion_resolve(
     Ok(original_completion)
 }
 
+pub(crate) fn handle_document_link(
+    snap: GlobalStateSnapshot,
+    params: lsp_types::DocumentLinkParams,
+) -> Result<Option<Vec<lsp_types::DocumentLink>>> {
+    let _p = profile::span("handle_document_link");
+    let file_id = from_proto::file_id(&snap, &params.text_document.uri)?;
+    let line_index = snap.file_line_index(file_id)?;
+    let mut res = Vec::new();
+    for link in snap.analysis.document_links(file_id)? {
+        res.extend(to_proto::document_link(&snap, &line_index, link)?);
+    }
+    Ok(Some(res))
+}
+
 pub(crate) fn handle_folding_range(
     snap: GlobalStateSnapshot,
     params: FoldingRangeParams,
@@ -929,6 +1065,22 @@ pub(crate) fn handle_hover(
     };
 
     let file_range = from_proto::file_range(&snap, params.text_document, range)?;
+    if let Some(cargo_toml) = snap.cargo_toml(file_range.file_id)? {
+        let line_index = snap.file_line_index(file_range.file_id)?;
+        let hover = cargo_toml.hover(file_range.range.start().into()).map(|(range, markup)| {
+            lsp_ext::Hover {
+                hover: lsp_types::Hover {
+                    contents: HoverContents::Markup(lsp_types::MarkupContent {
+                        kind: lsp_types::MarkupKind::Markdown,
+                        value: markup,
+                    }),
+                    range: Some(to_proto::range(&line_index, text_range(range))),
+                },
+                actions: Vec::new(),
+            }
+        });
+        return Ok(hover);
+    }
     let info = match snap.analysis.hover(&snap.config.hover(), file_range)? {
         None => return Ok(None),
         Some(info) => info,
@@ -1094,7 +1246,7 @@ pub(crate) fn handle_code_action(
     }
 
     // Fixes from `cargo check`.
-    for fix in snap.check_fixes.get(&frange.file_id).into_iter().flatten() {
+    for fix in snap.check_fixes.values().filter_map(|it| it.get(&frange.file_id)).flatten() {
         // FIXME: this mapping is awkward and shouldn't exist. Refactor
         // `snap.check_fixes` to not convert to LSP prematurely.
         let intersect_fix_range = fix
@@ -1275,6 +1427,25 @@ pub(crate) fn handle_document_highlight(
     Ok(Some(res))
 }
 
+pub(crate) fn handle_linked_editing_range(
+    snap: GlobalStateSnapshot,
+    params: lsp_types::LinkedEditingRangeParams,
+) -> Result<Option<lsp_types::LinkedEditingRanges>> {
+    let _p = profile::span("handle_linked_editing_range");
+    let position = from_proto::file_position(&snap, params.text_document_position_params)?;
+    let line_index = snap.file_line_index(position.file_id)?;
+
+    let ranges = match snap.analysis.linked_editing_ranges(position)? {
+        None => return Ok(None),
+        Some(ranges) => ranges,
+    };
+    let res = lsp_types::LinkedEditingRanges {
+        ranges: ranges.into_iter().map(|range| to_proto::range(&line_index, range)).collect(),
+        word_pattern: None,
+    };
+    Ok(Some(res))
+}
+
 pub(crate) fn handle_ssr(
     snap: GlobalStateSnapshot,
     params: lsp_ext::SsrParams,
@@ -1295,12 +1466,100 @@ pub(crate) fn handle_ssr(
     to_proto::workspace_edit(&snap, source_change)
 }
 
+pub(crate) fn handle_document_diagnostic(
+    snap: GlobalStateSnapshot,
+    params: lsp_ext::DocumentDiagnosticParams,
+) -> Result<lsp_ext::DocumentDiagnosticReport> {
+    let _p = profile::span("handle_document_diagnostic");
+    let file_id = from_proto::file_id(&snap, &params.text_document.uri)?;
+    diagnostic_report(&snap, file_id, params.previous_result_id.as_deref())
+}
+
+/// Reports the diagnostics of the unopened workspace files, streaming them in batches as partial
+/// results if the client asked for them.
+pub(crate) fn handle_workspace_diagnostic(
+    snap: GlobalStateSnapshot,
+    params: lsp_ext::WorkspaceDiagnosticParams,
+    send_partial_result: &dyn Fn(lsp_ext::WorkspaceDiagnosticProgressParams),
+) -> Result<lsp_ext::WorkspaceDiagnosticReport> {
+    const PARTIAL_RESULT_BATCH_SIZE: usize = 32;
+
+    let _p = profile::span("handle_workspace_diagnostic");
+    let previous_result_ids: FxHashMap<_, _> =
+        params.previous_result_ids.into_iter().map(|it| (it.uri, it.value)).collect();
+    let token = params.partial_result_params.partial_result_token;
+    let flush = |items: &mut Vec<_>| {
+        if let Some(token) = &token {
+            if !items.is_empty() {
+                let value = lsp_ext::WorkspaceDiagnosticReport { items: mem::take(items) };
+                send_partial_result(lsp_ext::WorkspaceDiagnosticProgressParams {
+                    token: token.clone(),
+                    value,
+                });
+            }
+        }
+    };
+
+    let mut items = Vec::new();
+    for (file_id, uri) in snap.unopened_workspace_files()? {
+        let previous_result_id = previous_result_ids.get(&uri).map(String::as_str);
+        let report = diagnostic_report(&snap, file_id, previous_result_id)?;
+        items.push(lsp_ext::WorkspaceDocumentDiagnosticReport { uri, version: None, report });
+        if items.len() == PARTIAL_RESULT_BATCH_SIZE {
+            flush(&mut items);
+        }
+    }
+    // Once partial results are reported, the response itself has to be empty.
+    flush(&mut items);
+    Ok(lsp_ext::WorkspaceDiagnosticReport { items })
+}
+
+/// Reports the native diagnostics of `file_id`, unless they can't have changed since the client
+/// pulled them with `previous_result_id`.
+fn diagnostic_report(
+    snap: &GlobalStateSnapshot,
+    file_id: FileId,
+    previous_result_id: Option<&str>,
+) -> Result<lsp_ext::DocumentDiagnosticReport> {
+    let crates = snap.analysis.relevant_crates_for(file_id)?;
+    let result_id = snap.diagnostic_revisions.result_id(file_id, &crates);
+    if previous_result_id == Some(&*result_id) {
+        let report = lsp_ext::UnchangedDocumentDiagnosticReport { result_id };
+        return Ok(lsp_ext::DocumentDiagnosticReport::Unchanged(report));
+    }
+    if let Some((cached_id, items)) = snap.pulled_diagnostics.lock().get(&file_id) {
+        if *cached_id == result_id {
+            let items = items.clone();
+            let report =
+                lsp_ext::FullDocumentDiagnosticReport { result_id: Some(result_id), items };
+            return Ok(lsp_ext::DocumentDiagnosticReport::Full(report));
+        }
+    }
+    // Manifest diagnostics are still published, as clients don't pull them for `Cargo.toml`.
+    let items = if !snap.config.publish_diagnostics()
+        || snap.analysis.is_library_file(file_id)?
+        || snap.cargo_toml(file_id)?.is_some()
+    {
+        Vec::new()
+    } else {
+        publish_diagnostics(snap, file_id)?
+    };
+    snap.pulled_diagnostics.lock().insert(file_id, (result_id.clone(), items.clone()));
+    let report = lsp_ext::FullDocumentDiagnosticReport { result_id: Some(result_id), items };
+    Ok(lsp_ext::DocumentDiagnosticReport::Full(report))
+}
+
 pub(crate) fn publish_diagnostics(
     snap: &GlobalStateSnapshot,
     file_id: FileId,
 ) -> Result<Vec<Diagnostic>> {
     let _p = profile::span("publish_diagnostics");
+    if let Some(diagnostics) = manifest_diagnostics(snap, file_id)? {
+        return Ok(diagnostics);
+    }
     let line_index = snap.file_line_index(file_id)?;
+    // If the file is analyzed for several targets, say which one the diagnostics are for.
+    let target = snap.analysis.file_targets(file_id)?.into_iter().next();
 
     let diagnostics: Vec<Diagnostic> = snap
         .analysis
@@ -1318,7 +1577,10 @@ pub(crate) fn publish_diagnostics(
                 .unwrap(),
             }),
             source: Some("rust-analyzer".to_string()),
-            message: d.message,
+            message: match &target {
+                Some(target) => format!("{} (target: {})", d.message, target),
+                None => d.message,
+            },
             related_information: None,
             tags: if d.unused { Some(vec![DiagnosticTag::UNNECESSARY]) } else { None },
             data: None,
@@ -1327,6 +1589,62 @@ pub(crate) fn publish_diagnostics(
     Ok(diagnostics)
 }
 
+/// Returns the diagnostics of `file_id` if it is the `Cargo.toml` of a workspace member. They are
+/// cached until the files or the workspaces change.
+fn manifest_diagnostics(
+    snap: &GlobalStateSnapshot,
+    file_id: FileId,
+) -> Result<Option<Vec<Diagnostic>>> {
+    let text = snap.analysis.file_text(file_id)?;
+    if let Some((cached_text, diagnostics)) = snap.manifest_diagnostics_cache.lock().get(&file_id) {
+        if *cached_text == text {
+            return Ok(Some(diagnostics.clone()));
+        }
+    }
+    let cargo_toml = match snap.cargo_toml(file_id)? {
+        Some(it) => it,
+        None => return Ok(None),
+    };
+    let line_index = snap.file_line_index(file_id)?;
+    let used = snap.used_dependencies(&cargo_toml)?;
+    let diagnostics: Vec<_> = cargo_toml
+        .diagnostics(used.as_ref())
+        .into_iter()
+        .map(|d| {
+            let (severity, code) = match d.kind {
+                ManifestDiagnosticKind::UnknownFeature => {
+                    (lsp_types::DiagnosticSeverity::ERROR, "unknown-feature")
+                }
+                ManifestDiagnosticKind::UnusedDependency => {
+                    (lsp_types::DiagnosticSeverity::WARNING, "unused-dependency")
+                }
+            };
+            Diagnostic {
+                range: to_proto::range(&line_index, text_range(d.range)),
+                severity: Some(severity),
+                code: Some(NumberOrString::String(code.to_string())),
+                code_description: None,
+                source: Some("rust-analyzer".to_string()),
+                message: d.message,
+                related_information: None,
+                tags: match d.kind {
+                    ManifestDiagnosticKind::UnusedDependency => {
+                        Some(vec![DiagnosticTag::UNNECESSARY])
+                    }
+                    ManifestDiagnosticKind::UnknownFeature => None,
+                },
+                data: None,
+            }
+        })
+        .collect();
+    snap.manifest_diagnostics_cache.lock().insert(file_id, (text, diagnostics.clone()));
+    Ok(Some(diagnostics))
+}
+
+fn text_range(range: ops::Range<usize>) -> TextRange {
+    TextRange::new(TextSize::from(range.start as u32), TextSize::from(range.end as u32))
+}
+
 pub(crate) fn handle_inlay_hints(
     snap: GlobalStateSnapshot,
     params: InlayHintParams,
@@ -1348,7 +1666,7 @@ pub(crate) fn handle_inlay_hints(
             .map(|it| {
                 to_proto::inlay_hint(&snap, &line_index, inlay_hints_config.render_colons, it)
             })
-            .collect(),
+            .collect::<Result<Vec<_>>>()?,
     ))
 }
 
@@ -1480,6 +1798,74 @@ pub(crate) fn handle_call_hierarchy_outgoing(
     Ok(Some(res))
 }
 
+pub(crate) fn handle_type_hierarchy_prepare(
+    snap: GlobalStateSnapshot,
+    params: lsp_types::TextDocumentPositionParams,
+) -> Result<Option<Vec<lsp_ext::TypeHierarchyItem>>> {
+    let _p = profile::span("handle_type_hierarchy_prepare");
+    let position = from_proto::file_position(&snap, params)?;
+
+    let nav_info = match snap.analysis.type_hierarchy(position)? {
+        None => return Ok(None),
+        Some(it) => it,
+    };
+
+    let RangeInfo { range: _, info: navs } = nav_info;
+    let res = navs
+        .into_iter()
+        .map(|it| to_proto::type_hierarchy_item(&snap, it))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Some(res))
+}
+
+pub(crate) fn handle_type_hierarchy_supertypes(
+    snap: GlobalStateSnapshot,
+    params: lsp_ext::TypeHierarchyParams,
+) -> Result<Option<Vec<lsp_ext::TypeHierarchyItem>>> {
+    let _p = profile::span("handle_type_hierarchy_supertypes");
+    let fpos = type_hierarchy_item_position(&snap, params.item)?;
+
+    let navs = match snap.analysis.supertypes(fpos)? {
+        None => return Ok(None),
+        Some(it) => it,
+    };
+    let res = navs
+        .into_iter()
+        .map(|it| to_proto::type_hierarchy_item(&snap, it))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Some(res))
+}
+
+pub(crate) fn handle_type_hierarchy_subtypes(
+    snap: GlobalStateSnapshot,
+    params: lsp_ext::TypeHierarchyParams,
+) -> Result<Option<Vec<lsp_ext::TypeHierarchyItem>>> {
+    let _p = profile::span("handle_type_hierarchy_subtypes");
+    let fpos = type_hierarchy_item_position(&snap, params.item)?;
+
+    let navs = match snap.analysis.subtypes(fpos)? {
+        None => return Ok(None),
+        Some(it) => it,
+    };
+    let res = navs
+        .into_iter()
+        .map(|it| to_proto::type_hierarchy_item(&snap, it))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Some(res))
+}
+
+fn type_hierarchy_item_position(
+    snap: &GlobalStateSnapshot,
+    item: lsp_ext::TypeHierarchyItem,
+) -> Result<FilePosition> {
+    let doc = TextDocumentIdentifier::new(item.uri);
+    let frange = from_proto::file_range(snap, doc, item.selection_range)?;
+    Ok(FilePosition { file_id: frange.file_id, offset: frange.range.start() })
+}
+
 pub(crate) fn handle_semantic_tokens_full(
     snap: GlobalStateSnapshot,
     params: SemanticTokensParams,
@@ -1690,7 +2076,8 @@ fn runnable_action_links(
         group.commands.push(to_command_link(run_command, r.label.clone()));
     }
 
-    if hover_actions_config.debug && client_commands_config.debug_single {
+    let can_debug = r.kind == lsp_ext::RunnableKind::Cargo;
+    if hover_actions_config.debug && can_debug && client_commands_config.debug_single {
         let dbg_command = to_proto::command::debug_single(&r);
         group.commands.push(to_command_link(dbg_command, r.label));
     }
@@ -8,20 +8,25 @@ use std::{
 
 use always_assert::always;
 use crossbeam_channel::{select, Receiver};
-use ide_db::base_db::{SourceDatabaseExt, VfsPath};
+use ide_db::base_db::{FileLoader, SourceDatabaseExt, VfsPath};
 use lsp_server::{Connection, Notification, Request};
 use lsp_types::notification::Notification as _;
-use vfs::{ChangeKind, FileId};
+use rustc_hash::FxHashSet;
+use vfs::{AbsPathBuf, ChangeKind, FileId};
 
 use crate::{
-    config::Config,
+    config::{Config, FlycheckTrigger},
+    diagnostics::{map_diagnostic, map_fix},
     dispatch::{NotificationDispatcher, RequestDispatcher},
     from_proto,
     global_state::{file_id_to_url, url_to_file_id, GlobalState},
     handlers, lsp_ext,
     lsp_utils::{apply_document_changes, notification_is, Progress},
-    mem_docs::DocumentData,
-    reload::{self, BuildDataProgress, ProjectWorkspaceProgress},
+    mem_docs::{DocumentData, DocumentEdit},
+    reload::{
+        self, BuildDataProgress, DiscoverProjectProgress, FetchWorkspaceKind,
+        ProjectWorkspaceProgress,
+    },
     Result,
 };
 
@@ -61,10 +66,13 @@ enum Event {
 pub(crate) enum Task {
     Response(lsp_server::Response),
     Retry(lsp_server::Request),
+    Notification(lsp_server::Notification),
     Diagnostics(Vec<(FileId, Vec<lsp_types::Diagnostic>)>),
     PrimeCaches(PrimeCachesProgress),
     FetchWorkspace(ProjectWorkspaceProgress),
+    DiscoverProject(DiscoverProjectProgress),
     FetchBuildData(BuildDataProgress),
+    DetachedFiles(Vec<AbsPathBuf>),
 }
 
 #[derive(Debug)]
@@ -110,6 +118,7 @@ impl GlobalState {
     fn run(mut self, inbox: Receiver<lsp_server::Message>) -> Result<()> {
         if self.config.linked_projects().is_empty()
             && self.config.detached_files().is_empty()
+            && self.config.discover_project().is_none()
             && self.config.notifications().cargo_toml_not_found
         {
             self.show_and_log_error("rust-analyzer failed to discover workspace".to_string(), None);
@@ -150,9 +159,9 @@ impl GlobalState {
             );
         }
 
-        self.fetch_workspaces_queue.request_op("startup".to_string());
-        if let Some(cause) = self.fetch_workspaces_queue.should_start_op() {
-            self.fetch_workspaces(cause);
+        self.fetch_workspaces_queue.request_op("startup".to_string(), FetchWorkspaceKind::Full);
+        if let Some((cause, kind)) = self.fetch_workspaces_queue.should_start_op() {
+            self.fetch_workspaces(cause, kind);
         }
 
         while let Some(event) = self.next_event(&inbox) {
@@ -210,6 +219,7 @@ impl GlobalState {
                     match task {
                         Task::Response(response) => self.respond(response),
                         Task::Retry(req) => self.on_request(req),
+                        Task::Notification(not) => self.send(not.into()),
                         Task::Diagnostics(diagnostics_per_file) => {
                             for (file_id, diagnostics) in diagnostics_per_file {
                                 self.diagnostics.set_native_diagnostics(file_id, diagnostics)
@@ -234,7 +244,14 @@ impl GlobalState {
                                 ProjectWorkspaceProgress::Report(msg) => {
                                     (Progress::Report, Some(msg))
                                 }
-                                ProjectWorkspaceProgress::End(workspaces) => {
+                                ProjectWorkspaceProgress::End(workspaces, projects) => {
+                                    self.fetched_projects = projects
+                                        .into_iter()
+                                        .zip(&workspaces)
+                                        .filter_map(|(project, ws)| {
+                                            Some((project, ws.as_ref().ok()?.clone()))
+                                        })
+                                        .collect();
                                     self.fetch_workspaces_queue.op_completed(workspaces);
 
                                     let old = Arc::clone(&self.workspaces);
@@ -243,14 +260,28 @@ impl GlobalState {
 
                                     if self.config.run_build_scripts() && workspaces_updated {
                                         self.fetch_build_data_queue
-                                            .request_op(format!("workspace updated"));
+                                            .request_op(format!("workspace updated"), ());
                                     }
 
                                     (Progress::End, None)
                                 }
                             };
 
-                            self.report_progress("Fetching", state, msg, None);
+                            self.report_progress("Fetching", state, msg, None, false);
+                        }
+                        Task::DiscoverProject(progress) => {
+                            let (state, msg) = match progress {
+                                DiscoverProjectProgress::Begin => (Progress::Begin, None),
+                                DiscoverProjectProgress::Report(msg) => {
+                                    (Progress::Report, Some(msg))
+                                }
+                                DiscoverProjectProgress::End(projects) => {
+                                    self.set_discovered_projects(projects);
+                                    (Progress::End, None)
+                                }
+                            };
+
+                            self.report_progress("Discovering", state, msg, None, false);
                         }
                         Task::FetchBuildData(progress) => {
                             let (state, msg) = match progress {
@@ -268,7 +299,13 @@ impl GlobalState {
                             };
 
                             if let Some(state) = state {
-                                self.report_progress("Loading", state, msg, None);
+                                self.report_progress("Loading", state, msg, None, false);
+                            }
+                        }
+                        Task::DetachedFiles(files) => {
+                            self.discover_projects_for(&files);
+                            if self.config.analyze_detached_files() {
+                                self.set_detached_files(files);
                             }
                         }
                     }
@@ -316,12 +353,12 @@ impl GlobalState {
                             self.prime_caches_queue.op_completed(());
                             if cancelled {
                                 self.prime_caches_queue
-                                    .request_op("restart after cancellation".to_string());
+                                    .request_op("restart after cancellation".to_string(), ());
                             }
                         }
                     };
 
-                    self.report_progress("Indexing", state, message, Some(fraction));
+                    self.report_progress("Indexing", state, message, Some(fraction), false);
                 }
             }
             Event::Vfs(mut task) => {
@@ -357,6 +394,7 @@ impl GlobalState {
                                 state,
                                 Some(format!("{}/{}", n_done, n_total)),
                                 Some(Progress::fraction(n_done, n_total)),
+                                false,
                             )
                         }
                     }
@@ -371,8 +409,10 @@ impl GlobalState {
                 let _p = profile::span("GlobalState::handle_event/flycheck");
                 loop {
                     match task {
-                        flycheck::Message::AddDiagnostic { workspace_root, diagnostic } => {
+                        flycheck::Message::AddDiagnostic { id, workspace_root, diagnostic } => {
                             let snap = self.snapshot();
+                            let profile_name =
+                                self.flycheck.get(id).and_then(|it| it.profile.name.clone());
                             let diagnostics =
                                 crate::diagnostics::to_proto::map_rust_diagnostic_to_lsp(
                                     &self.config.diagnostics_map(),
@@ -380,9 +420,27 @@ impl GlobalState {
                                     &workspace_root,
                                     &snap,
                                 );
-                            for diag in diagnostics {
+                            for mut diag in diagnostics {
+                                if let Some(name) = &profile_name {
+                                    diag.diagnostic.source = Some(name.clone());
+                                }
+                                // The check ran on the contents on disk, which may have been
+                                // edited since.
+                                let doc = from_proto::vfs_path(&diag.url)
+                                    .ok()
+                                    .and_then(|path| self.mem_docs.get(&path));
+                                if let Some(doc) = doc {
+                                    let map = |range| doc.map_range(range);
+                                    if !map_diagnostic(&mut diag.diagnostic, &diag.url, map) {
+                                        continue;
+                                    }
+                                    diag.fix = diag.fix.take().and_then(|mut fix| {
+                                        map_fix(&mut fix, &diag.url, map).then(|| fix)
+                                    });
+                                }
                                 match url_to_file_id(&self.vfs.read().0, &diag.url) {
                                     Ok(file_id) => self.diagnostics.add_check_diagnostic(
+                                        id,
                                         file_id,
                                         diag.diagnostic,
                                         diag.fix,
@@ -397,16 +455,27 @@ impl GlobalState {
                             }
                         }
 
-                        flycheck::Message::Progress { id, progress } => {
+                        flycheck::Message::Progress { id, progress }
+                            if id < self.flycheck.len() =>
+                        {
                             let (state, message) = match progress {
                                 flycheck::Progress::DidStart => {
-                                    self.diagnostics.clear_check();
+                                    self.diagnostics.clear_check(id);
+                                    self.flycheck[id].is_running = true;
+                                    (Progress::Begin, None)
+                                }
+                                flycheck::Progress::DidStartPackages(packages) => {
+                                    self.clear_check_for_packages(id, &packages);
+                                    self.flycheck[id].is_running = true;
                                     (Progress::Begin, None)
                                 }
                                 flycheck::Progress::DidCheckCrate(target) => {
                                     (Progress::Report, Some(target))
                                 }
-                                flycheck::Progress::DidCancel => (Progress::End, None),
+                                flycheck::Progress::DidCancel => {
+                                    self.flycheck[id].is_running = false;
+                                    (Progress::End, None)
+                                }
                                 flycheck::Progress::DidFinish(result) => {
                                     if let Err(err) = result {
                                         self.show_and_log_error(
@@ -414,22 +483,20 @@ impl GlobalState {
                                             Some(err.to_string()),
                                         );
                                     }
+                                    self.flycheck[id].is_running = false;
+                                    let flycheck = &self.flycheck[id];
+                                    if flycheck.profile.trigger == FlycheckTrigger::OnSave {
+                                        self.start_idle_flychecks(flycheck.workspace);
+                                    }
                                     (Progress::End, None)
                                 }
                             };
 
-                            // When we're running multiple flychecks, we have to include a disambiguator in
-                            // the title, or the editor complains. Note that this is a user-facing string.
-                            let title = if self.flycheck.len() == 1 {
-                                match self.config.flycheck() {
-                                    Some(config) => format!("{}", config),
-                                    None => "cargo check".to_string(),
-                                }
-                            } else {
-                                format!("cargo check (#{})", id + 1)
-                            };
-                            self.report_progress(&title, state, message, None);
+                            let title = self.flycheck_title(id);
+                            self.report_progress(&title, state, message, None, true);
                         }
+                        // Messages of flychecks replaced by a reload might still arrive.
+                        flycheck::Message::Progress { .. } => (),
                     }
                     // Coalesce many flycheck updates into a single loop turn
                     task = match self.flycheck_receiver.try_recv() {
@@ -446,13 +513,20 @@ impl GlobalState {
         if self.is_quiescent() {
             if !was_quiescent {
                 for flycheck in &self.flycheck {
-                    flycheck.update();
+                    if flycheck.profile.trigger == FlycheckTrigger::OnSave {
+                        flycheck.handle.update();
+                    }
                 }
+                self.start_idle_flychecks_without_on_save();
                 if self.config.prefill_caches() {
-                    self.prime_caches_queue.request_op("became quiescent".to_string());
+                    self.prime_caches_queue.request_op("became quiescent".to_string(), ());
                 }
             }
 
+            if !was_quiescent || memdocs_added_or_removed {
+                self.fetch_detached_files();
+            }
+
             if !was_quiescent || state_changed {
                 // Refresh semantic tokens if the client supports it.
                 if self.config.semantic_tokens_refresh() {
@@ -466,6 +540,13 @@ impl GlobalState {
                 }
             }
 
+            // Clients pull diagnostics again when a document changes on their own, but not
+            // when the workspace is loaded.
+            if !was_quiescent && self.config.pull_diagnostics() && self.config.diagnostics_refresh()
+            {
+                self.send_request::<lsp_ext::WorkspaceDiagnosticRefresh>((), |_, _| ());
+            }
+
             if !was_quiescent || state_changed || memdocs_added_or_removed {
                 if self.config.publish_diagnostics() {
                     self.update_diagnostics()
@@ -498,19 +579,23 @@ impl GlobalState {
             }
         }
 
+        if let Some((cause, ())) = self.discover_project_queue.should_start_op() {
+            self.discover_projects(cause);
+        }
+
         if self.config.cargo_autoreload() {
-            if let Some(cause) = self.fetch_workspaces_queue.should_start_op() {
-                self.fetch_workspaces(cause);
+            if let Some((cause, kind)) = self.fetch_workspaces_queue.should_start_op() {
+                self.fetch_workspaces(cause, kind);
             }
         }
 
         if !self.fetch_workspaces_queue.op_in_progress() {
-            if let Some(cause) = self.fetch_build_data_queue.should_start_op() {
+            if let Some((cause, ())) = self.fetch_build_data_queue.should_start_op() {
                 self.fetch_build_data(cause);
             }
         }
 
-        if let Some(cause) = self.prime_caches_queue.should_start_op() {
+        if let Some((cause, ())) = self.prime_caches_queue.should_start_op() {
             tracing::debug!(%cause, "will prime caches");
             let num_worker_threads = self.config.prime_caches_num_threads();
 
@@ -588,6 +673,9 @@ impl GlobalState {
             .on_sync_mut::<lsp_ext::ReloadWorkspace>(handlers::handle_workspace_reload)
             .on_sync_mut::<lsp_ext::MemoryUsage>(handlers::handle_memory_usage)
             .on_sync_mut::<lsp_ext::ShuffleCrateGraph>(handlers::handle_shuffle_crate_graph)
+            .on_sync_mut::<lsp_ext::ClearProcMacroCache>(handlers::handle_clear_proc_macro_cache)
+            .on_sync_mut::<lsp_ext::SetActiveTarget>(handlers::handle_set_active_target)
+            .on_sync_mut::<lsp_ext::ToggleFeature>(handlers::handle_toggle_feature)
             .on_sync::<lsp_ext::JoinLines>(handlers::handle_join_lines)
             .on_sync::<lsp_ext::OnEnter>(handlers::handle_on_enter)
             .on_sync::<lsp_types::request::SelectionRangeRequest>(handlers::handle_selection_range)
@@ -597,6 +685,7 @@ impl GlobalState {
             .on::<lsp_ext::ViewHir>(handlers::handle_view_hir)
             .on::<lsp_ext::ViewFileText>(handlers::handle_view_file_text)
             .on::<lsp_ext::ViewCrateGraph>(handlers::handle_view_crate_graph)
+            .on::<lsp_ext::FileTargets>(handlers::handle_file_targets)
             .on::<lsp_ext::ViewItemTree>(handlers::handle_view_item_tree)
             .on::<lsp_ext::ExpandMacro>(handlers::handle_expand_macro)
             .on::<lsp_ext::ParentModule>(handlers::handle_parent_module)
@@ -621,6 +710,7 @@ impl GlobalState {
             .on::<lsp_types::request::ResolveCompletionItem>(handlers::handle_completion_resolve)
             .on::<lsp_types::request::CodeLensRequest>(handlers::handle_code_lens)
             .on::<lsp_types::request::CodeLensResolve>(handlers::handle_code_lens_resolve)
+            .on::<lsp_types::request::DocumentLinkRequest>(handlers::handle_document_link)
             .on::<lsp_types::request::FoldingRangeRequest>(handlers::handle_folding_range)
             .on::<lsp_types::request::SignatureHelpRequest>(handlers::handle_signature_help)
             .on::<lsp_types::request::PrepareRenameRequest>(handlers::handle_prepare_rename)
@@ -629,6 +719,7 @@ impl GlobalState {
             .on::<lsp_types::request::Formatting>(handlers::handle_formatting)
             .on::<lsp_types::request::RangeFormatting>(handlers::handle_range_formatting)
             .on::<lsp_types::request::DocumentHighlightRequest>(handlers::handle_document_highlight)
+            .on::<lsp_types::request::LinkedEditingRange>(handlers::handle_linked_editing_range)
             .on::<lsp_types::request::CallHierarchyPrepare>(handlers::handle_call_hierarchy_prepare)
             .on::<lsp_types::request::CallHierarchyIncomingCalls>(
                 handlers::handle_call_hierarchy_incoming,
@@ -636,6 +727,9 @@ impl GlobalState {
             .on::<lsp_types::request::CallHierarchyOutgoingCalls>(
                 handlers::handle_call_hierarchy_outgoing,
             )
+            .on::<lsp_ext::TypeHierarchyPrepare>(handlers::handle_type_hierarchy_prepare)
+            .on::<lsp_ext::TypeHierarchySupertypes>(handlers::handle_type_hierarchy_supertypes)
+            .on::<lsp_ext::TypeHierarchySubtypes>(handlers::handle_type_hierarchy_subtypes)
             .on::<lsp_types::request::SemanticTokensFullRequest>(
                 handlers::handle_semantic_tokens_full,
             )
@@ -646,6 +740,12 @@ impl GlobalState {
                 handlers::handle_semantic_tokens_range,
             )
             .on::<lsp_types::request::WillRenameFiles>(handlers::handle_will_rename_files)
+            .on::<lsp_types::request::WillCreateFiles>(handlers::handle_will_create_files)
+            .on::<lsp_types::request::WillDeleteFiles>(handlers::handle_will_delete_files)
+            .on::<lsp_ext::DocumentDiagnosticRequest>(handlers::handle_document_diagnostic)
+            .on_with_partial_results::<lsp_ext::WorkspaceDiagnosticRequest, lsp_ext::WorkspaceDiagnosticProgress>(
+                handlers::handle_workspace_diagnostic,
+            )
             .on::<lsp_ext::Ssr>(handlers::handle_ssr)
             .finish();
     }
@@ -660,10 +760,17 @@ impl GlobalState {
                 this.cancel(id);
                 Ok(())
             })?
-            .on::<lsp_types::notification::WorkDoneProgressCancel>(|_this, _params| {
-                // Just ignore this. It is OK to continue sending progress
+            .on::<lsp_types::notification::WorkDoneProgressCancel>(|this, params| {
+                // Only flychecks can be cancelled. It is OK to continue sending progress
                 // notifications for this token, as the client can't know when
                 // we accepted notification.
+                if let lsp_types::NumberOrString::String(token) = &params.token {
+                    for (id, flycheck) in this.flycheck.iter().enumerate() {
+                        if *token == format!("rustAnalyzer/{}", this.flycheck_title(id)) {
+                            flycheck.handle.cancel();
+                        }
+                    }
+                }
                 Ok(())
             })?
             .on::<lsp_types::notification::DidOpenTextDocument>(|this, params| {
@@ -684,11 +791,24 @@ impl GlobalState {
             })?
             .on::<lsp_types::notification::DidChangeTextDocument>(|this, params| {
                 if let Ok(path) = from_proto::vfs_path(&params.text_document.uri) {
+                    let encoding = this.config.offset_encoding();
+                    let edits: Vec<_> = params
+                        .content_changes
+                        .iter()
+                        .map(|change| {
+                            change
+                                .range
+                                .map(|range| DocumentEdit::new(range, &change.text, &encoding))
+                        })
+                        .collect();
                     match this.mem_docs.get_mut(&path) {
                         Some(doc) => {
                             // The version passed in DidChangeTextDocument is the version after all edits are applied
                             // so we should apply it before the vfs is notified.
                             doc.version = params.text_document.version;
+                            for &edit in &edits {
+                                doc.record_edit(edit);
+                            }
                         }
                         None => {
                             tracing::error!("unexpected DidChangeTextDocument: {}", path);
@@ -696,17 +816,39 @@ impl GlobalState {
                         }
                     };
 
-                    let vfs = &mut this.vfs.write().0;
-                    let file_id = vfs.file_id(&path).unwrap();
-                    let mut text = String::from_utf8(vfs.file_contents(file_id).to_vec()).unwrap();
-                    apply_document_changes(&mut text, params.content_changes);
+                    let file_id = {
+                        let vfs = &mut this.vfs.write().0;
+                        let file_id = vfs.file_id(&path).unwrap();
+                        let mut text =
+                            String::from_utf8(vfs.file_contents(file_id).to_vec()).unwrap();
+                        apply_document_changes(&mut text, params.content_changes);
+
+                        vfs.set_file_contents(path, Some(text.into_bytes()));
+                        file_id
+                    };
 
-                    vfs.set_file_contents(path, Some(text.into_bytes()));
+                    for edit in edits {
+                        match edit {
+                            Some(edit) => this.diagnostics.apply_check_edit(
+                                file_id,
+                                &params.text_document.uri,
+                                &edit,
+                            ),
+                            None => this.diagnostics.clear_check_for_file(file_id),
+                        }
+                    }
                 }
                 Ok(())
             })?
             .on::<lsp_types::notification::DidCloseTextDocument>(|this, params| {
                 if let Ok(path) = from_proto::vfs_path(&params.text_document.uri) {
+                    // The document reverts to its contents on disk, which the flycheck
+                    // diagnostics we moved along with the unsaved edits don't match anymore.
+                    if this.mem_docs.get(&path).map_or(false, |doc| doc.has_unsaved_edits()) {
+                        if let Some(file_id) = this.vfs.read().0.file_id(&path) {
+                            this.diagnostics.clear_check_for_file(file_id);
+                        }
+                    }
                     if this.mem_docs.remove(&path).is_err() {
                         tracing::error!("orphan DidCloseTextDocument: {}", path);
                     }
@@ -720,14 +862,20 @@ impl GlobalState {
                 Ok(())
             })?
             .on::<lsp_types::notification::DidSaveTextDocument>(|this, params| {
-                for flycheck in &this.flycheck {
-                    flycheck.update();
+                if let Ok(path) = from_proto::vfs_path(&params.text_document.uri) {
+                    if let Some(doc) = this.mem_docs.get_mut(&path) {
+                        doc.saved();
+                    }
                 }
+                this.update_flycheck_for(&params.text_document.uri);
                 if let Ok(abs_path) = from_proto::abs_path(&params.text_document.uri) {
                     if reload::should_refresh_for_change(&abs_path, ChangeKind::Modify) {
-                        this.fetch_workspaces_queue
-                            .request_op(format!("DidSaveTextDocument {}", abs_path.display()));
+                        this.fetch_workspaces_queue.request_op(
+                            format!("DidSaveTextDocument {}", abs_path.display()),
+                            FetchWorkspaceKind::Full,
+                        );
                     }
+                    this.rediscover_projects_for_change(&abs_path);
                 }
                 Ok(())
             })?
@@ -772,9 +920,40 @@ impl GlobalState {
 
                 Ok(())
             })?
+            .on::<lsp_types::notification::DidChangeWorkspaceFolders>(|this, params| {
+                let to_paths = |folders: Vec<lsp_types::WorkspaceFolder>| {
+                    folders
+                        .into_iter()
+                        .filter_map(|it| from_proto::abs_path(&it.uri).ok())
+                        .collect::<Vec<_>>()
+                };
+                let removed = to_paths(params.event.removed);
+                let mut config = Config::clone(&*this.config);
+                config.change_workspace_roots(to_paths(params.event.added), &removed);
+                config.rediscover_workspaces();
+                let projects_changed = config.linked_projects() != this.config.linked_projects();
+                this.config = Arc::new(config);
+                // A fetch requested for another reason has to reload all workspaces anyway.
+                if projects_changed && !this.fetch_workspaces_queue.op_requested() {
+                    this.fetch_workspaces_queue.request_op(
+                        "workspace folders changed".to_string(),
+                        FetchWorkspaceKind::KeepLoaded,
+                    );
+                }
+                Ok(())
+            })?
+            .on::<lsp_types::notification::DidCreateFiles>(|this, params| {
+                this.invalidate_files(params.files.iter().map(|it| &it.uri));
+                Ok(())
+            })?
+            .on::<lsp_types::notification::DidDeleteFiles>(|this, params| {
+                this.invalidate_files(params.files.iter().map(|it| &it.uri));
+                Ok(())
+            })?
             .on::<lsp_types::notification::DidChangeWatchedFiles>(|this, params| {
                 for change in params.changes {
                     if let Ok(path) = from_proto::abs_path(&change.uri) {
+                        this.rediscover_projects_for_change(&path);
                         this.loader.handle.invalidate(path);
                     }
                 }
@@ -784,10 +963,127 @@ impl GlobalState {
         Ok(())
     }
 
-    fn update_diagnostics(&mut self) {
+    /// Reloads files created or deleted by the client, without waiting for the file watcher.
+    fn invalidate_files<'a>(&mut self, uris: impl Iterator<Item = &'a String>) {
+        for uri in uris {
+            if let Some(path) =
+                lsp_types::Url::parse(uri).ok().and_then(|it| from_proto::abs_path(&it).ok())
+            {
+                self.loader.handle.invalidate(path);
+            }
+        }
+    }
+
+    /// Restarts the flychecks after `uri` was saved, limited to the packages owning it if
+    /// configured so.
+    fn update_flycheck_for(&self, uri: &lsp_types::Url) {
+        let packages = self.config.flycheck_workspace_delay().and_then(|delay| {
+            let snap = self.snapshot();
+            let file_id = snap.url_to_file_id(uri).ok()?;
+            Some((delay, snap.packages_for_file(file_id).ok()?))
+        });
+        let on_save =
+            self.flycheck.iter().filter(|it| it.profile.trigger == FlycheckTrigger::OnSave);
+        match packages {
+            Some((delay, packages)) if !packages.is_empty() => {
+                for flycheck in on_save {
+                    if let Some(packages) = packages.get(&flycheck.workspace) {
+                        flycheck.handle.update_packages(packages.clone(), delay);
+                    }
+                }
+            }
+            _ => {
+                for flycheck in on_save {
+                    flycheck.handle.update();
+                }
+            }
+        }
+        self.start_idle_flychecks_without_on_save();
+    }
+
+    /// Starts the flychecks of the `ws`th workspace which run when idle, once none of the
+    /// ones run on save is running anymore.
+    fn start_idle_flychecks(&self, ws: usize) {
+        let flychecks = self.flycheck.iter().filter(|it| it.workspace == ws);
+        let (on_save, on_idle): (Vec<_>, Vec<_>) =
+            flychecks.partition(|it| it.profile.trigger == FlycheckTrigger::OnSave);
+        if on_save.iter().any(|it| it.is_running) {
+            return;
+        }
+        for flycheck in on_idle {
+            flycheck.handle.update();
+        }
+    }
+
+    /// Starts the flychecks which run when idle of the workspaces without any run on save, as
+    /// no finishing check would start them.
+    fn start_idle_flychecks_without_on_save(&self) {
+        let has_on_save = |ws: usize| {
+            self.flycheck
+                .iter()
+                .any(|it| it.workspace == ws && it.profile.trigger == FlycheckTrigger::OnSave)
+        };
+        for flycheck in &self.flycheck {
+            if flycheck.profile.trigger == FlycheckTrigger::OnIdle
+                && !has_on_save(flycheck.workspace)
+            {
+                flycheck.handle.update();
+            }
+        }
+    }
+
+    /// Returns the title of the progress of the `id`th flycheck.
+    fn flycheck_title(&self, id: usize) -> String {
+        let flycheck = &self.flycheck[id];
+        let title = match &flycheck.profile.name {
+            Some(name) => name.clone(),
+            None => flycheck.profile.config.to_string(),
+        };
+        // When we're running multiple flychecks, we have to include a disambiguator in
+        // the title, or the editor complains. Note that this is a user-facing string.
+        if self.flycheck.iter().filter(|it| it.profile == flycheck.profile).count() == 1 {
+            title
+        } else {
+            format!("{} (#{})", title, flycheck.workspace + 1)
+        }
+    }
+
+    /// Clears the diagnostics of the `id`th flycheck for the files of `packages`, i.e. the files
+    /// in the source roots of their crates.
+    fn clear_check_for_packages(&mut self, id: usize, packages: &[String]) {
+        let workspace = self.flycheck[id].workspace;
+        let snap = self.snapshot();
+        let db = self.analysis_host.raw_database();
+        let file_ids: Vec<_> = self
+            .diagnostics
+            .check
+            .get(&id)
+            .into_iter()
+            .flat_map(|it| it.keys())
+            .copied()
+            .filter(|&file_id| {
+                let crates = db.relevant_crates(file_id).iter().copied().collect::<Vec<_>>();
+                snap.packages_for_crates(crates)
+                    .get(&workspace)
+                    .map_or(false, |it| it.iter().any(|package| packages.contains(package)))
+            })
+            .collect();
+        self.diagnostics.clear_check_for(id, &file_ids);
+    }
+
+    pub(crate) fn update_diagnostics(&mut self) {
+        // Clients which pull diagnostics ask for those of the Rust documents they show, but not
+        // for manifests. Only the manifests of workspace members have diagnostics.
+        let pull_diagnostics = self.config.pull_diagnostics();
+        let member_manifests: FxHashSet<_> =
+            reload::member_manifests(&self.workspaces).into_iter().map(VfsPath::from).collect();
         let subscriptions = self
             .mem_docs
             .iter()
+            .filter(|path| match path.name_and_extension() {
+                Some(("Cargo", Some("toml"))) => member_manifests.contains(path),
+                _ => !pull_diagnostics,
+            })
             .map(|path| self.vfs.read().0.file_id(path).unwrap())
             .collect::<Vec<_>>();
 
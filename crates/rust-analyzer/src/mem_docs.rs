@@ -1,10 +1,17 @@
 //! In-memory document information.
 
-use std::mem;
+use std::{mem, sync::Arc};
 
+use lsp_types::{Position, Range};
 use rustc_hash::FxHashMap;
 use vfs::VfsPath;
 
+use crate::line_index::OffsetEncoding;
+
+/// The number of unsaved edits we keep track of, before giving up on mapping
+/// ranges of the contents on disk.
+const MAX_UNSAVED_EDITS: usize = 1024;
+
 /// Holds the set of in-memory documents.
 ///
 /// For these document, there true contents is maintained by the client. It
@@ -56,10 +63,145 @@ impl MemDocs {
 #[derive(Debug, Clone)]
 pub(crate) struct DocumentData {
     pub(crate) version: i32,
+    /// The edits applied since the document was last saved. These map ranges in
+    /// the contents on disk, which is what flycheck reports diagnostics for, to
+    /// the current contents. `None` if they can't be mapped anymore.
+    unsaved_edits: Option<Arc<Vec<DocumentEdit>>>,
 }
 
 impl DocumentData {
     pub(crate) fn new(version: i32) -> Self {
-        DocumentData { version }
+        DocumentData { version, unsaved_edits: Some(Arc::default()) }
+    }
+
+    /// Records an edit of the document, `None` meaning that its whole contents
+    /// were replaced.
+    pub(crate) fn record_edit(&mut self, edit: Option<DocumentEdit>) {
+        match (&mut self.unsaved_edits, edit) {
+            (Some(edits), Some(edit)) if edits.len() < MAX_UNSAVED_EDITS => {
+                Arc::make_mut(edits).push(edit)
+            }
+            _ => self.unsaved_edits = None,
+        }
+    }
+
+    pub(crate) fn has_unsaved_edits(&self) -> bool {
+        self.unsaved_edits.as_ref().map_or(true, |it| !it.is_empty())
+    }
+
+    pub(crate) fn saved(&mut self) {
+        self.unsaved_edits = Some(Arc::default());
+    }
+
+    /// Maps a range in the contents on disk to the current contents, returning
+    /// `None` if it has been edited since.
+    pub(crate) fn map_range(&self, range: Range) -> Option<Range> {
+        self.unsaved_edits.as_ref()?.iter().try_fold(range, |range, edit| edit.map_range(range))
+    }
+}
+
+/// A change of the text of a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DocumentEdit {
+    /// The replaced range, in the text before the edit.
+    range: Range,
+    /// The end of the inserted text, in the text after the edit.
+    new_end: Position,
+}
+
+impl DocumentEdit {
+    pub(crate) fn new(range: Range, text: &str, encoding: &OffsetEncoding) -> DocumentEdit {
+        let width = |text: &str| match encoding {
+            OffsetEncoding::Utf8 => text.len() as u32,
+            OffsetEncoding::Utf16 => text.encode_utf16().count() as u32,
+        };
+        let new_end = match text.rsplit_once('\n') {
+            Some((before, last_line)) => Position::new(
+                range.start.line + before.matches('\n').count() as u32 + 1,
+                width(last_line),
+            ),
+            None => Position::new(range.start.line, range.start.character + width(text)),
+        };
+        DocumentEdit { range, new_end }
+    }
+
+    /// Maps a range in the text before the edit to the text after it, returning
+    /// `None` if the edit overlaps it.
+    pub(crate) fn map_range(&self, range: Range) -> Option<Range> {
+        if range.start >= self.range.end {
+            Some(Range::new(self.map_position(range.start), self.map_position(range.end)))
+        } else if range.end <= self.range.start {
+            Some(range)
+        } else {
+            None
+        }
+    }
+
+    fn map_position(&self, position: Position) -> Position {
+        let end = self.range.end;
+        if position.line == end.line {
+            Position::new(
+                self.new_end.line,
+                self.new_end.character + position.character - end.character,
+            )
+        } else {
+            Position::new(position.line - end.line + self.new_end.line, position.character)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    #[test]
+    fn map_ranges_through_unsaved_edits() {
+        let mut doc = DocumentData::new(0);
+        let error = range((3, 4), (3, 10));
+        assert_eq!(doc.map_range(error), Some(error));
+
+        // Two lines inserted above.
+        doc.record_edit(Some(DocumentEdit::new(
+            range((1, 0), (1, 0)),
+            "let a = 1;\nlet b = 2;\n",
+            &OffsetEncoding::Utf16,
+        )));
+        assert_eq!(doc.map_range(error), Some(range((5, 4), (5, 10))));
+
+        // A multi-byte character inserted before, on the same line.
+        doc.record_edit(Some(DocumentEdit::new(
+            range((5, 0), (5, 0)),
+            "é",
+            &OffsetEncoding::Utf16,
+        )));
+        assert_eq!(doc.map_range(error), Some(range((5, 5), (5, 11))));
+
+        // Edits after the range don't move it.
+        doc.record_edit(Some(DocumentEdit::new(
+            range((5, 11), (6, 0)),
+            "",
+            &OffsetEncoding::Utf16,
+        )));
+        assert_eq!(doc.map_range(error), Some(range((5, 5), (5, 11))));
+
+        // Edits inside invalidate it.
+        let mut edited = doc.clone();
+        edited.record_edit(Some(DocumentEdit::new(
+            range((5, 6), (5, 7)),
+            "x",
+            &OffsetEncoding::Utf16,
+        )));
+        assert_eq!(edited.map_range(error), None);
+
+        // As does replacing the whole document.
+        doc.record_edit(None);
+        assert_eq!(doc.map_range(error), None);
+
+        doc.saved();
+        assert_eq!(doc.map_range(error), Some(error));
     }
 }
@@ -12,33 +12,55 @@
 //! correct. Instead, we try to provide a best-effort service. Even if the
 //! project is currently loading and we don't have a full project model, we
 //! still want to respond to various  requests.
-use std::{mem, sync::Arc};
+use std::{ffi::OsString, mem, ops::Range, sync::Arc};
 
+use cfg::{CfgAtom, CfgDiff};
 use flycheck::{FlycheckConfig, FlycheckHandle};
 use hir::db::DefDatabase;
-use ide::Change;
+use ide::{Analysis, Cancellable, Change, FileId};
 use ide_db::base_db::{
-    CrateGraph, Env, ProcMacro, ProcMacroExpander, ProcMacroExpansionError, ProcMacroKind,
-    ProcMacroLoadResult, SourceRoot, VfsPath,
+    CrateDisplayName, CrateGraph, CrateId, CrateName, CrateOrigin, Dependency, Edition, Env,
+    LangCrateOrigin, ProcMacro, ProcMacroExpander, ProcMacroExpansionError,
+    ProcMacroExpansionPolicy, ProcMacroKind, ProcMacroLoadResult, SourceDatabase, SourceRoot,
+    VfsPath,
 };
-use proc_macro_api::{MacroDylib, ProcMacroServer};
-use project_model::{ProjectWorkspace, WorkspaceBuildScripts};
+use proc_macro_api::{ExpansionCache, MacroDylib, ProcMacroServer};
+use project_model::{PackageRoot, ProjectJson, ProjectWorkspace, WorkspaceBuildScripts};
+use rustc_hash::{FxHashMap, FxHashSet};
 use syntax::SmolStr;
 use vfs::{file_set::FileSetConfig, AbsPath, AbsPathBuf, ChangeKind};
 
 use crate::{
     config::{Config, FilesWatcher, LinkedProject},
-    global_state::GlobalState,
+    global_state::{Flycheck, GlobalState},
     lsp_ext,
     main_loop::Task,
     op_queue::Cause,
 };
 
+/// What a workspace fetch has to load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FetchWorkspaceKind {
+    /// Loads all the projects again.
+    Full,
+    /// Keeps the workspaces of the projects which were loaded already, as only the workspace
+    /// folders changed.
+    KeepLoaded,
+}
+
 #[derive(Debug)]
 pub(crate) enum ProjectWorkspaceProgress {
     Begin,
     Report(String),
-    End(Vec<anyhow::Result<ProjectWorkspace>>),
+    /// The fetched workspaces, and the projects the first ones were loaded from.
+    End(Vec<anyhow::Result<ProjectWorkspace>>, Vec<LinkedProject>),
+}
+
+#[derive(Debug)]
+pub(crate) enum DiscoverProjectProgress {
+    Begin,
+    Report(String),
+    End(Vec<(AbsPathBuf, anyhow::Result<ProjectJson>)>),
 }
 
 #[derive(Debug)]
@@ -51,6 +73,7 @@ pub(crate) enum BuildDataProgress {
 impl GlobalState {
     pub(crate) fn is_quiescent(&self) -> bool {
         !(self.fetch_workspaces_queue.op_in_progress()
+            || self.discover_project_queue.op_in_progress()
             || self.fetch_build_data_queue.op_in_progress()
             || self.vfs_progress_config_version < self.vfs_config_version
             || self.vfs_progress_n_done < self.vfs_progress_n_total)
@@ -59,15 +82,35 @@ impl GlobalState {
     pub(crate) fn update_configuration(&mut self, config: Config) {
         let _p = profile::span("GlobalState::update_configuration");
         let old_config = mem::replace(&mut self.config, Arc::new(config));
+        // The diagnostics depend on the config.
+        self.invalidate_pulled_diagnostics();
         if self.config.lru_capacity() != old_config.lru_capacity() {
             self.analysis_host.update_lru_capacity(self.config.lru_capacity());
         }
         if self.config.linked_projects() != old_config.linked_projects() {
-            self.fetch_workspaces_queue.request_op("linked projects changed".to_string())
+            self.fetch_workspaces_queue
+                .request_op("linked projects changed".to_string(), FetchWorkspaceKind::Full)
         } else if self.config.flycheck() != old_config.flycheck() {
             self.reload_flycheck();
         }
 
+        if self.config.proc_macro_srv() != old_config.proc_macro_srv()
+            || self.config.proc_macro_timeout() != old_config.proc_macro_timeout()
+            || self.config.proc_macro_cache() != old_config.proc_macro_cache()
+        {
+            // Already loaded macros keep the settings of the server they were loaded from, so
+            // restart the servers and reload them. The build data stays valid.
+            if !self.workspaces.is_empty() {
+                self.reload_proc_macros();
+            }
+        } else if self.config.proc_macro_policies() != old_config.proc_macro_policies() {
+            // Policies are baked into the crate graph when the macros are loaded, but the
+            // running servers can load them again.
+            let mut change = Change::new();
+            self.recreate_crate_graph(&mut change);
+            self.apply_change(change);
+        }
+
         if self.analysis_host.raw_database().enable_proc_attr_macros()
             != self.config.expand_proc_attr_macros()
         {
@@ -101,6 +144,22 @@ impl GlobalState {
             status.health = lsp_ext::Health::Warning;
             status.message = Some("Workspace reload required".to_string())
         }
+        let missing_deps = self.workspaces.iter().find_map(|ws| match ws {
+            ProjectWorkspace::Cargo { cargo, .. } => cargo.missing_deps(),
+            _ => None,
+        });
+        if let Some(error) = missing_deps {
+            status.health = lsp_ext::Health::Warning;
+            let message = format!(
+                "Dependencies couldn't be resolved offline, the workspace was loaded without them: {}",
+                error
+            );
+            // Keep the other warnings, like failing build scripts, which this likely causes.
+            status.message = Some(match status.message.take() {
+                Some(other) => format!("{}\n\n{}", other, message),
+                None => message,
+            });
+        }
 
         if let Err(error) = self.fetch_workspace_error() {
             status.health = lsp_ext::Health::Error;
@@ -109,13 +168,25 @@ impl GlobalState {
         status
     }
 
-    pub(crate) fn fetch_workspaces(&mut self, cause: Cause) {
-        tracing::info!(%cause, "will fetch workspaces");
+    pub(crate) fn fetch_workspaces(&mut self, cause: Cause, kind: FetchWorkspaceKind) {
+        tracing::info!(%cause, ?kind, "will fetch workspaces");
 
         self.task_pool.handle.spawn_with_sender({
-            let linked_projects = self.config.linked_projects();
-            let detached_files = self.config.detached_files().to_vec();
+            let mut linked_projects = self.config.linked_projects();
+            linked_projects.extend(
+                self.discovered_projects
+                    .iter()
+                    .map(|(_, project)| LinkedProject::InlineJsonProject(project.clone())),
+            );
+            let mut detached_files = self.config.detached_files().to_vec();
+            if linked_projects.is_empty() {
+                detached_files.extend(self.detached_files.iter().cloned());
+            }
             let cargo_config = self.config.cargo();
+            let reusable_workspaces = match kind {
+                FetchWorkspaceKind::Full => Vec::new(),
+                FetchWorkspaceKind::KeepLoaded => self.reusable_workspaces(),
+            };
 
             move |sender| {
                 let progress = {
@@ -131,19 +202,26 @@ impl GlobalState {
 
                 let mut workspaces = linked_projects
                     .iter()
-                    .map(|project| match project {
-                        LinkedProject::ProjectManifest(manifest) => {
-                            project_model::ProjectWorkspace::load(
-                                manifest.clone(),
-                                &cargo_config,
-                                &progress,
-                            )
+                    .map(|project| {
+                        if let Some((_, ws)) =
+                            reusable_workspaces.iter().find(|(it, _)| it == project)
+                        {
+                            return Ok(ws.clone());
                         }
-                        LinkedProject::InlineJsonProject(it) => {
-                            project_model::ProjectWorkspace::load_inline(
-                                it.clone(),
-                                cargo_config.target.as_deref(),
-                            )
+                        match project {
+                            LinkedProject::ProjectManifest(manifest) => {
+                                project_model::ProjectWorkspace::load(
+                                    manifest.clone(),
+                                    &cargo_config,
+                                    &progress,
+                                )
+                            }
+                            LinkedProject::InlineJsonProject(it) => {
+                                project_model::ProjectWorkspace::load_inline(
+                                    it.clone(),
+                                    cargo_config.target.as_deref(),
+                                )
+                            }
                         }
                     })
                     .collect::<Vec<_>>();
@@ -155,12 +233,145 @@ impl GlobalState {
 
                 tracing::info!("did fetch workspaces {:?}", workspaces);
                 sender
-                    .send(Task::FetchWorkspace(ProjectWorkspaceProgress::End(workspaces)))
+                    .send(Task::FetchWorkspace(ProjectWorkspaceProgress::End(
+                        workspaces,
+                        linked_projects,
+                    )))
                     .unwrap();
             }
         });
     }
 
+    /// Returns the workspaces of the last fetch by their projects, preferring the active ones,
+    /// which also have their build data.
+    fn reusable_workspaces(&self) -> Vec<(LinkedProject, ProjectWorkspace)> {
+        self.fetched_projects
+            .iter()
+            .map(|(project, fetched)| {
+                let ws = self
+                    .workspaces
+                    .iter()
+                    .find(|ws| eq_ignore_build_data(ws, fetched))
+                    .unwrap_or(fetched);
+                (project.clone(), ws.clone())
+            })
+            .collect()
+    }
+
+    /// Asks the project discovery command about opened files which don't belong to any crate.
+    pub(crate) fn discover_projects_for(&mut self, files: &[AbsPathBuf]) {
+        if self.config.discover_project().is_none() {
+            return;
+        }
+        // Files of the same directory almost always belong to the same project, so only ask
+        // about one of them.
+        let mut new_files: Vec<AbsPathBuf> = Vec::new();
+        for file in files {
+            let same_dir = |it: &AbsPathBuf| it.parent() == file.parent();
+            if !self.discover_project_queries.iter().any(same_dir)
+                && !self.discovered_projects.iter().map(|(it, _)| it).any(same_dir)
+                && !new_files.iter().any(same_dir)
+            {
+                new_files.push(file.clone());
+            }
+        }
+        if new_files.is_empty() {
+            return;
+        }
+        let cause = format!("opened files outside of any crate: {:?}", new_files);
+        self.discover_project_queries.extend(new_files);
+        self.discover_project_queue.request_op(cause, ());
+    }
+
+    /// Asks the project discovery command again about the projects whose build inputs might
+    /// have changed with `path`, that is the ones discovered for a file below its directory.
+    pub(crate) fn rediscover_projects_for_change(&mut self, path: &AbsPath) {
+        if !should_rediscover_for_change(&self.config, path) {
+            return;
+        }
+        let dir = match path.parent() {
+            Some(it) => it,
+            None => return,
+        };
+        let stale = self
+            .discovered_projects
+            .iter()
+            .map(|(file, _)| file)
+            .filter(|file| file.starts_with(dir) && !self.discover_project_queries.contains(file))
+            .cloned()
+            .collect::<Vec<_>>();
+        if stale.is_empty() {
+            return;
+        }
+        self.discover_project_queries.extend(stale);
+        self.discover_project_queue
+            .request_op(format!("build file changed: {}", path.display()), ());
+    }
+
+    pub(crate) fn discover_projects(&mut self, cause: Cause) {
+        tracing::info!(%cause, "will discover projects");
+        let config = match self.config.discover_project() {
+            Some(it) if !self.discover_project_queries.is_empty() => it,
+            _ => {
+                self.discover_project_queue.op_completed(());
+                return;
+            }
+        };
+        let queries = self.discover_project_queries.clone();
+        let root = self.config.root_path().clone();
+        self.task_pool.handle.spawn_with_sender(move |sender| {
+            sender.send(Task::DiscoverProject(DiscoverProjectProgress::Begin)).unwrap();
+            let mut res = Vec::new();
+            for file in queries {
+                let msg = file.display().to_string();
+                sender.send(Task::DiscoverProject(DiscoverProjectProgress::Report(msg))).unwrap();
+                let project = ProjectJson::discover(&config.command, &file, &root);
+                res.push((file, project));
+            }
+            sender.send(Task::DiscoverProject(DiscoverProjectProgress::End(res))).unwrap();
+        });
+    }
+
+    pub(crate) fn set_discovered_projects(
+        &mut self,
+        results: Vec<(AbsPathBuf, anyhow::Result<ProjectJson>)>,
+    ) {
+        let mut projects = self.discovered_projects.clone();
+        let mut errors = Vec::new();
+        // Keep the files queried while the discovery was running, they still need to be asked
+        // about.
+        self.discover_project_queries.retain(|it| !results.iter().any(|(file, _)| file == it));
+        for (file, res) in results {
+            // What was discovered now replaces the project previously discovered for the file.
+            projects.retain(|(it, _)| *it != file);
+            match res {
+                // Only one file per project is needed to rediscover it later on.
+                Ok(project) => {
+                    if !projects.iter().any(|(_, it)| *it == project) {
+                        projects.push((file, project));
+                    }
+                }
+                // Failed files are asked about again once they are reopened.
+                Err(err) => errors.push(format!("{}: {:#}", file.display(), err)),
+            }
+        }
+        self.discover_project_queue.op_completed(());
+        if !errors.is_empty() {
+            self.show_and_log_error(
+                format!("rust-analyzer failed to discover projects for {} file(s)", errors.len()),
+                Some(errors.join("\n")),
+            );
+        }
+        let changed =
+            projects.iter().map(|(_, it)| it).ne(self.discovered_projects.iter().map(|(_, it)| it));
+        self.discovered_projects = projects;
+        if changed {
+            tracing::info!("discovered projects changed: {:?}", self.discovered_projects);
+            self.fetch_workspaces_queue
+                .request_op("discovered projects changed".to_string(), FetchWorkspaceKind::Full);
+        }
+    }
+
     pub(crate) fn fetch_build_data(&mut self, cause: Cause) {
         tracing::info!(%cause, "will fetch build data");
         let workspaces = Arc::clone(&self.workspaces);
@@ -209,28 +420,6 @@ impl GlobalState {
             .filter_map(|res| res.as_ref().ok().cloned())
             .collect::<Vec<_>>();
 
-        fn eq_ignore_build_data<'a>(
-            left: &'a ProjectWorkspace,
-            right: &'a ProjectWorkspace,
-        ) -> bool {
-            let key = |p: &'a ProjectWorkspace| match p {
-                ProjectWorkspace::Cargo {
-                    cargo,
-                    sysroot,
-                    rustc,
-                    rustc_cfg,
-                    cfg_overrides,
-
-                    build_scripts: _,
-                } => Some((cargo, sysroot, rustc, rustc_cfg, cfg_overrides)),
-                _ => None,
-            };
-            match (key(left), key(right)) {
-                (Some(lk), Some(rk)) => lk == rk,
-                _ => left == right,
-            }
-        }
-
         let same_workspaces = workspaces.len() == self.workspaces.len()
             && workspaces
                 .iter()
@@ -265,10 +454,18 @@ impl GlobalState {
 
             // Here, we completely changed the workspace (Cargo.toml edit), so
             // we don't care about build-script results, they are stale.
-            self.workspaces = Arc::new(workspaces)
+            self.workspaces = Arc::new(workspaces);
+            // The crates the active targets were chosen for might be gone.
+            self.analysis_host.clear_active_targets();
+            // The features of the dependencies might have changed.
+            self.manifest_diagnostics_cache.lock().clear();
         }
 
         if let FilesWatcher::Client = self.config.files().watcher {
+            let discover_watched_files = match self.config.discover_project() {
+                Some(it) => it.watched_files,
+                None => Vec::new(),
+            };
             let registration_options = lsp_types::DidChangeWatchedFilesRegistrationOptions {
                 watchers: self
                     .workspaces
@@ -277,11 +474,17 @@ impl GlobalState {
                     .filter(|it| it.is_local)
                     .flat_map(|root| {
                         root.include.into_iter().flat_map(|it| {
+                            let build_files = discover_watched_files
+                                .iter()
+                                .map(|name| format!("{}/**/{}", it.display(), name))
+                                .collect::<Vec<_>>();
                             [
                                 format!("{}/**/*.rs", it.display()),
                                 format!("{}/**/Cargo.toml", it.display()),
                                 format!("{}/**/Cargo.lock", it.display()),
                             ]
+                            .into_iter()
+                            .chain(build_files)
                         })
                     })
                     .map(|glob_pattern| lsp_types::FileSystemWatcher { glob_pattern, kind: None })
@@ -301,22 +504,10 @@ impl GlobalState {
         let mut change = Change::new();
 
         let files_config = self.config.files();
-        let project_folders = ProjectFolders::new(&self.workspaces, &files_config.exclude);
-
-        if self.proc_macro_client.is_none() {
-            if let Some((path, args)) = self.config.proc_macro_srv() {
-                match ProcMacroServer::spawn(path.clone(), args) {
-                    Ok(it) => self.proc_macro_client = Some(it),
-                    Err(err) => {
-                        tracing::error!(
-                            "Failed to run proc_macro_srv from path {}, error: {:?}",
-                            path.display(),
-                            err
-                        );
-                    }
-                }
-            }
-        }
+        let project_folders =
+            ProjectFolders::new(&self.workspaces, &self.detached_files, &files_config.exclude);
+
+        self.spawn_proc_macro_servers();
 
         let watch = match files_config.watcher {
             FilesWatcher::Client => vec![],
@@ -329,17 +520,76 @@ impl GlobalState {
             version: self.vfs_config_version,
         });
 
-        // Create crate graph from all the workspaces
-        let crate_graph = {
-            let proc_macro_client = self.proc_macro_client.as_ref();
-            let dummy_replacements = self.config.dummy_replacements();
-            let mut load_proc_macro = move |crate_name: &str, path: &AbsPath| {
-                load_proc_macro(
-                    proc_macro_client,
-                    path,
-                    dummy_replacements.get(crate_name).map(|v| &**v).unwrap_or_default(),
-                )
-            };
+        self.recreate_crate_graph(&mut change);
+
+        self.source_root_config = project_folders.source_root_config;
+
+        self.apply_change(change);
+        self.process_changes();
+        self.reload_flycheck();
+        tracing::info!("did switch workspaces");
+    }
+
+    /// Spawns the proc-macro servers of the workspaces, unless they're already running or
+    /// disabled, and stops the ones no workspace uses anymore.
+    fn spawn_proc_macro_servers(&mut self) {
+        let mut old_servers = mem::take(&mut self.proc_macro_servers);
+        let (path, args) = match self.config.proc_macro_srv() {
+            Some(it) => it,
+            None => return,
+        };
+        // All servers share one cache, so that it survives restarts of the servers.
+        let cache = old_servers.values().flatten().find_map(|it| it.expansion_cache()).cloned();
+        let cache =
+            cache.or_else(|| self.config.proc_macro_cache().map(ExpansionCache::new).map(Arc::new));
+        let timeout = self.config.proc_macro_timeout();
+        let configure = |mut it: ProcMacroServer| {
+            it.set_expansion_cache(cache.clone());
+            it.set_expansion_timeout(timeout);
+            it
+        };
+
+        for ws in self.workspaces.iter() {
+            let toolchain_srv = ws
+                .sysroot()
+                .and_then(|it| it.proc_macro_srv())
+                .filter(|_| self.config.prefer_toolchain_proc_macro_srv());
+            if let Some(srv) = toolchain_srv {
+                if !self.proc_macro_servers.contains_key(srv) {
+                    let server = old_servers
+                        .remove(srv)
+                        .unwrap_or_else(|| spawn_toolchain_proc_macro_srv(srv).map(&configure));
+                    self.proc_macro_servers.insert(srv.to_path_buf(), server);
+                }
+                if let Some(Some(_)) = self.proc_macro_servers.get(srv) {
+                    continue;
+                }
+            }
+            if !self.proc_macro_servers.contains_key(&path) {
+                let server = old_servers.remove(&path).unwrap_or_else(|| {
+                    match ProcMacroServer::spawn(path.clone(), &args) {
+                        Ok(it) => Some(configure(it)),
+                        Err(err) => {
+                            tracing::error!(
+                                "Failed to run proc_macro_srv from path {}, error: {:?}",
+                                path.display(),
+                                err
+                            );
+                            None
+                        }
+                    }
+                });
+                self.proc_macro_servers.insert(path.clone(), server);
+            }
+        }
+    }
+
+    /// Recreates the crate graph of the workspaces, loading their proc-macros with the current
+    /// server and policies.
+    fn recreate_crate_graph(&mut self, change: &mut Change) {
+        let (crate_graph, starts) = {
+            let policies = self.config.proc_macro_policies();
+            let proc_macro_srv = self.config.proc_macro_srv();
 
             let vfs = &mut self.vfs.write().0;
             let loader = &mut self.loader;
@@ -358,20 +608,198 @@ impl GlobalState {
                 res
             };
 
+            let graphs: Vec<_> = self
+                .workspaces
+                .iter()
+                .map(|ws| {
+                    let server = proc_macro_server(
+                        &self.proc_macro_servers,
+                        &self.config,
+                        &proc_macro_srv,
+                        ws,
+                    );
+                    let mut load_proc_macro = |crate_name: &str, path: &AbsPath| {
+                        load_proc_macro(server, path, |name| policies.policy(crate_name, name))
+                    };
+                    ws.to_crate_graph(&mut load_proc_macro, &mut load)
+                })
+                .collect();
+            let (old_workspaces, old_starts) = &self.workspace_crate_ids;
+            let starts = crate_id_starts(&self.workspaces, &graphs, old_workspaces, old_starts);
+
             let mut crate_graph = CrateGraph::default();
-            for ws in self.workspaces.iter() {
-                crate_graph.extend(ws.to_crate_graph(&mut load_proc_macro, &mut load));
+            let mut graphs: Vec<_> = graphs.into_iter().zip(starts.iter().copied()).collect();
+            graphs.sort_by_key(|&(_, start)| start);
+            for (graph, start) in graphs {
+                crate_graph.extend_at(graph, start);
             }
-            crate_graph
+            (crate_graph, starts)
         };
-        change.set_crate_graph(crate_graph);
+        self.workspace_crate_ids = (Arc::clone(&self.workspaces), starts);
+        self.workspace_crate_graph = Arc::new(crate_graph);
+        change.set_crate_graph(self.crate_graph_with_detached_files());
+    }
+
+    /// Restarts the proc-macro servers and reloads the proc-macros of the current workspaces,
+    /// without running the build scripts again.
+    fn reload_proc_macros(&mut self) {
+        self.proc_macro_servers.clear();
+        self.spawn_proc_macro_servers();
+        let mut change = Change::new();
+        self.recreate_crate_graph(&mut change);
+        self.apply_change(change);
+    }
+
+    /// Looks for opened files which don't belong to any crate, so that they can be analyzed as
+    /// standalone crates.
+    pub(crate) fn fetch_detached_files(&mut self) {
+        if !self.config.analyze_detached_files() {
+            self.set_detached_files(Vec::new());
+            // The files still need to be looked for to run the project discovery on them.
+            if self.config.discover_project().is_none() {
+                return;
+            }
+        }
 
+        let analysis = self.analysis_host.analysis();
+        let vfs = Arc::clone(&self.vfs);
+        let (open_files, detached_roots) = {
+            let vfs = &self.vfs.read().0;
+            let open_files = self
+                .mem_docs
+                .iter()
+                .filter_map(|path| {
+                    let abs_path = path.as_path()?;
+                    if abs_path.extension().unwrap_or_default() != "rs" {
+                        return None;
+                    }
+                    Some((vfs.file_id(path)?, abs_path.to_path_buf()))
+                })
+                .collect::<Vec<_>>();
+            let detached_roots = self
+                .detached_files
+                .iter()
+                .filter_map(|path| vfs.file_id(&VfsPath::from(path.clone())))
+                .collect::<FxHashSet<_>>();
+            (open_files, detached_roots)
+        };
+        let previous = self.detached_files.clone();
+        self.task_pool.handle.spawn(move || {
+            let mut unowned = Vec::new();
+            for (file_id, path) in open_files {
+                match belongs_to_crate(&analysis, file_id, &detached_roots) {
+                    Ok(true) => (),
+                    Ok(false) => unowned.push(path),
+                    // The files will be checked again on the next change.
+                    Err(_) => return Task::DetachedFiles(previous),
+                }
+            }
+            let vfs = &vfs.read().0;
+            let files = unowned.into_iter().filter(|path| !is_linkable(vfs, path)).collect();
+            Task::DetachedFiles(files)
+        });
+    }
+
+    pub(crate) fn set_detached_files(&mut self, files: Vec<AbsPathBuf>) {
+        if files == self.detached_files {
+            return;
+        }
+        tracing::info!("detached files changed: {:?}", files);
+        self.detached_files = files;
+
+        if self.config.linked_projects().is_empty() && self.discovered_projects.is_empty() {
+            // Without any workspace there's no sysroot to back the files with, so load them as a
+            // detached files workspace instead.
+            self.fetch_workspaces_queue
+                .request_op("detached files changed".to_string(), FetchWorkspaceKind::Full);
+            return;
+        }
+
+        let mut change = Change::new();
+        let project_folders = ProjectFolders::new(
+            &self.workspaces,
+            &self.detached_files,
+            &self.config.files().exclude,
+        );
         self.source_root_config = project_folders.source_root_config;
+        change.set_roots(self.source_root_config.partition(&self.vfs.read().0));
+        change.set_crate_graph(self.crate_graph_with_detached_files());
+        self.apply_change(change);
+    }
 
-        self.analysis_host.apply_change(change);
-        self.process_changes();
-        self.reload_flycheck();
-        tracing::info!("did switch workspaces");
+    /// Flips `feature` of the workspace packages called `package`, returning whether it is now
+    /// enabled.
+    pub(crate) fn toggle_feature(&mut self, package: &str, feature: &str) -> Result<bool, String> {
+        let crate_graph = self.analysis_host.raw_database().crate_graph();
+        let packages = find_packages(&crate_graph, &self.workspaces, &self.vfs.read().0, package);
+        if packages.is_empty() {
+            return Err(format!("no workspace package named `{}`", package));
+        }
+        if !packages.iter().any(|(_, features)| features.contains_key(feature)) {
+            return Err(format!("package `{}` has no feature named `{}`", package, feature));
+        }
+
+        let atom = feature_atom(feature);
+        let enabled = !packages
+            .iter()
+            .flat_map(|(crates, _)| crates)
+            .any(|&krate| crate_graph[krate].cfg_options.check(&atom.clone().into()) == Some(true));
+        if enabled {
+            // Only the cfgs of the package can be changed, the crate graph stays as it is.
+            for (crates, features) in &packages {
+                for (dep, dep_feature) in enabled_dependencies(features, feature) {
+                    let name = dep.replace('-', "_");
+                    let is_present = crates.iter().all(|&krate| {
+                        crate_graph[krate].dependencies.iter().any(|it| it.name.to_string() == name)
+                    });
+                    if !is_present {
+                        return Err(format!(
+                            "feature `{}` needs the optional dependency `{}`, enable it with \
+                             `rust-analyzer.cargo.features` instead",
+                            feature, dep
+                        ));
+                    }
+                    if let Some(dep_feature) = dep_feature {
+                        tracing::warn!(
+                            "toggling `{}` doesn't enable the feature `{}` of `{}`",
+                            feature,
+                            dep_feature,
+                            dep
+                        );
+                    }
+                }
+            }
+        }
+        drop(crate_graph);
+
+        self.feature_toggles.retain(|(p, f, _)| (p.as_str(), f.as_str()) != (package, feature));
+        self.feature_toggles.push((package.to_string(), feature.to_string(), enabled));
+
+        let mut change = Change::new();
+        change.set_crate_graph(self.crate_graph_with_detached_files());
+        self.apply_change(change);
+        Ok(enabled)
+    }
+
+    /// Detached files only change when they are opened or closed. Their contents live in local,
+    /// low-durability source roots, while the crate graph stays at high durability so that
+    /// edits don't revalidate every library query.
+    fn crate_graph_with_detached_files(&self) -> CrateGraph {
+        let mut crate_graph = (*self.workspace_crate_graph).clone();
+        apply_feature_toggles(
+            &mut crate_graph,
+            &self.workspaces,
+            &self.vfs.read().0,
+            &self.feature_toggles,
+        );
+        add_detached_crates(
+            &mut crate_graph,
+            &self.workspaces,
+            &self.detached_files,
+            &self.vfs.read().0,
+            self.config.detached_files_workspace_dependencies(),
+        );
+        crate_graph
     }
 
     fn fetch_workspace_error(&self) -> Result<(), String> {
@@ -413,42 +841,66 @@ impl GlobalState {
 
     fn reload_flycheck(&mut self) {
         let _p = profile::span("GlobalState::reload_flycheck");
-        let config = match self.config.flycheck() {
-            Some(it) => it,
-            None => {
-                self.flycheck = Vec::new();
-                self.diagnostics.clear_check();
-                return;
-            }
-        };
+        let profiles = self.config.flycheck();
+        if profiles.is_empty() {
+            self.flycheck = Vec::new();
+            self.diagnostics.clear_check_all();
+            return;
+        }
 
         let sender = self.flycheck_sender.clone();
-        self.flycheck = self
-            .workspaces
-            .iter()
-            .enumerate()
-            .filter_map(|(id, w)| match w {
-                ProjectWorkspace::Cargo { cargo, .. } => Some((id, cargo.workspace_root())),
-                ProjectWorkspace::Json { project, .. } => {
-                    // Enable flychecks for json projects if a custom flycheck command was supplied
-                    // in the workspace configuration.
-                    match config {
-                        FlycheckConfig::CustomCommand { .. } => Some((id, project.path())),
-                        _ => None,
+        let mut flycheck = Vec::new();
+        for (ws, w) in self.workspaces.iter().enumerate() {
+            for (idx, profile) in profiles.iter().enumerate() {
+                let (config, root) = match w {
+                    ProjectWorkspace::Cargo { cargo, .. } => {
+                        (profile.config.clone(), cargo.workspace_root())
                     }
-                }
-                ProjectWorkspace::DetachedFiles { .. } => None,
-            })
-            .map(|(id, root)| {
+                    ProjectWorkspace::Json { project, .. } => match &profile.config {
+                        // Enable flychecks for json projects if a custom flycheck command was
+                        // supplied in the workspace configuration, or else if the project
+                        // provides one, which is only run for the first profile.
+                        FlycheckConfig::CustomCommand { .. } => {
+                            (profile.config.clone(), project.path())
+                        }
+                        FlycheckConfig::CargoCommand { .. } => {
+                            match project
+                                .member_runnables(project_model::RunnableKind::Check)
+                                .next()
+                            {
+                                Some(it) if idx == 0 => {
+                                    let config = FlycheckConfig::CustomCommand {
+                                        command: it.program.clone(),
+                                        args: it.args.clone(),
+                                    };
+                                    (config, &*it.cwd)
+                                }
+                                _ => continue,
+                            }
+                        }
+                    },
+                    ProjectWorkspace::DetachedFiles { .. } => continue,
+                };
                 let sender = sender.clone();
-                FlycheckHandle::spawn(
-                    id,
+                let handle = FlycheckHandle::spawn(
+                    flycheck.len(),
                     Box::new(move |msg| sender.send(msg).unwrap()),
-                    config.clone(),
+                    config,
                     root.to_path_buf(),
-                )
-            })
-            .collect();
+                );
+                flycheck.push(Flycheck {
+                    handle,
+                    workspace: ws,
+                    profile: profile.clone(),
+                    is_running: false,
+                });
+            }
+        }
+        // The diagnostics of flychecks which don't exist anymore would never be cleared.
+        for id in flycheck.len()..self.flycheck.len() {
+            self.diagnostics.clear_check(id);
+        }
+        self.flycheck = flycheck;
     }
 }
 
@@ -462,13 +914,27 @@ pub(crate) struct ProjectFolders {
 impl ProjectFolders {
     pub(crate) fn new(
         workspaces: &[ProjectWorkspace],
+        detached_files: &[AbsPathBuf],
         global_excludes: &[AbsPathBuf],
     ) -> ProjectFolders {
         let mut res = ProjectFolders::default();
         let mut fsc = FileSetConfig::builder();
         let mut local_filesets = vec![];
 
-        for root in workspaces.iter().flat_map(|ws| ws.to_roots()) {
+        let mut roots = workspaces.iter().flat_map(|ws| ws.to_roots()).collect::<Vec<_>>();
+        // Detached files outside of all workspaces get their own local roots, so that they are
+        // treated like the user's own code.
+        for file in detached_files {
+            if !roots.iter().any(|root| root.include.iter().any(|it| file.starts_with(it))) {
+                roots.push(PackageRoot {
+                    is_local: true,
+                    include: vec![file.clone()],
+                    exclude: Vec::new(),
+                });
+            }
+        }
+
+        for root in roots {
             let file_set_roots: Vec<VfsPath> =
                 root.include.iter().cloned().map(VfsPath::from).collect();
 
@@ -501,6 +967,13 @@ impl ProjectFolders {
             fsc.add_file_set(file_set_roots)
         }
 
+        // The manifests of workspace members have diagnostics, hover and go-to-definition too.
+        let manifests = member_manifests(workspaces);
+        if !manifests.is_empty() {
+            res.watch.push(res.load.len());
+            res.load.push(vfs::loader::Entry::Files(manifests));
+        }
+
         let fsc = fsc.build();
         res.source_root_config = SourceRootConfig { fsc, local_filesets };
 
@@ -533,21 +1006,128 @@ impl SourceRootConfig {
     }
 }
 
-/// Load the proc-macros for the given lib path, replacing all expanders whose names are in `dummy_replace`
-/// with an identity dummy expander.
+/// Returns the first crate id of each of the `workspaces`, with the crates of `graphs`.
+///
+/// The workspaces which were loaded before keep the ids they had, if they still fit, so that
+/// the ids of their crates don't shift when other workspaces are added or removed.
+fn crate_id_starts(
+    workspaces: &[ProjectWorkspace],
+    graphs: &[CrateGraph],
+    old_workspaces: &[ProjectWorkspace],
+    old_starts: &[u32],
+) -> Vec<u32> {
+    let mut starts = vec![None; workspaces.len()];
+    let mut taken: Vec<Range<u32>> = Vec::new();
+    for ((ws, graph), start) in workspaces.iter().zip(graphs).zip(&mut starts) {
+        let old_start = old_workspaces
+            .iter()
+            .zip(old_starts)
+            .find(|(old, _)| eq_ignore_build_data(old, ws))
+            .map(|(_, &start)| start);
+        if let Some(old_start) = old_start {
+            let range = old_start..old_start + graph.len() as u32;
+            if taken.iter().all(|it| it.end <= range.start || range.end <= it.start) {
+                taken.push(range);
+                *start = Some(old_start);
+            }
+        }
+    }
+
+    let mut next = taken.iter().map(|it| it.end).max().unwrap_or(0);
+    starts
+        .into_iter()
+        .zip(graphs)
+        .map(|(start, graph)| {
+            start.unwrap_or_else(|| {
+                let start = next;
+                next += graph.len() as u32;
+                start
+            })
+        })
+        .collect()
+}
+
+fn eq_ignore_build_data<'a>(left: &'a ProjectWorkspace, right: &'a ProjectWorkspace) -> bool {
+    let key = |p: &'a ProjectWorkspace| match p {
+        ProjectWorkspace::Cargo {
+            cargo,
+            sysroot,
+            rustc,
+            rustc_cfg,
+            cfg_overrides,
+
+            build_scripts: _,
+        } => Some((cargo, sysroot, rustc, rustc_cfg, cfg_overrides)),
+        _ => None,
+    };
+    match (key(left), key(right)) {
+        (Some(lk), Some(rk)) => lk == rk,
+        _ => left == right,
+    }
+}
+
+fn spawn_toolchain_proc_macro_srv(path: &AbsPath) -> Option<ProcMacroServer> {
+    match ProcMacroServer::spawn_toolchain(path.to_path_buf()) {
+        Ok(it) => {
+            tracing::info!("Using the toolchain proc-macro server {}", path.display());
+            Some(it)
+        }
+        Err(err) => {
+            tracing::warn!(
+                "Failed to run toolchain proc-macro server {}, falling back to the vendored ABIs: {:?}",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+/// The server which expands the proc-macros of `ws`: the one distributed with its sysroot if
+/// that one is preferred and running, ours otherwise.
+fn proc_macro_server<'a>(
+    servers: &'a FxHashMap<AbsPathBuf, Option<ProcMacroServer>>,
+    config: &Config,
+    proc_macro_srv: &Option<(AbsPathBuf, Vec<OsString>)>,
+    ws: &ProjectWorkspace,
+) -> Option<&'a ProcMacroServer> {
+    let toolchain_srv = ws
+        .sysroot()
+        .and_then(|it| it.proc_macro_srv())
+        .filter(|_| config.prefer_toolchain_proc_macro_srv());
+    toolchain_srv
+        .and_then(|path| servers.get(path)?.as_ref())
+        .or_else(|| servers.get(&proc_macro_srv.as_ref()?.0)?.as_ref())
+}
+
+/// Load the proc-macros for the given lib path, assigning each of them the expansion policy
+/// returned by `policy` for its name.
 pub(crate) fn load_proc_macro(
     server: Option<&ProcMacroServer>,
     path: &AbsPath,
-    dummy_replace: &[Box<str>],
+    policy: impl Fn(&str) -> ProcMacroExpansionPolicy,
 ) -> ProcMacroLoadResult {
     let res: Result<_, String> = (|| {
         let dylib = MacroDylib::new(path.to_path_buf())
             .map_err(|io| format!("Proc-macro dylib loading failed: {io}"))?;
         Ok(if let Some(it) = server {
+            match it.bridge_abi(&dylib) {
+                Some(abi) => tracing::info!(
+                    "Expanding proc-macros of {} (built by rustc {}.{}.{}) with the {abi}",
+                    path.display(),
+                    dylib.info().version.0,
+                    dylib.info().version.1,
+                    dylib.info().version.2,
+                ),
+                None => {
+                    return Err(format!(
+                        "Proc-macro dylib loading failed: no vendored ABI supports {:?}",
+                        dylib.info()
+                    ))
+                }
+            }
             let vec = it.load_dylib(dylib).map_err(|e| format!("{e}"))?;
-            vec.into_iter()
-                .map(|expander| expander_to_proc_macro(expander, dummy_replace))
-                .collect()
+            vec.into_iter().map(|expander| expander_to_proc_macro(expander, &policy)).collect()
         } else {
             Vec::new()
         })
@@ -569,7 +1149,7 @@ pub(crate) fn load_proc_macro(
 
     fn expander_to_proc_macro(
         expander: proc_macro_api::ProcMacro,
-        dummy_replace: &[Box<str>],
+        policy: &dyn Fn(&str) -> ProcMacroExpansionPolicy,
     ) -> ProcMacro {
         let name = SmolStr::from(expander.name());
         let kind = match expander.kind() {
@@ -577,13 +1157,8 @@ pub(crate) fn load_proc_macro(
             proc_macro_api::ProcMacroKind::FuncLike => ProcMacroKind::FuncLike,
             proc_macro_api::ProcMacroKind::Attr => ProcMacroKind::Attr,
         };
-        let expander: Arc<dyn ProcMacroExpander> =
-            if dummy_replace.iter().any(|replace| &**replace == name) {
-                Arc::new(DummyExpander)
-            } else {
-                Arc::new(Expander(expander))
-            };
-        ProcMacro { name, kind, expander }
+        let policy = policy(&name);
+        ProcMacro { name, kind, expander: Arc::new(Expander(expander)), policy }
     }
 
     #[derive(Debug)]
@@ -604,21 +1179,270 @@ pub(crate) fn load_proc_macro(
             }
         }
     }
+}
 
-    /// Dummy identity expander, used for proc-macros that are deliberately ignored by the user.
-    #[derive(Debug)]
-    struct DummyExpander;
+/// Whether the file is part of any crate, apart from the standalone crates created for
+/// `detached_roots`.
+fn belongs_to_crate(
+    analysis: &Analysis,
+    file_id: FileId,
+    detached_roots: &FxHashSet<FileId>,
+) -> Cancellable<bool> {
+    for krate in analysis.crate_for(file_id)? {
+        if !detached_roots.contains(&analysis.crate_root(krate)?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
 
-    impl ProcMacroExpander for DummyExpander {
-        fn expand(
-            &self,
-            subtree: &tt::Subtree,
-            _: Option<&tt::Subtree>,
-            _: &Env,
-        ) -> Result<tt::Subtree, ProcMacroExpansionError> {
-            Ok(subtree.clone())
+/// Whether an existing module could include the file with a `mod` item. Such files were most
+/// likely just forgotten, so we leave them to the `unlinked-file` diagnostic.
+fn is_linkable(vfs: &vfs::Vfs, path: &AbsPath) -> bool {
+    let (dir, stem) = match (path.parent(), path.file_stem()) {
+        (Some(dir), Some(stem)) => (dir, stem),
+        _ => return false,
+    };
+    let dir = if stem == "mod" { dir.parent().unwrap_or(dir) } else { dir };
+    let mut candidates = vec![dir.join("mod.rs"), dir.join("lib.rs"), dir.join("main.rs")];
+    if let (Some(parent), Some(name)) = (dir.parent(), dir.file_name()) {
+        let mut name = name.to_os_string();
+        name.push(".rs");
+        candidates.push(parent.join(name));
+    }
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate != path)
+        .any(|candidate| vfs.file_id(&VfsPath::from(candidate)).is_some())
+}
+
+/// Returns the crates of each workspace package called `name`, along with its features.
+fn find_packages<'a>(
+    crate_graph: &CrateGraph,
+    workspaces: &'a [ProjectWorkspace],
+    vfs: &vfs::Vfs,
+    name: &str,
+) -> Vec<(Vec<CrateId>, &'a FxHashMap<String, Vec<String>>)> {
+    let mut res = Vec::new();
+    for ws in workspaces {
+        let cargo = match ws {
+            ProjectWorkspace::Cargo { cargo, .. } => cargo,
+            _ => continue,
+        };
+        for pkg in cargo.packages().filter(|&pkg| cargo[pkg].name == name) {
+            let roots: FxHashSet<FileId> = cargo[pkg]
+                .targets
+                .iter()
+                .filter_map(|&tgt| vfs.file_id(&VfsPath::from(cargo[tgt].root.clone())))
+                .collect();
+            let crates =
+                crate_graph.iter().filter(|&it| roots.contains(&crate_graph[it].root_file_id));
+            res.push((crates.collect(), &cargo[pkg].features));
+        }
+    }
+    res
+}
+
+fn apply_feature_toggles(
+    crate_graph: &mut CrateGraph,
+    workspaces: &[ProjectWorkspace],
+    vfs: &vfs::Vfs,
+    toggles: &[(String, String, bool)],
+) {
+    for (package, feature, enabled) in toggles {
+        for (crates, features) in find_packages(crate_graph, workspaces, vfs, package) {
+            let diff = if *enabled {
+                // Enabling a feature also enables the package's features it implies.
+                let implied = implied_features(features, feature);
+                CfgDiff::new(implied.into_iter().map(feature_atom).collect(), Vec::new())
+            } else {
+                // Features implying the disabled one can't stay enabled without it.
+                let implying = implying_features(features, feature);
+                CfgDiff::new(Vec::new(), implying.into_iter().map(feature_atom).collect())
+            };
+            let diff = match diff {
+                Some(it) => it,
+                None => continue,
+            };
+            for krate in crates {
+                let mut cfg_options = crate_graph[krate].cfg_options.clone();
+                cfg_options.apply_diff(diff.clone());
+                crate_graph.set_cfg_options(krate, cfg_options);
+            }
+        }
+    }
+}
+
+/// Returns `feature` together with the package features it implies, directly or transitively.
+fn implied_features<'a>(
+    features: &'a FxHashMap<String, Vec<String>>,
+    feature: &'a str,
+) -> Vec<&'a str> {
+    let mut implied = vec![feature];
+    let mut i = 0;
+    while let Some(&feat) = implied.get(i) {
+        for it in features.get(feat).into_iter().flatten() {
+            if features.contains_key(it) && !implied.contains(&it.as_str()) {
+                implied.push(it);
+            }
+        }
+        i += 1;
+    }
+    implied
+}
+
+/// Returns `feature` together with the package features implying it.
+fn implying_features<'a>(
+    features: &'a FxHashMap<String, Vec<String>>,
+    feature: &'a str,
+) -> Vec<&'a str> {
+    let mut implying = vec![feature];
+    implying.extend(
+        features
+            .keys()
+            .map(String::as_str)
+            .filter(|&it| it != feature && implied_features(features, it).contains(&feature)),
+    );
+    implying
+}
+
+/// Returns the dependencies enabled by `feature` and the features it implies, together with the
+/// feature of the dependency they enable, if any. Weak dependency features (`dep?/feature`)
+/// don't enable their dependency and are skipped.
+fn enabled_dependencies<'a>(
+    features: &'a FxHashMap<String, Vec<String>>,
+    feature: &'a str,
+) -> Vec<(&'a str, Option<&'a str>)> {
+    let mut res = Vec::new();
+    for feat in implied_features(features, feature) {
+        for it in features.get(feat).into_iter().flatten() {
+            let dep = match (it.strip_prefix("dep:"), it.split_once('/')) {
+                (Some(dep), _) => (dep, None),
+                (None, Some((dep, _))) if dep.ends_with('?') => continue,
+                (None, Some((dep, dep_feature))) => (dep, Some(dep_feature)),
+                (None, None) => continue,
+            };
+            if !res.contains(&dep) {
+                res.push(dep);
+            }
         }
     }
+    res
+}
+
+fn feature_atom(feature: &str) -> CfgAtom {
+    CfgAtom::KeyValue { key: "feature".into(), value: feature.into() }
+}
+
+/// Adds a standalone crate for each of the detached `files` to `crate_graph`. The crates depend
+/// on the sysroot or, if `workspace_deps` is set, on the workspace package enclosing the file
+/// together with its dependencies.
+fn add_detached_crates(
+    crate_graph: &mut CrateGraph,
+    workspaces: &[ProjectWorkspace],
+    files: &[AbsPathBuf],
+    vfs: &vfs::Vfs,
+    workspace_deps: bool,
+) {
+    let file_id_of = |path: &AbsPath| vfs.file_id(&VfsPath::from(path.to_path_buf()));
+
+    let mut sysroot = None;
+    let mut sysroot_deps = Vec::new();
+    for (origin, name) in [
+        (LangCrateOrigin::Core, "core"),
+        (LangCrateOrigin::Alloc, "alloc"),
+        (LangCrateOrigin::Std, "std"),
+    ] {
+        let krate = crate_graph
+            .iter()
+            .find(|&krate| crate_graph[krate].origin == CrateOrigin::Lang(origin));
+        if let Some(krate) = krate {
+            sysroot_deps.push(Dependency::new(CrateName::new(name).unwrap(), krate));
+            sysroot = Some(krate);
+        }
+    }
+
+    for path in files {
+        let file_id = match file_id_of(path) {
+            Some(it) => it,
+            None => continue,
+        };
+        if crate_graph.crate_id_for_crate_root(file_id).is_some() {
+            continue;
+        }
+
+        let enclosing = workspaces
+            .iter()
+            .filter(|_| workspace_deps)
+            .find_map(|ws| ws.enclosing_crate_root(path))
+            .and_then(|(root, edition)| {
+                Some((crate_graph.crate_id_for_crate_root(file_id_of(root)?)?, edition))
+            });
+        let (edition, cfg_options, deps) = match enclosing {
+            Some((krate, edition)) => {
+                let data = &crate_graph[krate];
+                let mut deps = data.dependencies.clone();
+                if let Some(name) = &data.display_name {
+                    deps.push(Dependency::new(name.crate_name().clone(), krate));
+                }
+                (edition, data.cfg_options.clone(), deps)
+            }
+            None => {
+                let cfg_options =
+                    sysroot.map(|krate| crate_graph[krate].cfg_options.clone()).unwrap_or_default();
+                (Edition::CURRENT, cfg_options, sysroot_deps.clone())
+            }
+        };
+
+        let display_name = path
+            .file_stem()
+            .and_then(|it| it.to_str())
+            .map(|it| CrateDisplayName::from_canonical_name(it.to_string()));
+        let krate = crate_graph.add_crate_root(
+            file_id,
+            edition,
+            display_name,
+            None,
+            cfg_options.clone(),
+            cfg_options,
+            Env::default(),
+            Ok(Vec::new()),
+            false,
+            CrateOrigin::CratesIo { repo: None },
+        );
+        for dep in deps {
+            if let Err(err) = crate_graph.add_dep(krate, dep) {
+                tracing::error!("{}", err);
+            }
+        }
+    }
+}
+
+/// Whether a change to `path` should re-run the project discovery command.
+fn should_rediscover_for_change(config: &Config, path: &AbsPath) -> bool {
+    let file_name = match path.file_name().and_then(|it| it.to_str()) {
+        Some(it) => it,
+        None => return false,
+    };
+    match config.discover_project() {
+        Some(it) => it.watched_files.iter().any(|name| name == file_name),
+        None => false,
+    }
+}
+
+/// Returns the `Cargo.toml` files of the members of the Cargo workspaces.
+pub(crate) fn member_manifests(workspaces: &[ProjectWorkspace]) -> Vec<AbsPathBuf> {
+    workspaces
+        .iter()
+        .flat_map(|ws| match ws {
+            ProjectWorkspace::Cargo { cargo, .. } => cargo
+                .packages()
+                .filter(|&pkg| cargo[pkg].is_member)
+                .map(|pkg| cargo[pkg].manifest.to_path_buf())
+                .collect(),
+            ProjectWorkspace::Json { .. } | ProjectWorkspace::DetachedFiles { .. } => Vec::new(),
+        })
+        .collect()
 }
 
 pub(crate) fn should_refresh_for_change(path: &AbsPath, change_kind: ChangeKind) -> bool {
@@ -661,3 +1485,185 @@ pub(crate) fn should_refresh_for_change(path: &AbsPath, change_kind: ChangeKind)
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use cfg::CfgOptions;
+    use ide_db::base_db::{CrateOrigin, Edition, Env, FileId};
+    use project_model::ProjectJson;
+
+    use super::*;
+
+    fn base(name: &str) -> AbsPathBuf {
+        AbsPathBuf::assert(std::env::temp_dir().join(name))
+    }
+
+    fn workspace(name: &str) -> ProjectWorkspace {
+        workspace_with_crates(name, serde_json::json!([]))
+    }
+
+    fn workspace_with_crates(name: &str, crates: serde_json::Value) -> ProjectWorkspace {
+        let data = serde_json::from_value(serde_json::json!({ "crates": crates })).unwrap();
+        ProjectWorkspace::Json {
+            project: ProjectJson::new(&base(name), data),
+            sysroot: None,
+            crate_sysroots: Vec::new(),
+            rustc_cfg: Vec::new(),
+        }
+    }
+
+    fn graph(n_crates: u32) -> CrateGraph {
+        let mut graph = CrateGraph::default();
+        for file in 0..n_crates {
+            add_crate(&mut graph, FileId(file), None, CrateOrigin::CratesIo { repo: None });
+        }
+        graph
+    }
+
+    fn add_crate(
+        graph: &mut CrateGraph,
+        file_id: FileId,
+        name: Option<&str>,
+        origin: CrateOrigin,
+    ) -> CrateId {
+        graph.add_crate_root(
+            file_id,
+            Edition::CURRENT,
+            name.map(|it| CrateDisplayName::from_canonical_name(it.to_string())),
+            None,
+            CfgOptions::default(),
+            CfgOptions::default(),
+            Env::default(),
+            Ok(Vec::new()),
+            false,
+            origin,
+        )
+    }
+
+    fn vfs(files: &[&AbsPath]) -> vfs::Vfs {
+        let mut vfs = vfs::Vfs::default();
+        for file in files {
+            vfs.set_file_contents(VfsPath::from(file.to_path_buf()), Some(Vec::new()));
+        }
+        vfs
+    }
+
+    /// A graph with a sysroot and a crate `foo` depending on `bar`, whose root is
+    /// `base/src/lib.rs`.
+    fn graph_with_sysroot(vfs: &vfs::Vfs, base: &AbsPath) -> CrateGraph {
+        let mut graph = CrateGraph::default();
+        for (file, name, origin) in [
+            (100, "core", LangCrateOrigin::Core),
+            (101, "alloc", LangCrateOrigin::Alloc),
+            (102, "std", LangCrateOrigin::Std),
+        ] {
+            add_crate(&mut graph, FileId(file), Some(name), CrateOrigin::Lang(origin));
+        }
+        let lib = vfs.file_id(&VfsPath::from(base.join("src/lib.rs"))).unwrap();
+        let foo = add_crate(&mut graph, lib, Some("foo"), CrateOrigin::CratesIo { repo: None });
+        let bar =
+            add_crate(&mut graph, FileId(103), Some("bar"), CrateOrigin::CratesIo { repo: None });
+        graph.add_dep(foo, Dependency::new(CrateName::new("bar").unwrap(), bar)).unwrap();
+        graph
+    }
+
+    fn dependency_names(graph: &CrateGraph, root: &AbsPath, vfs: &vfs::Vfs) -> Vec<String> {
+        let file_id = vfs.file_id(&VfsPath::from(root.to_path_buf())).unwrap();
+        let krate = graph.crate_id_for_crate_root(file_id).unwrap();
+        graph[krate].dependencies.iter().map(|dep| dep.name.to_string()).collect()
+    }
+
+    #[test]
+    fn crate_ids_of_kept_workspaces_are_stable() {
+        let (a, b, c) = (workspace("a"), workspace("b"), workspace("c"));
+        let starts = crate_id_starts(&[a.clone(), b.clone()], &[graph(2), graph(3)], &[], &[]);
+        assert_eq!(starts, vec![0, 2]);
+
+        // Removing `a` keeps the ids of `b`, and `c` is placed after them.
+        let starts = crate_id_starts(&[b.clone(), c], &[graph(3), graph(1)], &[a, b], &starts);
+        assert_eq!(starts, vec![2, 5]);
+    }
+
+    #[test]
+    fn detached_files_depend_on_the_sysroot() {
+        let base = base("detached-sysroot");
+        let (lib, detached) = (base.join("src/lib.rs"), base.join("src/detached.rs"));
+        let vfs = vfs(&[&lib, &detached]);
+        let workspaces = [workspace_with_crates(
+            "detached-sysroot",
+            serde_json::json!([{ "root_module": "src/lib.rs", "edition": "2021", "deps": [] }]),
+        )];
+        let mut graph = graph_with_sysroot(&vfs, &base);
+
+        add_detached_crates(&mut graph, &workspaces, &[detached.clone()], &vfs, false);
+        assert_eq!(dependency_names(&graph, &detached, &vfs), ["core", "alloc", "std"]);
+    }
+
+    #[test]
+    fn detached_files_can_depend_on_the_enclosing_package() {
+        let base = base("detached-workspace-deps");
+        let (lib, detached) = (base.join("src/lib.rs"), base.join("src/detached.rs"));
+        let vfs = vfs(&[&lib, &detached]);
+        let workspaces = [workspace_with_crates(
+            "detached-workspace-deps",
+            serde_json::json!([{ "root_module": "src/lib.rs", "edition": "2021", "deps": [] }]),
+        )];
+        let mut graph = graph_with_sysroot(&vfs, &base);
+
+        add_detached_crates(&mut graph, &workspaces, &[detached.clone()], &vfs, true);
+        assert_eq!(dependency_names(&graph, &detached, &vfs), ["bar", "foo"]);
+    }
+
+    #[test]
+    fn linkable_files_are_left_to_unlinked_file() {
+        let base = base("linkable");
+        let lib = base.join("src/lib.rs");
+        let vfs = vfs(&[&lib]);
+
+        assert!(is_linkable(&vfs, &base.join("src/forgotten.rs")));
+        assert!(is_linkable(&vfs, &base.join("src/forgotten/mod.rs")));
+        assert!(!is_linkable(&vfs, &base.join("src/nested/deeper.rs")));
+        assert!(!is_linkable(&vfs, &base.join("scratch.rs")));
+        assert!(!is_linkable(&vfs, &lib));
+    }
+
+    fn features(features: &[(&str, &[&str])]) -> FxHashMap<String, Vec<String>> {
+        features
+            .iter()
+            .map(|(name, implied)| {
+                (name.to_string(), implied.iter().map(|it| it.to_string()).collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn toggled_features_follow_implications() {
+        let features = features(&[
+            ("default", &["std"]),
+            ("std", &["alloc"]),
+            ("alloc", &[]),
+            ("serde", &["dep:serde"]),
+        ]);
+
+        assert_eq!(implied_features(&features, "default"), ["default", "std", "alloc"]);
+        assert_eq!(implied_features(&features, "serde"), ["serde"]);
+
+        let mut implying = implying_features(&features, "alloc");
+        implying.sort_unstable();
+        assert_eq!(implying, ["alloc", "default", "std"]);
+        assert_eq!(implying_features(&features, "serde"), ["serde"]);
+    }
+
+    #[test]
+    fn toggled_features_find_the_dependencies_they_enable() {
+        let features = features(&[
+            ("default", &["derive", "rand?/std"]),
+            ("derive", &["dep:serde_derive", "serde/derive"]),
+        ]);
+
+        assert_eq!(
+            enabled_dependencies(&features, "default"),
+            [("serde_derive", None), ("serde", Some("derive"))]
+        );
+    }
+}
@@ -7,10 +7,11 @@ use std::{
 
 use ide::{
     Annotation, AnnotationKind, Assist, AssistKind, Cancellable, CompletionItem,
-    CompletionItemKind, CompletionRelevance, Documentation, FileId, FileRange, FileSystemEdit,
-    Fold, FoldKind, Highlight, HlMod, HlOperator, HlPunct, HlRange, HlTag, Indel, InlayHint,
-    InlayKind, Markup, NavigationTarget, ReferenceCategory, RenameError, Runnable, Severity,
-    SignatureHelp, SourceChange, StructureNodeKind, SymbolKind, TextEdit, TextRange, TextSize,
+    CompletionItemKind, CompletionRelevance, DocumentLink, DocumentLinkTarget, Documentation,
+    FileId, FileRange, FileSystemEdit, Fold, FoldKind, Highlight, HlMod, HlOperator, HlPunct,
+    HlRange, HlTag, Indel, InlayHint, InlayHintLabel, InlayKind, Markup, NavigationTarget,
+    ReferenceCategory, RenameError, Runnable, Severity, SignatureHelp, SourceChange,
+    StructureNodeKind, SymbolKind, TextEdit, TextRange, TextSize,
 };
 use itertools::Itertools;
 use serde_json::to_value;
@@ -418,9 +419,47 @@ pub(crate) fn inlay_hint(
     snap: &GlobalStateSnapshot,
     line_index: &LineIndex,
     render_colons: bool,
-    inlay_hint: InlayHint,
-) -> lsp_types::InlayHint {
-    lsp_types::InlayHint {
+    mut inlay_hint: InlayHint,
+) -> Result<lsp_types::InlayHint> {
+    let data = (|| match inlay_hint.tooltip {
+        Some(ide::InlayTooltip::HoverOffset(file_id, offset)) => {
+            let uri = url(snap, file_id);
+            let line_index = snap.file_line_index(file_id).ok()?;
+
+            let text_document = lsp_types::TextDocumentIdentifier { uri };
+            to_value(lsp_ext::InlayHintResolveData {
+                text_document,
+                position: lsp_ext::PositionOrRange::Position(position(&line_index, offset)),
+            })
+            .ok()
+        }
+        Some(ide::InlayTooltip::HoverRanged(file_id, text_range)) => {
+            let uri = url(snap, file_id);
+            let text_document = lsp_types::TextDocumentIdentifier { uri };
+            let line_index = snap.file_line_index(file_id).ok()?;
+            to_value(lsp_ext::InlayHintResolveData {
+                text_document,
+                position: lsp_ext::PositionOrRange::Range(range(&line_index, text_range)),
+            })
+            .ok()
+        }
+        _ => None,
+    })();
+    // Hover tooltips are expensive, leave them to `inlayHint/resolve` if the client can do that.
+    let tooltip = match inlay_hint.tooltip {
+        Some(ide::InlayTooltip::String(s)) => Some(lsp_types::InlayHintTooltip::String(s)),
+        _ if data.is_some() && snap.config.inlay_hint_resolve_support("tooltip") => None,
+        _ => Some(lsp_types::InlayHintTooltip::String(inlay_hint.label.to_string())),
+    };
+
+    match inlay_hint.kind {
+        InlayKind::ParameterHint if render_colons => inlay_hint.label.append_str(":"),
+        InlayKind::TypeHint if render_colons => inlay_hint.label.prepend_str(": "),
+        InlayKind::ClosureReturnTypeHint => inlay_hint.label.prepend_str(" -> "),
+        _ => {}
+    }
+
+    Ok(lsp_types::InlayHint {
         position: match inlay_hint.kind {
             // before annotated thing
             InlayKind::ParameterHint
@@ -451,15 +490,9 @@ pub(crate) fn inlay_hint(
             | InlayKind::ImplicitReborrowHint
             | InlayKind::TypeHint
             | InlayKind::ClosingBraceHint => false,
-            InlayKind::BindingModeHint => inlay_hint.label != "&",
+            InlayKind::BindingModeHint => inlay_hint.label.as_simple_str() != Some("&"),
             InlayKind::ParameterHint | InlayKind::LifetimeHint => true,
         }),
-        label: lsp_types::InlayHintLabel::String(match inlay_hint.kind {
-            InlayKind::ParameterHint if render_colons => format!("{}:", inlay_hint.label),
-            InlayKind::TypeHint if render_colons => format!(": {}", inlay_hint.label),
-            InlayKind::ClosureReturnTypeHint => format!(" -> {}", inlay_hint.label),
-            _ => inlay_hint.label.clone(),
-        }),
         kind: match inlay_hint.kind {
             InlayKind::ParameterHint => Some(lsp_types::InlayHintKind::PARAMETER),
             InlayKind::ClosureReturnTypeHint | InlayKind::TypeHint | InlayKind::ChainingHint => {
@@ -471,36 +504,33 @@ pub(crate) fn inlay_hint(
             | InlayKind::ImplicitReborrowHint
             | InlayKind::ClosingBraceHint => None,
         },
+        label: inlay_hint_label(snap, inlay_hint.label)?,
         text_edits: None,
-        data: (|| match inlay_hint.tooltip {
-            Some(ide::InlayTooltip::HoverOffset(file_id, offset)) => {
-                let uri = url(snap, file_id);
-                let line_index = snap.file_line_index(file_id).ok()?;
-
-                let text_document = lsp_types::TextDocumentIdentifier { uri };
-                to_value(lsp_ext::InlayHintResolveData {
-                    text_document,
-                    position: lsp_ext::PositionOrRange::Position(position(&line_index, offset)),
-                })
-                .ok()
-            }
-            Some(ide::InlayTooltip::HoverRanged(file_id, text_range)) => {
-                let uri = url(snap, file_id);
-                let text_document = lsp_types::TextDocumentIdentifier { uri };
-                let line_index = snap.file_line_index(file_id).ok()?;
-                to_value(lsp_ext::InlayHintResolveData {
-                    text_document,
-                    position: lsp_ext::PositionOrRange::Range(range(&line_index, text_range)),
-                })
-                .ok()
-            }
-            _ => None,
-        })(),
-        tooltip: Some(match inlay_hint.tooltip {
-            Some(ide::InlayTooltip::String(s)) => lsp_types::InlayHintTooltip::String(s),
-            _ => lsp_types::InlayHintTooltip::String(inlay_hint.label),
-        }),
+        data,
+        tooltip,
+    })
+}
+
+fn inlay_hint_label(
+    snap: &GlobalStateSnapshot,
+    label: InlayHintLabel,
+) -> Result<lsp_types::InlayHintLabel> {
+    if let Some(s) = label.as_simple_str() {
+        return Ok(lsp_types::InlayHintLabel::String(s.into()));
     }
+    let parts = label
+        .parts
+        .into_iter()
+        .map(|part| {
+            Ok(lsp_types::InlayHintLabelPart {
+                value: part.text,
+                tooltip: None,
+                location: part.linked_location.map(|it| location(snap, it)).transpose()?,
+                command: None,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(lsp_types::InlayHintLabel::LabelParts(parts))
 }
 
 static TOKEN_RESULT_COUNTER: AtomicU32 = AtomicU32::new(1);
@@ -1028,6 +1058,55 @@ pub(crate) fn call_hierarchy_item(
     })
 }
 
+pub(crate) fn document_link(
+    snap: &GlobalStateSnapshot,
+    line_index: &LineIndex,
+    link: DocumentLink,
+) -> Result<Option<lsp_types::DocumentLink>> {
+    let target = match link.target {
+        DocumentLinkTarget::Definition(nav) => {
+            let mut url = url(snap, nav.file_id);
+            let target_line_index = snap.file_line_index(nav.file_id)?;
+            let position = position(&target_line_index, nav.focus_or_full_range().start());
+            // Editors open links to files with this fragment at the given line and column.
+            url.set_fragment(Some(&format!("L{},{}", position.line + 1, position.character + 1)));
+            url
+        }
+        DocumentLinkTarget::File(file_id) => url(snap, file_id),
+        DocumentLinkTarget::Path(path) => snap.anchored_path(&path),
+        DocumentLinkTarget::Url(url) => match lsp_types::Url::parse(&url) {
+            Ok(it) => it,
+            Err(_) => return Ok(None),
+        },
+    };
+    Ok(Some(lsp_types::DocumentLink {
+        range: range(line_index, link.range),
+        target: Some(target),
+        tooltip: None,
+        data: None,
+    }))
+}
+
+pub(crate) fn type_hierarchy_item(
+    snap: &GlobalStateSnapshot,
+    target: NavigationTarget,
+) -> Result<lsp_ext::TypeHierarchyItem> {
+    let name = target.name.to_string();
+    let detail = target.description.clone();
+    let kind = target.kind.map(symbol_kind).unwrap_or(lsp_types::SymbolKind::STRUCT);
+    let (uri, range, selection_range) = location_info(snap, target)?;
+    Ok(lsp_ext::TypeHierarchyItem {
+        name,
+        kind,
+        tags: None,
+        detail,
+        uri,
+        range,
+        selection_range,
+        data: None,
+    })
+}
+
 pub(crate) fn code_action_kind(kind: AssistKind) -> lsp_types::CodeActionKind {
     match kind {
         AssistKind::None | AssistKind::Generate => lsp_types::CodeActionKind::EMPTY,
@@ -1079,6 +1158,24 @@ pub(crate) fn runnable(
 ) -> Result<lsp_ext::Runnable> {
     let config = snap.config.runnables();
     let spec = CargoTargetSpec::for_file(snap, runnable.nav.file_id)?;
+    if spec.is_none() {
+        let (template_kind, test_id) = match &runnable.kind {
+            ide::RunnableKind::Test { test_id, .. }
+            | ide::RunnableKind::Bench { test_id }
+            | ide::RunnableKind::DocTest { test_id } => {
+                (project_model::RunnableKind::Test, test_id.to_string())
+            }
+            ide::RunnableKind::TestMod { path } => {
+                (project_model::RunnableKind::Test, path.clone())
+            }
+            ide::RunnableKind::Bin => (project_model::RunnableKind::Run, String::new()),
+        };
+        if let Some(template) = snap.project_json_runnable(runnable.nav.file_id, template_kind)? {
+            let label = runnable.label(None);
+            let location = location_link(snap, None, runnable.nav)?;
+            return Ok(project_json_runnable(label, Some(location), template, &test_id));
+        }
+    }
     let workspace_root = spec.as_ref().map(|it| it.workspace_root.clone());
     let target = spec.as_ref().map(|s| s.target.clone());
     let (cargo_args, executable_args) =
@@ -1090,17 +1187,36 @@ pub(crate) fn runnable(
         label,
         location: Some(location),
         kind: lsp_ext::RunnableKind::Cargo,
-        args: lsp_ext::CargoRunnable {
+        args: lsp_ext::RunnableArgs::Cargo(lsp_ext::CargoRunnable {
             workspace_root: workspace_root.map(|it| it.into()),
             override_cargo: config.override_cargo,
             cargo_args,
             cargo_extra_args: config.cargo_extra_args,
             executable_args,
             expect_test: None,
-        },
+        }),
     })
 }
 
+/// Turns a `rust-project.json` command template into a shell runnable.
+pub(crate) fn project_json_runnable(
+    label: String,
+    location: Option<lsp_types::LocationLink>,
+    template: project_model::Runnable,
+    test_id: &str,
+) -> lsp_ext::Runnable {
+    lsp_ext::Runnable {
+        label,
+        location,
+        kind: lsp_ext::RunnableKind::Shell,
+        args: lsp_ext::RunnableArgs::Shell(lsp_ext::ShellRunnable {
+            cwd: template.cwd.into(),
+            program: template.program,
+            args: template.args.iter().map(|arg| arg.replace("{test_id}", test_id)).collect(),
+        }),
+    }
+}
+
 pub(crate) fn code_lens(
     acc: &mut Vec<lsp_types::CodeLens>,
     snap: &GlobalStateSnapshot,
@@ -1131,6 +1247,8 @@ pub(crate) fn code_lens(
                     data: None,
                 })
             }
+            // Only Cargo runnables can be turned into a debug configuration.
+            let can_debug = can_debug && r.kind == lsp_ext::RunnableKind::Cargo;
             if lens_config.debug && can_debug && client_commands_config.debug_single {
                 let command = command::debug_single(&r);
                 acc.push(lsp_types::CodeLens {
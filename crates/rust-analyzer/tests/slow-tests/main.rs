@@ -17,22 +17,29 @@ use std::{collections::HashMap, path::PathBuf, time::Instant};
 
 use expect_test::expect;
 use lsp_types::{
-    notification::DidOpenTextDocument,
+    notification::{DidChangeTextDocument, DidChangeWorkspaceFolders, DidOpenTextDocument},
     request::{
         CodeActionRequest, Completion, Formatting, GotoTypeDefinition, HoverRequest,
-        WillRenameFiles, WorkspaceSymbol,
+        InlayHintRequest, WillCreateFiles, WillDeleteFiles, WillRenameFiles, WorkspaceSymbol,
     },
-    CodeActionContext, CodeActionParams, CompletionParams, DidOpenTextDocumentParams,
-    DocumentFormattingParams, FileRename, FormattingOptions, GotoDefinitionParams, HoverParams,
-    PartialResultParams, Position, Range, RenameFilesParams, TextDocumentItem,
-    TextDocumentPositionParams, WorkDoneProgressParams,
+    CodeActionContext, CodeActionParams, CompletionParams, CreateFilesParams, DeleteFilesParams,
+    DidChangeTextDocumentParams, DidChangeWorkspaceFoldersParams, DidOpenTextDocumentParams,
+    DocumentFormattingParams, FileCreate, FileDelete, FileRename, FormattingOptions,
+    GotoDefinitionParams, HoverParams, InlayHintParams, PartialResultParams, Position, Range,
+    RenameFilesParams, TextDocumentContentChangeEvent, TextDocumentItem,
+    TextDocumentPositionParams, VersionedTextDocumentIdentifier, WorkDoneProgressParams,
+    WorkspaceFolder, WorkspaceFoldersChangeEvent, WorkspaceSymbolParams,
+};
+use rust_analyzer::lsp_ext::{
+    DocumentDiagnosticParams, DocumentDiagnosticReport, DocumentDiagnosticRequest, OnEnter,
+    PreviousResultId, Runnables, RunnablesParams, WorkspaceDiagnosticParams,
+    WorkspaceDiagnosticRequest,
 };
-use rust_analyzer::lsp_ext::{OnEnter, Runnables, RunnablesParams};
 use serde_json::json;
 use test_utils::skip_slow_tests;
 
 use crate::{
-    support::{project, Project},
+    support::{project, Project, Server},
     testdir::TestDir,
 };
 
@@ -1057,6 +1064,81 @@ fn main() {}
     );
 }
 
+#[test]
+fn test_will_create_and_delete_files() {
+    if skip_slow_tests() {
+        return;
+    }
+
+    let server = Project::with_fixture(
+        r#"
+//- /Cargo.toml
+[package]
+name = "foo"
+version = "0.0.0"
+
+//- /src/lib.rs
+mod old;
+use old::Old;
+fn lib() {}
+
+//- /src/old.rs
+pub struct Old;
+"#,
+    )
+    .with_config(serde_json::json!({
+        "files": { "removeImportsOnDelete": { "enable": true } }
+    }))
+    .server()
+    .wait_until_workspace_is_loaded();
+
+    let lib_uri = server.doc_id("src/lib.rs").uri;
+    let lib_text = std::fs::read_to_string(server.path().join("src/lib.rs")).unwrap();
+    let edit = server.send_request::<WillCreateFiles>(CreateFilesParams {
+        files: ["src/new.rs", "src/other.rs"]
+            .iter()
+            .map(|it| FileCreate { uri: server.doc_id(it).uri.to_string() })
+            .collect(),
+    });
+    assert_eq!(
+        apply_workspace_edit(&lib_text, &lib_uri, &edit),
+        "mod old;\nmod new;\nmod other;\nuse old::Old;\nfn lib() {}\n\n"
+    );
+
+    let edit = server.send_request::<WillDeleteFiles>(DeleteFilesParams {
+        files: vec![FileDelete { uri: server.doc_id("src/old.rs").uri.to_string() }],
+    });
+    assert_eq!(apply_workspace_edit(&lib_text, &lib_uri, &edit), "fn lib() {}\n\n");
+}
+
+/// Applies the edits of the `WorkspaceEdit` for the document at `uri` to its `text`.
+fn apply_workspace_edit(text: &str, uri: &lsp_types::Url, edit: &serde_json::Value) -> String {
+    let edit: lsp_types::WorkspaceEdit = serde_json::from_value(edit.clone()).unwrap();
+    let mut edits: Vec<_> = match edit.document_changes {
+        Some(lsp_types::DocumentChanges::Edits(changes)) => changes
+            .into_iter()
+            .filter(|it| it.text_document.uri == *uri)
+            .flat_map(|it| it.edits)
+            .map(|it| match it {
+                lsp_types::OneOf::Left(edit) => edit,
+                lsp_types::OneOf::Right(edit) => edit.text_edit,
+            })
+            .collect(),
+        changes => panic!("expected text document edits, got {:?}", changes),
+    };
+    let offset = |position: Position| {
+        let line: usize =
+            text.split_inclusive('\n').take(position.line as usize).map(str::len).sum();
+        line + position.character as usize
+    };
+    let mut text = text.to_string();
+    edits.sort_by_key(|it| (it.range.start.line, it.range.start.character));
+    for edit in edits.iter().rev() {
+        text.replace_range(offset(edit.range.start)..offset(edit.range.end), &edit.new_text);
+    }
+    text
+}
+
 #[test]
 fn test_exclude_config_works() {
     if skip_slow_tests() {
@@ -1094,3 +1176,226 @@ pub fn bar() {}
 
     server.request::<WorkspaceSymbol>(Default::default(), json!([]));
 }
+
+#[test]
+fn inlay_hint_label_parts() {
+    if skip_slow_tests() {
+        return;
+    }
+
+    let fixture = r#"
+//- /Cargo.toml
+[package]
+name = "foo"
+version = "0.0.0"
+
+//- /src/lib.rs
+pub struct Foo;
+fn f() {
+    let foo = &Foo;
+}
+"#;
+    let type_hint = |server: &Server| {
+        let res = server.send_request::<InlayHintRequest>(InlayHintParams {
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            text_document: server.doc_id("src/lib.rs"),
+            range: Range::new(Position::new(0, 0), Position::new(4, 0)),
+        });
+        let mut hints = res.as_array().unwrap().clone();
+        assert_eq!(hints.len(), 1);
+        hints.remove(0)
+    };
+
+    // The type links to its definition, and the tooltip is left to `inlayHint/resolve`.
+    let server = Project::with_fixture(fixture)
+        .with_caps(json!({
+            "textDocument": { "inlayHint": { "resolveSupport": { "properties": ["tooltip"] } } }
+        }))
+        .server()
+        .wait_until_workspace_is_loaded();
+    let hint = type_hint(&server);
+    assert_eq!(
+        hint["label"],
+        json!([
+            { "value": ": &" },
+            {
+                "value": "Foo",
+                "location": {
+                    "uri": server.doc_id("src/lib.rs").uri,
+                    "range": {
+                        "start": { "line": 0, "character": 11 },
+                        "end": { "line": 0, "character": 14 }
+                    }
+                }
+            }
+        ])
+    );
+    assert_eq!(hint.get("tooltip"), None);
+    drop(server);
+
+    // Otherwise, the tooltip is the label without the colon.
+    let server = Project::with_fixture(fixture).server().wait_until_workspace_is_loaded();
+    assert_eq!(type_hint(&server)["tooltip"], json!("&Foo"));
+}
+
+#[test]
+fn pull_diagnostics() {
+    if skip_slow_tests() {
+        return;
+    }
+
+    let server = Project::with_fixture(
+        r#"
+//- /Cargo.toml
+[workspace]
+members = ["foo", "baz"]
+
+//- /foo/Cargo.toml
+[package]
+name = "foo"
+version = "0.0.0"
+
+//- /foo/src/lib.rs
+mod bar;
+
+//- /baz/Cargo.toml
+[package]
+name = "baz"
+version = "0.0.0"
+
+//- /baz/src/lib.rs
+pub fn baz() {}
+"#,
+    )
+    .with_raw_caps(json!({ "textDocument": { "diagnostic": {} } }))
+    .server()
+    .wait_until_workspace_is_loaded();
+
+    let pull = |previous_result_id: Option<String>| {
+        let report = server.send_request::<DocumentDiagnosticRequest>(DocumentDiagnosticParams {
+            text_document: server.doc_id("foo/src/lib.rs"),
+            identifier: None,
+            previous_result_id,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        serde_json::from_value::<DocumentDiagnosticReport>(report).unwrap()
+    };
+
+    let result_id = match pull(None) {
+        DocumentDiagnosticReport::Full(report) => {
+            let codes: Vec<_> = report.items.iter().map(|it| it.code.clone().unwrap()).collect();
+            assert_eq!(
+                codes,
+                vec![lsp_types::NumberOrString::String("unresolved-module".to_string())]
+            );
+            report.result_id.unwrap()
+        }
+        report => panic!("expected a full report, got {:?}", report),
+    };
+    assert!(matches!(pull(Some(result_id.clone())), DocumentDiagnosticReport::Unchanged(_)));
+
+    // Editing a crate `foo` doesn't depend on keeps its diagnostics unchanged.
+    server.notification::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+        text_document: TextDocumentItem {
+            uri: server.doc_id("baz/src/lib.rs").uri,
+            language_id: "rust".to_string(),
+            version: 0,
+            text: "pub fn baz() {}\n".to_string(),
+        },
+    });
+    server.notification::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+        text_document: VersionedTextDocumentIdentifier {
+            uri: server.doc_id("baz/src/lib.rs").uri,
+            version: 1,
+        },
+        content_changes: vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "pub fn baz() { missing() }\n".to_string(),
+        }],
+    });
+    assert!(matches!(pull(Some(result_id.clone())), DocumentDiagnosticReport::Unchanged(_)));
+
+    // The reports are streamed as partial results, leaving the response empty.
+    let report = server.send_request::<WorkspaceDiagnosticRequest>(WorkspaceDiagnosticParams {
+        identifier: None,
+        previous_result_ids: vec![PreviousResultId {
+            uri: server.doc_id("foo/src/lib.rs").uri,
+            value: result_id.clone(),
+        }],
+        work_done_progress_params: WorkDoneProgressParams::default(),
+        partial_result_params: PartialResultParams {
+            partial_result_token: Some(lsp_types::NumberOrString::String("pull".to_string())),
+        },
+    });
+    assert_eq!(report, json!({ "items": [] }));
+    assert_eq!(
+        server.received_notifications("$/progress"),
+        vec![json!({
+            "token": "pull",
+            "value": {
+                "items": [{
+                    "uri": server.doc_id("foo/src/lib.rs").uri,
+                    "version": null,
+                    "kind": "unchanged",
+                    "resultId": result_id,
+                }]
+            }
+        })]
+    );
+}
+
+#[test]
+fn workspace_folders_can_be_added_and_removed() {
+    if skip_slow_tests() {
+        return;
+    }
+
+    let server = Project::with_fixture(
+        r#"
+//- /foo/Cargo.toml
+[package]
+name = "foo"
+version = "0.0.0"
+
+//- /foo/src/lib.rs
+pub fn foo() {}
+
+//- /bar/Cargo.toml
+[package]
+name = "bar"
+version = "0.0.0"
+
+//- /bar/src/lib.rs
+pub fn bar() {}
+"#,
+    )
+    .root("foo")
+    .server()
+    .wait_until_workspace_is_loaded();
+
+    let symbols = |query: &str| {
+        let res = server.send_request::<WorkspaceSymbol>(WorkspaceSymbolParams {
+            query: query.to_string(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        res.as_array().map_or(0, |it| it.len())
+    };
+    let folder =
+        |name: &str| WorkspaceFolder { uri: server.doc_id(name).uri, name: name.to_string() };
+    assert_eq!((symbols("foo"), symbols("bar")), (1, 0));
+
+    server.notification::<DidChangeWorkspaceFolders>(DidChangeWorkspaceFoldersParams {
+        event: WorkspaceFoldersChangeEvent { added: vec![folder("bar")], removed: Vec::new() },
+    });
+    server.wait_until_workspace_is_reloaded();
+    assert_eq!((symbols("foo"), symbols("bar")), (1, 1));
+
+    server.notification::<DidChangeWorkspaceFolders>(DidChangeWorkspaceFoldersParams {
+        event: WorkspaceFoldersChangeEvent { added: Vec::new(), removed: vec![folder("foo")] },
+    });
+    server.wait_until_workspace_is_reloaded();
+    assert_eq!((symbols("foo"), symbols("bar")), (0, 1));
+}
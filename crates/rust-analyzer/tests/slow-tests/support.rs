@@ -23,6 +23,8 @@ pub(crate) struct Project<'a> {
     tmp_dir: Option<TestDir>,
     roots: Vec<PathBuf>,
     config: serde_json::Value,
+    caps: serde_json::Value,
+    raw_caps: serde_json::Value,
 }
 
 impl<'a> Project<'a> {
@@ -41,6 +43,8 @@ impl<'a> Project<'a> {
                     },
                 }
             }),
+            caps: serde_json::json!({}),
+            raw_caps: serde_json::Value::Null,
         }
     }
 
@@ -54,17 +58,19 @@ impl<'a> Project<'a> {
         self
     }
 
+    /// Adds to the default client capabilities.
+    pub(crate) fn with_caps(mut self, caps: serde_json::Value) -> Project<'a> {
+        merge(&mut self.caps, caps);
+        self
+    }
+
+    /// Sets client capabilities lsp-types doesn't know about.
+    pub(crate) fn with_raw_caps(mut self, raw_caps: serde_json::Value) -> Project<'a> {
+        self.raw_caps = raw_caps;
+        self
+    }
+
     pub(crate) fn with_config(mut self, config: serde_json::Value) -> Project<'a> {
-        fn merge(dst: &mut serde_json::Value, src: serde_json::Value) {
-            match (dst, src) {
-                (Value::Object(dst), Value::Object(src)) => {
-                    for (k, v) in src {
-                        merge(dst.entry(k).or_insert(v.clone()), v)
-                    }
-                }
-                (dst, src) => *dst = src,
-            }
-        }
         merge(&mut self.config, config);
         self
     }
@@ -96,48 +102,50 @@ impl<'a> Project<'a> {
             roots.push(tmp_dir_path.clone());
         }
         let discovered_projects = roots
-            .into_iter()
+            .iter()
             .map(|it| ProjectManifest::discover_single(&it).unwrap())
             .collect::<Vec<_>>();
 
-        let mut config = Config::new(
-            tmp_dir_path,
-            lsp_types::ClientCapabilities {
-                workspace: Some(lsp_types::WorkspaceClientCapabilities {
-                    did_change_watched_files: Some(
-                        lsp_types::DidChangeWatchedFilesClientCapabilities {
-                            dynamic_registration: Some(true),
-                        },
-                    ),
+        let mut caps = serde_json::to_value(lsp_types::ClientCapabilities {
+            workspace: Some(lsp_types::WorkspaceClientCapabilities {
+                did_change_watched_files: Some(
+                    lsp_types::DidChangeWatchedFilesClientCapabilities {
+                        dynamic_registration: Some(true),
+                    },
+                ),
+                ..Default::default()
+            }),
+            text_document: Some(lsp_types::TextDocumentClientCapabilities {
+                definition: Some(lsp_types::GotoCapability {
+                    link_support: Some(true),
                     ..Default::default()
                 }),
-                text_document: Some(lsp_types::TextDocumentClientCapabilities {
-                    definition: Some(lsp_types::GotoCapability {
-                        link_support: Some(true),
-                        ..Default::default()
-                    }),
-                    code_action: Some(lsp_types::CodeActionClientCapabilities {
-                        code_action_literal_support: Some(
-                            lsp_types::CodeActionLiteralSupport::default(),
-                        ),
-                        ..Default::default()
-                    }),
-                    hover: Some(lsp_types::HoverClientCapabilities {
-                        content_format: Some(vec![lsp_types::MarkupKind::Markdown]),
-                        ..Default::default()
-                    }),
+                code_action: Some(lsp_types::CodeActionClientCapabilities {
+                    code_action_literal_support: Some(
+                        lsp_types::CodeActionLiteralSupport::default(),
+                    ),
                     ..Default::default()
                 }),
-                window: Some(lsp_types::WindowClientCapabilities {
-                    work_done_progress: Some(false),
+                hover: Some(lsp_types::HoverClientCapabilities {
+                    content_format: Some(vec![lsp_types::MarkupKind::Markdown]),
                     ..Default::default()
                 }),
-                experimental: Some(json!({
-                    "serverStatusNotification": true,
-                })),
                 ..Default::default()
-            },
-        );
+            }),
+            window: Some(lsp_types::WindowClientCapabilities {
+                work_done_progress: Some(false),
+                ..Default::default()
+            }),
+            experimental: Some(json!({
+                "serverStatusNotification": true,
+            })),
+            ..Default::default()
+        })
+        .unwrap();
+        merge(&mut caps, self.caps);
+        let mut config = Config::new(tmp_dir_path, serde_json::from_value(caps).unwrap());
+        config.set_raw_caps(self.raw_caps);
+        config.set_workspace_roots(roots);
         config.discovered_projects = Some(discovered_projects);
         config.update(self.config).expect("invalid config");
 
@@ -149,6 +157,17 @@ pub(crate) fn project(fixture: &str) -> Server {
     Project::with_fixture(fixture).server()
 }
 
+fn merge(dst: &mut serde_json::Value, src: serde_json::Value) {
+    match (dst, src) {
+        (Value::Object(dst), Value::Object(src)) => {
+            for (k, v) in src {
+                merge(dst.entry(k).or_insert(v.clone()), v)
+            }
+        }
+        (dst, src) => *dst = src,
+    }
+}
+
 pub(crate) struct Server {
     req_id: Cell<i32>,
     messages: RefCell<Vec<Message>>,
@@ -242,20 +261,28 @@ impl Server {
         }
         panic!("no response for {:?}", r);
     }
+    /// Returns the params of the notifications with `method` received so far.
+    pub(crate) fn received_notifications(&self, method: &str) -> Vec<Value> {
+        self.messages
+            .borrow()
+            .iter()
+            .filter_map(|msg| match msg {
+                Message::Notification(n) if n.method == method => Some(n.params.clone()),
+                _ => None,
+            })
+            .collect()
+    }
     pub(crate) fn wait_until_workspace_is_loaded(self) -> Server {
-        self.wait_for_message_cond(1, &|msg: &Message| match msg {
-            Message::Notification(n) if n.method == "experimental/serverStatus" => {
-                let status = n
-                    .clone()
-                    .extract::<lsp_ext::ServerStatusParams>("experimental/serverStatus")
-                    .unwrap();
-                status.quiescent
-            }
-            _ => false,
-        })
-        .unwrap_or_else(|Timeout| panic!("timeout while waiting for ws to load"));
+        self.wait_for_message_cond(1, &is_quiescent_status)
+            .unwrap_or_else(|Timeout| panic!("timeout while waiting for ws to load"));
         self
     }
+    /// Waits for the workspace to be loaded once more, after a change to it.
+    pub(crate) fn wait_until_workspace_is_reloaded(&self) {
+        let loaded = self.messages.borrow().iter().filter(|msg| is_quiescent_status(msg)).count();
+        self.wait_for_message_cond(loaded + 1, &is_quiescent_status)
+            .unwrap_or_else(|Timeout| panic!("timeout while waiting for ws to reload"));
+    }
     fn wait_for_message_cond(
         &self,
         n: usize,
@@ -301,6 +328,19 @@ impl Drop for Server {
 
 struct Timeout;
 
+fn is_quiescent_status(msg: &Message) -> bool {
+    match msg {
+        Message::Notification(n) if n.method == "experimental/serverStatus" => {
+            let status = n
+                .clone()
+                .extract::<lsp_ext::ServerStatusParams>("experimental/serverStatus")
+                .unwrap();
+            status.quiescent
+        }
+        _ => false,
+    }
+}
+
 fn recv_timeout(receiver: &Receiver<Message>) -> Result<Option<Message>, Timeout> {
     let timeout =
         if cfg!(target_os = "macos") { Duration::from_secs(300) } else { Duration::from_secs(120) };
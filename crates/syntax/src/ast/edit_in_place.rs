@@ -375,22 +375,13 @@ impl ast::UseTreeList {
 
 impl ast::Use {
     pub fn remove(&self) {
-        let next_ws = self
-            .syntax()
-            .next_sibling_or_token()
-            .and_then(|it| it.into_token())
-            .and_then(ast::Whitespace::cast);
-        if let Some(next_ws) = next_ws {
-            let ws_text = next_ws.syntax().text();
-            if let Some(rest) = ws_text.strip_prefix('\n') {
-                if rest.is_empty() {
-                    ted::remove(next_ws.syntax());
-                } else {
-                    ted::replace(next_ws.syntax(), make::tokens::whitespace(rest));
-                }
-            }
-        }
-        ted::remove(self.syntax());
+        remove_item(self.syntax());
+    }
+}
+
+impl ast::Module {
+    pub fn remove(&self) {
+        remove_item(self.syntax());
     }
 }
 
@@ -612,6 +603,23 @@ impl ast::StmtList {
     }
 }
 
+/// Removes an item along with the line break after it.
+fn remove_item(item: &SyntaxNode) {
+    let next_ws =
+        item.next_sibling_or_token().and_then(|it| it.into_token()).and_then(ast::Whitespace::cast);
+    if let Some(next_ws) = next_ws {
+        let ws_text = next_ws.syntax().text();
+        if let Some(rest) = ws_text.strip_prefix('\n') {
+            if rest.is_empty() {
+                ted::remove(next_ws.syntax());
+            } else {
+                ted::replace(next_ws.syntax(), make::tokens::whitespace(rest));
+            }
+        }
+    }
+    ted::remove(item);
+}
+
 fn normalize_ws_between_braces(node: &SyntaxNode) -> Option<()> {
     let l = node
         .children_with_tokens()
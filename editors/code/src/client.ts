@@ -260,6 +260,67 @@ export async function createClient(
     return client;
 }
 
+// `Cargo.toml` isn't a Rust document, so the client doesn't sync it or send requests for it on
+// its own. Sync the opened manifests, which the server reports diagnostics for, and forward the
+// requests the server answers for the manifests of workspace members.
+export function registerCargoTomlProviders(client: lc.LanguageClient): vscode.Disposable {
+    const selector = { scheme: "file", pattern: "**/Cargo.toml" };
+    const isCargoToml = (document: vscode.TextDocument) =>
+        vscode.languages.match(selector, document) > 0;
+    const didOpen = (document: vscode.TextDocument) => {
+        if (!isCargoToml(document)) return;
+        void client.sendNotification(lc.DidOpenTextDocumentNotification.type, {
+            textDocument: {
+                uri: document.uri.toString(),
+                languageId: document.languageId,
+                version: document.version,
+                text: document.getText(),
+            },
+        });
+    };
+    vscode.workspace.textDocuments.forEach(didOpen);
+    return vscode.Disposable.from(
+        vscode.workspace.onDidOpenTextDocument(didOpen),
+        vscode.workspace.onDidChangeTextDocument(({ document }) => {
+            if (!isCargoToml(document)) return;
+            void client.sendNotification(lc.DidChangeTextDocumentNotification.type, {
+                textDocument: { uri: document.uri.toString(), version: document.version },
+                contentChanges: [{ text: document.getText() }],
+            });
+        }),
+        vscode.workspace.onDidCloseTextDocument((document) => {
+            if (!isCargoToml(document)) return;
+            void client.sendNotification(lc.DidCloseTextDocumentNotification.type, {
+                textDocument: { uri: document.uri.toString() },
+            });
+        }),
+        vscode.languages.registerHoverProvider(selector, {
+            async provideHover(document, position, token) {
+                const result = await client.sendRequest(
+                    ra.hover,
+                    {
+                        textDocument:
+                            client.code2ProtocolConverter.asTextDocumentIdentifier(document),
+                        position: client.code2ProtocolConverter.asPosition(position),
+                    },
+                    token
+                );
+                return client.protocol2CodeConverter.asHover(result);
+            },
+        }),
+        vscode.languages.registerDefinitionProvider(selector, {
+            async provideDefinition(document, position, token) {
+                const result = await client.sendRequest(
+                    lc.DefinitionRequest.type,
+                    client.code2ProtocolConverter.asTextDocumentPositionParams(document, position),
+                    token
+                );
+                return client.protocol2CodeConverter.asDefinitionResult(result);
+            },
+        })
+    );
+}
+
 class ExperimentalFeatures implements lc.StaticFeature {
     fillClientCapabilities(capabilities: lc.ClientCapabilities): void {
         const caps: any = capabilities.experimental ?? {};
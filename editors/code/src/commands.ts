@@ -90,6 +90,62 @@ export function shuffleCrateGraph(ctx: Ctx): Cmd {
     };
 }
 
+export function clearProcMacroCache(ctx: Ctx): Cmd {
+    return async () => {
+        const client = ctx.client;
+        if (!client) return;
+
+        await client.sendRequest(ra.clearProcMacroCache);
+    };
+}
+
+export function toggleFeature(ctx: Ctx): Cmd {
+    return async () => {
+        const client = ctx.client;
+        if (!client) return;
+
+        const pkg = await vscode.window.showInputBox({ prompt: "Package to toggle a feature of" });
+        if (!pkg) return;
+        const feature = await vscode.window.showInputBox({ prompt: `Feature of \`${pkg}\`` });
+        if (!feature) return;
+
+        const enabled = await client.sendRequest(ra.toggleFeature, { package: pkg, feature });
+        await vscode.window.showInformationMessage(
+            `Feature \`${feature}\` of \`${pkg}\` is now ${enabled ? "enabled" : "disabled"}.`
+        );
+    };
+}
+
+export function switchActiveTarget(ctx: Ctx): Cmd {
+    return async () => {
+        const editor = ctx.activeRustEditor;
+        const client = ctx.client;
+        if (!editor || !client) return;
+
+        const textDocument = client.code2ProtocolConverter.asTextDocumentIdentifier(
+            editor.document
+        );
+        const targets = await client.sendRequest(ra.fileTargets, textDocument);
+        if (targets.length < 2) {
+            await vscode.window.showInformationMessage(
+                "This file is only analyzed for a single target."
+            );
+            return;
+        }
+
+        const items = targets.map((target, idx) => ({
+            label: target,
+            description: idx === 0 ? "active" : undefined,
+        }));
+        const picked = await vscode.window.showQuickPick(items, {
+            placeHolder: "Target to analyze this file for",
+        });
+        if (!picked) return;
+
+        await client.sendRequest(ra.setActiveTarget, { textDocument, target: picked.label });
+    };
+}
+
 export function matchingBrace(ctx: Ctx): Cmd {
     return async () => {
         const editor = ctx.activeRustEditor;
@@ -896,10 +952,12 @@ export function copyRunCommandLine(ctx: Ctx) {
     return async () => {
         const item = await selectRunnable(ctx, prevRunnable);
         if (!item) return;
-        const args = createArgs(item.runnable);
-        const commandLine = ["cargo", ...args].join(" ");
+        const commandLine =
+            item.runnable.kind === "cargo"
+                ? ["cargo", ...createArgs(item.runnable)].join(" ")
+                : [item.runnable.args.program, ...item.runnable.args.args].join(" ");
         await vscode.env.clipboard.writeText(commandLine);
-        await vscode.window.showInformationMessage("Cargo invocation copied to the clipboard.");
+        await vscode.window.showInformationMessage("Command line copied to the clipboard.");
     };
 }
 
@@ -3,7 +3,7 @@ import * as lc from "vscode-languageclient/node";
 import * as ra from "./lsp_ext";
 
 import { Config } from "./config";
-import { createClient } from "./client";
+import { createClient, registerCargoTomlProviders } from "./client";
 import { isRustEditor, RustEditor } from "./util";
 import { ServerStatusParams } from "./lsp_ext";
 
@@ -45,6 +45,7 @@ export class Ctx {
         res.pushCleanup(client.start());
         await client.onReady();
         client.onNotification(ra.serverStatus, (params) => res.setServerStatus(params));
+        res.pushCleanup(registerCargoTomlProviders(client));
         return res;
     }
 
@@ -9,7 +9,7 @@ import { prepareEnv } from "./run";
 
 const debugOutput = vscode.window.createOutputChannel("Debug");
 type DebugConfigProvider = (
-    config: ra.Runnable,
+    config: ra.CargoRunnable,
     executable: string,
     env: Record<string, string>,
     sourceFileMap?: Record<string, string>
@@ -18,6 +18,10 @@ type DebugConfigProvider = (
 export async function makeDebugConfig(ctx: Ctx, runnable: ra.Runnable): Promise<void> {
     const scope = ctx.activeRustEditor?.document.uri;
     if (!scope) return;
+    if (runnable.kind !== "cargo") {
+        await vscode.window.showErrorMessage("Only Cargo runnables can be debugged.");
+        return;
+    }
 
     const debugConfig = await getDebugConfiguration(ctx, runnable);
     if (!debugConfig) return;
@@ -43,6 +47,11 @@ export async function makeDebugConfig(ctx: Ctx, runnable: ra.Runnable): Promise<
 }
 
 export async function startDebugSession(ctx: Ctx, runnable: ra.Runnable): Promise<boolean> {
+    if (runnable.kind !== "cargo") {
+        await vscode.window.showErrorMessage("Only Cargo runnables can be debugged.");
+        return false;
+    }
+
     let debugConfig: vscode.DebugConfiguration | undefined = undefined;
     let message = "";
 
@@ -67,7 +76,7 @@ export async function startDebugSession(ctx: Ctx, runnable: ra.Runnable): Promis
 
 async function getDebugConfiguration(
     ctx: Ctx,
-    runnable: ra.Runnable
+    runnable: ra.CargoRunnable
 ): Promise<vscode.DebugConfiguration | undefined> {
     const editor = ctx.activeRustEditor;
     if (!editor) return;
@@ -156,7 +165,7 @@ async function getDebugConfiguration(
     return debugConfig;
 }
 
-async function getDebugExecutable(runnable: ra.Runnable): Promise<string> {
+async function getDebugExecutable(runnable: ra.CargoRunnable): Promise<string> {
     const cargo = new Cargo(runnable.args.workspaceRoot || ".", debugOutput);
     const executable = await cargo.executableFromArgs(runnable.args.cargoArgs);
 
@@ -165,7 +174,7 @@ async function getDebugExecutable(runnable: ra.Runnable): Promise<string> {
 }
 
 function getLldbDebugConfig(
-    runnable: ra.Runnable,
+    runnable: ra.CargoRunnable,
     executable: string,
     env: Record<string, string>,
     sourceFileMap?: Record<string, string>
@@ -184,7 +193,7 @@ function getLldbDebugConfig(
 }
 
 function getCppvsDebugConfig(
-    runnable: ra.Runnable,
+    runnable: ra.CargoRunnable,
     executable: string,
     env: Record<string, string>,
     sourceFileMap?: Record<string, string>
@@ -23,6 +23,27 @@ export const serverStatus = new lc.NotificationType<ServerStatusParams>(
 );
 
 export const reloadWorkspace = new lc.RequestType0<null, void>("rust-analyzer/reloadWorkspace");
+export const clearProcMacroCache = new lc.RequestType0<null, void>(
+    "rust-analyzer/clearProcMacroCache"
+);
+
+export interface ToggleFeatureParams {
+    package: string;
+    feature: string;
+}
+export const toggleFeature = new lc.RequestType<ToggleFeatureParams, boolean, void>(
+    "rust-analyzer/toggleFeature"
+);
+export const fileTargets = new lc.RequestType<lc.TextDocumentIdentifier, string[], void>(
+    "rust-analyzer/fileTargets"
+);
+export interface SetActiveTargetParams {
+    textDocument: lc.TextDocumentIdentifier;
+    target: string;
+}
+export const setActiveTarget = new lc.RequestType<SetActiveTargetParams, void, void>(
+    "rust-analyzer/setActiveTarget"
+);
 
 export const hover = new lc.RequestType<HoverParams, lc.Hover | null, void>("textDocument/hover");
 
@@ -106,7 +127,8 @@ export interface RunnablesParams {
     position: lc.Position | null;
 }
 
-export interface Runnable {
+export type Runnable = CargoRunnable | ShellRunnable;
+export interface CargoRunnable {
     label: string;
     location?: lc.LocationLink;
     kind: "cargo";
@@ -119,6 +141,16 @@ export interface Runnable {
         overrideCargo?: string;
     };
 }
+export interface ShellRunnable {
+    label: string;
+    location?: lc.LocationLink;
+    kind: "shell";
+    args: {
+        cwd: string;
+        program: string;
+        args: string[];
+    };
+}
 export const runnables = new lc.RequestType<RunnablesParams, Runnable[], void>(
     "experimental/runnables"
 );
@@ -142,6 +142,9 @@ async function initCommonContext(context: vscode.ExtensionContext, ctx: Ctx) {
     ctx.registerCommand("memoryUsage", commands.memoryUsage);
     ctx.registerCommand("shuffleCrateGraph", commands.shuffleCrateGraph);
     ctx.registerCommand("reloadWorkspace", commands.reloadWorkspace);
+    ctx.registerCommand("clearProcMacroCache", commands.clearProcMacroCache);
+    ctx.registerCommand("switchActiveTarget", commands.switchActiveTarget);
+    ctx.registerCommand("toggleFeature", commands.toggleFeature);
     ctx.registerCommand("matchingBrace", commands.matchingBrace);
     ctx.registerCommand("joinLines", commands.joinLines);
     ctx.registerCommand("parentModule", commands.parentModule);
@@ -38,7 +38,10 @@ export async function selectRunnable(
             continue;
         }
 
-        if (debuggeeOnly && (r.label.startsWith("doctest") || r.label.startsWith("cargo"))) {
+        if (
+            debuggeeOnly &&
+            (r.kind !== "cargo" || r.label.startsWith("doctest") || r.label.startsWith("cargo"))
+        ) {
             continue;
         }
         items.push(new RunnableQuickPick(r));
@@ -73,8 +76,12 @@ export async function selectRunnable(
             }),
             quickPick.onDidChangeActive((active) => {
                 if (showButtons && active.length > 0) {
-                    if (active[0].label.startsWith("cargo")) {
-                        // save button makes no sense for `cargo test` or `cargo check`
+                    if (
+                        active[0].label.startsWith("cargo") ||
+                        active[0].runnable.kind !== "cargo"
+                    ) {
+                        // save button makes no sense for `cargo test`, `cargo check` or shell
+                        // runnables, which can't be debugged
                         quickPick.buttons = [];
                     } else if (quickPick.buttons.length === 0) {
                         quickPick.buttons = quickPickButtons;
@@ -104,7 +111,7 @@ export function prepareEnv(
 ): Record<string, string> {
     const env: Record<string, string> = { RUST_BACKTRACE: "short" };
 
-    if (runnable.args.expectTest) {
+    if (runnable.kind === "cargo" && runnable.args.expectTest) {
         env["UPDATE_EXPECT"] = "1";
     }
 
@@ -126,11 +133,26 @@ export function prepareEnv(
 }
 
 export async function createTask(runnable: ra.Runnable, config: Config): Promise<vscode.Task> {
-    if (runnable.kind !== "cargo") {
-        // rust-analyzer supports only one kind, "cargo"
-        // do not use tasks.TASK_TYPE here, these are completely different meanings.
-
-        throw `Unexpected runnable kind: ${runnable.kind}`;
+    if (runnable.kind === "shell") {
+        // Commands of `rust-project.json` projects, run as they are.
+        const exec = new vscode.ProcessExecution(runnable.args.program, runnable.args.args, {
+            cwd: runnable.args.cwd,
+            env: prepareEnv(runnable, config.runnableEnv),
+        });
+        const definition = { type: "shell", command: runnable.args.program };
+        // eslint-disable-next-line @typescript-eslint/no-unnecessary-type-assertion
+        const target = vscode.workspace.workspaceFolders![0]; // safe, see main activate()
+        const shellTask = new vscode.Task(
+            definition,
+            target,
+            runnable.label,
+            tasks.TASK_SOURCE,
+            exec,
+            ["$rustc"]
+        );
+        shellTask.presentationOptions.clear = true;
+        shellTask.presentationOptions.focus = false;
+        return shellTask;
     }
 
     const args = createArgs(runnable);
@@ -163,7 +185,7 @@ export async function createTask(runnable: ra.Runnable, config: Config): Promise
     return cargoTask;
 }
 
-export function createArgs(runnable: ra.Runnable): string[] {
+export function createArgs(runnable: ra.CargoRunnable): string[] {
     const args = [...runnable.args.cargoArgs]; // should be a copy!
     if (runnable.args.cargoExtraArgs) {
         args.push(...runnable.args.cargoExtraArgs); // Append user-specified cargo options.